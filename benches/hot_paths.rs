@@ -0,0 +1,74 @@
+// Benchmarks for the parts of rendering that take the most time, for checking
+// whether a change has made things quicker (or slower):
+//
+//     cargo bench
+//
+// criterion keeps the results from the last run in target/criterion and says
+// how much each benchmark has changed since.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use rusrat::matrices::Matrix;
+use rusrat::precision::consts::FRAC_PI_3;
+use rusrat::rays::{IntersectionBuffer, Ray};
+use rusrat::shapes::sphere;
+use rusrat::tuple::Tuple;
+use rusrat::world::{render, view_transform, Camera, ObjectId, World};
+use rusrat::yaml;
+
+fn matrix_inverse(c: &mut Criterion) {
+    let m = Matrix::from_array([
+        [-5.0, 2.0, 6.0, -8.0],
+        [1.0, -5.0, 1.0, 8.0],
+        [7.0, 7.0, -6.0, -7.0],
+        [1.0, -3.0, 7.0, 4.0],
+    ]);
+    c.bench_function("matrix inverse", |b| b.iter(|| black_box(&m).inverse()));
+}
+
+fn sphere_intersection(c: &mut Criterion) {
+    let mut s = sphere::default();
+    s.set_transform(Matrix::translation(0.5, 0.0, 0.0).scale(2.0, 2.0, 2.0));
+    let r = Ray::new(
+        Tuple::point_new(0.0, 0.0, -5.0),
+        Tuple::vector_new(0.0, 0.1, 1.0),
+    );
+    let mut buffer = IntersectionBuffer::new();
+    c.bench_function("sphere intersection", |b| {
+        b.iter(|| {
+            buffer.clear();
+            black_box(&s).intersects_into(ObjectId(0), black_box(&r), &mut buffer);
+        })
+    });
+}
+
+fn default_world_render(c: &mut Criterion) {
+    let w = World::default();
+    let t = view_transform(
+        &Tuple::point_new(0.0, 1.5, -5.0),
+        &Tuple::point_new(0.0, 1.0, 0.0),
+        &Tuple::vector_new(0.0, 1.0, 0.0),
+    );
+    let mut cam = Camera::new(100, 100, FRAC_PI_3, t);
+    c.bench_function("default world 100x100", |b| b.iter(|| render(&mut cam, &w)));
+}
+
+// a glass ball with an air bubble in it, in front of a checked wall - most of
+// the work's in refraction and reflection
+fn glass_ball_render(c: &mut Criterion) {
+    let (w, cam) = yaml::parse_scene(include_str!("../examples/ball-in-ball.yaml"), None).unwrap();
+    let mut cam = cam.resized(100, 100);
+    // it takes long enough that fewer samples are plenty
+    let mut group = c.benchmark_group("glass ball");
+    group.sample_size(20);
+    group.bench_function("100x100", |b| b.iter(|| render(&mut cam, &w)));
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    matrix_inverse,
+    sphere_intersection,
+    default_world_render,
+    glass_ball_render
+);
+criterion_main!(benches);
@@ -0,0 +1,632 @@
+// A bounding volume hierarchy: the objects in a world sorted into a tree of
+// boxes, so a ray only has to be tested against the objects in the boxes it
+// goes through rather than against every one. Boxes are split where the
+// surface area heuristic says testing the two halves will be cheapest, which
+// copes much better than splitting down the middle with scenes where a few big
+// objects (floors, walls) sit among lots of small ones.
+//
+// Objects that go on forever, like planes, can't be boxed, so they're kept to
+// one side and tested against every ray.
+
+use crate::lanes::{self, Lanes};
+use crate::matrices::Matrix;
+use crate::precision::Float;
+use crate::rays::Ray;
+use crate::shapes::Shape;
+use crate::tuple::Tuple;
+use std::fmt;
+
+// how many buckets the centres of the objects are sorted into along each
+// axis, to find the best place to split a box
+const BINS: usize = 12;
+// the cost of testing a ray against a box, as a fraction of the cost of
+// testing it against an object
+const BOX_COST: Float = 0.125;
+// boxes with more objects than this are split even if the heuristic says
+// it's not worth it, so no one leaf ends up huge
+const MAX_LEAF_SIZE: usize = 8;
+
+// An axis-aligned box, from its lowest corner to its highest.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Bounds {
+    pub min: Tuple,
+    pub max: Tuple,
+}
+
+impl Bounds {
+    pub fn new(min: Tuple, max: Tuple) -> Bounds {
+        Bounds { min, max }
+    }
+
+    // a box around nothing, which grows to fit whatever's added to it
+    pub fn empty() -> Bounds {
+        Bounds::new(
+            Tuple::point_new(Float::INFINITY, Float::INFINITY, Float::INFINITY),
+            Tuple::point_new(
+                Float::NEG_INFINITY,
+                Float::NEG_INFINITY,
+                Float::NEG_INFINITY,
+            ),
+        )
+    }
+
+    pub fn around(points: impl IntoIterator<Item = Tuple>) -> Bounds {
+        points
+            .into_iter()
+            .fold(Bounds::empty(), |bounds, p| bounds.including(&p))
+    }
+
+    pub fn including(&self, p: &Tuple) -> Bounds {
+        Bounds::new(
+            Tuple::point_new(
+                self.min.x.min(p.x),
+                self.min.y.min(p.y),
+                self.min.z.min(p.z),
+            ),
+            Tuple::point_new(
+                self.max.x.max(p.x),
+                self.max.y.max(p.y),
+                self.max.z.max(p.z),
+            ),
+        )
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        self.including(&other.min).including(&other.max)
+    }
+
+    // the box around this one once it's been transformed
+    pub fn transformed(&self, m: &Matrix<Float, 4, 4>) -> Bounds {
+        let (lo, hi) = (&self.min, &self.max);
+        Bounds::around(
+            [
+                (lo.x, lo.y, lo.z),
+                (lo.x, lo.y, hi.z),
+                (lo.x, hi.y, lo.z),
+                (lo.x, hi.y, hi.z),
+                (hi.x, lo.y, lo.z),
+                (hi.x, lo.y, hi.z),
+                (hi.x, hi.y, lo.z),
+                (hi.x, hi.y, hi.z),
+            ]
+            .iter()
+            .map(|&(x, y, z)| m * &Tuple::point_new(x, y, z)),
+        )
+    }
+
+    pub fn centre(&self) -> Tuple {
+        Tuple::point_new(
+            (self.min.x + self.max.x) / 2.0,
+            (self.min.y + self.max.y) / 2.0,
+            (self.min.z + self.max.z) / 2.0,
+        )
+    }
+
+    fn surface_area(&self) -> Float {
+        let (x, y, z) = (
+            self.max.x - self.min.x,
+            self.max.y - self.min.y,
+            self.max.z - self.min.z,
+        );
+        if x < 0.0 || y < 0.0 || z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    // whether the ray goes through the box anywhere between t_min and t_max,
+    // given 1 divided by each of the ray's direction's components
+    fn hit_between(
+        &self,
+        ray: &Ray,
+        inverse_direction: &[Float; 3],
+        t_min: Float,
+        t_max: Float,
+    ) -> bool {
+        let (mut t_min, mut t_max) = (t_min, t_max);
+        for (axis, inverse) in inverse_direction.iter().enumerate() {
+            let origin = component(&ray.origin, axis);
+            let t0 = (component(&self.min, axis) - origin) * inverse;
+            let t1 = (component(&self.max, axis) - origin) * inverse;
+            t_min = t_min.max(t0.min(t1));
+            t_max = t_max.min(t0.max(t1));
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    // hit_between for each of the rays in the packet at once, with each
+    // ray's own t_max
+    fn packet_hits_between(&self, packet: &Packet, t_min: Float, t_max: Lanes) -> [bool; 4] {
+        let (mut t_min, mut t_max) = ([t_min; 4], t_max);
+        for axis in 0..3 {
+            let (origin, inverse) = (packet.origin[axis], packet.inverse_direction[axis]);
+            let t0 = lanes::mul(lanes::sub([component(&self.min, axis); 4], origin), inverse);
+            let t1 = lanes::mul(lanes::sub([component(&self.max, axis); 4], origin), inverse);
+            t_min = lanes::max(t_min, lanes::min(t0, t1));
+            t_max = lanes::min(t_max, lanes::max(t0, t1));
+        }
+        [0, 1, 2, 3].map(|i| t_min[i] <= t_max[i])
+    }
+}
+
+// Four rays to be traced through the tree together, kept a component at a
+// time so that a box can be tested against all of them at once.
+pub struct Packet {
+    origin: [Lanes; 3],
+    inverse_direction: [Lanes; 3],
+}
+
+impl Packet {
+    pub fn new(rays: &[Ray; 4]) -> Packet {
+        let across = |f: fn(&Ray) -> Float| rays.each_ref().map(f);
+        Packet {
+            origin: [
+                across(|r| r.origin.x),
+                across(|r| r.origin.y),
+                across(|r| r.origin.z),
+            ],
+            inverse_direction: [
+                across(|r| 1.0 / r.direction.x),
+                across(|r| 1.0 / r.direction.y),
+                across(|r| 1.0 / r.direction.z),
+            ],
+        }
+    }
+}
+
+fn component(t: &Tuple, axis: usize) -> Float {
+    [t.x, t.y, t.z][axis]
+}
+
+pub struct Bvh {
+    nodes: Vec<Node>,
+    // the indices of the bounded objects, in the order the leaves refer to
+    order: Vec<usize>,
+    // the indices of the objects that go on forever
+    unbounded: Vec<usize>,
+}
+
+struct Node {
+    bounds: Bounds,
+    contents: Contents,
+}
+
+enum Contents {
+    // a run of objects in order
+    Leaf { first: usize, count: usize },
+    // the indices of the two nodes the box is split into
+    Split { left: usize, right: usize },
+}
+
+// an object being sorted into the tree
+struct Item {
+    index: usize,
+    bounds: Bounds,
+    centre: Tuple,
+}
+
+impl Bvh {
+    pub fn new(objects: &[Shape]) -> Bvh {
+        Bvh::around(objects.iter().map(|o| o.bounds()))
+    }
+
+    // a BVH over anything with bounds, e.g. the triangles of a mesh - the
+    // indices it gives out are positions in bounds
+    pub fn around(bounds: impl IntoIterator<Item = Option<Bounds>>) -> Bvh {
+        let mut items = Vec::new();
+        let mut unbounded = Vec::new();
+        for (index, bounds) in bounds.into_iter().enumerate() {
+            match bounds {
+                Some(bounds) => items.push(Item {
+                    index,
+                    bounds,
+                    centre: bounds.centre(),
+                }),
+                None => unbounded.push(index),
+            }
+        }
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            order: Vec::new(),
+            unbounded,
+        };
+        if !items.is_empty() {
+            bvh.build(&mut items);
+        }
+        bvh
+    }
+
+    // adds a node for the items, and the nodes under it, returning its index
+    fn build(&mut self, items: &mut [Item]) -> usize {
+        let bounds = items
+            .iter()
+            .fold(Bounds::empty(), |bounds, item| bounds.union(&item.bounds));
+        let index = self.nodes.len();
+        self.nodes.push(Node {
+            bounds,
+            contents: Contents::Leaf { first: 0, count: 0 },
+        });
+        self.nodes[index].contents = match split(items, &bounds) {
+            Some(middle) => {
+                let (left_items, right_items) = items.split_at_mut(middle);
+                let left = self.build(left_items);
+                let right = self.build(right_items);
+                Contents::Split { left, right }
+            }
+            None => {
+                let first = self.order.len();
+                self.order.extend(items.iter().map(|item| item.index));
+                Contents::Leaf {
+                    first,
+                    count: items.len(),
+                }
+            }
+        };
+        index
+    }
+
+    // Calls visit with the index of each object the ray might hit between
+    // t_min and t_max - every unbounded one, then those in the boxes the ray
+    // goes through - until visit returns true. Returns whether it did. stack
+    // is somewhere to keep track of the boxes still to look in, which can be
+    // reused between rays.
+    pub fn visit<F>(
+        &self,
+        ray: &Ray,
+        range: (Float, Float),
+        stack: &mut Vec<usize>,
+        mut visit: F,
+    ) -> bool
+    where
+        F: FnMut(usize) -> bool,
+    {
+        self.visit_shrinking(ray, range, stack, |i, _| visit(i))
+    }
+
+    // As visit, but visit can bring t_max in, e.g. to the nearest hit found
+    // so far, so that boxes beyond it aren't looked in.
+    pub fn visit_shrinking<F>(
+        &self,
+        ray: &Ray,
+        (t_min, mut t_max): (Float, Float),
+        stack: &mut Vec<usize>,
+        mut visit: F,
+    ) -> bool
+    where
+        F: FnMut(usize, &mut Float) -> bool,
+    {
+        if self.unbounded.iter().any(|&i| visit(i, &mut t_max)) {
+            return true;
+        }
+        if self.nodes.is_empty() {
+            return false;
+        }
+        let d = &ray.direction;
+        let inverse_direction = [1.0 / d.x, 1.0 / d.y, 1.0 / d.z];
+        stack.clear();
+        stack.push(0);
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            if !node
+                .bounds
+                .hit_between(ray, &inverse_direction, t_min, t_max)
+            {
+                continue;
+            }
+            match node.contents {
+                Contents::Leaf { first, count } => {
+                    let objects = &self.order[first..first + count];
+                    if objects.iter().any(|&i| visit(i, &mut t_max)) {
+                        return true;
+                    }
+                }
+                Contents::Split { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+        false
+    }
+
+    // As visit_shrinking, for the four rays of a packet at once. visit is
+    // called with which ray and which object, for the objects in the boxes
+    // that ray goes through, and can bring in that ray's t_max. There's no
+    // stopping early - it's for finding each ray's nearest hit. Going down the
+    // tree is done once for all four, so rays that mostly go through the same
+    // boxes, like camera rays through pixels next to each other, share the
+    // work.
+    pub fn visit_packet<F>(
+        &self,
+        packet: &Packet,
+        t_min: Float,
+        t_max: &mut Lanes,
+        stack: &mut Vec<usize>,
+        mut visit: F,
+    ) where
+        F: FnMut(usize, usize, &mut Float),
+    {
+        for &i in &self.unbounded {
+            for (ray, t_max) in t_max.iter_mut().enumerate() {
+                visit(ray, i, t_max);
+            }
+        }
+        if self.nodes.is_empty() {
+            return;
+        }
+        stack.clear();
+        stack.push(0);
+        while let Some(n) = stack.pop() {
+            let node = &self.nodes[n];
+            let hits = node.bounds.packet_hits_between(packet, t_min, *t_max);
+            if !hits.contains(&true) {
+                continue;
+            }
+            match node.contents {
+                Contents::Leaf { first, count } => {
+                    for &i in &self.order[first..first + count] {
+                        for ray in (0..4).filter(|&ray| hits[ray]) {
+                            visit(ray, i, &mut t_max[ray]);
+                        }
+                    }
+                }
+                Contents::Split { left, right } => {
+                    stack.push(right);
+                    stack.push(left);
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> BvhStats {
+        let mut stats = BvhStats {
+            objects: self.order.len() + self.unbounded.len(),
+            unbounded: self.unbounded.len(),
+            nodes: self.nodes.len(),
+            leaves: 0,
+            depth: 0,
+            largest_leaf: 0,
+        };
+        // (node, how deep it is)
+        let mut stack = if self.nodes.is_empty() {
+            vec![]
+        } else {
+            vec![(0, 1)]
+        };
+        while let Some((n, depth)) = stack.pop() {
+            stats.depth = stats.depth.max(depth);
+            match self.nodes[n].contents {
+                Contents::Leaf { count, .. } => {
+                    stats.leaves += 1;
+                    stats.largest_leaf = stats.largest_leaf.max(count);
+                }
+                Contents::Split { left, right } => {
+                    stack.push((left, depth + 1));
+                    stack.push((right, depth + 1));
+                }
+            }
+        }
+        stats
+    }
+}
+
+// Sorts the items so that those going in the first half of the box come
+// first, and returns how many there are of them - or None if the items are
+// better off left together in a leaf.
+fn split(items: &mut [Item], bounds: &Bounds) -> Option<usize> {
+    if items.len() <= 1 {
+        return None;
+    }
+    let centres = Bounds::around(items.iter().map(|item| item.centre));
+    // the cheapest split, as (cost, axis, bin the second half starts at)
+    let mut best: Option<(Float, usize, usize)> = None;
+    for axis in 0..3 {
+        let (lo, hi) = (component(&centres.min, axis), component(&centres.max, axis));
+        if hi <= lo {
+            continue;
+        }
+        let mut bins = [(Bounds::empty(), 0); BINS];
+        for item in items.iter() {
+            let bin = &mut bins[bin_of(component(&item.centre, axis), lo, hi)];
+            *bin = (bin.0.union(&item.bounds), bin.1 + 1);
+        }
+        for first_right in 1..BINS {
+            let side = |bins: &[(Bounds, usize)]| {
+                bins.iter()
+                    .fold((Bounds::empty(), 0), |(b, n), (bin, count)| {
+                        (b.union(bin), n + count)
+                    })
+            };
+            let (left, left_count) = side(&bins[..first_right]);
+            let (right, right_count) = side(&bins[first_right..]);
+            if left_count == 0 || right_count == 0 {
+                continue;
+            }
+            // how likely a ray through the box is to go through each half,
+            // times how many objects it'd then have to be tested against
+            let cost = BOX_COST
+                + (left.surface_area() * left_count as Float
+                    + right.surface_area() * right_count as Float)
+                    / bounds.surface_area().max(Float::MIN_POSITIVE);
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, first_right));
+            }
+        }
+    }
+    let (cost, axis, first_right) = best?;
+    if cost >= items.len() as Float && items.len() <= MAX_LEAF_SIZE {
+        return None;
+    }
+    let (lo, hi) = (component(&centres.min, axis), component(&centres.max, axis));
+    items.sort_by_key(|item| bin_of(component(&item.centre, axis), lo, hi) >= first_right);
+    Some(
+        items
+            .iter()
+            .position(|item| bin_of(component(&item.centre, axis), lo, hi) >= first_right)
+            .unwrap(),
+    )
+}
+
+fn bin_of(centre: Float, lo: Float, hi: Float) -> usize {
+    (((centre - lo) / (hi - lo) * BINS as Float) as usize).min(BINS - 1)
+}
+
+// what --bvh-stats prints about the tree
+#[derive(Debug, PartialEq)]
+pub struct BvhStats {
+    pub objects: usize,
+    pub unbounded: usize,
+    pub nodes: usize,
+    pub leaves: usize,
+    // the most boxes inside one another, counting the one around everything
+    pub depth: usize,
+    // the most objects in one box
+    pub largest_leaf: usize,
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bounded = self.objects - self.unbounded;
+        writeln!(
+            f,
+            "BVH: {} objects, {} of which go on forever and are tested against every ray",
+            self.objects, self.unbounded
+        )?;
+        write!(
+            f,
+            "{} nodes, {} levels deep, with {} leaves of {:.1} objects on average and at most {}",
+            self.nodes,
+            self.depth,
+            self.leaves,
+            bounded as Float / self.leaves.max(1) as Float,
+            self.largest_leaf
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{plane, sphere};
+
+    // a row of n small spheres along x, with a floor
+    fn spheres(n: usize) -> Vec<Shape> {
+        let mut objects: Vec<Shape> = (0..n)
+            .map(|i| {
+                let mut s = sphere::default();
+                s.set_transform(Matrix::scaling(0.4, 0.4, 0.4).translate(i as Float, 0.0, 0.0));
+                s
+            })
+            .collect();
+        objects.push(plane::default());
+        objects
+    }
+
+    fn visited(bvh: &Bvh, ray: &Ray) -> Vec<usize> {
+        let mut found = Vec::new();
+        bvh.visit(
+            ray,
+            (Float::NEG_INFINITY, Float::INFINITY),
+            &mut Vec::new(),
+            |i| {
+                found.push(i);
+                false
+            },
+        );
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn bounds_are_transformed_with_the_shape() {
+        let b = Bounds::new(
+            Tuple::point_new(-1.0, -1.0, -1.0),
+            Tuple::point_new(1.0, 1.0, 1.0),
+        );
+        let moved = b.transformed(&Matrix::scaling(2.0, 1.0, 1.0).translate(5.0, 0.0, 0.0));
+        assert_eq!(moved.min, Tuple::point_new(3.0, -1.0, -1.0));
+        assert_eq!(moved.max, Tuple::point_new(7.0, 1.0, 1.0));
+        assert_eq!(moved.centre(), Tuple::point_new(5.0, 0.0, 0.0));
+        assert_eq!(b.surface_area(), 24.0);
+        assert_eq!(Bounds::empty().surface_area(), 0.0);
+    }
+
+    #[test]
+    fn rays_only_visit_the_objects_they_might_hit() {
+        let objects = spheres(100);
+        let bvh = Bvh::new(&objects);
+        let down = Ray::new(
+            Tuple::point_new(42.0, 5.0, 0.0),
+            Tuple::vector_new(0.0, -1.0, 0.0),
+        );
+        // the plane's always visited, and only a few of the spheres
+        let found = visited(&bvh, &down);
+        assert!(found.contains(&42));
+        assert!(found.contains(&100));
+        assert!(found.len() <= 1 + MAX_LEAF_SIZE);
+        let along = Ray::new(
+            Tuple::point_new(-5.0, 0.0, 0.0),
+            Tuple::vector_new(1.0, 0.0, 0.0),
+        );
+        assert_eq!(visited(&bvh, &along), (0..=100).collect::<Vec<_>>());
+        let past = Ray::new(
+            Tuple::point_new(-5.0, 5.0, 0.0),
+            Tuple::vector_new(1.0, 0.0, 0.0),
+        );
+        assert_eq!(visited(&bvh, &past), vec![100]);
+    }
+
+    #[test]
+    fn visiting_stops_when_asked() {
+        let bvh = Bvh::new(&spheres(10));
+        let along = Ray::new(
+            Tuple::point_new(-5.0, 0.0, 0.0),
+            Tuple::vector_new(1.0, 0.0, 0.0),
+        );
+        let mut count = 0;
+        let range = (0.0, Float::INFINITY);
+        assert!(bvh.visit(&along, range, &mut Vec::new(), |_| {
+            count += 1;
+            count == 3
+        }));
+        assert_eq!(count, 3);
+        // boxes entirely before t_min or after t_max are skipped
+        let mut found = Vec::new();
+        bvh.visit(&along, (9.0, 11.0), &mut Vec::new(), |i| {
+            found.push(i);
+            false
+        });
+        assert!(found.contains(&5) && !found.contains(&0));
+    }
+
+    #[test]
+    fn splits_leave_small_leaves() {
+        let stats = Bvh::new(&spheres(100)).stats();
+        assert_eq!(stats.objects, 101);
+        assert_eq!(stats.unbounded, 1);
+        assert_eq!(stats.nodes, 2 * stats.leaves - 1);
+        assert!(stats.largest_leaf <= MAX_LEAF_SIZE);
+        assert!(stats.depth < 20);
+        assert_eq!(Bvh::new(&[]).stats().nodes, 0);
+    }
+
+    #[test]
+    fn reports_the_stats() {
+        let stats = BvhStats {
+            objects: 12,
+            unbounded: 2,
+            nodes: 7,
+            leaves: 4,
+            depth: 3,
+            largest_leaf: 4,
+        };
+        assert_eq!(
+            stats.to_string(),
+            "BVH: 12 objects, 2 of which go on forever and are tested against every ray\n\
+             7 nodes, 3 levels deep, with 4 leaves of 2.5 objects on average and at most 4"
+        );
+    }
+}
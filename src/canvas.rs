@@ -1,24 +1,43 @@
-use serde::Serialize;
+use crate::lanes::{self, Lanes};
+use crate::precision::{Float, EPSILON_SCALE};
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "files")]
 use std::fs::File;
-use std::io::Write;
+#[cfg(feature = "files")]
+use std::io::{self, BufWriter, Write};
 use std::ops::{Add, Mul, Sub};
+use std::path::Path;
 
-#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
 pub struct Colour {
-    red: f64,
-    green: f64,
-    blue: f64,
+    red: Float,
+    green: Float,
+    blue: Float,
 }
 
 impl Colour {
-    pub fn new(red: f64, green: f64, blue: f64) -> Colour {
+    pub fn new(red: Float, green: Float, blue: Float) -> Colour {
         Colour { red, green, blue }
     }
 
-    fn component_transform(comp: f64) -> i32 {
+    pub fn components(&self) -> [Float; 3] {
+        [self.red, self.green, self.blue]
+    }
+
+    // colours are worked on four lanes at a time like tuples, with one left
+    // empty
+    fn lanes(&self) -> Lanes {
+        [self.red, self.green, self.blue, 0.0]
+    }
+
+    fn from_lanes([red, green, blue, _]: Lanes) -> Colour {
+        Colour::new(red, green, blue)
+    }
+
+    fn component_transform(comp: Float) -> i32 {
         const MAX_VAL: i32 = 255;
         const MIN_VAL: i32 = 0;
-        let comp = (comp * MAX_VAL as f64) as i32;
+        let comp = (comp * MAX_VAL as Float) as i32;
         match comp {
             comp if comp > MAX_VAL => MAX_VAL,
             comp if comp < MIN_VAL => MIN_VAL,
@@ -33,23 +52,81 @@ impl Colour {
     pub fn white() -> Colour {
         Colour::new(1.0, 1.0, 1.0)
     }
+
+    // reads a colour written as in CSS, either "#rrggbb" or the short "#rgb"
+    pub fn from_hex(hex: &str) -> Option<Colour> {
+        let digits = hex.strip_prefix('#')?;
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let channel = |s: &str| u8::from_str_radix(s, 16).ok().map(|x| x as Float / 255.0);
+        match digits.len() {
+            6 => Some(Colour::new(
+                channel(&digits[0..2])?,
+                channel(&digits[2..4])?,
+                channel(&digits[4..6])?,
+            )),
+            // each digit is doubled, so #f80 is #ff8800
+            3 => Some(Colour::new(
+                channel(&digits[0..1].repeat(2))?,
+                channel(&digits[1..2].repeat(2))?,
+                channel(&digits[2..3].repeat(2))?,
+            )),
+            _ => None,
+        }
+    }
+
+    // a handful of common colours, by name
+    pub fn named(name: &str) -> Option<Colour> {
+        let (r, g, b) = match name {
+            "black" => (0.0, 0.0, 0.0),
+            "white" => (1.0, 1.0, 1.0),
+            "grey" | "gray" => (0.5, 0.5, 0.5),
+            "red" => (1.0, 0.0, 0.0),
+            "green" => (0.0, 1.0, 0.0),
+            "blue" => (0.0, 0.0, 1.0),
+            "yellow" => (1.0, 1.0, 0.0),
+            "cyan" => (0.0, 1.0, 1.0),
+            "magenta" => (1.0, 0.0, 1.0),
+            "orange" => (1.0, 0.5, 0.0),
+            "purple" => (0.5, 0.0, 0.5),
+            "pink" => (1.0, 0.75, 0.8),
+            "brown" => (0.6, 0.3, 0.1),
+            _ => return None,
+        };
+        Some(Colour::new(r, g, b))
+    }
+
+    // applies f to each of the components
+    fn map(&self, f: impl Fn(Float) -> Float) -> Colour {
+        Colour::new(f(self.red), f(self.green), f(self.blue))
+    }
+
+    // the largest difference between any of the two colours' components
+    pub fn max_difference(&self, other: &Colour) -> Float {
+        (self.red - other.red)
+            .abs()
+            .max((self.green - other.green).abs())
+            .max((self.blue - other.blue).abs())
+    }
 }
 
-impl ToString for Colour {
-    fn to_string(&self) -> String {
-        format![
-            "{} {} {}\n",
+impl std::fmt::Display for Colour {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} {} {}",
             Colour::component_transform(self.red),
             Colour::component_transform(self.green),
             Colour::component_transform(self.blue)
-        ]
+        )
     }
 }
 
 impl PartialEq for Colour {
     fn eq(&self, other: &Self) -> bool {
-        const EPSILON: f64 = 0.00001;
-        let close = |a: f64, b: f64| (a - b).abs() < EPSILON;
+        const EPSILON: Float = 0.00001 * EPSILON_SCALE;
+        let close = |a: Float, b: Float| (a - b).abs() < EPSILON;
         close(self.blue, other.blue) && close(self.green, other.green) && close(self.red, other.red)
     }
 }
@@ -57,42 +134,198 @@ impl PartialEq for Colour {
 impl Add for Colour {
     type Output = Self;
     fn add(self, other: Self) -> Self {
-        Colour::new(
-            other.red + self.red,
-            other.green + self.green,
-            other.blue + self.blue,
-        )
+        Colour::from_lanes(lanes::add(self.lanes(), other.lanes()))
     }
 }
 
 impl Sub for Colour {
     type Output = Self;
     fn sub(self, other: Self) -> Self {
-        Colour::new(
-            self.red - other.red,
-            self.green - other.green,
-            self.blue - other.blue,
-        )
+        Colour::from_lanes(lanes::sub(self.lanes(), other.lanes()))
     }
 }
 
-impl Mul<f64> for Colour {
+impl Mul<Float> for Colour {
     type Output = Self;
-    fn mul(self, other: f64) -> Self {
-        Colour::new(other * self.red, other * self.green, other * self.blue)
+    fn mul(self, other: Float) -> Self {
+        Colour::from_lanes(lanes::scale(self.lanes(), other))
     }
 }
 
 impl Mul for Colour {
     type Output = Self;
     fn mul(self, other: Self) -> Self {
-        Colour::new(
-            self.red * other.red,
-            self.green * other.green,
-            self.blue * other.blue,
-        )
+        Colour::from_lanes(lanes::mul(self.lanes(), other.lanes()))
+    }
+}
+// How colours brighter than white are squeezed into the range that can be
+// displayed.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum ToneMap {
+    // anything above 1 is cut off, which flattens bright highlights
+    #[default]
+    Clamp,
+    // c / (1 + c) - never quite reaches white, so highlights keep their detail
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve, with more contrast than Reinhard
+    Aces,
+}
+
+// Turns the light arriving at the camera into the colours written out.
+// The defaults leave the image unchanged.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct PostProcess {
+    // every colour is multiplied by this first
+    pub exposure: Float,
+    pub tone_map: ToneMap,
+    // the colours are raised to the power 1 / gamma last - 2.2 suits most
+    // screens
+    pub gamma: Float,
+}
+
+impl Default for PostProcess {
+    fn default() -> PostProcess {
+        PostProcess {
+            exposure: 1.0,
+            tone_map: ToneMap::Clamp,
+            gamma: 1.0,
+        }
+    }
+}
+
+impl PostProcess {
+    pub fn apply(&self, colour: Colour) -> Colour {
+        let tone_mapped = (colour * self.exposure).map(|c| {
+            let c = c.max(0.0);
+            match self.tone_map {
+                ToneMap::Clamp => c.min(1.0),
+                ToneMap::Reinhard => c / (1.0 + c),
+                ToneMap::Aces => {
+                    ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+                }
+            }
+        });
+        tone_mapped.map(|c| c.powf(1.0 / self.gamma))
+    }
+}
+
+// Makes the brightest parts of the image glow, by blurring whatever is
+// brighter than the threshold and adding it back on top.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Bloom {
+    // how far each colour component has to go before it glows - above 1, only
+    // things brighter than white do
+    pub threshold: Float,
+    // the size of the blur, in pixels
+    pub radius: Float,
+    // how much of the glow is added back on
+    pub strength: Float,
+}
+
+impl Bloom {
+    pub fn apply(&self, image: &Canvas) -> Canvas {
+        let bright = Canvas {
+            width: image.width,
+            height: image.height,
+            pixels: image
+                .pixels
+                .iter()
+                .map(|p| p.map(|c| (c - self.threshold).max(0.0)))
+                .collect(),
+        };
+        image.blend(&bright.blur(self.radius), BlendMode::Add, self.strength)
+    }
+}
+
+// How the colours of one canvas are combined with those of another, which
+// is laid on top of it.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum BlendMode {
+    // the top canvas replaces the bottom one
+    Normal,
+    // light from both is added together - good for glows
+    Add,
+    // only ever darkens, like stacking filters
+    Multiply,
+    // only ever lightens, like projecting both onto the same screen
+    Screen,
+}
+
+impl BlendMode {
+    fn blend(&self, bottom: Colour, top: Colour) -> Colour {
+        match self {
+            BlendMode::Normal => top,
+            BlendMode::Add => bottom + top,
+            BlendMode::Multiply => bottom * top,
+            BlendMode::Screen => {
+                Colour::white() - (Colour::white() - bottom) * (Colour::white() - top)
+            }
+        }
     }
 }
+
+// A grid of weights for convolving a canvas with. Each pixel becomes the
+// weighted sum of those around it, with the centre of the kernel over it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Kernel {
+    width: usize,
+    height: usize,
+    weights: Vec<Float>,
+}
+
+impl Kernel {
+    // The weights are row by row, and both sides must be odd so that there's
+    // a centre. Panics if a side is even or there aren't width * height
+    // weights, like Ray::new does when it's given its arguments the wrong way
+    // round.
+    pub fn new(width: usize, height: usize, weights: Vec<Float>) -> Kernel {
+        if width.is_multiple_of(2) || height.is_multiple_of(2) || weights.len() != width * height {
+            panic!("Invalid kernel size!");
+        }
+        Kernel {
+            width,
+            height,
+            weights,
+        }
+    }
+
+    // averages a square of side 2 * radius + 1
+    pub fn box_blur(radius: usize) -> Kernel {
+        let side = 2 * radius + 1;
+        Kernel::new(side, side, vec![1.0 / (side * side) as Float; side * side])
+    }
+
+    // A single row of gaussian weights, out to three standard deviations.
+    // Blurring with this and then its transpose is much quicker than with a
+    // square kernel. A sigma of 0 or less doesn't blur at all.
+    pub fn gaussian(sigma: Float) -> Kernel {
+        if sigma <= 0.0 {
+            return Kernel::new(1, 1, vec![1.0]);
+        }
+        let radius = (3.0 * sigma).ceil().max(0.0) as i64;
+        let weights: Vec<Float> = (-radius..=radius)
+            .map(|i| (-((i * i) as Float) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let total: Float = weights.iter().sum();
+        let side = weights.len();
+        Kernel::new(side, 1, weights.iter().map(|w| w / total).collect())
+    }
+
+    pub fn sharpen() -> Kernel {
+        Kernel::new(3, 3, vec![0.0, -1.0, 0.0, -1.0, 5.0, -1.0, 0.0, -1.0, 0.0])
+    }
+
+    pub fn transpose(&self) -> Kernel {
+        let weights = (0..self.width)
+            .flat_map(|x| (0..self.height).map(move |y| (x, y)))
+            .map(|(x, y)| self.weights[y * self.width + x])
+            .collect();
+        Kernel::new(self.height, self.width, weights)
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "SavedCanvas")]
 pub struct Canvas {
     width: usize,
     height: usize,
@@ -100,6 +333,34 @@ pub struct Canvas {
     pixels: Vec<Colour>,
 }
 
+#[derive(Deserialize)]
+struct SavedCanvas {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colour>,
+}
+
+impl std::convert::TryFrom<SavedCanvas> for Canvas {
+    type Error = String;
+
+    fn try_from(saved: SavedCanvas) -> Result<Canvas, String> {
+        if saved.pixels.len() != saved.width * saved.height {
+            return Err(format!(
+                "a {}x{} canvas needs {} pixels, not {}",
+                saved.width,
+                saved.height,
+                saved.width * saved.height,
+                saved.pixels.len()
+            ));
+        }
+        Ok(Canvas {
+            width: saved.width,
+            height: saved.height,
+            pixels: saved.pixels,
+        })
+    }
+}
+
 impl Canvas {
     pub fn new(width: usize, height: usize) -> Canvas {
         Canvas {
@@ -109,6 +370,86 @@ impl Canvas {
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn post_process(&mut self, settings: &PostProcess) {
+        for pixel in &mut self.pixels {
+            *pixel = settings.apply(*pixel);
+        }
+    }
+
+    // Lays another canvas of the same size on top of this one, panicking if
+    // the sizes differ. An opacity of 0 leaves this canvas as it is, and 1
+    // uses the fully blended colours.
+    pub fn blend(&self, top: &Canvas, mode: BlendMode, opacity: Float) -> Canvas {
+        if (self.width, self.height) != (top.width, top.height) {
+            panic!("Can't blend canvases of different sizes!");
+        }
+        let pixels = self
+            .pixels
+            .iter()
+            .zip(&top.pixels)
+            .map(|(&b, &t)| b * (1.0 - opacity) + mode.blend(b, t) * opacity)
+            .collect();
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    // Pixels beyond the edges are taken to be copies of the nearest edge pixel.
+    pub fn convolve(&self, kernel: &Kernel) -> Canvas {
+        let (half_w, half_h) = ((kernel.width / 2) as i64, (kernel.height / 2) as i64);
+        let mut out = Canvas::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let mut sum = Colour::black();
+                for ky in 0..kernel.height {
+                    let sy = (y as i64 + ky as i64 - half_h).clamp(0, self.height as i64 - 1);
+                    for kx in 0..kernel.width {
+                        let sx = (x as i64 + kx as i64 - half_w).clamp(0, self.width as i64 - 1);
+                        let weight = kernel.weights[ky * kernel.width + kx];
+                        sum = sum + *self.pixel_at(sx as usize, sy as usize) * weight;
+                    }
+                }
+                out.write_pixel((x, y), sum);
+            }
+        }
+        out
+    }
+
+    // a gaussian blur, done as a horizontal pass then a vertical one
+    pub fn blur(&self, sigma: Float) -> Canvas {
+        let kernel = Kernel::gaussian(sigma);
+        self.convolve(&kernel).convolve(&kernel.transpose())
+    }
+
+    // Brightness is added to every component. Contrast scales how far each
+    // component is from mid grey, so 1 leaves the image as it is.
+    pub fn adjust(&self, brightness: Float, contrast: Float) -> Canvas {
+        let pixels = self
+            .pixels
+            .iter()
+            .map(|p| p.map(|c| (c - 0.5) * contrast + 0.5 + brightness))
+            .collect();
+        Canvas {
+            width: self.width,
+            height: self.height,
+            pixels,
+        }
+    }
+
+    pub fn row(&self, y: usize) -> &[Colour] {
+        &self.pixels[y * self.width..(y + 1) * self.width]
+    }
+
     pub fn pixel_at(&self, x: usize, y: usize) -> &Colour {
         &self.pixels[y * self.width + x]
     }
@@ -116,19 +457,453 @@ impl Canvas {
     pub fn write_pixel(&mut self, (x, y): (usize, usize), colour: Colour) {
         self.pixels[y * self.width + x] = colour;
     }
-    // Change this to output a result, test it returns correctly
-    pub fn write_out_as_ppm_file(&self) {
-        let mut outfile = File::create("output.ppm").unwrap();
-        outfile.write_all(self.ppm_header().as_bytes()).unwrap();
-        outfile.write_all(self.ppm_pixel_data().as_bytes()).unwrap();
+
+    #[cfg(feature = "files")]
+    // Reads an image file (PNG, JPEG, PPM or Radiance HDR) into a canvas,
+    // scaling each colour component to between 0 and 1. HDR images keep their
+    // full range, so can go above 1. The format is worked out from the extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> image::ImageResult<Canvas> {
+        let img = image::open(path)?.into_rgb32f();
+        let (width, height) = img.dimensions();
+        let mut out = Canvas::new(width as usize, height as usize);
+        for (x, y, pixel) in img.enumerate_pixels() {
+            let [r, g, b] = pixel.0;
+            let colour = Colour::new(r as Float, g as Float, b as Float);
+            out.write_pixel((x as usize, y as usize), colour);
+        }
+        Ok(out)
+    }
+
+    // Samples the canvas as a texture, picking the nearest pixel.
+    // (0, 0) is the bottom left of the image and (1, 1) is the top right.
+    // An empty canvas has nothing to pick, so it's black all over.
+    pub fn uv_at(&self, u: Float, v: Float) -> Colour {
+        if self.width == 0 || self.height == 0 {
+            return Colour::black();
+        }
+        let x = (u * (self.width - 1) as Float).round() as usize;
+        let y = ((1.0 - v) * (self.height - 1) as Float).round() as usize;
+        *self.pixel_at(x.min(self.width - 1), y.min(self.height - 1))
+    }
+
+    // Like uv_at, but blends between the four nearest pixels.
+    pub fn bilinear_uv_at(&self, u: Float, v: Float) -> Colour {
+        if self.width == 0 || self.height == 0 {
+            return Colour::black();
+        }
+        let x = (u * (self.width - 1) as Float).clamp(0.0, (self.width - 1) as Float);
+        let y = ((1.0 - v) * (self.height - 1) as Float).clamp(0.0, (self.height - 1) as Float);
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(self.width - 1), (y0 + 1).min(self.height - 1));
+        let (fx, fy) = (x - x0 as Float, y - y0 as Float);
+        let top = *self.pixel_at(x0, y0) * (1.0 - fx) + *self.pixel_at(x1, y0) * fx;
+        let bottom = *self.pixel_at(x0, y1) * (1.0 - fx) + *self.pixel_at(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    #[cfg(feature = "files")]
+    // Writes the canvas out to a file, in a format worked out from the
+    // extension: PPM, PNG or JPEG. An unknown extension is an InvalidInput error.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save_dithered(path, Dither::None)
+    }
+
+    #[cfg(feature = "files")]
+    pub fn save_dithered<P: AsRef<Path>>(&self, path: P, dither: Dither) -> io::Result<()> {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("can't save {}, as its format isn't known", path.display()),
+            )
+        })?;
+        self.write_to(BufWriter::new(File::create(path)?), format, dither)
+    }
+
+    #[cfg(feature = "files")]
+    // Writes the canvas to anything, e.g. stdout, in the format given.
+    pub fn write_to<W: Write>(
+        &self,
+        mut out: W,
+        format: ImageFormat,
+        dither: Dither,
+    ) -> io::Result<()> {
+        use image::ImageEncoder;
+        let rows = self.quantise(dither);
+        let (width, height) = (self.width as u32, self.height as u32);
+        let encoded = match format {
+            ImageFormat::Ppm => {
+                out.write_all(self.ppm_header().as_bytes())?;
+                for row in rows {
+                    out.write_all(ppm_row(&row).as_bytes())?;
+                }
+                Ok(())
+            }
+            ImageFormat::BinaryPpm => {
+                write!(out, "P6\n{} {}\n255\n", width, height)?;
+                out.write_all(&rows.concat())
+            }
+            ImageFormat::Png => image::codecs::png::PngEncoder::new(&mut out)
+                .write_image(&rows.concat(), width, height, image::ColorType::Rgb8)
+                .map_err(image_error),
+            ImageFormat::Jpeg => image::codecs::jpeg::JpegEncoder::new(&mut out)
+                .write_image(&rows.concat(), width, height, image::ColorType::Rgb8)
+                .map_err(image_error),
+        };
+        encoded?;
+        out.flush()
+    }
+
+    // The canvas as bytes, four to a pixel - red, green, blue, and an alpha
+    // that's always 255 - top row first. It's what a browser's ImageData
+    // wants.
+    pub fn to_rgba(&self, dither: Dither) -> Vec<u8> {
+        self.quantise(dither)
+            .iter()
+            .flat_map(|row| row.chunks(3))
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+            .collect()
     }
 
+    // each row's components as bytes
+    fn quantise(&self, dither: Dither) -> Vec<Vec<u8>> {
+        let mut quantiser = Quantiser::new(dither);
+        self.pixels
+            .chunks(self.width.max(1))
+            .map(|row| quantiser.row(row))
+            .collect()
+    }
+
+    #[cfg(any(feature = "files", test))]
     fn ppm_header(&self) -> String {
         format!["P3\n{} {}\n255\n", self.width, self.height]
     }
 
+    // Each row of the image starts on a new line, and lines are wrapped so
+    // they're never longer than the 70 characters the PPM spec allows.
+    #[cfg(test)]
     fn ppm_pixel_data(&self) -> String {
-        self.pixels.iter().map(|pixel| pixel.to_string()).collect()
+        self.quantise(Dither::None)
+            .iter()
+            .map(|row| ppm_row(row))
+            .collect()
+    }
+}
+
+#[cfg(feature = "files")]
+fn image_error(e: image::ImageError) -> io::Error {
+    match e {
+        image::ImageError::IoError(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+// What an image can be written out as. Files get theirs from the extension,
+// but anything else, like stdout, has to be told.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ImageFormat {
+    // the plain text PPM from the book
+    Ppm,
+    // PPM with the components as bytes, rather than written out in text -
+    // a lot smaller, and what tools reading a stream of images expect
+    BinaryPpm,
+    Png,
+    Jpeg,
+}
+
+impl ImageFormat {
+    pub fn from_path(path: &Path) -> Option<ImageFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::Ppm),
+            "png" => Some(ImageFormat::Png),
+            "jpg" | "jpeg" => Some(ImageFormat::Jpeg),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(any(feature = "files", test))]
+fn ppm_row(row: &[u8]) -> String {
+    const MAX_LINE_LENGTH: usize = 70;
+    let mut out = String::new();
+    let mut line = String::new();
+    for component in row {
+        let value = component.to_string();
+        if !line.is_empty() && line.len() + 1 + value.len() > MAX_LINE_LENGTH {
+            out.push_str(&line);
+            out.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&value);
+    }
+    out.push_str(&line);
+    out.push('\n');
+    out
+}
+
+// How colours are rounded to the 256 levels per component that image files
+// hold. Without dithering, smooth gradients can show visible bands.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Dither {
+    #[default]
+    None,
+    // adds a repeating 4x4 pattern of small offsets before rounding
+    Ordered,
+    // Floyd-Steinberg - each pixel's rounding error is passed on to the
+    // neighbours that haven't been rounded yet
+    ErrorDiffusion,
+}
+
+const BAYER: [[Float; 4]; 4] = [
+    [0.0, 8.0, 2.0, 10.0],
+    [12.0, 4.0, 14.0, 6.0],
+    [3.0, 11.0, 1.0, 9.0],
+    [15.0, 7.0, 13.0, 5.0],
+];
+
+// Turns rows of colours into bytes, top to bottom, remembering what the
+// dithering needs to carry from one row to the next.
+struct Quantiser {
+    dither: Dither,
+    y: usize,
+    // rounding errors passed down to each component of the next row
+    errors_below: Vec<Float>,
+}
+
+impl Quantiser {
+    fn new(dither: Dither) -> Quantiser {
+        Quantiser {
+            dither,
+            y: 0,
+            errors_below: Vec::new(),
+        }
+    }
+
+    fn row(&mut self, row: &[Colour]) -> Vec<u8> {
+        // anything that can't be displayed is clamped first, so it isn't
+        // spread onto its neighbours
+        let values: Vec<Float> = row
+            .iter()
+            .flat_map(|c| c.components())
+            .map(|c| {
+                if c.is_nan() {
+                    0.0
+                } else {
+                    (c * 255.0).clamp(0.0, 255.0)
+                }
+            })
+            .collect();
+        let out = match self.dither {
+            Dither::None => values.iter().map(|&v| v as u8).collect(),
+            Dither::Ordered => values
+                .iter()
+                .enumerate()
+                .map(|(i, &v)| {
+                    let offset = (BAYER[self.y % 4][(i / 3) % 4] + 0.5) / 16.0;
+                    (v + offset).min(255.0) as u8
+                })
+                .collect(),
+            Dither::ErrorDiffusion => {
+                let n = values.len();
+                let mut errors = std::mem::take(&mut self.errors_below);
+                errors.resize(n, 0.0);
+                let mut below = vec![0.0; n];
+                let mut out = Vec::with_capacity(n);
+                // the same component of the neighbouring pixels is 3 away
+                for i in 0..n {
+                    let wanted = values[i] + errors[i];
+                    let rounded = wanted.round().clamp(0.0, 255.0);
+                    let error = wanted - rounded;
+                    if i + 3 < n {
+                        errors[i + 3] += error * 7.0 / 16.0;
+                        below[i + 3] += error / 16.0;
+                    }
+                    if i >= 3 {
+                        below[i - 3] += error * 3.0 / 16.0;
+                    }
+                    below[i] += error * 5.0 / 16.0;
+                    out.push(rounded as u8);
+                }
+                self.errors_below = below;
+                out
+            }
+        };
+        self.y += 1;
+        out
+    }
+}
+
+#[cfg(feature = "files")]
+// Writes an image out a row at a time, top to bottom, so the whole image
+// never has to be held in memory. Anything but JPEG can be written like this.
+pub struct RowWriter {
+    out: RowOutput,
+    quantiser: Quantiser,
+}
+
+#[cfg(feature = "files")]
+enum RowOutput {
+    Ppm(Box<dyn Write>),
+    BinaryPpm(Box<dyn Write>),
+    Png(Box<png::StreamWriter<'static, Box<dyn Write>>>),
+}
+
+#[cfg(feature = "files")]
+impl RowWriter {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        width: usize,
+        height: usize,
+        dither: Dither,
+    ) -> io::Result<RowWriter> {
+        let path = path.as_ref();
+        match ImageFormat::from_path(path) {
+            Some(format) if format != ImageFormat::Jpeg => {
+                let out = BufWriter::new(File::create(path)?);
+                RowWriter::new(Box::new(out), format, width, height, dither)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("can't write {} a row at a time", path.display()),
+            )),
+        }
+    }
+
+    pub fn new(
+        mut out: Box<dyn Write>,
+        format: ImageFormat,
+        width: usize,
+        height: usize,
+        dither: Dither,
+    ) -> io::Result<RowWriter> {
+        let out = match format {
+            ImageFormat::Ppm => {
+                write!(out, "P3\n{} {}\n255\n", width, height)?;
+                RowOutput::Ppm(out)
+            }
+            ImageFormat::BinaryPpm => {
+                write!(out, "P6\n{} {}\n255\n", width, height)?;
+                RowOutput::BinaryPpm(out)
+            }
+            ImageFormat::Png => {
+                let mut encoder = png::Encoder::new(out, width as u32, height as u32);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                let writer = encoder.write_header()?;
+                RowOutput::Png(Box::new(writer.into_stream_writer()?))
+            }
+            ImageFormat::Jpeg => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "JPEG images can't be written a row at a time",
+                ))
+            }
+        };
+        Ok(RowWriter {
+            out,
+            quantiser: Quantiser::new(dither),
+        })
+    }
+
+    pub fn write_row(&mut self, row: &[Colour]) -> io::Result<()> {
+        let bytes = self.quantiser.row(row);
+        match &mut self.out {
+            RowOutput::Ppm(out) => out.write_all(ppm_row(&bytes).as_bytes()),
+            RowOutput::BinaryPpm(out) => out.write_all(&bytes),
+            RowOutput::Png(out) => out.write_all(&bytes),
+        }
+    }
+
+    // must be called once every row has been written
+    pub fn finish(self) -> io::Result<()> {
+        match self.out {
+            RowOutput::Ppm(mut out) | RowOutput::BinaryPpm(mut out) => out.flush(),
+            RowOutput::Png(out) => Ok(out.finish()?),
+        }
+    }
+}
+
+// An image along with copies of it halved in size again and again, down to a
+// single pixel. A texture seen from far away is read from a smaller copy,
+// which has already been averaged down, rather than picking out single pixels.
+#[derive(Debug, PartialEq)]
+pub struct MipMap {
+    levels: Vec<Canvas>,
+}
+
+// only the full size image is saved, as the smaller ones can be made again
+impl Serialize for MipMap {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.levels[0].serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MipMap {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<MipMap, D::Error> {
+        let image = Canvas::deserialize(deserializer)?;
+        if image.width == 0 || image.height == 0 {
+            return Err(serde::de::Error::custom("textures can't be empty"));
+        }
+        Ok(MipMap::new(image))
+    }
+}
+
+impl MipMap {
+    pub fn new(image: Canvas) -> MipMap {
+        let mut levels = vec![image];
+        loop {
+            let last = levels.last().unwrap();
+            if last.width <= 1 && last.height <= 1 {
+                break;
+            }
+            let mut next = Canvas::new(last.width.div_ceil(2), last.height.div_ceil(2));
+            for y in 0..next.height {
+                for x in 0..next.width {
+                    let mut sum = Colour::black();
+                    for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                        let px = (2 * x + dx).min(last.width - 1);
+                        let py = (2 * y + dy).min(last.height - 1);
+                        sum = sum + *last.pixel_at(px, py);
+                    }
+                    next.write_pixel((x, y), sum * 0.25);
+                }
+            }
+            levels.push(next);
+        }
+        MipMap { levels }
+    }
+
+    // level 0 is the full size image
+    pub fn level(&self, i: usize) -> &Canvas {
+        &self.levels[i]
+    }
+
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn uv_at(&self, u: Float, v: Float) -> Colour {
+        self.levels[0].uv_at(u, v)
+    }
+
+    // Samples the image when one pixel of the render covers about `texels`
+    // pixels of the full size image, blending between the two closest levels.
+    pub fn filtered_uv_at(&self, u: Float, v: Float, texels: Float) -> Colour {
+        let top = (self.levels.len() - 1) as Float;
+        let level = if texels.is_finite() {
+            texels.max(1.0).log2().min(top)
+        } else {
+            top
+        };
+        let lower = level.floor() as usize;
+        let upper = (lower + 1).min(self.levels.len() - 1);
+        let blend = level - lower as Float;
+        self.levels[lower].bilinear_uv_at(u, v) * (1.0 - blend)
+            + self.levels[upper].bilinear_uv_at(u, v) * blend
     }
 }
 
@@ -143,6 +918,26 @@ mod tests {
         assert_eq!(c1 + c2, Colour::new(1.6, 0.7, 1.0));
     }
 
+    #[test]
+    fn colours_from_hex_and_names() {
+        assert_eq!(
+            Colour::from_hex("#ff8000"),
+            Some(Colour::new(1.0, 128.0 / 255.0, 0.0))
+        );
+        assert_eq!(Colour::from_hex("#FFF"), Some(Colour::white()));
+        assert_eq!(
+            Colour::from_hex("#f80"),
+            Some(Colour::new(1.0, 136.0 / 255.0, 0.0))
+        );
+        assert_eq!(Colour::from_hex("ff8000"), None);
+        assert_eq!(Colour::from_hex("#ff80"), None);
+        assert_eq!(Colour::from_hex("#gg0000"), None);
+        assert_eq!(Colour::from_hex("#+f+f+f"), None);
+        assert_eq!(Colour::named("red"), Some(Colour::new(1.0, 0.0, 0.0)));
+        assert_eq!(Colour::named("gray"), Colour::named("grey"));
+        assert_eq!(Colour::named("octarine"), None);
+    }
+
     #[test]
     fn subtract_colours() {
         let c1 = Colour::new(0.9, 0.6, 0.75);
@@ -163,6 +958,66 @@ mod tests {
         assert_eq!(c1 * 2.0, Colour::new(1.8, 1.2, 1.5));
     }
 
+    #[test]
+    fn difference_between_colours() {
+        let c1 = Colour::new(0.9, 0.6, 0.75);
+        let c2 = Colour::new(0.7, 0.1, 0.95);
+        assert_eq!(c1.max_difference(&c2), 0.5);
+        assert_eq!(c2.max_difference(&c1), 0.5);
+    }
+
+    #[test]
+    fn default_post_processing_only_clamps() {
+        let settings = PostProcess::default();
+        let c = Colour::new(0.25, 0.5, 0.75);
+        assert_eq!(settings.apply(c), c);
+        assert_eq!(
+            settings.apply(Colour::new(2.0, -1.0, 0.5)),
+            Colour::new(1.0, 0.0, 0.5)
+        );
+    }
+
+    #[test]
+    fn exposure_tone_mapping_and_gamma() {
+        let settings = PostProcess {
+            exposure: 2.0,
+            tone_map: ToneMap::Reinhard,
+            gamma: 1.0,
+        };
+        assert_eq!(
+            settings.apply(Colour::new(0.5, 1.5, 0.0)),
+            Colour::new(0.5, 0.75, 0.0)
+        );
+        let settings = PostProcess {
+            gamma: 2.0,
+            ..Default::default()
+        };
+        assert_eq!(
+            settings.apply(Colour::new(0.25, 0.0, 1.0)),
+            Colour::new(0.5, 0.0, 1.0)
+        );
+        let settings = PostProcess {
+            tone_map: ToneMap::Aces,
+            ..Default::default()
+        };
+        let bright = settings.apply(Colour::new(100.0, 0.0, 0.5));
+        assert!(bright.red > 0.99 && bright.red <= 1.0);
+        assert_eq!(bright.green, 0.0);
+        assert!(bright.blue > 0.5 && bright.blue < 0.7);
+    }
+
+    #[test]
+    fn post_processing_a_canvas() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel((1, 0), Colour::new(3.0, 1.0, 0.0));
+        c.post_process(&PostProcess {
+            tone_map: ToneMap::Reinhard,
+            ..Default::default()
+        });
+        assert_eq!(*c.pixel_at(0, 0), Colour::black());
+        assert_eq!(*c.pixel_at(1, 0), Colour::new(0.75, 0.5, 0.0));
+    }
+
     #[test]
     fn write_colour_to_canvas() {
         let mut c = Canvas::new(10, 20);
@@ -171,6 +1026,207 @@ mod tests {
         assert_eq!(*c.pixel_at(2, 3), red);
     }
 
+    #[test]
+    fn sampling_canvas_by_uv() {
+        let mut c = Canvas::new(10, 10);
+        let red = Colour::new(1.0, 0.0, 0.0);
+        let blue = Colour::new(0.0, 0.0, 1.0);
+        c.write_pixel((0, 9), red);
+        c.write_pixel((9, 0), blue);
+        assert_eq!(c.uv_at(0.0, 0.0), red);
+        assert_eq!(c.uv_at(1.0, 1.0), blue);
+        assert_eq!(c.uv_at(0.5, 0.5), Colour::black());
+    }
+
+    #[test]
+    fn looking_up_an_empty_canvas_gives_black() {
+        let c = Canvas::new(0, 3);
+        assert_eq!(c.uv_at(0.5, 0.5), Colour::black());
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn loading_ppm_into_canvas() {
+        let path = std::env::temp_dir().join("rusrat_loading_ppm_into_canvas.ppm");
+        std::fs::write(
+            &path,
+            "P3\n2 2\n255\n255 0 0  0 255 0\n0 0 255  255 255 255\n",
+        )
+        .unwrap();
+        let c = Canvas::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(*c.pixel_at(0, 0), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(*c.pixel_at(1, 0), Colour::new(0.0, 1.0, 0.0));
+        assert_eq!(*c.pixel_at(0, 1), Colour::new(0.0, 0.0, 1.0));
+        assert_eq!(*c.pixel_at(1, 1), Colour::white());
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn saves_in_the_format_given_by_the_extension() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel((0, 0), Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel((2, 1), Colour::white());
+        for extension in ["ppm", "png"] {
+            let path = std::env::temp_dir().join(format!(
+                "rusrat_saves_in_the_format_given_by_the_extension.{}",
+                extension
+            ));
+            c.save(&path).unwrap();
+            let loaded = Canvas::load(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded, c);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn writing_rows_one_at_a_time_matches_saving() {
+        let mut c = Canvas::new(30, 4);
+        c.write_pixel((0, 0), Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel((29, 3), Colour::new(0.5, 0.25, 1.0));
+        for extension in ["ppm", "png"] {
+            let whole = std::env::temp_dir().join(format!("rusrat_rows_whole.{}", extension));
+            let rows = std::env::temp_dir().join(format!("rusrat_rows_streamed.{}", extension));
+            c.save(&whole).unwrap();
+            let mut writer = RowWriter::create(&rows, 30, 4, Dither::None).unwrap();
+            for y in 0..4 {
+                writer.write_row(c.row(y)).unwrap();
+            }
+            writer.finish().unwrap();
+            let (a, b) = (Canvas::load(&whole).unwrap(), Canvas::load(&rows).unwrap());
+            std::fs::remove_file(&whole).unwrap();
+            std::fs::remove_file(&rows).unwrap();
+            assert_eq!(a, b);
+        }
+        let jpeg = std::env::temp_dir().join("rusrat_rows_streamed.jpg");
+        assert!(RowWriter::create(jpeg, 30, 4, Dither::None).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn writes_binary_ppm_to_a_stream() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel((0, 0), Colour::new(1.0, 0.0, 0.0));
+        c.write_pixel((1, 0), Colour::new(0.0, 0.5, 1.0));
+        let mut out = Vec::new();
+        c.write_to(&mut out, ImageFormat::BinaryPpm, Dither::None)
+            .unwrap();
+        assert_eq!(out, b"P6\n2 1\n255\n\xff\x00\x00\x00\x7f\xff");
+        // and the same, a row at a time
+        let streamed = std::env::temp_dir().join("rusrat_writes_binary_ppm_to_a_stream.ppm");
+        let file = Box::new(File::create(&streamed).unwrap());
+        let mut writer = RowWriter::new(file, ImageFormat::BinaryPpm, 2, 1, Dither::None).unwrap();
+        writer.write_row(c.row(0)).unwrap();
+        writer.finish().unwrap();
+        let rows = std::fs::read(&streamed).unwrap();
+        assert_eq!(rows, out);
+        let loaded = Canvas::load(&streamed).unwrap();
+        std::fs::remove_file(&streamed).unwrap();
+        assert_eq!(*loaded.pixel_at(1, 0), Colour::new(0.0, 127.0 / 255.0, 1.0));
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn writes_png_to_a_stream() {
+        let mut c = Canvas::new(3, 2);
+        c.write_pixel((2, 1), Colour::white());
+        let mut out = Vec::new();
+        c.write_to(&mut out, ImageFormat::Png, Dither::None)
+            .unwrap();
+        let loaded = image::load_from_memory(&out).unwrap().into_rgb8();
+        assert_eq!(loaded.dimensions(), (3, 2));
+        assert_eq!(loaded.get_pixel(2, 1).0, [255, 255, 255]);
+        assert_eq!(loaded.get_pixel(0, 0).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn rgba_has_an_opaque_alpha_after_each_pixel() {
+        let mut c = Canvas::new(2, 2);
+        c.write_pixel((1, 0), Colour::new(1.0, 0.5, 0.0));
+        c.write_pixel((0, 1), Colour::white());
+        assert_eq!(
+            c.to_rgba(Dither::None),
+            [0, 0, 0, 255, 255, 127, 0, 255, 255, 255, 255, 255, 0, 0, 0, 255]
+        );
+    }
+
+    #[test]
+    fn dithering_keeps_the_average_level() {
+        let mut c = Canvas::new(16, 16);
+        let level = 100.25 / 255.0;
+        for y in 0..16 {
+            for x in 0..16 {
+                c.write_pixel((x, y), Colour::new(level, level, level));
+            }
+        }
+        let average = |dither| {
+            let bytes = c.quantise(dither).concat();
+            bytes.iter().map(|&b| b as Float).sum::<Float>() / bytes.len() as Float
+        };
+        assert_eq!(average(Dither::None), 100.0);
+        assert_eq!(average(Dither::Ordered), 100.25);
+        // a little of the error is lost off the right and bottom edges
+        assert!((average(Dither::ErrorDiffusion) - 100.25).abs() < 0.05);
+        // without dithering, it's rounded the same way as before
+        assert_eq!(
+            c.quantise(Dither::None)[0][0] as i32,
+            Colour::component_transform(level)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn streamed_rows_are_dithered_like_saved_images() {
+        let mut c = Canvas::new(7, 5);
+        for y in 0..5 {
+            for x in 0..7 {
+                c.write_pixel(
+                    (x, y),
+                    Colour::new(x as Float / 7.0, y as Float / 5.0, 0.33),
+                );
+            }
+        }
+        for dither in [Dither::Ordered, Dither::ErrorDiffusion] {
+            let whole = std::env::temp_dir().join("rusrat_dithered_whole.ppm");
+            let rows = std::env::temp_dir().join("rusrat_dithered_rows.ppm");
+            c.save_dithered(&whole, dither).unwrap();
+            let mut writer = RowWriter::create(&rows, 7, 5, dither).unwrap();
+            for y in 0..5 {
+                writer.write_row(c.row(y)).unwrap();
+            }
+            writer.finish().unwrap();
+            let (a, b) = (
+                std::fs::read(&whole).unwrap(),
+                std::fs::read(&rows).unwrap(),
+            );
+            std::fs::remove_file(&whole).unwrap();
+            std::fs::remove_file(&rows).unwrap();
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn saving_in_an_unknown_format_is_an_error() {
+        let result = Canvas::new(1, 1).save(std::env::temp_dir().join("rusrat_unknown_format.xyz"));
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn saving_to_a_missing_directory_is_an_error() {
+        let c = Canvas::new(1, 1);
+        assert!(c.save("this/directory/does/not/exist.ppm").is_err());
+        assert!(c.save("this/directory/does/not/exist.png").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn loading_missing_file_is_an_error() {
+        assert!(Canvas::load("this/file/does/not/exist.png").is_err());
+    }
+
     #[test]
     fn ppm_header_is_correct() {
         let c = Canvas::new(5, 3);
@@ -190,14 +1246,43 @@ mod tests {
         let pix_data = c.ppm_pixel_data();
         assert_eq!(
             pix_data,
-            "255 0 0\n0 0 0\n0 0 0\n0 0 0\n0 0 0\n\
-             0 0 0\n0 0 0\n0 127 0\n0 0 0\n0 0 0\n\
-             0 0 0\n0 0 0\n0 0 0\n0 0 0\n0 0 255\n\
-             "
+            "255 0 0 0 0 0 0 0 0 0 0 0 0 0 0\n\
+             0 0 0 0 0 0 0 127 0 0 0 0 0 0 0\n\
+             0 0 0 0 0 0 0 0 0 0 0 0 0 0 255\n"
+        )
+    }
+
+    #[test]
+    fn ppm_lines_are_wrapped_at_70_characters() {
+        let mut c = Canvas::new(10, 2);
+        for y in 0..2 {
+            for x in 0..10 {
+                c.write_pixel((x, y), Colour::new(1.0, 0.8, 0.6));
+            }
+        }
+        assert_eq!(
+            c.ppm_pixel_data(),
+            "255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204\n\
+             153 255 204 153 255 204 153 255 204 153 255 204 153\n\
+             255 204 153 255 204 153 255 204 153 255 204 153 255 204 153 255 204\n\
+             153 255 204 153 255 204 153 255 204 153 255 204 153\n"
         )
     }
 
     #[test]
+    fn wide_ppm_files_never_have_long_lines() {
+        let mut c = Canvas::new(101, 3);
+        c.write_pixel((50, 1), Colour::new(0.5, 0.0, 1.0));
+        let data = c.ppm_pixel_data();
+        assert!(data.ends_with('\n'));
+        assert!(data.lines().all(|line| line.len() <= 70));
+        let values: Vec<&str> = data.split_whitespace().collect();
+        assert_eq!(values.len(), 101 * 3 * 3);
+        assert_eq!(values[(101 + 50) * 3..(101 + 51) * 3], ["127", "0", "255"]);
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
     fn save_ppm_file() {
         let mut c = Canvas::new(5, 3);
         let c1 = Colour::new(1.5, 0.0, 0.0);
@@ -206,7 +1291,165 @@ mod tests {
         c.write_pixel((0, 0), c1);
         c.write_pixel((2, 1), c2);
         c.write_pixel((4, 2), c3);
-        c.write_out_as_ppm_file();
-        assert_eq!(1, 1)
+        let path = std::env::temp_dir().join("rusrat_save_ppm_file.ppm");
+        assert!(c.save(&path).is_ok());
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(saved, c.ppm_header() + &c.ppm_pixel_data());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn checker_canvas(size: usize) -> Canvas {
+        let mut c = Canvas::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                if (x + y) % 2 == 0 {
+                    c.write_pixel((x, y), Colour::new(1.0, 1.0, 1.0));
+                }
+            }
+        }
+        c
+    }
+
+    #[test]
+    fn blending_canvases() {
+        let mut bottom = Canvas::new(2, 1);
+        bottom.write_pixel((0, 0), Colour::new(0.5, 0.5, 0.5));
+        let mut top = Canvas::new(2, 1);
+        top.write_pixel((0, 0), Colour::new(0.5, 0.2, 1.0));
+        top.write_pixel((1, 0), Colour::new(0.5, 0.5, 0.5));
+        let cases = [
+            (BlendMode::Normal, Colour::new(0.5, 0.2, 1.0)),
+            (BlendMode::Add, Colour::new(1.0, 0.7, 1.5)),
+            (BlendMode::Multiply, Colour::new(0.25, 0.1, 0.5)),
+            (BlendMode::Screen, Colour::new(0.75, 0.6, 1.0)),
+        ];
+        for (mode, expected) in cases {
+            assert_eq!(*bottom.blend(&top, mode, 1.0).pixel_at(0, 0), expected);
+        }
+        let half = bottom.blend(&top, BlendMode::Normal, 0.5);
+        assert_eq!(*half.pixel_at(0, 0), Colour::new(0.5, 0.35, 0.75));
+        assert_eq!(*half.pixel_at(1, 0), Colour::new(0.25, 0.25, 0.25));
+        assert_eq!(bottom.blend(&top, BlendMode::Add, 0.0), bottom);
+    }
+
+    #[test]
+    #[should_panic]
+    fn blending_canvases_of_different_sizes_panics() {
+        Canvas::new(2, 1).blend(&Canvas::new(1, 2), BlendMode::Normal, 1.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn kernels_need_a_centre() {
+        Kernel::new(2, 1, vec![0.5, 0.5]);
+    }
+
+    #[test]
+    fn convolving_with_a_kernel() {
+        let mut c = Canvas::new(3, 3);
+        c.write_pixel((1, 1), Colour::new(0.9, 0.9, 0.9));
+        let blurred = c.convolve(&Kernel::box_blur(1));
+        assert_eq!(*blurred.pixel_at(1, 1), Colour::new(0.1, 0.1, 0.1));
+        // the edges are extended outwards, so the corner sees the centre once
+        assert_eq!(*blurred.pixel_at(0, 0), Colour::new(0.1, 0.1, 0.1));
+        let sharpened = c.convolve(&Kernel::sharpen());
+        assert_eq!(*sharpened.pixel_at(1, 1), Colour::new(4.5, 4.5, 4.5));
+        assert_eq!(*sharpened.pixel_at(0, 1), Colour::new(-0.9, -0.9, -0.9));
+    }
+
+    #[test]
+    fn gaussian_blur_keeps_the_total_brightness() {
+        let mut c = Canvas::new(21, 21);
+        c.write_pixel((10, 10), Colour::white());
+        let blurred = c.blur(1.5);
+        let total = blurred.pixels.iter().fold(Colour::black(), |a, &p| a + p);
+        assert_eq!(total, Colour::white());
+        assert_eq!(blurred.pixel_at(9, 10), blurred.pixel_at(10, 9));
+        assert!(blurred.pixel_at(10, 10).red < 0.1);
+    }
+
+    #[test]
+    fn blurring_with_no_spread_leaves_the_image_alone() {
+        let mut c = Canvas::new(3, 3);
+        c.write_pixel((1, 1), Colour::white());
+        assert_eq!(Kernel::gaussian(0.0), Kernel::new(1, 1, vec![1.0]));
+        assert_eq!(c.blur(0.0), c);
+    }
+
+    #[test]
+    fn bloom_only_spreads_bright_pixels() {
+        let mut c = Canvas::new(11, 11);
+        c.write_pixel((2, 2), Colour::new(0.9, 0.9, 0.9));
+        c.write_pixel((8, 8), Colour::new(3.0, 1.0, 1.0));
+        let bloom = Bloom {
+            threshold: 1.0,
+            radius: 1.0,
+            strength: 0.5,
+        };
+        let bloomed = bloom.apply(&c);
+        // below the threshold, so no glow
+        assert_eq!(*bloomed.pixel_at(2, 3), Colour::black());
+        assert_eq!(*bloomed.pixel_at(2, 2), Colour::new(0.9, 0.9, 0.9));
+        // only the red was bright enough
+        let glow = bloomed.pixel_at(8, 9);
+        assert!(glow.red > 0.0);
+        assert_eq!(glow.green, 0.0);
+        assert!(bloomed.pixel_at(8, 8).red > 3.0);
+        let none = Bloom {
+            strength: 0.0,
+            ..bloom
+        };
+        assert_eq!(none.apply(&c), c);
+    }
+
+    #[test]
+    fn adjusting_brightness_and_contrast() {
+        let mut c = Canvas::new(2, 1);
+        c.write_pixel((0, 0), Colour::new(0.25, 0.5, 1.0));
+        assert_eq!(c.adjust(0.0, 1.0), c);
+        let adjusted = c.adjust(0.1, 2.0);
+        assert_eq!(*adjusted.pixel_at(0, 0), Colour::new(0.1, 0.6, 1.6));
+        assert_eq!(*adjusted.pixel_at(1, 0), Colour::new(-0.4, -0.4, -0.4));
+    }
+
+    #[test]
+    fn bilinear_lookup_blends_neighbouring_pixels() {
+        let c = checker_canvas(2);
+        assert_eq!(c.bilinear_uv_at(0.0, 1.0), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(c.bilinear_uv_at(0.5, 1.0), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(c.bilinear_uv_at(0.5, 0.5), Colour::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn filtering_an_empty_canvas_gives_black() {
+        let c = Canvas::new(0, 3);
+        assert_eq!(c.bilinear_uv_at(0.5, 0.5), Colour::black());
+        let m = MipMap::new(c);
+        assert_eq!(m.levels(), 3);
+        assert_eq!(m.filtered_uv_at(0.5, 0.5, 4.0), Colour::black());
+    }
+
+    #[test]
+    fn mip_map_halves_down_to_a_single_pixel() {
+        let m = MipMap::new(checker_canvas(8));
+        assert_eq!(m.levels(), 4);
+        assert_eq!(m.level(1).width(), 4);
+        assert_eq!(*m.level(1).pixel_at(0, 0), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(*m.level(3).pixel_at(0, 0), Colour::new(0.5, 0.5, 0.5));
+        let odd = MipMap::new(Canvas::new(5, 3));
+        assert_eq!(odd.level(1).width(), 3);
+        assert_eq!(odd.level(1).height(), 2);
+        assert_eq!(odd.levels(), 4);
+    }
+
+    #[test]
+    fn filtered_lookup_picks_a_level_from_the_footprint() {
+        let m = MipMap::new(checker_canvas(8));
+        assert_eq!(m.filtered_uv_at(0.0, 1.0, 1.0), Colour::new(1.0, 1.0, 1.0));
+        assert_eq!(m.filtered_uv_at(0.0, 1.0, 8.0), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(
+            m.filtered_uv_at(0.0, 1.0, Float::INFINITY),
+            Colour::new(0.5, 0.5, 0.5)
+        );
     }
 }
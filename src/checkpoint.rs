@@ -0,0 +1,314 @@
+use crate::canvas::{Canvas, Colour};
+use crate::precision::Float;
+use crate::world::{self, Camera, Region, World};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Long renders can save the tiles they've finished to a file every so often,
+// so that if they're stopped they can carry on from there rather than
+// starting again. The file is plain text - a header line, then a line for
+// each finished tile giving its corners and then its colours, row by row:
+//
+//   rusrat-checkpoint <scene> <width> <height>
+//   <x0> <y0> <x1> <y1> <r> <g> <b> <r> <g> <b> ...
+//
+// The scene is a number identifying what's being rendered (e.g. scene_id of
+// the scene file), so a checkpoint isn't picked up by a different render.
+pub struct Checkpoint {
+    path: PathBuf,
+    scene: u64,
+    size: (usize, usize),
+    interval: Duration,
+    state: Mutex<State>,
+    // held while a save is being written out, so saves don't overlap
+    saving: Mutex<()>,
+}
+
+struct State {
+    tiles: Vec<(Region, Vec<Colour>)>,
+    last_saved: Instant,
+}
+
+const HEADER: &str = "rusrat-checkpoint";
+
+// A hash of the parts of a render, for a checkpoint's scene. It's FNV-1a
+// rather than the standard library's hasher, which can change between builds
+// of rusrat and then wouldn't recognise its own checkpoints. Each part's
+// length goes in first, so ["ab", "c"] and ["a", "bc"] are different.
+pub fn scene_id(parts: &[&[u8]]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for part in parts {
+        for byte in (part.len() as u64).to_le_bytes().iter().chain(*part) {
+            hash ^= u64::from(*byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+impl Checkpoint {
+    // size is the size of the finished image, and interval is the least time
+    // to leave between saves
+    pub fn new<P: AsRef<Path>>(
+        path: P,
+        scene: u64,
+        size: (usize, usize),
+        interval: Duration,
+    ) -> Checkpoint {
+        Checkpoint {
+            path: path.as_ref().to_path_buf(),
+            scene,
+            size,
+            interval,
+            state: Mutex::new(State {
+                tiles: Vec::new(),
+                last_saved: Instant::now(),
+            }),
+            saving: Mutex::new(()),
+        }
+    }
+
+    // The tiles saved by an earlier run. There are none if there's no file,
+    // or it's for a different scene or size of image, or it can't be read.
+    pub fn load(&self) -> Vec<(Region, Vec<Colour>)> {
+        match fs::File::open(&self.path) {
+            Ok(file) => self.parse(BufReader::new(file)).unwrap_or_default(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    fn parse<R: BufRead>(&self, reader: R) -> Option<Vec<(Region, Vec<Colour>)>> {
+        let mut lines = reader.lines();
+        let header = lines.next()?.ok()?;
+        let expected = format!("{} {} {} {}", HEADER, self.scene, self.size.0, self.size.1);
+        if header != expected {
+            return None;
+        }
+        let mut tiles = Vec::new();
+        for line in lines {
+            let line = line.ok()?;
+            let mut words = line.split_whitespace();
+            let mut corner = || words.next()?.parse::<usize>().ok();
+            let tile = Region {
+                x0: corner()?,
+                y0: corner()?,
+                x1: corner()?,
+                y1: corner()?,
+            };
+            let values = words
+                .map(|w| w.parse::<Float>().ok())
+                .collect::<Option<Vec<Float>>>()?;
+            // a line cut short by being stopped halfway through saving
+            if tile.x1 < tile.x0 || tile.y1 < tile.y0 || values.len() != tile.pixels().count() * 3 {
+                return None;
+            }
+            let colours = values
+                .chunks(3)
+                .map(|c| Colour::new(c[0], c[1], c[2]))
+                .collect();
+            tiles.push((tile, colours));
+        }
+        Some(tiles)
+    }
+
+    // Remembers a finished tile, and saves everything finished so far if
+    // it's been long enough since the last save. The file's written after
+    // letting go of the tiles, so the other threads can carry on adding
+    // theirs, and a save is skipped if the last one is still being written.
+    pub fn add(&self, tile: Region, colours: &[Colour]) -> io::Result<()> {
+        let (contents, _saving) = {
+            let mut state = self.state.lock().unwrap();
+            state.tiles.push((tile, colours.to_vec()));
+            if state.last_saved.elapsed() < self.interval {
+                return Ok(());
+            }
+            let saving = match self.saving.try_lock() {
+                Ok(saving) => saving,
+                Err(_) => return Ok(()),
+            };
+            state.last_saved = Instant::now();
+            (self.contents(&state.tiles)?, saving)
+        };
+        self.write(&contents)
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let _saving = self.saving.lock().unwrap();
+        let contents = self.contents(&self.state.lock().unwrap().tiles)?;
+        self.write(&contents)
+    }
+
+    fn contents(&self, tiles: &[(Region, Vec<Colour>)]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        writeln!(
+            out,
+            "{} {} {} {}",
+            HEADER, self.scene, self.size.0, self.size.1
+        )?;
+        for (tile, colours) in tiles {
+            write!(out, "{} {} {} {}", tile.x0, tile.y0, tile.x1, tile.y1)?;
+            for c in colours.iter().flat_map(|c| c.components()) {
+                write!(out, " {}", c)?;
+            }
+            writeln!(out)?;
+        }
+        Ok(out)
+    }
+
+    // written to a temporary file first, so stopping halfway through a save
+    // doesn't lose the last checkpoint
+    fn write(&self, contents: &[u8]) -> io::Result<()> {
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, &self.path)
+    }
+
+    // once the image is safely written, the checkpoint isn't needed
+    pub fn remove(&self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Renders the image, skipping any tiles saved in the checkpoint, and saving
+// to it as tiles are finished. Problems saving the checkpoint don't stop the
+// render, and are only reported. progress and cancel are as for
+// world::render_resuming.
+pub fn render_with_checkpoint<F>(
+    cam: &Camera,
+    world: &World,
+    checkpoint: &Checkpoint,
+    progress: F,
+    cancel: &AtomicBool,
+) -> Canvas
+where
+    F: Fn(&world::Progress) + Sync,
+{
+    let done = checkpoint.load();
+    checkpoint.state.lock().unwrap().tiles = done.clone();
+    world::render_resuming(
+        cam,
+        world,
+        done,
+        |p| {
+            if let Err(e) = checkpoint.add(p.tile, p.colours) {
+                log::warn!("Couldn't save a checkpoint: {}", e);
+            }
+            progress(p);
+        },
+        cancel,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precision::consts::FRAC_PI_2;
+    use crate::tuple::Tuple;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusrat_{}.checkpoint", name))
+    }
+
+    fn camera() -> Camera {
+        let t = world::view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        Camera::new(40, 40, FRAC_PI_2, t)
+    }
+
+    #[test]
+    fn saved_tiles_can_be_loaded_again() {
+        let path = temp_path("saved_tiles_can_be_loaded_again");
+        let checkpoint = Checkpoint::new(&path, 7, (4, 2), Duration::from_secs(3600));
+        let tile = Region {
+            x0: 1,
+            y0: 0,
+            x1: 3,
+            y1: 1,
+        };
+        let colours = vec![
+            Colour::new(0.1, 0.2, 1.0 / 3.0),
+            Colour::new(4.0, -1.0, 0.0),
+        ];
+        checkpoint.add(tile, &colours).unwrap();
+        // not long enough since the last save
+        assert!(checkpoint.load().is_empty());
+        checkpoint.save().unwrap();
+        let loaded = checkpoint.load();
+        assert_eq!(loaded, vec![(tile, colours)]);
+        // exact, not just close
+        assert_eq!(loaded[0].1[0].components()[2], 1.0 / 3.0);
+        let other_scene = Checkpoint::new(&path, 8, (4, 2), Duration::ZERO);
+        assert!(other_scene.load().is_empty());
+        checkpoint.remove().unwrap();
+        assert!(checkpoint.load().is_empty());
+        assert!(checkpoint.remove().is_ok());
+    }
+
+    #[test]
+    fn scene_ids_dont_change_between_builds() {
+        assert_eq!(scene_id(&[b"scene", b""]), 0x3609_5ad7_cc65_bd68);
+        assert_ne!(scene_id(&[b"ab", b"c"]), scene_id(&[b"a", b"bc"]));
+    }
+
+    #[test]
+    fn a_damaged_checkpoint_is_ignored() {
+        let path = temp_path("a_damaged_checkpoint_is_ignored");
+        fs::write(&path, "rusrat-checkpoint 1 4 2\n0 0 2 1 0.5 0.5\n").unwrap();
+        let checkpoint = Checkpoint::new(&path, 1, (4, 2), Duration::ZERO);
+        assert!(checkpoint.load().is_empty());
+        checkpoint.remove().unwrap();
+    }
+
+    #[test]
+    fn resuming_only_renders_the_missing_tiles() {
+        let path = temp_path("resuming_only_renders_the_missing_tiles");
+        let w = World::default();
+        let c = camera();
+        let expected = world::render(&mut c.clone(), &w);
+        let tiles = c.render_region().tiles(world::TILE_SIZE);
+        assert_eq!(tiles.len(), 4);
+
+        // pretend an earlier run finished the first tile, filled with a colour
+        // that the render would never produce
+        let marker = Colour::new(0.0, 1.0, 0.0);
+        let first = vec![marker; tiles[0].pixels().count()];
+        let checkpoint = Checkpoint::new(&path, 1, (40, 40), Duration::ZERO);
+        checkpoint.add(tiles[0], &first).unwrap();
+
+        let rendered = AtomicUsize::new(0);
+        let checkpoint = Checkpoint::new(&path, 1, (40, 40), Duration::ZERO);
+        let done = checkpoint.load();
+        let image = world::render_resuming(
+            &c,
+            &w,
+            done,
+            |p| {
+                assert_ne!(p.tile, tiles[0]);
+                assert!(p.tiles_done >= 2);
+                rendered.fetch_add(1, Ordering::SeqCst);
+            },
+            &AtomicBool::new(false),
+        );
+        assert_eq!(rendered.load(Ordering::SeqCst), 3);
+        assert_eq!(*image.pixel_at(0, 0), marker);
+        assert_eq!(image.pixel_at(39, 39), expected.pixel_at(39, 39));
+
+        // and carrying on through the checkpoint saves every tile
+        let image = render_with_checkpoint(&c, &w, &checkpoint, |_| {}, &AtomicBool::new(false));
+        assert_eq!(*image.pixel_at(0, 0), marker);
+        assert_eq!(checkpoint.load().len(), 4);
+        checkpoint.remove().unwrap();
+    }
+}
@@ -0,0 +1,426 @@
+// The command line: which scene to render, where to, and how.
+
+use crate::canvas::ImageFormat;
+use crate::failure::ErrorFormat;
+use crate::precision::Float;
+use crate::quality::Quality;
+use clap::Parser;
+
+// what --output - writes to stdout in
+#[derive(clap::ValueEnum, Debug, PartialEq, Clone, Copy)]
+pub enum StdoutFormat {
+    // binary PPM, which ffmpeg and most image viewers read from a pipe
+    Ppm,
+    Png,
+}
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "rusrat",
+    version,
+    about = "Renders a scene described in a YAML or JSON file"
+)]
+pub struct Args {
+    #[arg(help = "The scene file to render", required_unless_present = "init")]
+    pub scene: Option<String>,
+
+    #[arg(
+        short,
+        long,
+        default_value = "output.ppm",
+        help = "Where to write the image - PPM, PNG or JPEG, going by the extension, or - for stdout"
+    )]
+    pub output: String,
+
+    #[arg(
+        long,
+        value_enum,
+        help = "With --output -, the format to write in [default: ppm]"
+    )]
+    pub format: Option<StdoutFormat>,
+
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Count,
+        conflicts_with = "quiet",
+        help = "Say more about what's going on - once for progress messages, twice for every tile"
+    )]
+    pub verbose: u8,
+
+    #[arg(
+        short,
+        long,
+        help = "Only print errors, with no warnings or progress bar"
+    )]
+    pub quiet: bool,
+
+    #[arg(long, help = "Which of the scene's cameras to render from, by name")]
+    pub camera: Option<String>,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Quality::Final,
+        help = "Turn down the size, samples, recursion depth and shadow rays together, for quicker previews"
+    )]
+    pub quality: Quality,
+
+    #[arg(
+        long,
+        value_parser = at_least_one,
+        help = "The width of the image, in place of the camera's"
+    )]
+    pub width: Option<usize>,
+
+    #[arg(
+        long,
+        value_parser = at_least_one,
+        help = "The height of the image, in place of the camera's"
+    )]
+    pub height: Option<usize>,
+
+    #[arg(
+        long,
+        value_parser = above_zero,
+        conflicts_with_all = ["width", "height"],
+        help = "Scale the image's width and height by this much, e.g. 0.25 for a quarter of the size"
+    )]
+    pub scale: Option<Float>,
+
+    #[arg(
+        long,
+        value_parser = at_least_one,
+        help = "Rays per pixel, in place of the camera's"
+    )]
+    pub samples: Option<usize>,
+
+    #[arg(
+        long,
+        help = "Seed the random placing of samples with this, in place of the scene's seeds"
+    )]
+    pub seed: Option<u64>,
+
+    #[arg(
+        long,
+        value_parser = at_least_one,
+        help = "How many threads to render with [default: one per core]"
+    )]
+    pub threads: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with = "threads",
+        help = "Render on one thread, so tiles are done in the same order every time"
+    )]
+    pub single_threaded: bool,
+
+    #[arg(
+        long,
+        help = "Treat settings that aren't recognised as errors, not warnings"
+    )]
+    pub strict: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ErrorFormat::Human,
+        help = "How to report a failure - as a message, or as a line of JSON for other programs to read"
+    )]
+    pub error_format: ErrorFormat,
+
+    #[arg(
+        long,
+        help = "Also write depth, normal and object id images next to the output"
+    )]
+    pub aovs: bool,
+
+    #[arg(
+        long,
+        conflicts_with = "checkpoint",
+        help = "Write the image out as it's rendered, rather than all at the end"
+    )]
+    pub stream: bool,
+
+    #[arg(
+        long,
+        help = "Save the render so far every 30 seconds, and carry on from there if run again"
+    )]
+    pub checkpoint: bool,
+
+    #[arg(
+        long,
+        help = "Print how long the render took, and how many rays and intersection tests it needed"
+    )]
+    pub stats: bool,
+
+    #[arg(
+        long,
+        help = "Print how the objects were sorted into boxes to speed up finding what rays hit"
+    )]
+    pub bvh_stats: bool,
+
+    #[arg(long, help = "Render the scene again every time the file is saved")]
+    pub watch: bool,
+
+    #[arg(
+        long,
+        value_name = "FRAMES",
+        value_parser = at_least_one,
+        conflicts_with = "watch",
+        help = "Render this many frames going once round the scene, into numbered files"
+    )]
+    pub turntable: Option<usize>,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        requires = "turntable",
+        help = "With --turntable, spin the objects with this name, rather than moving the camera"
+    )]
+    pub turntable_object: Option<String>,
+
+    #[arg(
+        long,
+        default_value_t = 1.0,
+        value_parser = above_zero,
+        requires = "watch",
+        help = "With --watch, the fraction of the full size to render at"
+    )]
+    pub preview_scale: Float,
+
+    #[arg(
+        long,
+        value_name = "FILE",
+        exclusive = true,
+        help = "Write a starter scene to learn from, rather than rendering"
+    )]
+    pub init: Option<String>,
+}
+
+impl Args {
+    // what clap can't check by itself
+    pub fn problem(&self) -> Option<String> {
+        if self.to_stdout() {
+            if self.aovs {
+                return Some("--aovs needs a file to write to, not stdout".to_string());
+            }
+            if self.checkpoint {
+                return Some("--checkpoint needs a file to write to, not stdout".to_string());
+            }
+        } else if self.format.is_some() {
+            return Some(format!(
+                "--format is only for --output -, as {}'s format comes from its extension",
+                self.output
+            ));
+        }
+        None
+    }
+
+    pub fn to_stdout(&self) -> bool {
+        self.output == "-"
+    }
+
+    pub fn stdout_format(&self) -> ImageFormat {
+        match self.format.unwrap_or(StdoutFormat::Ppm) {
+            StdoutFormat::Ppm => ImageFormat::BinaryPpm,
+            StdoutFormat::Png => ImageFormat::Png,
+        }
+    }
+
+    pub fn log_level(&self) -> log::LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => log::LevelFilter::Error,
+            (false, 0) => log::LevelFilter::Warn,
+            (false, 1) => log::LevelFilter::Info,
+            (false, 2) => log::LevelFilter::Debug,
+            (false, _) => log::LevelFilter::Trace,
+        }
+    }
+
+    // how many threads to render with, if not the default
+    pub fn threads(&self) -> Option<usize> {
+        if self.single_threaded {
+            Some(1)
+        } else {
+            self.threads
+        }
+    }
+
+    // The world and camera with everything asked for on the command line.
+    pub fn override_scene(
+        &self,
+        world: &mut crate::world::World,
+        camera: crate::world::Camera,
+    ) -> crate::world::Camera {
+        if let Some(seed) = self.seed {
+            world.shadow_sampler = world.shadow_sampler.with_seed(seed);
+        }
+        self.override_camera(camera)
+    }
+
+    // The camera with the size, samples and seed asked for, if any - these take
+    // the place of whatever the quality preset chose. Given just a width or
+    // just a height, the other is worked out to keep the camera's shape.
+    pub fn override_camera(&self, camera: crate::world::Camera) -> crate::world::Camera {
+        let aspect = camera.hsize as Float / camera.vsize as Float;
+        let round = |x: Float| (x.round() as usize).max(1);
+        let mut camera = match (self.width, self.height, self.scale) {
+            (Some(width), Some(height), _) => camera.resized(width, height),
+            (Some(width), None, _) => camera.resized(width, round(width as Float / aspect)),
+            (None, Some(height), _) => camera.resized(round(height as Float * aspect), height),
+            (None, None, Some(scale)) => camera.scaled(scale),
+            (None, None, None) => camera,
+        };
+        if let Some(samples) = self.samples {
+            camera.samples = samples;
+        }
+        if let Some(seed) = self.seed {
+            camera.sampler = camera.sampler.with_seed(seed);
+        }
+        camera
+    }
+}
+
+fn at_least_one(s: &str) -> Result<usize, String> {
+    match s.parse() {
+        Ok(n) if n >= 1 => Ok(n),
+        _ => Err("should be a whole number, 1 or more".to_string()),
+    }
+}
+
+fn above_zero(s: &str) -> Result<Float, String> {
+    match s.parse() {
+        Ok(x) if x > 0.0 => Ok(x),
+        _ => Err("should be a number above 0, like 0.5".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrices::Matrix;
+    use crate::sampling::Sampler;
+    use crate::world::{Camera, World};
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(std::iter::once("rusrat").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn parses_a_render() {
+        let args = parse(&[
+            "scene.yaml",
+            "-o",
+            "scene.png",
+            "--width",
+            "320",
+            "--strict",
+        ])
+        .unwrap();
+        assert_eq!(args.scene.as_deref(), Some("scene.yaml"));
+        assert_eq!(args.output, "scene.png");
+        assert_eq!(args.width, Some(320));
+        assert_eq!(args.height, None);
+        assert!(args.strict);
+        assert!(!args.watch);
+        let args = parse(&["scene.yaml"]).unwrap();
+        assert_eq!(args.output, "output.ppm");
+        assert_eq!(args.preview_scale, 1.0);
+        assert_eq!(args.quality, Quality::Final);
+        let args = parse(&["scene.yaml", "--quality", "draft"]).unwrap();
+        assert_eq!(args.quality, Quality::Draft);
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["scene.yaml", "--threads", "0"]).is_err());
+        assert!(parse(&["scene.yaml", "--samples", "many"]).is_err());
+        assert!(parse(&["scene.yaml", "--quality", "best"]).is_err());
+        assert!(parse(&["scene.yaml", "--preview-scale", "0.5"]).is_err());
+        assert!(parse(&["scene.yaml", "--watch", "--preview-scale", "-1"]).is_err());
+        assert!(parse(&["scene.yaml", "--stream", "--checkpoint"]).is_err());
+        assert!(parse(&["scene.yaml", "--threads", "2", "--single-threaded"]).is_err());
+        assert!(parse(&["scene.yaml", "--scale", "0"]).is_err());
+        assert!(parse(&["scene.yaml", "--scale", "0.5", "--width", "100"]).is_err());
+        assert!(parse(&["scene.yaml", "--init", "new.yaml"]).is_err());
+        assert!(parse(&["--init", "new.yaml"]).is_ok());
+        assert!(parse(&["scene.yaml", "--turntable", "0"]).is_err());
+        assert!(parse(&["scene.yaml", "--turntable-object", "teapot"]).is_err());
+        assert!(parse(&["scene.yaml", "--turntable", "36", "--watch"]).is_err());
+    }
+
+    #[test]
+    fn writes_to_stdout() {
+        let args = parse(&["scene.yaml", "-o", "-"]).unwrap();
+        assert!(args.to_stdout());
+        assert_eq!(args.stdout_format(), ImageFormat::BinaryPpm);
+        assert_eq!(args.problem(), None);
+        let args = parse(&["scene.yaml", "-o", "-", "--format", "png"]).unwrap();
+        assert_eq!(args.stdout_format(), ImageFormat::Png);
+        assert!(!parse(&["scene.yaml"]).unwrap().to_stdout());
+        let problem = |args: &[&str]| parse(args).unwrap().problem();
+        assert!(problem(&["scene.yaml", "-o", "-", "--aovs"]).is_some());
+        assert!(problem(&["scene.yaml", "-o", "-", "--checkpoint"]).is_some());
+        assert!(problem(&["scene.yaml", "-o", "a.png", "--format", "png"]).is_some());
+        assert!(parse(&["scene.yaml", "-o", "-", "--format", "gif"]).is_err());
+    }
+
+    #[test]
+    fn picks_the_log_level() {
+        let level = |args: &[&str]| parse(args).unwrap().log_level();
+        assert_eq!(level(&["scene.yaml"]), log::LevelFilter::Warn);
+        assert_eq!(level(&["scene.yaml", "-q"]), log::LevelFilter::Error);
+        assert_eq!(level(&["scene.yaml", "-v"]), log::LevelFilter::Info);
+        assert_eq!(level(&["scene.yaml", "-vv"]), log::LevelFilter::Debug);
+        assert_eq!(
+            level(&["scene.yaml", "-v", "-v", "-v"]),
+            log::LevelFilter::Trace
+        );
+        assert!(parse(&["scene.yaml", "-v", "--quiet"]).is_err());
+    }
+
+    #[test]
+    fn picks_the_number_of_threads() {
+        assert_eq!(parse(&["scene.yaml"]).unwrap().threads(), None);
+        let args = parse(&["scene.yaml", "--threads", "3"]).unwrap();
+        assert_eq!(args.threads(), Some(3));
+        let args = parse(&["scene.yaml", "--single-threaded"]).unwrap();
+        assert_eq!(args.threads(), Some(1));
+    }
+
+    #[test]
+    fn overrides_the_camera() {
+        let c = Camera::new(200, 100, 1.0, Matrix::identity());
+        let args = parse(&["scene.yaml", "--height", "50", "--samples", "9"]).unwrap();
+        let c = args.override_camera(c);
+        assert_eq!((c.hsize, c.vsize), (100, 50));
+        assert!((c.pixel_size - 2.0 * Float::tan(0.5) / 100.0).abs() < 1e-9);
+        assert_eq!(c.samples, 9);
+        let args = parse(&["scene.yaml", "--width", "301"]).unwrap();
+        let wide = args.override_camera(c.clone());
+        assert_eq!((wide.hsize, wide.vsize), (301, 151));
+        let args = parse(&["scene.yaml", "--width", "40", "--height", "40"]).unwrap();
+        let square = args.override_camera(c.clone());
+        assert_eq!((square.hsize, square.vsize), (40, 40));
+        let args = parse(&["scene.yaml", "--scale", "0.25"]).unwrap();
+        let small = args.override_camera(c.clone());
+        assert_eq!((small.hsize, small.vsize), (25, 13));
+        let args = parse(&["scene.yaml"]).unwrap();
+        assert_eq!(args.override_camera(c.clone()), c);
+    }
+
+    #[test]
+    fn overrides_the_seeds() {
+        let mut w = World::new();
+        w.shadow_sampler = Sampler::BlueNoise { seed: 1 };
+        let mut c = Camera::new(200, 100, 1.0, Matrix::identity());
+        c.sampler = Sampler::Stratified { seed: 2 };
+        let args = parse(&["scene.yaml", "--seed", "42"]).unwrap();
+        let c = args.override_scene(&mut w, c);
+        assert_eq!(c.sampler, Sampler::Stratified { seed: 42 });
+        assert_eq!(w.shadow_sampler, Sampler::BlueNoise { seed: 42 });
+        assert!(parse(&["scene.yaml", "--seed", "-1"]).is_err());
+    }
+}
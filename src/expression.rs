@@ -0,0 +1,236 @@
+// Simple arithmetic for scene files, so that numbers can be written as e.g.
+// pi/2 or deg(45) rather than worked out by hand. Supports + - * / ^,
+// brackets, the constants pi and tau, and the functions deg (degrees to
+// radians) and sqrt.
+
+use crate::precision::Float;
+
+pub fn evaluate(source: &str) -> Result<Float, String> {
+    let mut parser = Parser {
+        tokens: tokenise(source)?,
+        position: 0,
+    };
+    let value = parser.sum()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(token) => Err(format!("unexpected {}", token)),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Number(Float),
+    Name(String),
+    Operator(char),
+    Open,
+    Close,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(x) => write!(f, "{}", x),
+            Token::Name(name) => write!(f, "{}", name),
+            Token::Operator(op) => write!(f, "{}", op),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenise(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() || c == '.' {
+                    number.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            match number.parse() {
+                Ok(x) => tokens.push(Token::Number(x)),
+                Err(_) => return Err(format!("{} isn't a number", number)),
+            }
+        } else if c.is_alphabetic() {
+            let mut name = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    name.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Name(name));
+        } else {
+            chars.next();
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '^' => Token::Operator(c),
+                '(' => Token::Open,
+                ')' => Token::Close,
+                _ => return Err(format!("unexpected {}", c)),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is(&mut self, op: char) -> bool {
+        if self.peek() == Some(&Token::Operator(op)) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // terms added or subtracted together
+    fn sum(&mut self) -> Result<Float, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.next_is('+') {
+                value += self.product()?;
+            } else if self.next_is('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<Float, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.next_is('*') {
+                value *= self.unary()?;
+            } else if self.next_is('/') {
+                value /= self.unary()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Float, String> {
+        if self.next_is('-') {
+            Ok(-self.unary()?)
+        } else if self.next_is('+') {
+            self.unary()
+        } else {
+            self.power()
+        }
+    }
+
+    // ^ binds tighter than a minus sign in front, so -2^2 is -4
+    fn power(&mut self) -> Result<Float, String> {
+        let base = self.atom()?;
+        if self.next_is('^') {
+            Ok(base.powf(self.unary()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    fn atom(&mut self) -> Result<Float, String> {
+        match self.next() {
+            Some(Token::Number(x)) => Ok(x),
+            Some(Token::Open) => self.bracketed(),
+            Some(Token::Name(name)) => match name.as_str() {
+                "pi" => Ok(crate::precision::consts::PI),
+                "tau" => Ok(crate::precision::consts::TAU),
+                "deg" | "sqrt" => {
+                    match self.next() {
+                        Some(Token::Open) => (),
+                        _ => return Err(format!("{} needs brackets", name)),
+                    }
+                    let x = self.bracketed()?;
+                    Ok(if name == "deg" {
+                        x.to_radians()
+                    } else {
+                        x.sqrt()
+                    })
+                }
+                _ => Err(format!("unknown name {}", name)),
+            },
+            Some(token) => Err(format!("unexpected {}", token)),
+            None => Err("it ends too soon".to_string()),
+        }
+    }
+
+    // the rest of a bracket, after the opening one
+    fn bracketed(&mut self) -> Result<Float, String> {
+        let value = self.sum()?;
+        match self.next() {
+            Some(Token::Close) => Ok(value),
+            _ => Err("a bracket isn't closed".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float_eq;
+    use crate::precision::consts::PI;
+
+    #[test]
+    fn evaluates_numbers() {
+        assert_eq!(evaluate("2"), Ok(2.0));
+        assert_eq!(evaluate(" 0.25 "), Ok(0.25));
+        assert_eq!(evaluate("-3"), Ok(-3.0));
+    }
+
+    #[test]
+    fn evaluates_arithmetic() {
+        assert_eq!(evaluate("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(evaluate("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(evaluate("8 / 2 / 2"), Ok(2.0));
+        assert_eq!(evaluate("2 - 3 - 4"), Ok(-5.0));
+        assert_eq!(evaluate("2^3^2"), Ok(512.0));
+        assert_eq!(evaluate("-2^2"), Ok(-4.0));
+    }
+
+    #[test]
+    fn evaluates_constants_and_functions() {
+        assert!(float_eq(evaluate("pi/2").unwrap(), PI / 2.0));
+        assert!(float_eq(evaluate("-tau/4").unwrap(), -PI / 2.0));
+        assert!(float_eq(evaluate("deg(90)").unwrap(), PI / 2.0));
+        assert!(float_eq(evaluate("deg(45 * 3)").unwrap(), 0.75 * PI));
+        assert!(float_eq(evaluate("sqrt(2)/2").unwrap(), Float::sqrt(0.5)));
+    }
+
+    #[test]
+    fn rejects_malformed_sums() {
+        assert!(evaluate("").is_err());
+        assert!(evaluate("pi/").is_err());
+        assert!(evaluate("(1 + 2").is_err());
+        assert!(evaluate("1 2").is_err());
+        assert!(evaluate("deg 90").is_err());
+        assert!(evaluate("cos(1)").is_err());
+        assert!(evaluate("1.2.3").is_err());
+        assert!(evaluate("2 % 3").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+// How rusrat fails. Each kind of failure has its own exit code, so scripts
+// can tell them apart, and with --error-format json it's reported as a line
+// of JSON on stderr for editors and build systems to read, e.g.
+//
+//     {"kind":"scene","message":"scene.yaml: line 3, column 4: ...","file":"scene.yaml","line":3,"column":4}
+
+use crate::yaml::ParseError;
+use serde::Serialize;
+
+#[derive(clap::ValueEnum, Debug, PartialEq, Clone, Copy, Default)]
+pub enum ErrorFormat {
+    // a message for people
+    #[default]
+    Human,
+    Json,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    // the scene isn't valid, or has warnings with --strict
+    Scene,
+    // a file couldn't be read or written
+    Io,
+    // something went wrong while rendering
+    Render,
+}
+
+impl Kind {
+    // 2 is left for bad arguments, which clap exits with, and 130 for Ctrl-C
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Kind::Scene => 3,
+            Kind::Io => 4,
+            Kind::Render => 5,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Failure {
+    pub kind: Kind,
+    pub message: String,
+    // the file it's about, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file: Option<String>,
+    // where in the file, for scenes that aren't valid YAML or JSON
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+impl Failure {
+    pub fn new(kind: Kind, message: String) -> Failure {
+        Failure {
+            kind,
+            message,
+            file: None,
+            line: None,
+            column: None,
+        }
+    }
+
+    pub fn with_file(mut self, file: &str) -> Failure {
+        self.file = Some(file.to_string());
+        self
+    }
+
+    // a scene file that couldn't be parsed
+    pub fn scene(file: &str, error: &ParseError) -> Failure {
+        let failure = Failure::new(Kind::Scene, format!("{}: {}", file, error));
+        match error.syntax_position() {
+            Some((included, line, column)) => Failure {
+                line: Some(line),
+                column: Some(column),
+                ..failure.with_file(included.unwrap_or(file))
+            },
+            None => failure.with_file(file),
+        }
+    }
+
+    pub fn report(&self, format: ErrorFormat) {
+        match format {
+            ErrorFormat::Human => eprintln!("{}", self.message),
+            ErrorFormat::Json => eprintln!("{}", serde_json::to_string(self).unwrap()),
+        }
+    }
+
+    pub fn exit(&self, format: ErrorFormat) -> ! {
+        self.report(format);
+        std::process::exit(self.kind.exit_code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn every_kind_has_its_own_exit_code() {
+        let codes = [Kind::Scene, Kind::Io, Kind::Render].map(Kind::exit_code);
+        assert_eq!(codes, [3, 4, 5]);
+    }
+
+    #[test]
+    fn scene_failures_say_where_they_are() {
+        let error =
+            crate::yaml::parse_scene_in("- add: sphere\n  name: [1, 2\n", Path::new(""), None)
+                .err()
+                .unwrap();
+        let failure = Failure::scene("scene.yaml", &error);
+        assert_eq!(failure.kind, Kind::Scene);
+        assert_eq!(failure.file.as_deref(), Some("scene.yaml"));
+        assert_eq!(failure.line, Some(3));
+        assert!(failure.column.is_some());
+        assert_eq!(failure.message, format!("scene.yaml: {}", error));
+    }
+
+    #[test]
+    fn writes_failures_as_json() {
+        let failure = Failure::new(Kind::Io, "Couldn't read a.yaml: not found".to_string())
+            .with_file("a.yaml");
+        assert_eq!(
+            serde_json::to_string(&failure).unwrap(),
+            r#"{"kind":"io","message":"Couldn't read a.yaml: not found","file":"a.yaml"}"#
+        );
+    }
+}
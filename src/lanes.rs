@@ -0,0 +1,165 @@
+// The 4-wide arithmetic that rendering spends most of its time in - adding and
+// scaling tuples and colours, dot products, multiplying by 4x4 matrices, and
+// testing packets of four rays against boxes - done on plain arrays of four, so
+// tuples, colours, matrices and packets can all share it. By default it's
+// ordinary loops that the compiler's left to vectorise, but with the simd
+// feature it's done with std::simd, four lanes at a time. That needs a nightly
+// compiler, for now:
+//
+//     cargo +nightly build --release --features simd
+//
+// Matrices are stored a row at a time, so a matrix here is four rows.
+
+use crate::precision::Float;
+
+pub type Lanes = [Float; 4];
+
+#[cfg(feature = "simd")]
+mod imp {
+    use super::{Float, Lanes};
+    use std::simd::num::SimdFloat;
+    use std::simd::Simd;
+
+    type Wide = Simd<Float, 4>;
+
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        (Wide::from_array(a) + Wide::from_array(b)).to_array()
+    }
+
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        (Wide::from_array(a) - Wide::from_array(b)).to_array()
+    }
+
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        (Wide::from_array(a) * Wide::from_array(b)).to_array()
+    }
+
+    pub fn scale(a: Lanes, s: Float) -> Lanes {
+        (Wide::from_array(a) * Wide::splat(s)).to_array()
+    }
+
+    pub fn dot(a: Lanes, b: Lanes) -> Float {
+        (Wide::from_array(a) * Wide::from_array(b)).reduce_sum()
+    }
+
+    pub fn min(a: Lanes, b: Lanes) -> Lanes {
+        Wide::from_array(a).simd_min(Wide::from_array(b)).to_array()
+    }
+
+    pub fn max(a: Lanes, b: Lanes) -> Lanes {
+        Wide::from_array(a).simd_max(Wide::from_array(b)).to_array()
+    }
+
+    pub fn transform(m: &[Lanes; 4], v: Lanes) -> Lanes {
+        let v = Wide::from_array(v);
+        m.map(|row| (Wide::from_array(row) * v).reduce_sum())
+    }
+
+    // each row of the product is the rows of b, weighted by that row of a
+    pub fn multiply(a: &[Lanes; 4], b: &[Lanes; 4]) -> [Lanes; 4] {
+        let b = b.map(Wide::from_array);
+        a.map(|row| {
+            (0..4)
+                .map(|k| Wide::splat(row[k]) * b[k])
+                .fold(Wide::splat(0.0), |sum, x| sum + x)
+                .to_array()
+        })
+    }
+}
+
+#[cfg(not(feature = "simd"))]
+mod imp {
+    use super::{Float, Lanes};
+
+    fn zip(a: Lanes, b: Lanes, f: impl Fn(Float, Float) -> Float) -> Lanes {
+        [f(a[0], b[0]), f(a[1], b[1]), f(a[2], b[2]), f(a[3], b[3])]
+    }
+
+    pub fn add(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |x, y| x + y)
+    }
+
+    pub fn sub(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |x, y| x - y)
+    }
+
+    pub fn mul(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, |x, y| x * y)
+    }
+
+    pub fn scale(a: Lanes, s: Float) -> Lanes {
+        a.map(|x| x * s)
+    }
+
+    pub fn dot(a: Lanes, b: Lanes) -> Float {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2] + a[3] * b[3]
+    }
+
+    // like Float::min and max, a NaN loses to the other number
+    pub fn min(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, Float::min)
+    }
+
+    pub fn max(a: Lanes, b: Lanes) -> Lanes {
+        zip(a, b, Float::max)
+    }
+
+    pub fn transform(m: &[Lanes; 4], v: Lanes) -> Lanes {
+        m.map(|row| dot(row, v))
+    }
+
+    pub fn multiply(a: &[Lanes; 4], b: &[Lanes; 4]) -> [Lanes; 4] {
+        a.map(|row| {
+            let column = |j: usize| [b[0][j], b[1][j], b[2][j], b[3][j]];
+            [0, 1, 2, 3].map(|j| dot(row, column(j)))
+        })
+    }
+}
+
+pub use imp::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // these run against whichever version is built, so running them with and
+    // without the simd feature checks the two agree
+    #[test]
+    fn works_lane_by_lane() {
+        let a = [1.0, 2.0, 3.0, 4.0];
+        let b = [0.5, -1.0, 2.0, 0.0];
+        assert_eq!(add(a, b), [1.5, 1.0, 5.0, 4.0]);
+        assert_eq!(sub(a, b), [0.5, 3.0, 1.0, 4.0]);
+        assert_eq!(mul(a, b), [0.5, -2.0, 6.0, 0.0]);
+        assert_eq!(scale(a, 2.0), [2.0, 4.0, 6.0, 8.0]);
+        assert_eq!(dot(a, b), 4.5);
+        assert_eq!(min(a, b), [0.5, -1.0, 2.0, 0.0]);
+        assert_eq!(max(a, [Float::NAN; 4]), a);
+    }
+
+    #[test]
+    fn multiplies_by_matrices() {
+        let m = [
+            [1.0, 2.0, 3.0, 4.0],
+            [2.0, 4.0, 4.0, 2.0],
+            [8.0, 6.0, 4.0, 1.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ];
+        assert_eq!(transform(&m, [1.0, 2.0, 3.0, 1.0]), [18.0, 24.0, 33.0, 1.0]);
+        let n = [
+            [-2.0, 1.0, 2.0, 3.0],
+            [3.0, 2.0, 1.0, -1.0],
+            [4.0, 3.0, 6.0, 5.0],
+            [1.0, 2.0, 7.0, 8.0],
+        ];
+        assert_eq!(
+            multiply(&m, &n),
+            [
+                [20.0, 22.0, 50.0, 48.0],
+                [26.0, 26.0, 46.0, 38.0],
+                [19.0, 34.0, 53.0, 46.0],
+                [1.0, 2.0, 7.0, 8.0],
+            ]
+        );
+    }
+}
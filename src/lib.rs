@@ -0,0 +1,80 @@
+//! rusrat's a ray tracer. As well as the `rusrat` command, it can be used as a
+//! library, to build scenes in code (or read them in from YAML) and render
+//! them to a canvas:
+//!
+//! ```
+//! use rusrat::canvas::Colour;
+//! use rusrat::lighting::PointLight;
+//! use rusrat::matrices::Matrix;
+//! use rusrat::shapes::sphere;
+//! use rusrat::tuple::Tuple;
+//! use rusrat::world::{Camera, Renderer, World};
+//!
+//! let mut ball = sphere::default();
+//! ball.set_transform(Matrix::translation(0.0, 1.0, 0.0));
+//! let world = World::builder()
+//!     .object(ball)
+//!     .light(PointLight::new(
+//!         Colour::white(),
+//!         Tuple::point_new(-10.0, 10.0, -10.0),
+//!     ))
+//!     .build();
+//!
+//! let camera = Camera::builder()
+//!     .size(20, 10)
+//!     .fov_degrees(60.0)
+//!     .look_at(
+//!         Tuple::point_new(0.0, 1.5, -5.0),
+//!         Tuple::point_new(0.0, 1.0, 0.0),
+//!         Tuple::vector_new(0.0, 1.0, 0.0),
+//!     )
+//!     .build();
+//! let image = Renderer::default().render(&camera, &world);
+//! assert_ne!(*image.pixel_at(10, 5), Colour::black());
+//! ```
+//!
+//! The modules most programs need are:
+//!
+//! - [`tuple`](mod@tuple) - points and vectors
+//! - [`matrices`] - transforms, for moving shapes and cameras about
+//! - [`canvas`] - colours, and the images that get rendered
+//! - [`shapes`] - spheres, planes, cubes, triangles and the rest, along with
+//!   their materials and patterns
+//! - [`rays`] - rays, and where they hit things
+//! - [`lighting`] - lights, and shading what rays hit
+//! - [`world`] - worlds, cameras, and rendering
+//! - [`yaml`] - reading in scene files
+//! - [`web`] - rendering for a browser, when built for WebAssembly
+//!
+//! The rest are what those are built on.
+#![cfg_attr(feature = "simd", feature(portable_simd))]
+
+pub mod bvh;
+pub mod canvas;
+pub mod expression;
+pub mod lanes;
+pub mod lighting;
+pub mod matrices;
+pub mod noise;
+pub mod obj;
+pub mod parallel;
+pub mod precision;
+pub mod rays;
+pub mod sampling;
+pub mod scene;
+pub mod shapes;
+pub mod stats;
+pub mod tuple;
+pub mod uv;
+pub mod web;
+pub mod world;
+pub mod yaml;
+
+use precision::Float;
+
+pub const REFLECTION_RECURSION_DEPTH: usize = 7;
+
+pub fn float_eq(a: Float, b: Float) -> bool {
+    const EPSILON: Float = 0.000001;
+    (a - b).abs() < EPSILON
+}
@@ -1,13 +1,85 @@
 use crate::canvas::Colour;
-use crate::rays::{Intersection, Ray};
-use crate::shapes::{Material, Shape};
+use crate::precision::{Float, SURFACE_OFFSET};
+use crate::rays::{Footprint, Intersection, IntersectionBuffer, Ray};
+use crate::sampling;
+use crate::shapes::{Material, Pattern, Shape};
+use crate::stats::{self, Counter};
 use crate::tuple::Tuple;
-use crate::world::World;
+use crate::world::{ObjectId, World};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct PointLight {
     intensity: Colour,
     position: Tuple,
+    // an optional gobo, projected outwards from the light's position
+    pattern: Option<Pattern>,
+    attenuation: Attenuation,
+    // if set, the light is spread over an area rather than being a single point
+    area: Option<LightArea>,
+    // whether the light produces highlights - fill lights often shouldn't
+    specular: bool,
+    // the name shapes use to link to the light
+    group: Option<String>,
+    // which way the light shines
+    kind: LightKind,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum LightKind {
+    // shines equally in every direction from its position
+    Point,
+    // a cone of light from its position, pointing in the given direction. It's
+    // at full strength within inner_angle of the direction, fading away to
+    // nothing at outer_angle (both in radians)
+    Spot {
+        direction: Tuple,
+        inner_angle: Float,
+        outer_angle: Float,
+    },
+    // light from so far away that it all travels in the same direction, like
+    // sunlight. Its position is ignored, and it doesn't fade with distance
+    Directional {
+        direction: Tuple,
+    },
+}
+
+// A rectangle centred on the light's position, with sides uvec and vvec.
+// Shadows are found by testing usteps * vsteps points spread across it, which
+// softens their edges. Lighting otherwise comes from the centre.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct LightArea {
+    pub uvec: Tuple,
+    pub usteps: usize,
+    pub vvec: Tuple,
+    pub vsteps: usize,
+}
+
+// How a light fades with distance d: its intensity is divided by
+// constant + linear * d + quadratic * d^2. The default of (1, 0, 0) means no
+// falloff at all, and a physically correct inverse square law is (0, 0, 1).
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Attenuation {
+    pub constant: Float,
+    pub linear: Float,
+    pub quadratic: Float,
+}
+
+impl Attenuation {
+    fn factor(&self, distance: Float) -> Float {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance.powi(2))
+    }
+}
+
+impl Default for Attenuation {
+    fn default() -> Attenuation {
+        Attenuation {
+            constant: 1.0,
+            linear: 0.0,
+            quadratic: 0.0,
+        }
+    }
 }
 
 pub struct PreComputation<'a> {
@@ -16,18 +88,35 @@ pub struct PreComputation<'a> {
     eye_vec: Tuple,
     reflect_vec: Tuple,
     normal: Tuple,
-    t: f64,
+    // only the tests look at where along the ray the hit is
+    #[cfg(test)]
+    t: Float,
     inside: bool,
     over_point: Tuple,
     under_point: Tuple,
-    n1: f64,
-    n2: f64,
+    n1: Float,
+    n2: Float,
+    // only camera rays know how big a pixel is where they hit
+    footprint: Option<Footprint>,
 }
-#[derive(Default)]
 pub struct ShadowInformation {
-    in_shadow: bool,
-    shadowing_object_transparency: f64,
-    shadowing_object_colour: Colour,
+    // how much of the light's colour gets through whatever is in the way:
+    // white if nothing is, black if something opaque is
+    transmittance: Colour,
+}
+
+impl ShadowInformation {
+    pub fn in_shadow(&self) -> bool {
+        self.transmittance != Colour::white()
+    }
+}
+
+impl Default for ShadowInformation {
+    fn default() -> ShadowInformation {
+        ShadowInformation {
+            transmittance: Colour::white(),
+        }
+    }
 }
 
 impl PointLight {
@@ -35,21 +124,138 @@ impl PointLight {
         PointLight {
             intensity,
             position,
+            pattern: None,
+            attenuation: Attenuation::default(),
+            area: None,
+            specular: true,
+            group: None,
+            kind: LightKind::Point,
+        }
+    }
+
+    pub fn with_kind(self, kind: LightKind) -> PointLight {
+        PointLight { kind, ..self }
+    }
+
+    pub fn with_group(self, group: &str) -> PointLight {
+        PointLight {
+            group: Some(group.to_string()),
+            ..self
+        }
+    }
+
+    // Shapes without any light links are lit by everything, otherwise only by
+    // lights in a group they're linked to.
+    fn illuminates(&self, object: &Shape) -> bool {
+        match (&object.light_links, &self.group) {
+            (None, _) => true,
+            (Some(links), Some(group)) => links.contains(group),
+            (Some(_), None) => false,
+        }
+    }
+
+    pub fn with_specular(self, specular: bool) -> PointLight {
+        PointLight { specular, ..self }
+    }
+
+    pub fn with_area(self, area: LightArea) -> PointLight {
+        PointLight {
+            area: Some(area),
+            ..self
+        }
+    }
+
+    // An area light casting soft shadows with no more than steps shadow rays
+    // along each side, for quicker, noisier renders. Other lights are left as
+    // they are.
+    pub fn with_area_steps_at_most(self, steps: usize) -> PointLight {
+        PointLight {
+            area: self.area.map(|a| LightArea {
+                usteps: a.usteps.min(steps),
+                vsteps: a.vsteps.min(steps),
+                ..a
+            }),
+            ..self
+        }
+    }
+
+    pub fn with_attenuation(self, attenuation: Attenuation) -> PointLight {
+        PointLight {
+            attenuation,
+            ..self
+        }
+    }
+
+    // Makes the light shine through a pattern, like a slide in a projector.
+    // The pattern is sampled in the direction from the light to the point
+    // being lit, so its transform can be used to aim it.
+    pub fn with_pattern(self, pattern: Pattern) -> PointLight {
+        PointLight {
+            pattern: Some(pattern),
+            ..self
+        }
+    }
+
+    // the direction from the point towards the light
+    fn direction_from(&self, point: &Tuple) -> Tuple {
+        match self.kind {
+            LightKind::Directional { direction } => direction.negate().normalise(),
+            _ => (self.position - *point).normalise(),
+        }
+    }
+
+    fn intensity_towards(&self, point: &Tuple) -> Colour {
+        let light_to_point = match self.kind {
+            LightKind::Directional { direction } => direction,
+            _ => *point - self.position,
+        };
+        let intensity = match self.kind {
+            LightKind::Point => {
+                self.intensity * self.attenuation.factor(light_to_point.magnitude())
+            }
+            LightKind::Spot {
+                direction,
+                inner_angle,
+                outer_angle,
+            } => {
+                let angle = light_to_point
+                    .normalise()
+                    .dot(&direction.normalise())
+                    .clamp(-1.0, 1.0)
+                    .acos();
+                let cone = if angle <= inner_angle {
+                    1.0
+                } else if angle >= outer_angle {
+                    0.0
+                } else {
+                    (outer_angle - angle) / (outer_angle - inner_angle)
+                };
+                self.intensity * self.attenuation.factor(light_to_point.magnitude()) * cone
+            }
+            LightKind::Directional { .. } => self.intensity,
+        };
+        match &self.pattern {
+            None => intensity,
+            Some(p) => intensity * p.pattern_at_direction(&light_to_point),
         }
     }
 }
 
+// i is one of the world's intersections, and intersections the rest along the
+// ray, in order
 pub fn prepare_computations<'a>(
-    i: &Intersection<'a>,
+    w: &'a World,
+    i: &Intersection,
     r: &Ray,
-    intersections: &[Intersection<'a>],
+    intersections: &[Intersection],
 ) -> PreComputation<'a> {
-    const EPSILON: f64 = 0.0000001;
+    let object = w.object(i.object);
     let p = r.position(i.t);
     let mut out = PreComputation {
-        object: i.object,
+        object,
+        #[cfg(test)]
         t: i.t,
-        normal: i.object.normal_at(&p),
+        normal: object.normal_at_face(&p, i.face),
         point: p,
         eye_vec: r.direction.negate(),
         reflect_vec: Tuple::vector_new(0.0, 0.0, 0.0),
@@ -58,6 +264,7 @@ pub fn prepare_computations<'a>(
         under_point: Tuple::vector_new(0.0, 0.0, 0.0),
         n1: 0.0,
         n2: 0.0,
+        footprint: None,
     };
     if out.normal.dot(&out.eye_vec) < 0.0 {
         out.inside = true;
@@ -65,11 +272,14 @@ pub fn prepare_computations<'a>(
     };
     // needs to be done after normal is negated (if it is)
     out.reflect_vec = out.normal.reflect(&r.direction);
-    out.over_point = out.point + (EPSILON * &out.normal);
-    out.under_point = out.point - (EPSILON * &out.normal);
+    out.over_point = out.point + (SURFACE_OFFSET * &out.normal);
+    out.under_point = out.point - (SURFACE_OFFSET * &out.normal);
+    out.footprint = r
+        .differentials
+        .map(|d| d.footprint(&out.point, &out.normal));
 
     // this contains objects that have been entered but not yet exited by the ray
-    let mut objects_ray_is_inside_of: Vec<&Shape> = Vec::new();
+    let mut objects_ray_is_inside_of: Vec<ObjectId> = Vec::new();
     for intersect in intersections.iter() {
         if i == intersect {
             // then set n1 to the refractive index of either air (1.0) or the last
@@ -77,17 +287,18 @@ pub fn prepare_computations<'a>(
             out.n1 = if objects_ray_is_inside_of.is_empty() {
                 1.0
             } else {
-                objects_ray_is_inside_of
-                    .last()
-                    .unwrap()
+                w.object(*objects_ray_is_inside_of.last().unwrap())
                     .material
                     .refractive_index
             }
         }
+        // open shapes are thin shells which can be entered through their ends
+        // without an intersection, so they never count as containing the ray
         match objects_ray_is_inside_of
             .iter()
-            .position(|&obj| std::ptr::eq(intersect.object, obj))
+            .position(|&id| id == intersect.object)
         {
+            _ if w.object(intersect.object).shape.is_open() => {}
             Some(x) => {
                 objects_ray_is_inside_of.remove(x);
             }
@@ -101,9 +312,7 @@ pub fn prepare_computations<'a>(
             out.n2 = if objects_ray_is_inside_of.is_empty() {
                 1.0
             } else {
-                objects_ray_is_inside_of
-                    .last()
-                    .unwrap()
+                w.object(*objects_ray_is_inside_of.last().unwrap())
                     .material
                     .refractive_index
             };
@@ -113,6 +322,18 @@ pub fn prepare_computations<'a>(
     out
 }
 
+fn surface_colour(
+    material: &Material,
+    object: &Shape,
+    posn: &Tuple,
+    footprint: Option<&Footprint>,
+) -> Colour {
+    match &material.pattern {
+        None => material.colour,
+        Some(p) => p.pattern_at_object_filtered(object, posn, footprint),
+    }
+}
+
 pub fn calculate_lighting(
     material: &Material,
     object: &Shape,
@@ -122,11 +343,23 @@ pub fn calculate_lighting(
     normal: &Tuple,
     shadow_data: &ShadowInformation,
 ) -> Colour {
-    let light_vec = (light.position - *posn).normalise();
-    let effective_colour = match &material.pattern {
-        None => material.colour * light.intensity,
-        Some(p) => p.pattern_at_object(object, posn) * light.intensity,
-    };
+    let colour = surface_colour(material, object, posn, None);
+    light_surface(colour, material, light, posn, eye_vec, normal, shadow_data)
+}
+
+// Lights a point on a surface that's already known to be the given colour.
+fn light_surface(
+    colour: Colour,
+    material: &Material,
+    light: &PointLight,
+    posn: &Tuple,
+    eye_vec: &Tuple,
+    normal: &Tuple,
+    shadow_data: &ShadowInformation,
+) -> Colour {
+    let light_vec = light.direction_from(posn);
+    let intensity = light.intensity_towards(posn);
+    let effective_colour = colour * intensity;
     let ambient_term = effective_colour * material.ambient;
     let light_normal_dot = light_vec.dot(normal);
     let diffuse = if light_normal_dot < 0.0 {
@@ -135,7 +368,7 @@ pub fn calculate_lighting(
         effective_colour * material.diffuse * light_normal_dot
     };
 
-    let specular = if light_normal_dot < 0.0 {
+    let specular = if light_normal_dot < 0.0 || !light.specular {
         Colour::new(0.0, 0.0, 0.0)
     } else {
         let reflect_vec = normal.reflect(&light_vec.negate());
@@ -143,37 +376,152 @@ pub fn calculate_lighting(
         if reflect_eye_dot <= 0.0 {
             Colour::new(0.0, 0.0, 0.0)
         } else {
-            light.intensity * material.specular * reflect_eye_dot.powf(material.shininess)
+            intensity * material.specular * reflect_eye_dot.powf(material.shininess)
         }
     };
-    match shadow_data.in_shadow {
-        true => {
-            ambient_term
-                + ((diffuse * shadow_data.shadowing_object_colour)
-                    * shadow_data.shadowing_object_transparency.powi(2))
+    ambient_term + (diffuse + specular) * shadow_data.transmittance
+}
+
+// What a material's shader is given to work out the colour of a point on its
+// surface. Rays can be traced from the point, so shaders can do their own
+// reflections, and standard gives the colour the point would have without
+// the shader, for shaders that only adjust it.
+pub struct ShadeContext<'a> {
+    pub object: &'a Shape,
+    pub material: &'a Material,
+    // the colour of the surface here, from its pattern if it has one
+    pub colour: Colour,
+    // nudged off the surface, so rays can start from it without hitting it
+    pub point: Tuple,
+    pub normal: Tuple,
+    // towards where the ray being shaded came from
+    pub eye: Tuple,
+    world: &'a World,
+    comps: &'a PreComputation<'a>,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: RefCell<&'a mut IntersectionBuffer>,
+}
+
+// The light arriving at a point from one light: where from, and how much of
+// it, after falloff and shadows.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Illumination {
+    pub direction: Tuple,
+    pub colour: Colour,
+}
+
+impl<'a> ShadeContext<'a> {
+    // the lights the object is lit by
+    pub fn lights(&self) -> impl Iterator<Item = &'a PointLight> + 'a {
+        let object = self.object;
+        self.world
+            .lights
+            .iter()
+            .filter(move |l| l.illuminates(object))
+    }
+
+    pub fn illumination(&self, light: &PointLight) -> Illumination {
+        let mut buffer = self.buffer.borrow_mut();
+        let shadow = is_shadowed(self.world, light, &self.point, &mut buffer);
+        Illumination {
+            direction: light.direction_from(&self.point),
+            colour: light.intensity_towards(&self.point) * shadow.transmittance,
         }
-        false => ambient_term + diffuse + specular,
+    }
+
+    // The colour seen along the ray. Like reflections, this counts towards the
+    // recursion depth, and once that's used up it's black.
+    pub fn trace(&self, ray: &Ray) -> Colour {
+        if self.remaining_recursions == 0 {
+            return Colour::black();
+        }
+        let mut buffer = self.buffer.borrow_mut();
+        trace(
+            self.world,
+            ray,
+            self.remaining_recursions - 1,
+            self.contribution,
+            &mut buffer,
+        )
+    }
+
+    pub fn standard(&self) -> Colour {
+        let mut buffer = self.buffer.borrow_mut();
+        standard_shading(
+            self.world,
+            self.comps,
+            self.remaining_recursions,
+            self.contribution,
+            &mut buffer,
+        )
     }
 }
 
-fn shade_hit(w: &World, c: &PreComputation, remaining_recursions: usize) -> Colour {
-    let mut out = Colour::new(0.0, 0.0, 0.0);
-    for light in &w.lights {
+// Reflected and refracted rays that would make up less than this much of the
+// final colour aren't traced, so deep stacks of mirrors and glass stop once
+// further bounces can't be seen. The recursion depth still applies as well.
+const MIN_CONTRIBUTION: Float = 0.001;
+
+// contribution is how much of the final colour this hit makes up, going by
+// the reflectivity and transparency of everything the ray's come through
+fn shade_hit(
+    w: &World,
+    c: &PreComputation,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    let material = &c.object.material;
+    match &material.shader {
+        None => standard_shading(w, c, remaining_recursions, contribution, buffer),
+        Some(shader) => shader.shade(&ShadeContext {
+            object: c.object,
+            material,
+            colour: surface_colour(material, c.object, &c.over_point, c.footprint.as_ref()),
+            point: c.over_point,
+            normal: c.normal,
+            eye: c.eye_vec,
+            world: w,
+            comps: c,
+            remaining_recursions,
+            contribution,
+            buffer: RefCell::new(buffer),
+        }),
+    }
+}
+
+fn standard_shading(
+    w: &World,
+    c: &PreComputation,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    let mut out = c.object.material.emission;
+    let colour = surface_colour(
+        &c.object.material,
+        c.object,
+        &c.over_point,
+        c.footprint.as_ref(),
+    );
+    for light in w.lights.iter().filter(|l| l.illuminates(c.object)) {
         out = out
-            + calculate_lighting(
+            + light_surface(
+                colour,
                 &c.object.material,
-                c.object,
                 light,
                 // helps prevent chessboard acne
                 &c.over_point,
                 &c.eye_vec,
                 &c.normal,
                 // prevent 'acne'
-                &is_shadowed(w, &c.over_point),
+                &is_shadowed(w, light, &c.over_point, buffer),
             );
     }
-    let reflected = reflected_colour(w, c, remaining_recursions);
-    let refracted = refracted_colour(w, c, remaining_recursions);
+    out = out + environment_lighting(w, c, buffer);
+    let reflected = reflected_colour(w, c, remaining_recursions, contribution, buffer);
+    let refracted = refracted_colour(w, c, remaining_recursions, contribution, buffer);
 
     let material = &c.object.material;
     if material.reflectivity > 0.0 && material.transparency > 0.0 {
@@ -185,62 +533,267 @@ fn shade_hit(w: &World, c: &PreComputation, remaining_recursions: usize) -> Colo
 }
 
 pub fn colour_at(w: &World, r: &Ray, remaining_recursions: usize) -> Colour {
-    let inters = r.intersects_world(w);
-    let hit = Intersection::hit(&inters);
-    match hit {
-        Some(h) => {
-            let comps = prepare_computations(h, r, &inters);
-            shade_hit(w, &comps, remaining_recursions)
+    colour_at_with_buffer(w, r, remaining_recursions, &mut IntersectionBuffer::new())
+}
+
+// As colour_at, but collecting intersections in the given buffer, so that
+// rendering can reuse one buffer for every ray a thread traces.
+pub fn colour_at_with_buffer(
+    w: &World,
+    r: &Ray,
+    remaining_recursions: usize,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    trace(w, r, remaining_recursions, 1.0, buffer)
+}
+
+// As colour_at_with_buffer, for a ray whose nearest hit (if any) has already
+// been found - e.g. along with other camera rays, in a packet.
+pub fn colour_of_hit(
+    w: &World,
+    r: &Ray,
+    hit: Option<Intersection>,
+    remaining_recursions: usize,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    shade_nearest(w, r, hit, remaining_recursions, 1.0, buffer)
+}
+
+fn trace(
+    w: &World,
+    r: &Ray,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    let hit = r.nearest_hit(w, buffer);
+    shade_nearest(w, r, hit, remaining_recursions, contribution, buffer)
+}
+
+fn shade_nearest(
+    w: &World,
+    r: &Ray,
+    hit: Option<Intersection>,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    let hit = match hit {
+        Some(h) => h,
+        None => return w.environment_colour(&r.direction),
+    };
+    // refraction needs to know which objects the ray's inside of when it
+    // hits, which takes every intersection along it in order. nothing else
+    // does, so for anything that isn't see-through there's no need for them
+    let comps = if w.object(hit.object).material.transparency > 0.0 {
+        r.intersects_world_into(w, buffer);
+        prepare_computations(w, &hit, r, buffer)
+    } else {
+        prepare_computations(w, &hit, r, &[])
+    };
+    let t = hit.t;
+    // the buffer's free to be used for the rays traced from the hit now
+    let colour = shade_hit(w, &comps, remaining_recursions, contribution, buffer);
+    match &w.fog {
+        Some(fog) => fog.apply(colour, t),
+        None => colour,
+    }
+}
+
+fn is_shadowed(
+    w: &World,
+    light: &PointLight,
+    p: &Tuple,
+    buffer: &mut IntersectionBuffer,
+) -> ShadowInformation {
+    // with shadows turned off, every light reaches everything
+    if !buffer.shadows() {
+        return ShadowInformation::default();
+    }
+    // where in the shadow cache this light's occluder goes, if it's one of
+    // the world's lights
+    let slot = w
+        .shadow_cache
+        .then(|| w.lights.iter().position(|l| std::ptr::eq(l, light)))
+        .flatten();
+    if let LightKind::Directional { direction } = light.kind {
+        // nothing's further away than the light
+        let ray = Ray::new(*p, direction.negate());
+        return ShadowInformation {
+            transmittance: transmittance_along(w, &ray, Float::INFINITY, slot, buffer),
+        };
+    }
+    let transmittance = match &light.area {
+        None => transmittance_between(w, p, &light.position, slot, buffer),
+        Some(area) => {
+            // seeding from the point keeps the noise the same between runs
+            // with the f32 feature the bits are a u32, so this cast is needed
+            #[allow(clippy::unnecessary_cast)]
+            let key = sampling::hash(&[p.x, p.y, p.z].map(|x| x.to_bits() as u64));
+            let samples = w.shadow_sampler.samples(area.usteps, area.vsteps, key);
+            let corner = light.position - area.uvec * 0.5 - area.vvec * 0.5;
+            let total = samples.iter().fold(Colour::black(), |acc, (u, v)| {
+                let light_point = corner + area.uvec * *u + area.vvec * *v;
+                acc + transmittance_between(w, p, &light_point, slot, buffer)
+            });
+            total * (1.0 / samples.len() as Float)
+        }
+    };
+    ShadowInformation { transmittance }
+}
+
+fn transmittance_between(
+    w: &World,
+    p: &Tuple,
+    light_position: &Tuple,
+    slot: Option<usize>,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    let point_to_light = *light_position - *p;
+    let point_to_light_ray = Ray::new(*p, point_to_light);
+    let distance = point_to_light.magnitude();
+    transmittance_along(w, &point_to_light_ray, distance, slot, buffer)
+}
+
+// How much light gets along the ray from its origin up to the given distance.
+// With a slot in the shadow cache, whatever opaque object blocked the last ray
+// towards the light is tried before the rest of the world.
+fn transmittance_along(
+    w: &World,
+    ray: &Ray,
+    distance_to_light: Float,
+    slot: Option<usize>,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    stats::count(Counter::ShadowRays);
+    if let Some(occluder) = slot.and_then(|s| buffer.occluder(s)) {
+        if ray.hits_before(w, occluder, distance_to_light, buffer) {
+            return Colour::black();
         }
-        None => Colour::new(0.0, 0.0, 0.0),
+    }
+    // every surface crossed on the way to the light filters out some of it,
+    // tinted by the colour of the object. the order they're crossed in
+    // doesn't matter, so they're taken as they're found, and once nothing
+    // gets through there's no need to look any further
+    let mut transmittance = Colour::white();
+    let blocked = ray.find_before(w, distance_to_light, buffer, |i| {
+        let material = &w.object(i.object).material;
+        transmittance = transmittance * material.colour * material.transparency;
+        transmittance == Colour::black()
+    });
+    // a ray that wasn't blocked outright means the next one probably won't
+    // be either, so there's nothing worth trying first
+    let occluder = blocked
+        .map(|i| i.object)
+        .filter(|&id| w.object(id).material.transparency == 0.0);
+    if let Some(s) = slot {
+        buffer.set_occluder(s, occluder);
+    }
+    match blocked {
+        Some(_) => Colour::black(),
+        None => transmittance,
     }
 }
 
-fn is_shadowed(w: &World, p: &Tuple) -> ShadowInformation {
-    // need to adjust for multiple lights
-    let point_to_light = w.lights[0].position - *p;
-    let distance_to_light = point_to_light.magnitude();
-    let point_to_light_ray = Ray::new(*p, point_to_light.normalise());
-    let intersections = point_to_light_ray.intersects_world(w);
-    match Intersection::hit(&intersections) {
-        None => ShadowInformation {
-            in_shadow: false,
-            ..Default::default()
-        },
-        Some(h) => ShadowInformation {
-            in_shadow: h.t < distance_to_light,
-            shadowing_object_transparency: h.object.material.transparency,
-            shadowing_object_colour: h.object.material.colour,
-        },
+// Diffuse light arriving from the environment, found by casting rays out over
+// the hemisphere around the normal and averaging the ones that escape. The
+// rays are spread in a fixed golden-angle spiral rather than at random, and
+// are denser towards the normal to account for the angle of incidence.
+fn environment_lighting(w: &World, c: &PreComputation, buffer: &mut IntersectionBuffer) -> Colour {
+    if w.environment.is_none() || w.environment_samples == 0 {
+        return Colour::black();
     }
+    let golden_angle = crate::precision::consts::PI * (3.0 - Float::sqrt(5.0));
+    let helper = if c.normal.x.abs() > 0.9 {
+        Tuple::vector_new(0.0, 1.0, 0.0)
+    } else {
+        Tuple::vector_new(1.0, 0.0, 0.0)
+    };
+    let tangent = helper.cross(&c.normal).normalise();
+    let bitangent = c.normal.cross(&tangent);
+
+    let mut total = Colour::black();
+    for i in 0..w.environment_samples {
+        // a point on the unit disc, projected up onto the hemisphere
+        let radius = ((i as Float + 0.5) / w.environment_samples as Float).sqrt();
+        let angle = i as Float * golden_angle;
+        let height = (1.0 - radius.powi(2)).max(0.0).sqrt();
+        let direction = tangent * (radius * angle.cos())
+            + bitangent * (radius * angle.sin())
+            + c.normal * height;
+        let ray = Ray::new(c.over_point, direction);
+        stats::count(Counter::EnvironmentRays);
+        if !ray.is_blocked(w, Float::INFINITY, buffer) {
+            total = total + w.environment_colour(&direction);
+        }
+    }
+    let material = &c.object.material;
+    surface_colour(material, c.object, &c.point, c.footprint.as_ref())
+        * material.diffuse
+        * total
+        * (1.0 / w.environment_samples as Float)
 }
 
-fn reflected_colour(w: &World, c: &PreComputation, remaining_recursions: usize) -> Colour {
-    if remaining_recursions == 0 || c.object.material.reflectivity == 0.0 {
+fn reflected_colour(
+    w: &World,
+    c: &PreComputation,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
+    let reflectivity = c.object.material.reflectivity;
+    let contribution = contribution * reflectivity;
+    if remaining_recursions == 0 || reflectivity == 0.0 || contribution < MIN_CONTRIBUTION {
         Colour::new(0.0, 0.0, 0.0)
     } else {
         let reflected_ray = Ray::new(c.over_point, c.reflect_vec);
-        let colour = colour_at(w, &reflected_ray, remaining_recursions - 1);
-        colour * c.object.material.reflectivity
+        stats::count(Counter::ReflectionRays);
+        let colour = trace(
+            w,
+            &reflected_ray,
+            remaining_recursions - 1,
+            contribution,
+            buffer,
+        );
+        colour * reflectivity
     }
 }
 
-fn refracted_colour(w: &World, c: &PreComputation, remaining_recursions: usize) -> Colour {
+fn refracted_colour(
+    w: &World,
+    c: &PreComputation,
+    remaining_recursions: usize,
+    contribution: Float,
+    buffer: &mut IntersectionBuffer,
+) -> Colour {
     // check for total internal refraction
     let n_ratio = c.n1 / c.n2;
     let cos_i = c.eye_vec.dot(&c.normal);
     let sin2_t = n_ratio.powi(2) * (1.0 - cos_i.powi(2));
-    if c.object.material.transparency == 0.0 || remaining_recursions == 0 || sin2_t > 1.0 {
+    let transparency = c.object.material.transparency;
+    let contribution = contribution * transparency;
+    if transparency == 0.0
+        || remaining_recursions == 0
+        || sin2_t > 1.0
+        || contribution < MIN_CONTRIBUTION
+    {
         Colour::black()
     } else {
         let cos_t = (1.0 - sin2_t).sqrt();
         let dirn = c.normal * (n_ratio * cos_i - cos_t) - c.eye_vec * n_ratio;
         let refracted_ray = Ray::new(c.under_point, dirn);
-        colour_at(w, &refracted_ray, remaining_recursions - 1) * c.object.material.transparency
+        stats::count(Counter::RefractionRays);
+        trace(
+            w,
+            &refracted_ray,
+            remaining_recursions - 1,
+            contribution,
+            buffer,
+        ) * transparency
     }
 }
 
-fn schlick(c: &PreComputation) -> f64 {
+fn schlick(c: &PreComputation) -> Float {
     let mut cosine = c.eye_vec.dot(&c.normal);
     if c.n1 > c.n2 {
         let n = c.n1 / c.n2;
@@ -259,8 +812,10 @@ fn schlick(c: &PreComputation) -> f64 {
 mod tests {
     use super::*;
     use crate::float_eq;
-    use crate::matrices::Matrix;
-    use crate::shapes::{plane, sphere, Pattern};
+    use crate::matrices::{Matrix, Transform};
+    use crate::sampling::Sampler;
+    use crate::shapes::{plane, sphere, Pattern, PatternFunction};
+    use crate::world::Fog;
 
     #[test]
     fn eye_between_light_and_surface() {
@@ -285,9 +840,83 @@ mod tests {
         assert_eq!(result, Colour::new(1.9, 1.9, 1.9));
     }
 
+    #[test]
+    fn light_projects_its_pattern() {
+        let s = Shape::default();
+        let m = Material::default();
+        let eye_vec = Tuple::vector_new(0.0, 0.0, -1.0);
+        let normal_vec = Tuple::vector_new(0.0, 0.0, -1.0);
+        let plain = PointLight::new(Colour::white(), Tuple::point_new(0.0, 0.0, -10.0));
+        let gobo = PointLight::new(Colour::white(), Tuple::point_new(0.0, 0.0, -10.0))
+            .with_pattern(Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            });
+        let light_at = |light: &PointLight, x: Float| {
+            calculate_lighting(
+                &m,
+                &s,
+                light,
+                &Tuple::point_new(x, 0.0, 0.0),
+                &eye_vec,
+                &normal_vec,
+                &ShadowInformation::default(),
+            )
+        };
+        assert_eq!(light_at(&gobo, 0.5), light_at(&plain, 0.5));
+        assert_eq!(light_at(&gobo, -0.5), Colour::black());
+    }
+
+    #[test]
+    fn light_fades_with_distance() {
+        let m = Material {
+            ambient: 1.0,
+            diffuse: 0.0,
+            specular: 0.0,
+            ..Default::default()
+        };
+        let lit_from = |z: Float, attenuation| {
+            let light = PointLight::new(Colour::white(), Tuple::point_new(0.0, 0.0, z))
+                .with_attenuation(attenuation);
+            calculate_lighting(
+                &m,
+                &Shape::default(),
+                &light,
+                &Tuple::point_new(0.0, 0.0, 0.0),
+                &Tuple::vector_new(0.0, 0.0, -1.0),
+                &Tuple::vector_new(0.0, 0.0, -1.0),
+                &ShadowInformation::default(),
+            )
+        };
+        // no falloff by default
+        assert_eq!(
+            lit_from(-1000.0, Attenuation::default()),
+            lit_from(-1.0, Attenuation::default())
+        );
+        let inverse_square = Attenuation {
+            constant: 0.0,
+            linear: 0.0,
+            quadratic: 1.0,
+        };
+        assert_eq!(
+            lit_from(-2.0, inverse_square),
+            Colour::new(0.25, 0.25, 0.25)
+        );
+        let mixed = Attenuation {
+            constant: 1.0,
+            linear: 0.5,
+            quadratic: 0.25,
+        };
+        assert_eq!(
+            lit_from(-2.0, mixed),
+            Colour::new(1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0)
+        );
+    }
+
     #[test]
     fn eye_between_light_and_surface_eye_offset_45deg() {
-        use std::f64::consts::FRAC_1_SQRT_2;
+        use crate::precision::consts::FRAC_1_SQRT_2;
         let s = Shape::default();
         let m = Material::default();
         let posn = Tuple::point_new(0.0, 0.0, 0.0);
@@ -334,7 +963,7 @@ mod tests {
 
     #[test]
     fn eye_in_path_of_reflection_vector() {
-        use std::f64::consts::FRAC_1_SQRT_2;
+        use crate::precision::consts::FRAC_1_SQRT_2;
         let s = Shape::default();
         let m = Material::default();
         let posn = Tuple::point_new(0.0, 0.0, 0.0);
@@ -356,6 +985,31 @@ mod tests {
         assert_eq!(result, Colour::new(1.6364, 1.6364, 1.6364));
     }
 
+    #[test]
+    fn lights_can_skip_the_specular_term() {
+        use crate::precision::consts::FRAC_1_SQRT_2;
+        let s = Shape::default();
+        let m = Material::default();
+        let posn = Tuple::point_new(0.0, 0.0, 0.0);
+        let eye_vec = Tuple::vector_new(0.0, -FRAC_1_SQRT_2, -FRAC_1_SQRT_2);
+        let normal_vec = Tuple::vector_new(0.0, 0.0, -1.0);
+        let light = PointLight::new(
+            Colour::new(1.0, 1.0, 1.0),
+            Tuple::point_new(0.0, 10.0, -10.0),
+        )
+        .with_specular(false);
+        let result = calculate_lighting(
+            &m,
+            &s,
+            &light,
+            &posn,
+            &eye_vec,
+            &normal_vec,
+            &ShadowInformation::default(),
+        );
+        assert_eq!(result, Colour::new(0.7364, 0.7364, 0.7364));
+    }
+
     #[test]
     fn lighting_with_light_behind_surface() {
         let s = Shape::default();
@@ -382,11 +1036,12 @@ mod tests {
             Tuple::point_new(0.0, 0.0, -5.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let s = sphere::default();
-        let i = Intersection::new(4.0, &s);
-        let comps = prepare_computations(&i, &r, &[i]);
+        let mut w = World::new();
+        let s = w.add_object(sphere::default());
+        let i = Intersection::new(4.0, s);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
         assert_eq!(comps.t, i.t);
-        assert_eq!(comps.object, i.object);
+        assert!(std::ptr::eq(comps.object, w.object(i.object)));
         assert_eq!(comps.eye_vec, Tuple::vector_new(0.0, 0.0, -1.0));
         assert_eq!(comps.normal, Tuple::vector_new(0.0, 0.0, -1.0));
         assert_eq!(comps.point, Tuple::point_new(0.0, 0.0, -1.0));
@@ -398,9 +1053,10 @@ mod tests {
             Tuple::point_new(0.0, 0.0, -5.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let s = sphere::default();
-        let i = Intersection::new(4.0, &s);
-        let comps = prepare_computations(&i, &r, &[i]);
+        let mut w = World::new();
+        let s = w.add_object(sphere::default());
+        let i = Intersection::new(4.0, s);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
         assert!(!comps.inside);
     }
 
@@ -410,9 +1066,10 @@ mod tests {
             Tuple::point_new(0.0, 0.0, 0.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let s = sphere::default();
-        let i = Intersection::new(4.0, &s);
-        let comps = prepare_computations(&i, &r, &[i]);
+        let mut w = World::new();
+        let s = w.add_object(sphere::default());
+        let i = Intersection::new(4.0, s);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
         assert!(comps.inside);
     }
 
@@ -423,10 +1080,10 @@ mod tests {
             Tuple::point_new(0.0, 0.0, -5.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let s = &w.objects[0];
+        let s = ObjectId(0);
         let i = Intersection::new(4.0, s);
-        let comp = prepare_computations(&i, &r, &[i]);
-        let c = shade_hit(&w, &comp, 5);
+        let comp = prepare_computations(&w, &i, &r, &[i]);
+        let c = shade_hit(&w, &comp, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(c, Colour::new(0.38066, 0.47583, 0.2855));
     }
 
@@ -438,10 +1095,10 @@ mod tests {
             Tuple::point_new(0.0, 0.0, 0.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let s = &w.objects[1];
+        let s = ObjectId(1);
         let i = Intersection::new(0.5, s);
-        let comp = prepare_computations(&i, &r, &[i]);
-        let c = shade_hit(&w, &comp, 5);
+        let comp = prepare_computations(&w, &i, &r, &[i]);
+        let c = shade_hit(&w, &comp, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(c, Colour::new(0.90498, 0.90498, 0.90498));
     }
 
@@ -456,6 +1113,156 @@ mod tests {
         assert_eq!(c, Colour::new(0.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn shaders_replace_the_usual_lighting() {
+        use crate::shapes::Shader;
+        let mut w = World {
+            background: Colour::new(0.1, 0.2, 0.3),
+            ..World::default()
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        let plain = colour_at(&w, &r, 5);
+        w.objects[0].material.shader = Some(Shader::new(|ctx| ctx.standard() * 0.5));
+        assert_eq!(colour_at(&w, &r, 5), plain * 0.5);
+        // toon shading - the full colour where lit, only ambient elsewhere
+        w.objects[0].material.shader = Some(Shader::new(|ctx| {
+            let lit = ctx.lights().any(|l| {
+                let light = ctx.illumination(l);
+                light.direction.dot(&ctx.normal) > 0.5 && light.colour != Colour::black()
+            });
+            match lit {
+                true => ctx.colour,
+                false => ctx.colour * ctx.material.ambient,
+            }
+        }));
+        assert_eq!(colour_at(&w, &r, 5), Colour::new(0.8, 1.0, 0.6));
+        // rays traced back towards the eye miss everything, and see the
+        // background - until the recursion depth runs out
+        w.objects[0].material.shader =
+            Some(Shader::new(|ctx| ctx.trace(&Ray::new(ctx.point, ctx.eye))));
+        assert_eq!(colour_at(&w, &r, 5), w.background);
+        assert_eq!(colour_at(&w, &r, 0), Colour::black());
+    }
+
+    #[test]
+    fn emissive_objects_glow_without_light() {
+        let mut w = World::new();
+        w.objects.push(Shape {
+            material: Material {
+                emission: Colour::new(1.0, 0.5, 0.0),
+                ..Default::default()
+            },
+            ..sphere::default()
+        });
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        assert_eq!(colour_at(&w, &r, 5), Colour::new(1.0, 0.5, 0.0));
+        w.lights.push(PointLight::new(
+            Colour::white(),
+            Tuple::point_new(0.0, 0.0, -10.0),
+        ));
+        assert_eq!(colour_at(&w, &r, 5), Colour::new(2.9, 2.4, 1.9));
+    }
+
+    #[test]
+    fn ray_miss_sees_background_colour() {
+        let w = World {
+            background: Colour::new(0.2, 0.4, 0.8),
+            ..World::default()
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(colour_at(&w, &r, 5), Colour::new(0.2, 0.4, 0.8));
+    }
+
+    #[test]
+    fn ray_miss_sees_environment() {
+        let w = World {
+            environment: Some(Pattern::Test {
+                transform: Transform::default(),
+            }),
+            ..World::default()
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 2.0, 0.0),
+        );
+        let c = colour_at(&w, &r, 5);
+        assert_eq!(c, Colour::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn mirror_reflects_environment() {
+        use crate::precision::consts::FRAC_1_SQRT_2;
+        let mut w = World::new();
+        w.environment = Some(Pattern::Test {
+            transform: Transform::default(),
+        });
+        w.objects.push(Shape {
+            material: Material {
+                ambient: 0.0,
+                diffuse: 0.0,
+                specular: 0.0,
+                reflectivity: 1.0,
+                ..Default::default()
+            },
+            ..plane::default()
+        });
+        let r = Ray::new(
+            Tuple::point_new(0.0, 1.0, -1.0),
+            Tuple::vector_new(0.0, -FRAC_1_SQRT_2, FRAC_1_SQRT_2),
+        );
+        let c = colour_at(&w, &r, 5);
+        assert_eq!(c, Colour::new(0.0, FRAC_1_SQRT_2, FRAC_1_SQRT_2));
+    }
+
+    #[test]
+    fn environment_lights_surfaces_when_sampled() {
+        let sky = || Pattern::Function {
+            function: PatternFunction::new(|_| Colour::new(0.5, 0.5, 0.5)),
+            transform: Transform::default(),
+        };
+        let floor = Shape {
+            material: Material {
+                colour: Colour::new(1.0, 0.8, 0.6),
+                ambient: 0.0,
+                diffuse: 0.5,
+                specular: 0.0,
+                ..Default::default()
+            },
+            ..plane::default()
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 1.0, -1.0),
+            Tuple::vector_new(0.0, -1.0, 1.0).normalise(),
+        );
+        let mut w = World::new();
+        w.objects.push(floor);
+        w.environment = Some(sky());
+        assert_eq!(colour_at(&w, &r, 5), Colour::black());
+
+        // with nothing in the way every sample sees the same sky, so there's
+        // no noise in the result
+        w.environment_samples = 16;
+        assert_eq!(colour_at(&w, &r, 5), Colour::new(0.25, 0.2, 0.15));
+
+        // anything above the floor blocks some of the sky
+        w.objects.push(Shape {
+            transform: Matrix::translation(0.0, 1.5, 0.0).into(),
+            ..sphere::default()
+        });
+        let c = colour_at(&w, &r, 5);
+        assert_ne!(c, Colour::new(0.25, 0.2, 0.15));
+        assert_ne!(c, Colour::black());
+    }
+
     #[test]
     fn ray_hit_colour() {
         let w = World::default();
@@ -467,6 +1274,31 @@ mod tests {
         assert_eq!(c, Colour::new(0.38066, 0.47583, 0.2855));
     }
 
+    #[test]
+    fn fog_fades_hits_by_distance() {
+        let w = World {
+            fog: Some(Fog::Linear {
+                colour: Colour::white(),
+                start: 2.0,
+                end: 6.0,
+            }),
+            ..World::default()
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        // the hit is 4 units away - halfway into the fog
+        let c = colour_at(&w, &r, 5);
+        assert_eq!(c, Colour::new(0.69033, 0.73792, 0.64275));
+        // the background isn't fogged
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(colour_at(&w, &r, 5), Colour::black());
+    }
+
     #[test]
     fn colour_intersection_behind_ray() {
         let mut w = World::default();
@@ -502,8 +1334,7 @@ mod tests {
             &eye_vec,
             &normal_vec,
             &ShadowInformation {
-                in_shadow: true,
-                ..Default::default()
+                transmittance: Colour::black(),
             },
         );
         assert_eq!(result, Colour::new(0.1, 0.1, 0.1));
@@ -513,40 +1344,286 @@ mod tests {
     fn no_shadow_when_nothing_between_point_and_light() {
         let w = World::default();
         let p = Tuple::point_new(0.0, 10.0, 0.0);
-        assert!(!is_shadowed(&w, &p).in_shadow);
+        assert!(!is_shadowed(&w, &w.lights[0], &p, &mut IntersectionBuffer::new()).in_shadow());
     }
 
     #[test]
     fn shadow_when_object_between_point_and_light() {
         let w = World::default();
         let p = Tuple::point_new(10.0, -10.0, 10.0);
-        assert!(is_shadowed(&w, &p).in_shadow);
+        assert!(is_shadowed(&w, &w.lights[0], &p, &mut IntersectionBuffer::new()).in_shadow());
+    }
+
+    #[test]
+    fn shadow_rays_remember_what_blocked_them() {
+        let w = World::default();
+        let mut buffer = IntersectionBuffer::new();
+        let p = Tuple::point_new(10.0, -10.0, 10.0);
+        assert!(is_shadowed(&w, &w.lights[0], &p, &mut buffer).in_shadow());
+        assert!(buffer.occluder(0).is_some());
+        // it's tried first, but doesn't put a point that's lit in shadow
+        let lit = Tuple::point_new(-2.0, 2.0, -2.0);
+        assert!(!is_shadowed(&w, &w.lights[0], &lit, &mut buffer).in_shadow());
+        // after which it's forgotten
+        assert!(buffer.occluder(0).is_none());
+        assert!(is_shadowed(&w, &w.lights[0], &p, &mut buffer).in_shadow());
+        // nothing's remembered with the cache turned off
+        let w = World {
+            shadow_cache: false,
+            ..World::default()
+        };
+        let mut buffer = IntersectionBuffer::new();
+        assert!(is_shadowed(&w, &w.lights[0], &p, &mut buffer).in_shadow());
+        assert!(buffer.occluder(0).is_none());
+    }
+
+    #[test]
+    fn spot_lights_shine_in_a_cone() {
+        let m = Material {
+            ambient: 1.0,
+            diffuse: 0.0,
+            specular: 0.0,
+            ..Default::default()
+        };
+        // pointing straight down from above the origin
+        let light = PointLight::new(Colour::white(), Tuple::point_new(0.0, 1.0, 0.0)).with_kind(
+            LightKind::Spot {
+                direction: Tuple::vector_new(0.0, -1.0, 0.0),
+                inner_angle: crate::precision::consts::PI / 8.0,
+                outer_angle: crate::precision::consts::PI / 4.0,
+            },
+        );
+        // x is the tangent of the angle away from the centre of the cone
+        let lit_at = |x: Float| {
+            calculate_lighting(
+                &m,
+                &Shape::default(),
+                &light,
+                &Tuple::point_new(x, 0.0, 0.0),
+                &Tuple::vector_new(0.0, 1.0, 0.0),
+                &Tuple::vector_new(0.0, 1.0, 0.0),
+                &ShadowInformation::default(),
+            )
+        };
+        assert_eq!(lit_at(0.0), Colour::white());
+        assert_eq!(lit_at(0.3), Colour::white());
+        let halfway = (3.0 * crate::precision::consts::PI / 16.0).tan();
+        assert_eq!(lit_at(halfway), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(lit_at(1.5), Colour::black());
+    }
+
+    #[test]
+    fn directional_lights_come_from_one_direction() {
+        let m = Material {
+            ambient: 0.0,
+            diffuse: 1.0,
+            specular: 0.0,
+            ..Default::default()
+        };
+        // its position doesn't matter
+        let light = PointLight::new(Colour::white(), Tuple::point_new(0.0, -100.0, 0.0))
+            .with_attenuation(Attenuation {
+                constant: 0.0,
+                linear: 0.0,
+                quadratic: 1.0,
+            })
+            .with_kind(LightKind::Directional {
+                direction: Tuple::vector_new(0.0, -2.0, 0.0),
+            });
+        let lit_at = |y: Float| {
+            calculate_lighting(
+                &m,
+                &Shape::default(),
+                &light,
+                &Tuple::point_new(0.0, y, 0.0),
+                &Tuple::vector_new(0.0, 1.0, 0.0),
+                &Tuple::vector_new(0.0, 1.0, 0.0),
+                &ShadowInformation::default(),
+            )
+        };
+        assert_eq!(lit_at(0.0), Colour::white());
+        assert_eq!(lit_at(1000.0), Colour::white());
+
+        // the default world's spheres are at the origin, so shadow below it
+        let w = World::default();
+        let p = Tuple::point_new(0.0, -10.0, 0.0);
+        assert!(is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new()).in_shadow());
+        let p = Tuple::point_new(5.0, -10.0, 0.0);
+        assert!(!is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new()).in_shadow());
     }
 
     #[test]
     fn no_shadow_when_object_behind_light() {
         let w = World::default();
         let p = Tuple::point_new(-20.0, 20.0, -20.0);
-        assert!(!is_shadowed(&w, &p).in_shadow);
+        assert!(!is_shadowed(&w, &w.lights[0], &p, &mut IntersectionBuffer::new()).in_shadow());
     }
 
     #[test]
     fn no_shadow_when_object_behind_point() {
         let w = World::default();
         let p = Tuple::point_new(-20.0, 20.0, -20.0);
-        assert!(!is_shadowed(&w, &p).in_shadow);
+        assert!(!is_shadowed(&w, &w.lights[0], &p, &mut IntersectionBuffer::new()).in_shadow());
+    }
+
+    #[test]
+    fn transparent_objects_cast_tinted_shadows() {
+        let light = PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0));
+        let world_with_ball = |material| World {
+            objects: vec![Shape {
+                material,
+                transform: Matrix::translation(0.0, 5.0, 0.0).into(),
+                ..sphere::default()
+            }],
+            ..World::new()
+        };
+        let p = Tuple::point_new(0.0, 0.0, 0.0);
+
+        let w = world_with_ball(Material::default());
+        assert_eq!(
+            is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new()).transmittance,
+            Colour::black()
+        );
+
+        let w = world_with_ball(sphere::glass_sphere().material);
+        let shadow = is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new());
+        assert!(!shadow.in_shadow());
+
+        // the light passes through two surfaces of the ball
+        let w = world_with_ball(Material {
+            colour: Colour::new(1.0, 0.5, 0.5),
+            transparency: 0.8,
+            ..Default::default()
+        });
+        let shadow = is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new());
+        assert!(shadow.in_shadow());
+        assert_eq!(shadow.transmittance, Colour::new(0.64, 0.16, 0.16));
+    }
+
+    #[test]
+    fn area_lights_cast_soft_shadows() {
+        let light = PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0)).with_area(
+            LightArea {
+                uvec: Tuple::vector_new(4.0, 0.0, 0.0),
+                usteps: 4,
+                vvec: Tuple::vector_new(0.0, 0.0, 4.0),
+                vsteps: 4,
+            },
+        );
+        // a wall blocking everything on the -x side of the light
+        let mut w = World::new();
+        w.objects.push(Shape {
+            transform: Matrix::scaling(5.0, 0.1, 5.0)
+                .translate(-5.0, 5.0, 0.0)
+                .into(),
+            ..crate::shapes::cube::default()
+        });
+        w.shadow_sampler = Sampler::Grid;
+        let p = Tuple::point_new(0.0, 0.0, 0.0);
+        assert_eq!(
+            is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new()).transmittance,
+            Colour::new(0.5, 0.5, 0.5)
+        );
+        assert!(!is_shadowed(
+            &w,
+            &light,
+            &Tuple::point_new(9.0, 0.0, 0.0),
+            &mut IntersectionBuffer::new()
+        )
+        .in_shadow());
+
+        // jittered samples are noisy, but the same every time
+        w.shadow_sampler = Sampler::BlueNoise { seed: 4 };
+        let t1 = is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new()).transmittance;
+        let t2 = is_shadowed(&w, &light, &p, &mut IntersectionBuffer::new()).transmittance;
+        assert_eq!(t1, t2);
+        assert!(t1 != Colour::white() && t1 != Colour::black());
+    }
+
+    #[test]
+    fn shapes_are_only_lit_by_linked_lights() {
+        let key = PointLight::new(Colour::white(), Tuple::point_new(-10.0, 10.0, -10.0))
+            .with_group("key");
+        let fill = PointLight::new(
+            Colour::new(0.5, 0.5, 0.5),
+            Tuple::point_new(-10.0, 10.0, -10.0),
+        )
+        .with_group("fill");
+        let plain = PointLight::new(Colour::white(), Tuple::point_new(-10.0, 10.0, -10.0));
+        let s = Shape {
+            light_links: Some(vec!["fill".to_string()]),
+            ..sphere::default()
+        };
+        assert!(!key.illuminates(&s));
+        assert!(fill.illuminates(&s));
+        assert!(!plain.illuminates(&s));
+        assert!(key.illuminates(&sphere::default()));
+
+        // the key light makes no difference to the linked sphere
+        let world_with = |lights: Vec<PointLight>| {
+            let mut w = World::new();
+            w.objects.push(Shape {
+                light_links: Some(vec!["fill".to_string()]),
+                ..sphere::default()
+            });
+            w.lights = lights;
+            w
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        assert_eq!(
+            colour_at(&world_with(vec![key, fill.clone()]), &r, 5),
+            colour_at(&world_with(vec![fill]), &r, 5)
+        );
+    }
+
+    #[test]
+    fn shadows_are_tested_against_each_light() {
+        let light_above = PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0));
+        let light_beside = PointLight::new(Colour::white(), Tuple::point_new(10.0, 10.0, 0.0));
+        let world_with_lights = |lights| World {
+            objects: vec![
+                plane::default(),
+                Shape {
+                    transform: Matrix::translation(0.0, 2.0, 0.0).into(),
+                    ..sphere::default()
+                },
+            ],
+            lights,
+            ..World::new()
+        };
+        let p = Tuple::point_new(0.0, 0.001, 0.0);
+        let w = world_with_lights(vec![light_above, light_beside]);
+        assert!(is_shadowed(&w, &w.lights[0], &p, &mut IntersectionBuffer::new()).in_shadow());
+        assert!(!is_shadowed(&w, &w.lights[1], &p, &mut IntersectionBuffer::new()).in_shadow());
+
+        // the order of the lights shouldn't matter
+        let r = Ray::new(
+            Tuple::point_new(0.0, 1.0, -5.0),
+            Tuple::vector_new(0.0, -1.0, 5.0).normalise(),
+        );
+        let c1 = colour_at(&w, &r, 5);
+        let reversed = world_with_lights(vec![
+            PointLight::new(Colour::white(), Tuple::point_new(10.0, 10.0, 0.0)),
+            PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0)),
+        ]);
+        let c2 = colour_at(&reversed, &r, 5);
+        assert_eq!(c1, c2);
     }
 
     #[test]
     fn precomputing_reflection_vector() {
-        use std::f64::consts::SQRT_2;
-        let pln = plane::default();
+        use crate::precision::consts::SQRT_2;
+        let mut w = World::new();
+        let pln = w.add_object(plane::default());
         let r = Ray::new(
             Tuple::point_new(0.0, 1.0, -1.0),
             Tuple::vector_new(0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0),
         );
-        let i = Intersection::new(SQRT_2, &pln);
-        let comps = prepare_computations(&i, &r, &[i]);
+        let i = Intersection::new(SQRT_2, pln);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
         assert_eq!(
             comps.reflect_vec,
             Tuple::vector_new(0.0, SQRT_2 / 2.0, SQRT_2 / 2.0)
@@ -560,23 +1637,23 @@ mod tests {
             Tuple::point_new(0.0, 0.0, 0.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let s = &w.objects[1];
+        let s = ObjectId(1);
         let i = Intersection::new(1.0, s);
-        let comps = prepare_computations(&i, &r, &[i]);
-        let colour = reflected_colour(&w, &comps, 5);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
+        let colour = reflected_colour(&w, &comps, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(colour, Colour::new(0.0, 0.0, 0.0));
     }
 
     #[test]
     fn reflected_colour_for_reflective_material() {
-        use std::f64::consts::SQRT_2;
+        use crate::precision::consts::SQRT_2;
         let mut w = World::default();
         let pln = Shape {
             material: Material {
                 reflectivity: 0.5,
                 ..Default::default()
             },
-            transform: Matrix::translation(00.0, -1.0, 0.0),
+            transform: Matrix::translation(00.0, -1.0, 0.0).into(),
             ..plane::default()
         };
         w.objects.push(pln);
@@ -584,34 +1661,37 @@ mod tests {
             Tuple::point_new(0.0, 0.0, -3.0),
             Tuple::vector_new(0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0),
         );
-        let s = &w.objects[2];
+        let s = ObjectId(2);
         let i = Intersection::new(SQRT_2, s);
-        let comps = prepare_computations(&i, &r, &[i]);
-        let colour = reflected_colour(&w, &comps, 5);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
+        let colour = reflected_colour(&w, &comps, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(colour, Colour::new(0.19033, 0.23791, 0.14275));
+        // seen through enough other mirrors, the reflection's too faint to matter
+        let faint = reflected_colour(&w, &comps, 5, 0.001, &mut IntersectionBuffer::new());
+        assert_eq!(faint, Colour::black());
     }
 
     #[test]
     fn shade_hit_with_reflective_material() {
-        use std::f64::consts::SQRT_2;
+        use crate::precision::consts::SQRT_2;
         let mut w = World::default();
         let pln = Shape {
             material: Material {
                 reflectivity: 0.5,
                 ..Default::default()
             },
-            transform: Matrix::translation(0.0, -1.0, 0.0),
+            transform: Matrix::translation(0.0, -1.0, 0.0).into(),
             ..plane::default()
         };
         w.objects.push(pln);
-        let s = &w.objects[2];
+        let s = ObjectId(2);
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, -3.0),
             Tuple::vector_new(0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0),
         );
         let i = Intersection::new(SQRT_2, s);
-        let comps = prepare_computations(&i, &r, &[i]);
-        let colour = shade_hit(&w, &comps, 5);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
+        let colour = shade_hit(&w, &comps, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(colour, Colour::new(0.876756, 0.924338, 0.829173));
     }
 
@@ -625,7 +1705,7 @@ mod tests {
                 reflectivity: 1.0,
                 ..Default::default()
             },
-            transform: Matrix::translation(0.0, -1.0, 0.0),
+            transform: Matrix::translation(0.0, -1.0, 0.0).into(),
             ..plane::default()
         });
         w.objects.push(Shape {
@@ -633,7 +1713,7 @@ mod tests {
                 reflectivity: 1.0,
                 ..Default::default()
             },
-            transform: Matrix::translation(0.0, 1.0, 0.0),
+            transform: Matrix::translation(0.0, 1.0, 0.0).into(),
             ..plane::default()
         });
         let r = Ray::new(
@@ -646,25 +1726,25 @@ mod tests {
 
     #[test]
     fn reflected_colour_at_max_recursion() {
-        use std::f64::consts::SQRT_2;
+        use crate::precision::consts::SQRT_2;
         let mut w = World::default();
         let pln = Shape {
             material: Material {
                 reflectivity: 0.5,
                 ..Default::default()
             },
-            transform: Matrix::translation(0.0, -1.0, 0.0),
+            transform: Matrix::translation(0.0, -1.0, 0.0).into(),
             ..plane::default()
         };
         w.objects.push(pln);
-        let s = &w.objects[2];
+        let s = ObjectId(2);
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, -3.0),
             Tuple::vector_new(0.0, -SQRT_2 / 2.0, SQRT_2 / 2.0),
         );
         let i = Intersection::new(SQRT_2, s);
-        let comps = prepare_computations(&i, &r, &[i]);
-        let colour = reflected_colour(&w, &comps, 0);
+        let comps = prepare_computations(&w, &i, &r, &[i]);
+        let colour = reflected_colour(&w, &comps, 0, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(colour, Colour::new(0.0, 0.0, 0.0));
     }
 
@@ -673,27 +1753,29 @@ mod tests {
         let mut a = sphere::glass_sphere();
         let mut b = sphere::glass_sphere();
         let mut c = sphere::glass_sphere();
-        a.transform = Matrix::scaling(2.0, 2.0, 2.0);
-        b.transform = Matrix::translation(0.0, 0.0, -0.25);
-        c.transform = Matrix::translation(0.0, 0.0, 0.25);
+        a.set_transform(Matrix::scaling(2.0, 2.0, 2.0));
+        b.set_transform(Matrix::translation(0.0, 0.0, -0.25));
+        c.set_transform(Matrix::translation(0.0, 0.0, 0.25));
         a.material.refractive_index = 1.5;
         b.material.refractive_index = 2.0;
         c.material.refractive_index = 2.5;
+        let mut w = World::new();
+        let (a, b, c) = (w.add_object(a), w.add_object(b), w.add_object(c));
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, -4.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let intersections = vec![
-            Intersection::new(2.0, &a),
-            Intersection::new(2.75, &b),
-            Intersection::new(3.25, &c),
-            Intersection::new(4.75, &b),
-            Intersection::new(5.25, &c),
-            Intersection::new(6.0, &a),
+            Intersection::new(2.0, a),
+            Intersection::new(2.75, b),
+            Intersection::new(3.25, c),
+            Intersection::new(4.75, b),
+            Intersection::new(5.25, c),
+            Intersection::new(6.0, a),
         ];
-        let refractive_index_vals = vec![1.0, 1.5, 2.0, 2.5, 2.5, 1.5, 1.0];
+        let refractive_index_vals = [1.0, 1.5, 2.0, 2.5, 2.5, 1.5, 1.0];
         for (index, intersection) in intersections.iter().enumerate() {
-            let comps = prepare_computations(intersection, &r, &intersections);
+            let comps = prepare_computations(&w, intersection, &r, &intersections);
             let failstring = format![
                 "\n\nFailed on {}, returning n1: {} and n2: {} rather than {} and {}\n",
                 index,
@@ -715,28 +1797,90 @@ mod tests {
         }
     }
 
+    #[test]
+    fn normal_seen_from_inside_open_cylinder() {
+        use crate::shapes::cylinder;
+        let mut w = World::new();
+        let cyl = w.add_object(cylinder::truncated(-1.0, 1.0, false));
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, 0.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        let xs = w.object(cyl).intersects(cyl, &r);
+        let comps = prepare_computations(&w, &xs[1], &r, &xs);
+        assert!(comps.inside);
+        assert_eq!(comps.normal, Tuple::vector_new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn ray_through_open_end_does_not_enter_cylinder() {
+        use crate::shapes::cylinder;
+        let mut cyl = cylinder::truncated(-1.0, 1.0, false);
+        cyl.material.transparency = 1.0;
+        cyl.material.refractive_index = 1.5;
+        let mut w = World::new();
+        let cyl = w.add_object(cyl);
+        let r = Ray::new(
+            Tuple::point_new(0.0, -2.0, 0.0),
+            Tuple::vector_new(0.0, 1.0, 0.5),
+        );
+        let xs = w.object(cyl).intersects(cyl, &r);
+        assert_eq!(xs.len(), 1);
+        let comps = prepare_computations(&w, &xs[0], &r, &xs);
+        assert!(comps.inside);
+        assert!(float_eq(comps.n1, 1.0));
+        assert!(float_eq(comps.n2, 1.0));
+    }
+
+    #[test]
+    fn open_cylinder_nested_in_glass_sphere() {
+        use crate::shapes::cylinder;
+        let mut w = World::new();
+        w.objects.push(Shape {
+            transform: Matrix::scaling(3.0, 3.0, 3.0).into(),
+            ..sphere::glass_sphere()
+        });
+        let mut tube = cylinder::truncated(-1.0, 1.0, false);
+        tube.material.transparency = 1.0;
+        tube.material.refractive_index = 2.0;
+        w.objects.push(tube);
+        // enters the tube through its bottom end, leaves through the wall
+        let r = Ray::new(
+            Tuple::point_new(0.0, -2.0, 0.0),
+            Tuple::vector_new(0.0, 1.0, 0.5),
+        );
+        let xs = r.intersects_world(&w);
+        assert_eq!(xs.len(), 3);
+        let expected = [(1.0, 1.5), (1.5, 1.5), (1.5, 1.0)];
+        for (i, (n1, n2)) in expected.iter().enumerate() {
+            let comps = prepare_computations(&w, &xs[i], &r, &xs);
+            assert!(float_eq(comps.n1, *n1), "n1 wrong at intersection {}", i);
+            assert!(float_eq(comps.n2, *n2), "n2 wrong at intersection {}", i);
+        }
+    }
+
     #[test]
     fn refracted_colour_opaque_surface() {
         let w = World::default();
-        let shape = &w.objects[1];
+        let shape = ObjectId(1);
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, -5.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let intersections = vec![Intersection::new(4.0, shape), Intersection::new(6.0, shape)];
-        let comps = prepare_computations(&intersections[0], &r, &intersections);
-        let c = refracted_colour(&w, &comps, 5);
+        let comps = prepare_computations(&w, &intersections[0], &r, &intersections);
+        let c = refracted_colour(&w, &comps, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(c, Colour::black());
     }
 
     #[test]
     fn refracted_colour_when_total_internal_reflection() {
-        use std::f64::consts::SQRT_2;
+        use crate::precision::consts::SQRT_2;
         let mut w = World::default();
         let shape = &mut w.objects[1];
         shape.material.transparency = 1.0;
         shape.material.refractive_index = 1.5;
-        let shape: &Shape = &w.objects[1];
+        let shape = ObjectId(1);
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, SQRT_2 / 2.0),
             Tuple::vector_new(0.0, 1.0, 0.0),
@@ -745,8 +1889,8 @@ mod tests {
             Intersection::new(-SQRT_2 / 2.0, shape),
             Intersection::new(SQRT_2 / 2.0, shape),
         ];
-        let comps = prepare_computations(&intersections[1], &r, &intersections);
-        let c = refracted_colour(&w, &comps, 5);
+        let comps = prepare_computations(&w, &intersections[1], &r, &intersections);
+        let c = refracted_colour(&w, &comps, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(c, Colour::black());
     }
 
@@ -755,12 +1899,12 @@ mod tests {
         let mut w = World::default();
         w.objects[0].material.ambient = 1.0;
         w.objects[0].material.pattern = Some(Pattern::Test {
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         });
         w.objects[1].material.transparency = 1.0;
         w.objects[1].material.refractive_index = 1.5;
-        let a = &w.objects[0];
-        let b = &w.objects[1];
+        let a = ObjectId(0);
+        let b = ObjectId(1);
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, 0.1),
             Tuple::vector_new(0.0, 1.0, 0.0),
@@ -771,53 +1915,56 @@ mod tests {
             Intersection::new(0.4899, b),
             Intersection::new(0.9899, a),
         ];
-        let comps = prepare_computations(&intersections[2], &r, &intersections);
-        let col = refracted_colour(&w, &comps, 5);
+        let comps = prepare_computations(&w, &intersections[2], &r, &intersections);
+        let col = refracted_colour(&w, &comps, 5, 1.0, &mut IntersectionBuffer::new());
         assert_eq!(col, Colour::new(0.0, 0.99888, 0.04722));
     }
 
     #[test]
     fn shlick_approximation_under_total_internal_reflection() {
-        use std::f64::consts::SQRT_2;
-        let sphere = sphere::glass_sphere();
+        use crate::precision::consts::SQRT_2;
+        let mut w = World::new();
+        let sphere = w.add_object(sphere::glass_sphere());
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, SQRT_2 / 2.0),
             Tuple::vector_new(0.0, 1.0, 0.0),
         );
         let intersections = vec![
-            Intersection::new(-SQRT_2 / 2.0, &sphere),
-            Intersection::new(SQRT_2 / 2.0, &sphere),
+            Intersection::new(-SQRT_2 / 2.0, sphere),
+            Intersection::new(SQRT_2 / 2.0, sphere),
         ];
-        let comps = prepare_computations(&intersections[1], &r, &intersections);
+        let comps = prepare_computations(&w, &intersections[1], &r, &intersections);
         let reflectance = schlick(&comps);
         assert!(float_eq(reflectance, 1.0));
     }
 
     #[test]
     fn shlick_approximation_perpendicular_viewing_angle() {
-        let sphere = sphere::glass_sphere();
+        let mut w = World::new();
+        let sphere = w.add_object(sphere::glass_sphere());
         let r = Ray::new(
             Tuple::point_new(0.0, 0.0, 0.0),
             Tuple::vector_new(0.0, 1.0, 0.0),
         );
         let intersections = vec![
-            Intersection::new(-1.0, &sphere),
-            Intersection::new(1.0, &sphere),
+            Intersection::new(-1.0, sphere),
+            Intersection::new(1.0, sphere),
         ];
-        let comps = prepare_computations(&intersections[1], &r, &intersections);
+        let comps = prepare_computations(&w, &intersections[1], &r, &intersections);
         let reflectance = schlick(&comps);
         assert!(float_eq(reflectance, 0.04));
     }
 
     #[test]
     fn shlick_approximation_small_angle_n2_gt_n1() {
-        let sphere = sphere::glass_sphere();
+        let mut w = World::new();
+        let sphere = w.add_object(sphere::glass_sphere());
         let r = Ray::new(
             Tuple::point_new(0.0, 0.99, -2.0),
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
-        let intersections = vec![Intersection::new(1.8589, &sphere)];
-        let comps = prepare_computations(&intersections[0], &r, &intersections);
+        let intersections = vec![Intersection::new(1.8589, sphere)];
+        let comps = prepare_computations(&w, &intersections[0], &r, &intersections);
         let reflectance = schlick(&comps);
         assert!(float_eq(reflectance, 0.48873));
     }
@@ -1,31 +1,461 @@
-#![allow(dead_code)]
+mod checkpoint;
+mod cli;
+mod failure;
+mod progress;
+mod quality;
+mod turntable;
+mod watch;
 
-mod canvas;
-mod lighting;
-mod matrices;
-mod rays;
-mod shapes;
-mod tuple;
-mod world;
-mod yaml;
+// the library's modules, so the ones here can reach them through crate::
+// like they always have
+use rusrat::{
+    canvas, matrices, precision, shapes, stats, tuple, world, yaml, REFLECTION_RECURSION_DEPTH,
+};
+#[cfg(test)]
+use rusrat::{lighting, sampling};
 
-use yaml::parse_config;
-use yaml_rust::YamlLoader;
+use clap::{CommandFactory, Parser};
+use failure::{Failure, Kind};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use yaml::{parse_animation_with_warnings, SceneFormat};
 
-pub const REFLECTION_RECURSION_DEPTH: usize = 7;
+// the commented scene written out by --init
+const STARTER_SCENE: &str = include_str!("../examples/starter.yaml");
 
-pub fn float_eq(a: f64, b: f64) -> bool {
-    const EPSILON: f64 = 0.000001;
-    (a - b).abs() < EPSILON
+fn main() {
+    let args = cli::Args::parse();
+    if let Some(problem) = args.problem() {
+        cli::Args::command()
+            .error(clap::error::ErrorKind::ArgumentConflict, problem)
+            .exit();
+    }
+    start_logging(args.log_level());
+    // --init <file> writes a starter scene to learn from, rather than
+    // rendering one
+    if let Some(file) = &args.init {
+        match write_starter_scene(Path::new(file)) {
+            Ok(()) => {
+                println!(
+                    "Wrote a starter scene to {} - render it with: rusrat {}",
+                    file, file
+                );
+                std::process::exit(0);
+            }
+            Err(e) => Failure::new(Kind::Io, format!("Couldn't write {}: {}", file, e))
+                .with_file(file)
+                .exit(args.error_format),
+        }
+    }
+    // clap makes sure there's a scene whenever there isn't --init
+    let yaml_file = args.scene.as_deref().unwrap_or_default();
+    // everything's rendered on a pool of its own, with as many threads as
+    // asked for - or rayon's default, which is one per core unless
+    // RAYON_NUM_THREADS says otherwise
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(args.threads().unwrap_or(0))
+        .thread_name(|i| format!("rusrat-render-{}", i))
+        .build();
+    let pool = match pool {
+        Ok(pool) => pool,
+        Err(e) => Failure::new(
+            Kind::Render,
+            format!("Couldn't start the threads to render with: {}", e),
+        )
+        .exit(args.error_format),
+    };
+    // a panic while rendering is a bug, but is still reported like any
+    // other failure - without the usual message, if that has to be JSON
+    if args.error_format == failure::ErrorFormat::Json {
+        std::panic::set_hook(Box::new(|_| {}));
+    }
+    let rendered = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        pool.install(|| render_scene_file(&args, yaml_file))
+    }));
+    if let Err(panic) = rendered {
+        let reason = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "no reason given".to_string());
+        Failure::new(Kind::Render, format!("The render failed: {}", reason))
+            .with_file(yaml_file)
+            .exit(args.error_format);
+    }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().collect();
-    let yaml_file = &args[1];
-    let s = std::fs::read_to_string(yaml_file).unwrap();
-    let yaml = YamlLoader::load_from_str(&s).unwrap();
-    let config = &yaml[0];
-    let (w, mut c) = parse_config(config);
-    let canv = world::render(&mut c, &w);
-    canv.write_out_as_ppm_file();
+fn render_scene_file(args: &cli::Args, yaml_file: &str) {
+    let camera = args.camera.as_deref();
+    // --watch renders the scene again every time the file is saved, until
+    // it's stopped
+    if args.watch {
+        watch(args, yaml_file);
+    }
+    let (s, frames) = match load_scene(yaml_file, camera, args.strict) {
+        Ok(scene) => scene,
+        Err(failure) => failure.exit(args.error_format),
+    };
+    let cancel = stop_on_ctrl_c();
+    if let Some(count) = args.turntable {
+        render_turntable(args, yaml_file, &s, frames, count, &cancel);
+        return;
+    }
+    // an animation's frames are each written to their own numbered file
+    let animated = frames.len() > 1;
+    for (i, (mut w, c)) in frames.into_iter().enumerate() {
+        let output = frame_output(&args.output, i, animated);
+        let c = args.quality.apply(&mut w, c);
+        let c = args.override_scene(&mut w, c);
+        let scene_id = checkpoint::scene_id(&[
+            s.as_bytes(),
+            camera.unwrap_or_default().as_bytes(),
+            &(i as u64).to_le_bytes(),
+        ]);
+        render_frame(args, scene_id, &w, c, &output, &cancel);
+    }
+}
+
+// Renders the scene count times, a full turn round it between them, into
+// numbered files like an animation.
+fn render_turntable(
+    args: &cli::Args,
+    yaml_file: &str,
+    s: &str,
+    frames: Vec<(world::World, world::Camera)>,
+    count: usize,
+    cancel: &Arc<AtomicBool>,
+) {
+    let object = args.turntable_object.as_deref();
+    let fail = |message: String| {
+        Failure::new(Kind::Scene, format!("{}: {}", yaml_file, message))
+            .with_file(yaml_file)
+            .exit(args.error_format)
+    };
+    if frames.len() > 1 {
+        fail("--turntable can't be used on an animation".to_string());
+    }
+    let (mut w, camera) = frames.into_iter().next().unwrap();
+    let turntable = match turntable::Turntable::new(&w, count, object) {
+        Ok(turntable) => turntable,
+        Err(e) => fail(e),
+    };
+    for i in 0..count {
+        let output = frame_output(&args.output, i, count > 1);
+        let c = turntable.frame(i, &mut w, &camera);
+        let c = args.quality.apply(&mut w, c);
+        let c = args.override_scene(&mut w, c);
+        let scene_id = checkpoint::scene_id(&[
+            s.as_bytes(),
+            args.camera.as_deref().unwrap_or_default().as_bytes(),
+            &(count as u64).to_le_bytes(),
+            object.unwrap_or_default().as_bytes(),
+            &(i as u64).to_le_bytes(),
+        ]);
+        render_frame(args, scene_id, &w, c, &output, cancel);
+    }
+}
+
+// The first Ctrl-C stops the render, which then writes out what it's
+// finished so far. A second one stops everything straight away.
+fn stop_on_ctrl_c() -> Arc<AtomicBool> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    let cancel = Arc::new(AtomicBool::new(false));
+    for signal in [SIGINT, SIGTERM] {
+        // registered first, so it sees whether cancel was already set
+        let registered =
+            signal_hook::flag::register_conditional_shutdown(signal, 130, Arc::clone(&cancel))
+                .and_then(|_| signal_hook::flag::register(signal, Arc::clone(&cancel)));
+        if let Err(e) = registered {
+            log::warn!("Couldn't set up stopping with Ctrl-C: {}", e);
+        }
+    }
+    cancel
+}
+
+// Messages go to stderr, starting with their level, e.g. "warning: ...".
+// RUST_LOG can be used instead of -v and -q for finer control.
+fn start_logging(level: log::LevelFilter) {
+    use std::io::Write;
+    env_logger::Builder::new()
+        .filter_level(level)
+        .parse_default_env()
+        .format(|f, record| {
+            let level = match record.level() {
+                log::Level::Error => "error",
+                log::Level::Warn => "warning",
+                log::Level::Info => "info",
+                log::Level::Debug => "debug",
+                log::Level::Trace => "trace",
+            };
+            writeln!(f, "{}: {}", level, record.args())
+        })
+        .init();
+}
+
+// Renders the scene and writes it out, leaving if it can't be written.
+// scene_id identifies the scene, to check a checkpoint is for the same one.
+// If cancel is set partway through, what's been rendered is written out (and
+// checkpointed) and then rusrat stops.
+fn render_frame(
+    args: &cli::Args,
+    scene_id: u64,
+    w: &world::World,
+    c: world::Camera,
+    output: &str,
+    cancel: &Arc<AtomicBool>,
+) {
+    if args.aovs {
+        if let Err(e) = world::render_aovs(&c, w).save(output) {
+            Failure::new(Kind::Io, format!("Couldn't write the AOV images: {}", e))
+                .with_file(output)
+                .exit(args.error_format);
+        }
+    }
+    // a checkpoint goes in <output>.checkpoint
+    let checkpoint = if args.checkpoint {
+        Some(checkpoint::Checkpoint::new(
+            format!("{}.checkpoint", output),
+            scene_id,
+            c.output_size(),
+            Duration::from_secs(30),
+        ))
+    } else {
+        None
+    };
+    if args.stats {
+        stats::Stats::reset();
+    }
+    if args.bvh_stats {
+        match &w.bvh {
+            Some(bvh) => eprintln!("{}", bvh.stats()),
+            None => eprintln!("There's no BVH - every ray is tested against every object"),
+        }
+    }
+    let start = Instant::now();
+    let name = output_name(output);
+    log::info!(
+        "Rendering {} at {}x{}, with {} samples per pixel",
+        name,
+        c.hsize,
+        c.vsize,
+        c.samples
+    );
+    let result = if args.stream {
+        stream_render(args, &c, w, output, cancel)
+    } else {
+        let bar = progress::ProgressBar::new("tiles");
+        let progress = |p: &world::Progress| {
+            log::debug!(
+                "Finished tile {} of {}: x {} to {}, y {} to {}",
+                p.tiles_done,
+                p.tiles_total,
+                p.tile.x0,
+                p.tile.x1,
+                p.tile.y0,
+                p.tile.y1
+            );
+            bar.update(p.tiles_done, p.tiles_total)
+        };
+        let image = match &checkpoint {
+            Some(checkpoint) => {
+                checkpoint::render_with_checkpoint(&c, w, checkpoint, progress, cancel)
+            }
+            None => world::render_resuming(&c, w, Vec::new(), progress, cancel),
+        };
+        write_image(args, &image, output, c.dither)
+    };
+    if let Err(e) = result {
+        let mut failure = Failure::new(
+            Kind::Io,
+            format!("Couldn't write the image to {}: {}", name, e),
+        );
+        if output != "-" {
+            failure = failure.with_file(output);
+        }
+        failure.exit(args.error_format);
+    }
+    if cancel.load(Ordering::SeqCst) {
+        let resume = match &checkpoint {
+            Some(checkpoint) => match checkpoint.save() {
+                Ok(()) => ", and running the same command again carries on from there",
+                Err(e) => {
+                    log::warn!("Couldn't save the checkpoint: {}", e);
+                    ""
+                }
+            },
+            None => "",
+        };
+        log::warn!("Stopped early - what was finished is in {}{}", name, resume);
+        std::process::exit(130);
+    }
+    log::info!("Wrote {} in {:.2}s", name, start.elapsed().as_secs_f64());
+    if args.stats {
+        let region = c.render_region();
+        let report = stats::Report {
+            stats: stats::Stats::now(),
+            elapsed: start.elapsed(),
+            width: region.width(),
+            height: region.height(),
+        };
+        eprintln!("{}", report);
+    }
+    if let Some(Err(e)) = checkpoint.map(|c| c.remove()) {
+        log::warn!("Couldn't remove the checkpoint: {}", e);
+    }
+}
+
+// Reads the scene, or says what's wrong with it. With strict set, unknown
+// keys and the like are errors as well.
+fn load_scene(
+    yaml_file: &str,
+    camera: Option<&str>,
+    strict: bool,
+) -> Result<(String, Vec<(world::World, world::Camera)>), Failure> {
+    let s = std::fs::read_to_string(yaml_file).map_err(|e| {
+        Failure::new(Kind::Io, format!("Couldn't read {}: {}", yaml_file, e)).with_file(yaml_file)
+    })?;
+    let directory = Path::new(yaml_file)
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let format = SceneFormat::from_path(Path::new(yaml_file));
+    let (frames, warnings) = parse_animation_with_warnings(&s, format, directory, camera)
+        .map_err(|e| Failure::scene(yaml_file, &e))?;
+    for warning in &warnings {
+        if strict {
+            log::error!("{}: {}", yaml_file, warning);
+        } else {
+            log::warn!("{}: {}", yaml_file, warning);
+        }
+    }
+    if strict && !warnings.is_empty() {
+        let message = format!(
+            "{}: {} warning{}, which --strict doesn't allow",
+            yaml_file,
+            warnings.len(),
+            if warnings.len() == 1 { "" } else { "s" }
+        );
+        return Err(Failure::new(Kind::Scene, message).with_file(yaml_file));
+    }
+    // without a camera, the camera's left 0x0 and so is the image
+    if frames.iter().any(|(_, c)| c.hsize == 0 || c.vsize == 0) {
+        log::warn!("{}: there's no camera, so the image is empty", yaml_file);
+    }
+    for (i, (w, c)) in frames.iter().enumerate() {
+        log::info!(
+            "Read {}{}: {} objects and {} lights, {}x{} pixels from {}",
+            yaml_file,
+            if frames.len() > 1 {
+                format!(" frame {}", i + 1)
+            } else {
+                String::new()
+            },
+            w.objects.len(),
+            w.lights.len(),
+            c.hsize,
+            c.vsize,
+            c.name.as_deref().unwrap_or("an unnamed camera")
+        );
+    }
+    Ok((s, frames))
+}
+
+// A scene that can't be read is reported, and then waited on like any other,
+// so that mistakes can be fixed without starting again.
+fn watch(args: &cli::Args, yaml_file: &str) -> ! {
+    let mut watcher = watch::Watcher::new(Path::new(yaml_file), Duration::from_millis(250));
+    loop {
+        match load_scene(yaml_file, args.camera.as_deref(), args.strict) {
+            Ok((_, frames)) => render_preview(args, yaml_file, frames),
+            Err(failure) => failure.report(args.error_format),
+        }
+        log::info!("Waiting for {} to change...", yaml_file);
+        watcher.wait();
+    }
+}
+
+fn render_preview(args: &cli::Args, yaml_file: &str, frames: Vec<(world::World, world::Camera)>) {
+    let animated = frames.len() > 1;
+    for (i, (mut w, c)) in frames.into_iter().enumerate() {
+        let output = frame_output(&args.output, i, animated);
+        let c = args.quality.apply(&mut w, c);
+        let c = args.override_scene(&mut w, c);
+        let mut c = c.scaled(args.preview_scale);
+        let name = output_name(&output);
+        match write_image(args, &world::render(&mut c, &w), &output, c.dither) {
+            Ok(()) => log::info!("Rendered {} to {}", yaml_file, name),
+            Err(e) => log::warn!("Couldn't write the image to {}: {}", name, e),
+        }
+    }
+}
+
+// won't replace a file that's already there
+fn write_starter_scene(path: &Path) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)?;
+    file.write_all(STARTER_SCENE.as_bytes())
+}
+
+// Where the frame with the given index goes. Frames written to stdout follow
+// one after another, as a stream of images.
+fn frame_output(output: &str, index: usize, animated: bool) -> String {
+    if animated && output != "-" {
+        world::frame_path(Path::new(output), index + 1)
+            .display()
+            .to_string()
+    } else {
+        output.to_string()
+    }
+}
+
+// "-" is stdout, for messages
+fn output_name(output: &str) -> &str {
+    if output == "-" {
+        "stdout"
+    } else {
+        output
+    }
+}
+
+fn write_image(
+    args: &cli::Args,
+    image: &canvas::Canvas,
+    output: &str,
+    dither: canvas::Dither,
+) -> std::io::Result<()> {
+    if output == "-" {
+        let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+        image.write_to(stdout, args.stdout_format(), dither)
+    } else {
+        image.save_dithered(output, dither)
+    }
+}
+
+fn stream_render(
+    args: &cli::Args,
+    c: &world::Camera,
+    w: &world::World,
+    output: &str,
+    cancel: &Arc<AtomicBool>,
+) -> std::io::Result<()> {
+    let (width, height) = c.output_size();
+    let mut writer = if output == "-" {
+        let stdout = Box::new(std::io::BufWriter::new(std::io::stdout().lock()));
+        canvas::RowWriter::new(stdout, args.stdout_format(), width, height, c.dither)?
+    } else {
+        canvas::RowWriter::create(output, width, height, c.dither)?
+    };
+    let bar = progress::ProgressBar::new("rows");
+    let mut rows = 0;
+    let cancel = world::CancellationToken::from(Arc::clone(cancel));
+    world::render_rows(c, w, &cancel, |row| {
+        rows += 1;
+        bar.update(rows, height);
+        writer.write_row(row)
+    })?;
+    writer.finish()
 }
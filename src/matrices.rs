@@ -1,15 +1,17 @@
+use crate::lanes;
+use crate::precision::{Float, EPSILON_SCALE};
 use crate::tuple::Tuple;
 use itertools::iproduct;
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Deserializer, Serialize};
 use std::ops::{Index, IndexMut, Mul};
-// use serde::{Serialize, Serializer};
-use serde::ser::{Serialize, SerializeStruct, Serializer};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Matrix<T, const ROWS: usize, const COLUMNS: usize> {
     data: [[T; ROWS]; COLUMNS],
 }
 
-impl Serialize for Matrix<f64, 4, 4> {
+impl Serialize for Matrix<Float, 4, 4> {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
@@ -20,18 +22,29 @@ impl Serialize for Matrix<f64, 4, 4> {
     }
 }
 
+// what a 4x4 matrix is saved as
+#[derive(Deserialize)]
+struct Matrix4x4 {
+    data: [[Float; 4]; 4],
+}
+
+impl<'de> Deserialize<'de> for Matrix<Float, 4, 4> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Matrix4x4::deserialize(deserializer).map(|m| Matrix::from_array(m.data))
+    }
+}
+
 // Implementations for floating point square matrix types
-impl<const SIZE: usize> Matrix<f64, SIZE, SIZE> {
-    pub fn from_array(values: &[[f64; SIZE]; SIZE]) -> Self {
-        Matrix {
-            // rows: SIZE,
-            // columns: SIZE,
-            data: *values,
-        }
+impl<const SIZE: usize> Matrix<Float, SIZE, SIZE> {
+    pub fn from_array(data: [[Float; SIZE]; SIZE]) -> Self {
+        Matrix { data }
     }
 
     pub fn new() -> Self {
-        Matrix::from_array(&[[f64::default(); SIZE]; SIZE])
+        Matrix::from_array([[0.0; SIZE]; SIZE])
     }
 
     pub fn transpose(&self) -> Self {
@@ -51,9 +64,12 @@ impl<const SIZE: usize> Matrix<f64, SIZE, SIZE> {
     }
 }
 
-// Implementations for specific square matrices
-impl Matrix<f64, 2, 2> {
-    fn determinant(&self) -> f64 {
+// Implementations for specific square matrices. The cofactor expansion for
+// determinants and inverses is too slow to use for real, but it's easy to
+// follow, so it's kept for the tests to check the quicker way against.
+#[cfg(test)]
+impl Matrix<Float, 2, 2> {
+    fn determinant(&self) -> Float {
         self.data[0][0] * self.data[1][1] - self.data[0][1] * self.data[1][0]
     }
 }
@@ -61,8 +77,9 @@ impl Matrix<f64, 2, 2> {
 // Annoyingly const generics aren't at the stage where we can have ROW - 1 and
 // COLUMN - 1 in the submatrix function's return type. So, we have to implement
 // these seperately.
-impl Matrix<f64, 3, 3> {
-    fn submatrix(&self, row: usize, column: usize) -> Matrix<f64, 2, 2> {
+#[cfg(test)]
+impl Matrix<Float, 3, 3> {
+    fn submatrix(&self, row: usize, column: usize) -> Matrix<Float, 2, 2> {
         const SIZE: usize = 3;
         let mut out = Matrix::new();
         let row_indices: Vec<_> = (0..SIZE).filter(|i| *i != row).collect();
@@ -74,11 +91,11 @@ impl Matrix<f64, 3, 3> {
     }
 
     // could do these two seperately tbf
-    fn minor(&self, row: usize, column: usize) -> f64 {
+    fn minor(&self, row: usize, column: usize) -> Float {
         self.submatrix(row, column).determinant()
     }
 
-    fn cofactor(&self, row: usize, column: usize) -> f64 {
+    fn cofactor(&self, row: usize, column: usize) -> Float {
         match (row + column) % 2 {
             0 => self.minor(row, column),
             1 => -self.minor(row, column),
@@ -86,14 +103,15 @@ impl Matrix<f64, 3, 3> {
         }
     }
 
-    fn determinant(&self) -> f64 {
+    fn determinant(&self) -> Float {
         const SIZE: usize = 3;
         (0..SIZE).map(|i| self[0][i] * self.cofactor(0, i)).sum()
     }
 }
 
-impl Matrix<f64, 4, 4> {
-    fn submatrix(&self, row: usize, column: usize) -> Matrix<f64, 3, 3> {
+#[cfg(test)]
+impl Matrix<Float, 4, 4> {
+    fn submatrix(&self, row: usize, column: usize) -> Matrix<Float, 3, 3> {
         const SIZE: usize = 4;
         let mut out = Matrix::new();
         let row_indices: Vec<_> = (0..SIZE).filter(|i| *i != row).collect();
@@ -104,11 +122,11 @@ impl Matrix<f64, 4, 4> {
         out
     }
 
-    fn minor(&self, row: usize, column: usize) -> f64 {
+    fn minor(&self, row: usize, column: usize) -> Float {
         self.submatrix(row, column).determinant()
     }
 
-    fn cofactor(&self, row: usize, column: usize) -> f64 {
+    fn cofactor(&self, row: usize, column: usize) -> Float {
         match (row + column) % 2 {
             0 => self.minor(row, column),
             1 => -self.minor(row, column),
@@ -116,71 +134,144 @@ impl Matrix<f64, 4, 4> {
         }
     }
 
-    fn determinant(&self) -> f64 {
+    fn cofactor_determinant(&self) -> Float {
         const SIZE: usize = 4;
         (0..SIZE).map(|i| self[0][i] * self.cofactor(0, i)).sum()
     }
 
-    fn is_invertible(&self) -> bool {
-        self.determinant() != 0.0
-    }
-
-    pub fn inverse(&self) -> Self {
-        assert!(
-            self.is_invertible(),
-            "Attempted to take the inverse of a non-invertible matrix!"
-        );
+    fn cofactor_inverse(&self) -> Self {
         const SIZE: usize = 4;
-        let det = self.determinant();
+        let det = self.cofactor_determinant();
         let mut out = Matrix::new();
         for (i, j) in iproduct!(0..SIZE, 0..SIZE) {
             out[j][i] = self.cofactor(i, j) / det;
         }
         out
     }
+}
+
+// The 2x2 determinants of the top two rows and of the bottom two, which
+// every 3x3 minor of a 4x4 matrix can be built from, so each is only worked
+// out once. s pairs up columns of the top rows, c of the bottom ones.
+struct Minors {
+    s: [Float; 6],
+    c: [Float; 6],
+}
+
+impl Minors {
+    fn new(m: &Matrix<Float, 4, 4>) -> Minors {
+        let pair =
+            |row: usize, a: usize, b: usize| m[row][a] * m[row + 1][b] - m[row][b] * m[row + 1][a];
+        let columns = [(0, 1), (0, 2), (0, 3), (1, 2), (1, 3), (2, 3)];
+        Minors {
+            s: columns.map(|(a, b)| pair(0, a, b)),
+            c: columns.map(|(a, b)| pair(2, a, b)),
+        }
+    }
+
+    fn determinant(&self) -> Float {
+        let (s, c) = (&self.s, &self.c);
+        s[0] * c[5] - s[1] * c[4] + s[2] * c[3] + s[3] * c[2] - s[4] * c[1] + s[5] * c[0]
+    }
+}
+
+impl Matrix<Float, 4, 4> {
+    fn determinant(&self) -> Float {
+        Minors::new(self).determinant()
+    }
 
-    pub fn translate(&self, x: f64, y: f64, z: f64) -> Self {
+    pub fn is_invertible(&self) -> bool {
+        self.determinant() != 0.0
+    }
+
+    // the adjugate divided by the determinant, written out in full
+    pub fn inverse(&self) -> Self {
+        let minors = Minors::new(self);
+        let det = minors.determinant();
+        assert!(
+            det != 0.0,
+            "Attempted to take the inverse of a non-invertible matrix!"
+        );
+        let (s, c, m) = (&minors.s, &minors.c, &self.data);
+        let adjugate = [
+            [
+                m[1][1] * c[5] - m[1][2] * c[4] + m[1][3] * c[3],
+                -m[0][1] * c[5] + m[0][2] * c[4] - m[0][3] * c[3],
+                m[3][1] * s[5] - m[3][2] * s[4] + m[3][3] * s[3],
+                -m[2][1] * s[5] + m[2][2] * s[4] - m[2][3] * s[3],
+            ],
+            [
+                -m[1][0] * c[5] + m[1][2] * c[2] - m[1][3] * c[1],
+                m[0][0] * c[5] - m[0][2] * c[2] + m[0][3] * c[1],
+                -m[3][0] * s[5] + m[3][2] * s[2] - m[3][3] * s[1],
+                m[2][0] * s[5] - m[2][2] * s[2] + m[2][3] * s[1],
+            ],
+            [
+                m[1][0] * c[4] - m[1][1] * c[2] + m[1][3] * c[0],
+                -m[0][0] * c[4] + m[0][1] * c[2] - m[0][3] * c[0],
+                m[3][0] * s[4] - m[3][1] * s[2] + m[3][3] * s[0],
+                -m[2][0] * s[4] + m[2][1] * s[2] - m[2][3] * s[0],
+            ],
+            [
+                -m[1][0] * c[3] + m[1][1] * c[1] - m[1][2] * c[0],
+                m[0][0] * c[3] - m[0][1] * c[1] + m[0][2] * c[0],
+                -m[3][0] * s[3] + m[3][1] * s[1] - m[3][2] * s[0],
+                m[2][0] * s[3] - m[2][1] * s[1] + m[2][2] * s[0],
+            ],
+        ];
+        Matrix::from_array(adjugate.map(|row| row.map(|x| x / det)))
+    }
+
+    pub fn translate(&self, x: Float, y: Float, z: Float) -> Self {
         Matrix::translation(x, y, z) * self
     }
 
-    pub fn scale(&self, x: f64, y: f64, z: f64) -> Self {
+    pub fn scale(&self, x: Float, y: Float, z: Float) -> Self {
         Matrix::scaling(x, y, z) * self
     }
 
-    pub fn rotate_x(&self, radians: f64) -> Self {
+    pub fn rotate_x(&self, radians: Float) -> Self {
         Matrix::rotation_x(radians) * self
     }
 
-    pub fn rotate_y(&self, radians: f64) -> Self {
+    pub fn rotate_y(&self, radians: Float) -> Self {
         Matrix::rotation_y(radians) * self
     }
 
-    pub fn rotate_z(&self, radians: f64) -> Self {
+    pub fn rotate_z(&self, radians: Float) -> Self {
         Matrix::rotation_z(radians) * self
     }
 
-    pub fn shear(&self, x_y: f64, x_z: f64, y_x: f64, y_z: f64, z_x: f64, z_y: f64) -> Self {
+    pub fn shear(
+        &self,
+        x_y: Float,
+        x_z: Float,
+        y_x: Float,
+        y_z: Float,
+        z_x: Float,
+        z_y: Float,
+    ) -> Self {
         Matrix::shearing(x_y, x_z, y_x, y_z, z_x, z_y) * self
     }
 
-    pub fn translation(x: f64, y: f64, z: f64) -> Matrix<f64, 4, 4> {
-        let mut out: Matrix<f64, 4, 4> = Matrix::identity();
+    pub fn translation(x: Float, y: Float, z: Float) -> Matrix<Float, 4, 4> {
+        let mut out: Matrix<Float, 4, 4> = Matrix::identity();
         for i in 0..3 {
             out[i][3] = [x, y, z][i];
         }
         out
     }
 
-    pub fn scaling(x: f64, y: f64, z: f64) -> Matrix<f64, 4, 4> {
-        let mut out: Matrix<f64, 4, 4> = Matrix::identity();
+    pub fn scaling(x: Float, y: Float, z: Float) -> Matrix<Float, 4, 4> {
+        let mut out: Matrix<Float, 4, 4> = Matrix::identity();
         for i in 0..3 {
             out[i][i] = [x, y, z][i];
         }
         out
     }
 
-    pub fn rotation_x(radians: f64) -> Matrix<f64, 4, 4> {
-        Matrix::from_array(&[
+    pub fn rotation_x(radians: Float) -> Matrix<Float, 4, 4> {
+        Matrix::from_array([
             [1.0, 0.0, 0.0, 0.0],
             [0.0, radians.cos(), -radians.sin(), 0.0],
             [0.0, radians.sin(), radians.cos(), 0.0],
@@ -188,8 +279,8 @@ impl Matrix<f64, 4, 4> {
         ])
     }
 
-    pub fn rotation_y(radians: f64) -> Matrix<f64, 4, 4> {
-        Matrix::from_array(&[
+    pub fn rotation_y(radians: Float) -> Matrix<Float, 4, 4> {
+        Matrix::from_array([
             [radians.cos(), 0.0, radians.sin(), 0.0],
             [0.0, 1.0, 0.0, 0.0],
             [-radians.sin(), 0.0, radians.cos(), 0.0],
@@ -197,8 +288,8 @@ impl Matrix<f64, 4, 4> {
         ])
     }
 
-    pub fn rotation_z(radians: f64) -> Matrix<f64, 4, 4> {
-        Matrix::from_array(&[
+    pub fn rotation_z(radians: Float) -> Matrix<Float, 4, 4> {
+        Matrix::from_array([
             [radians.cos(), -radians.sin(), 0.0, 0.0],
             [radians.sin(), radians.cos(), 0.0, 0.0],
             [0.0, 0.0, 1.0, 0.0],
@@ -207,14 +298,14 @@ impl Matrix<f64, 4, 4> {
     }
 
     pub fn shearing(
-        x_y: f64,
-        x_z: f64,
-        y_x: f64,
-        y_z: f64,
-        z_x: f64,
-        z_y: f64,
-    ) -> Matrix<f64, 4, 4> {
-        Matrix::from_array(&[
+        x_y: Float,
+        x_z: Float,
+        y_x: Float,
+        y_z: Float,
+        z_x: Float,
+        z_y: Float,
+    ) -> Matrix<Float, 4, 4> {
+        Matrix::from_array([
             [1.0, x_y, x_z, 0.0],
             [y_x, 1.0, y_z, 0.0],
             [z_x, z_y, 1.0, 0.0],
@@ -242,98 +333,150 @@ impl<T: Copy, const ROWS: usize, const COLUMNS: usize> IndexMut<usize>
         &mut self.data[i]
     }
 }
-// This allows us to multiply matrices of the same size together.
-impl<const SIZE: usize> Mul<&Matrix<f64, SIZE, SIZE>> for Matrix<f64, SIZE, SIZE> {
+// This allows us to multiply 4x4 matrices together. The work's done with
+// references - matrices are Copy, so the impls for values just borrow them.
+impl Mul for &Matrix<Float, 4, 4> {
+    type Output = Matrix<Float, 4, 4>;
+
+    fn mul(self, rhs: Self) -> Matrix<Float, 4, 4> {
+        Matrix::from_array(lanes::multiply(&self.data, &rhs.data))
+    }
+}
+
+impl Mul<&Matrix<Float, 4, 4>> for Matrix<Float, 4, 4> {
     type Output = Self;
 
     fn mul(self, rhs: &Self) -> Self {
-        let mut out = Matrix::new();
-        for (i, j) in iproduct!(0..SIZE, 0..SIZE) {
-            let row = self.data[i].iter();
-            let column = rhs.data.iter().map(|r| r[j]);
-            out[i][j] = row.zip(column).map(|(a, b)| a * b).sum();
-        }
-        out
+        Mul::mul(&self, rhs)
     }
 }
 
-// This allows us to multiply matrices of the same size together.
-impl<const SIZE: usize> Mul for Matrix<f64, SIZE, SIZE> {
+impl Mul for Matrix<Float, 4, 4> {
     type Output = Self;
 
     fn mul(self, rhs: Self) -> Self {
-        let mut out = Matrix::new();
-        for (i, j) in iproduct!(0..SIZE, 0..SIZE) {
-            let row = self.data[i].iter();
-            let column = rhs.data.iter().map(|r| r[j]);
-            out[i][j] = row.zip(column).map(|(a, b)| a * b).sum();
-        }
-        out
+        Mul::mul(&self, &rhs)
     }
 }
+
 // Allows us to multiply a 4x4 matrix by a 4-tuple, returning a tuple.
-// This can be implemented much more elegantly, but will do for now.
-impl Mul<&Tuple> for Matrix<f64, 4, 4> {
+impl Mul<&Tuple> for &Matrix<Float, 4, 4> {
     type Output = Tuple;
 
     fn mul(self, rhs: &Tuple) -> Tuple {
-        const SIZE: usize = 4;
-        let mut out = Vec::new();
-        for i in 0..SIZE {
-            let row = self.data[i].iter();
-            let tuple_iterator = rhs.vector_copy();
-            out.push(row.zip(tuple_iterator).map(|(a, b)| a * b).sum());
-        }
-        Tuple::new(out[0], out[1], out[2], out[3])
+        Tuple::from_components(lanes::transform(&self.data, rhs.components()))
     }
 }
 
-impl Mul<&Tuple> for &Matrix<f64, 4, 4> {
+impl Mul<&Tuple> for Matrix<Float, 4, 4> {
     type Output = Tuple;
 
     fn mul(self, rhs: &Tuple) -> Tuple {
-        const SIZE: usize = 4;
-        let mut out = Vec::new();
-        for i in 0..SIZE {
-            let row = self.data[i].iter();
-            let tuple_iterator = rhs.vector_copy();
-            out.push(row.zip(tuple_iterator).map(|(a, b)| a * b).sum());
-        }
-        Tuple::new(out[0], out[1], out[2], out[3])
+        Mul::mul(&self, rhs)
     }
 }
 
-impl<const ROWS: usize, const COLUMNS: usize> PartialEq for Matrix<f64, ROWS, COLUMNS> {
+impl<const ROWS: usize, const COLUMNS: usize> PartialEq for Matrix<Float, ROWS, COLUMNS> {
     fn eq(&self, other: &Self) -> bool {
-        const EPSILON: f64 = 0.00001;
-        let floats_close = |(a, b): (&f64, &f64)| (a - b).abs() < EPSILON;
+        const EPSILON: Float = 0.00001 * EPSILON_SCALE;
+        let floats_close = |(a, b): (&Float, &Float)| (a - b).abs() < EPSILON;
         let lhs = self.data.iter().flatten();
-        match other
-            .data
-            .iter()
-            .flatten()
-            .zip(lhs)
-            .map(floats_close)
-            .position(|b| !b)
-        {
-            None => true,
-            Some(_) => false,
-        }
+        other.data.iter().flatten().zip(lhs).all(floats_close)
     }
 }
 
-impl<const SIZE: usize> Default for Matrix<f64, SIZE, SIZE> {
-    fn default() -> Matrix<f64, SIZE, SIZE> {
+impl<const SIZE: usize> Default for Matrix<Float, SIZE, SIZE> {
+    fn default() -> Matrix<Float, SIZE, SIZE> {
         Matrix::identity()
     }
 }
 
+// A transform, along with its inverse and the inverse's transpose. Rays are
+// taken into object space by the inverse and normals brought back out by its
+// transpose, so these are worked out once, when the transform is set, rather
+// than for every ray. Like the inverse, a transform that squashes things flat
+// and so can't be undone panics. Only the transform itself is saved by serde.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "Matrix4x4", into = "Matrix<Float, 4, 4>")]
+pub struct Transform {
+    matrix: Matrix<Float, 4, 4>,
+    inverse: Matrix<Float, 4, 4>,
+    inverse_transpose: Matrix<Float, 4, 4>,
+}
+
+impl Transform {
+    pub fn new(matrix: Matrix<Float, 4, 4>) -> Transform {
+        let inverse = matrix.inverse();
+        Transform {
+            inverse_transpose: inverse.transpose(),
+            inverse,
+            matrix,
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix<Float, 4, 4> {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Matrix<Float, 4, 4> {
+        &self.inverse
+    }
+
+    pub fn inverse_transpose(&self) -> &Matrix<Float, 4, 4> {
+        &self.inverse_transpose
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::new(Matrix::identity())
+    }
+}
+
+impl From<Matrix<Float, 4, 4>> for Transform {
+    fn from(matrix: Matrix<Float, 4, 4>) -> Transform {
+        Transform::new(matrix)
+    }
+}
+
+// rather than panicking, loading a transform that can't be undone is an error
+impl std::convert::TryFrom<Matrix4x4> for Transform {
+    type Error = &'static str;
+
+    fn try_from(saved: Matrix4x4) -> Result<Transform, Self::Error> {
+        let matrix = Matrix::from_array(saved.data);
+        match matrix.is_invertible() {
+            true => Ok(Transform::new(matrix)),
+            false => Err("transforms have to be invertible"),
+        }
+    }
+}
+
+impl From<Transform> for Matrix<Float, 4, 4> {
+    fn from(transform: Transform) -> Matrix<Float, 4, 4> {
+        transform.matrix
+    }
+}
+
+// the inverses follow from the transform, so only it needs comparing
+impl PartialEq for Transform {
+    fn eq(&self, other: &Transform) -> bool {
+        self.matrix == other.matrix
+    }
+}
+
+impl PartialEq<Matrix<Float, 4, 4>> for Transform {
+    fn eq(&self, other: &Matrix<Float, 4, 4>) -> bool {
+        self.matrix == *other
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     #[test]
     fn construct_and_inspect_4x4() {
-        let m = Matrix::from_array(&[
+        let m = Matrix::from_array([
             [1.0, 2.0, 3.0, 4.0],
             [5.5, 6.5, 7.5, 8.5],
             [9.0, 10.0, 11.0, 12.0],
@@ -346,14 +489,14 @@ mod tests {
     }
     #[test]
     fn construct_and_inspect_3x3() {
-        let m = Matrix::from_array(&[[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
+        let m = Matrix::from_array([[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
         assert_eq!(m[0][0], 1.0);
         assert_eq!(m[1][1], 6.5);
         assert_eq!(m[2][1], 10.0);
     }
     #[test]
     fn construct_and_inspect_2x2() {
-        let m = Matrix::from_array(&[[1.0, 2.0], [5.5, 6.5]]);
+        let m = Matrix::from_array([[1.0, 2.0], [5.5, 6.5]]);
         assert_eq!(m[0][0], 1.0);
         assert_eq!(m[1][0], 5.5);
         assert_eq!(m[1][1], 6.5);
@@ -361,33 +504,33 @@ mod tests {
 
     #[test]
     fn matrix_equality() {
-        let m1 = Matrix::from_array(&[[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
-        let m2 = Matrix::from_array(&[[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
+        let m1 = Matrix::from_array([[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
+        let m2 = Matrix::from_array([[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
         assert_eq!(m1, m2);
     }
 
     #[test]
     fn matrix_inequality() {
-        let m1 = Matrix::from_array(&[[1.0, 2.0, 3.4], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
-        let m2 = Matrix::from_array(&[[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
+        let m1 = Matrix::from_array([[1.0, 2.0, 3.4], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
+        let m2 = Matrix::from_array([[1.0, 2.0, 3.0], [5.5, 6.5, 7.5], [9.0, 10.0, 11.0]]);
         assert_ne!(m1, m2);
     }
 
     #[test]
     fn matrix_multiplication() {
-        let m1 = Matrix::from_array(&[
+        let m1 = Matrix::from_array([
             [1.0, 2.0, 3.0, 4.0],
             [5.0, 6.0, 7.0, 8.0],
             [9.0, 8.0, 7.0, 6.0],
             [5.0, 4.0, 3.0, 2.0],
         ]);
-        let m2 = Matrix::from_array(&[
+        let m2 = Matrix::from_array([
             [-2.0, 1.0, 2.0, 3.0],
             [3.0, 2.0, 1.0, -1.0],
             [4.0, 3.0, 6.0, 5.0],
             [1.0, 2.0, 7.0, 8.0],
         ]);
-        let m3 = Matrix::from_array(&[
+        let m3 = Matrix::from_array([
             [20.0, 22.0, 50.0, 48.0],
             [44.0, 54.0, 114.0, 108.0],
             [40.0, 58.0, 110.0, 102.0],
@@ -398,7 +541,7 @@ mod tests {
 
     #[test]
     fn multiply_matrix_by_tuple() {
-        let m1 = Matrix::from_array(&[
+        let m1 = Matrix::from_array([
             [1.0, 2.0, 3.0, 4.0],
             [2.0, 4.0, 4.0, 2.0],
             [8.0, 6.0, 4.0, 1.0],
@@ -410,13 +553,13 @@ mod tests {
 
     #[test]
     fn transpose_matrix() {
-        let m1 = Matrix::from_array(&[
+        let m1 = Matrix::from_array([
             [1.0, 2.0, 3.0, 4.0],
             [2.0, 4.0, 4.0, 2.0],
             [8.0, 6.0, 4.0, 1.0],
             [0.0, 0.0, 0.0, 1.0],
         ]);
-        let m1_tran = Matrix::from_array(&[
+        let m1_tran = Matrix::from_array([
             [1.0, 2.0, 8.0, 0.0],
             [2.0, 4.0, 6.0, 0.0],
             [3.0, 4.0, 4.0, 0.0],
@@ -427,51 +570,51 @@ mod tests {
 
     #[test]
     fn determinant_2x2() {
-        let m = Matrix::from_array(&[[1.0, 2.0], [3.0, 4.0]]);
+        let m = Matrix::from_array([[1.0, 2.0], [3.0, 4.0]]);
         assert_eq!(m.determinant(), -2.0);
     }
 
     #[test]
     fn submatrix_3x3() {
-        let m = Matrix::from_array(&[[1.0, 5.0, 0.0], [-3.0, 2.0, 7.0], [0.0, 6.0, -3.0]]);
-        let subm = Matrix::from_array(&[[-3.0, 2.0], [0.0, 6.0]]);
+        let m = Matrix::from_array([[1.0, 5.0, 0.0], [-3.0, 2.0, 7.0], [0.0, 6.0, -3.0]]);
+        let subm = Matrix::from_array([[-3.0, 2.0], [0.0, 6.0]]);
         assert_eq!(m.submatrix(0, 2), subm);
     }
 
     #[test]
     fn submatrix_4x4() {
-        let m = Matrix::from_array(&[
+        let m = Matrix::from_array([
             [6.0, 1.0, 1.0, 6.0],
             [8.0, 5.0, 8.0, 6.0],
             [1.0, 0.0, 8.0, 2.0],
             [7.0, 1.0, 1.0, 1.0],
         ]);
-        let subm = Matrix::from_array(&[[6.0, 1.0, 6.0], [8.0, 8.0, 6.0], [7.0, 1.0, 1.0]]);
+        let subm = Matrix::from_array([[6.0, 1.0, 6.0], [8.0, 8.0, 6.0], [7.0, 1.0, 1.0]]);
         assert_eq!(m.submatrix(2, 1), subm);
     }
 
     #[test]
     fn minor_3x3() {
-        let m = Matrix::from_array(&[[3.0, 5.0, 0.0], [2.0, -1.0, -7.0], [6.0, -1.0, 5.0]]);
+        let m = Matrix::from_array([[3.0, 5.0, 0.0], [2.0, -1.0, -7.0], [6.0, -1.0, 5.0]]);
         assert_eq!(m.minor(1, 0), 25.0);
     }
 
     #[test]
     fn cofactor_3x3() {
-        let m = Matrix::from_array(&[[3.0, 5.0, 0.0], [2.0, -1.0, -7.0], [6.0, -1.0, 5.0]]);
+        let m = Matrix::from_array([[3.0, 5.0, 0.0], [2.0, -1.0, -7.0], [6.0, -1.0, 5.0]]);
         assert_eq!(m.cofactor(0, 0), -12.0);
         assert_eq!(m.cofactor(1, 0), -25.0);
     }
 
     #[test]
     fn determinant_3x3() {
-        let m = Matrix::from_array(&[[1.0, 2.0, 6.0], [-5.0, 8.0, -4.0], [2.0, 6.0, 4.0]]);
+        let m = Matrix::from_array([[1.0, 2.0, 6.0], [-5.0, 8.0, -4.0], [2.0, 6.0, 4.0]]);
         assert_eq!(m.determinant(), -196.0);
     }
 
     #[test]
     fn determinant_4x4() {
-        let m = Matrix::from_array(&[
+        let m = Matrix::from_array([
             [-2.0, -8.0, 3.0, 5.0],
             [-3.0, 1.0, 7.0, 3.0],
             [1.0, 2.0, -9.0, 6.0],
@@ -482,13 +625,13 @@ mod tests {
 
     #[test]
     fn invertibility_check() {
-        let m1 = Matrix::from_array(&[
+        let m1 = Matrix::from_array([
             [-2.0, -8.0, 3.0, 5.0],
             [-3.0, 1.0, 7.0, 3.0],
             [1.0, 2.0, -9.0, 6.0],
             [-6.0, 7.0, 7.0, -9.0],
         ]);
-        let m2 = Matrix::from_array(&[
+        let m2 = Matrix::from_array([
             [-4.0, 2.0, -2.0, -3.0],
             [9.0, 6.0, 2.0, 6.0],
             [0.0, -5.0, 1.0, -5.0],
@@ -500,13 +643,13 @@ mod tests {
 
     #[test]
     fn invert_4x4_matrix() {
-        let m = Matrix::from_array(&[
+        let m = Matrix::from_array([
             [-5.0, 2.0, 6.0, -8.0],
             [1.0, -5.0, 1.0, 8.0],
             [7.0, 7.0, -6.0, -7.0],
             [1.0, -3.0, 7.0, 4.0],
         ]);
-        let inv = Matrix::from_array(&[
+        let inv = Matrix::from_array([
             [0.21805, 0.45113, 0.24060, -0.04511],
             [-0.80827, -1.45677, -0.44361, 0.52068],
             [-0.07895, -0.22368, -0.05263, 0.19737],
@@ -515,6 +658,34 @@ mod tests {
         assert_eq!(m.inverse(), inv);
     }
 
+    #[test]
+    fn quick_inverse_matches_cofactor_expansion() {
+        let transforms = [
+            Matrix::from_array([
+                [-2.0, -8.0, 3.0, 5.0],
+                [-3.0, 1.0, 7.0, 3.0],
+                [1.0, 2.0, -9.0, 6.0],
+                [-6.0, 7.0, 7.0, -9.0],
+            ]),
+            Matrix::from_array([
+                [8.0, -5.0, 9.0, 2.0],
+                [7.0, 5.0, 6.0, 1.0],
+                [-6.0, 0.0, 9.0, 6.0],
+                [-3.0, 0.0, -9.0, -4.0],
+            ]),
+            Matrix::rotation_x(0.3)
+                .shear(1.0, 0.5, 0.0, 2.0, 0.0, 0.0)
+                .scale(2.0, 0.5, 3.0)
+                .rotate_z(1.2)
+                .translate(4.0, -1.0, 7.0),
+        ];
+        for m in transforms.iter() {
+            assert!((m.determinant() - m.cofactor_determinant()).abs() < 1e-9);
+            assert_eq!(m.inverse(), m.cofactor_inverse());
+            assert_eq!(*m * m.inverse(), Matrix::identity());
+        }
+    }
+
     #[test]
     fn translate_point() {
         let m = Matrix::translation(5.0, -3.0, 2.0);
@@ -559,7 +730,7 @@ mod tests {
 
     #[test]
     fn rotate_point_about_x_axis() {
-        use std::f64::consts::{PI, SQRT_2};
+        use crate::precision::consts::{PI, SQRT_2};
         let p = Tuple::point_new(0.0, 1.0, 0.0);
         let eigth_turn = Matrix::rotation_x(PI / 4.0);
         let quarter_turn = Matrix::rotation_x(PI / 2.0);
@@ -572,7 +743,7 @@ mod tests {
 
     #[test]
     fn rotate_point_about_y_axis() {
-        use std::f64::consts::{PI, SQRT_2};
+        use crate::precision::consts::{PI, SQRT_2};
         let p = Tuple::point_new(0.0, 0.0, 1.0);
         let eigth_turn = Matrix::rotation_y(PI / 4.0);
         let quarter_turn = Matrix::rotation_y(PI / 2.0);
@@ -585,7 +756,7 @@ mod tests {
 
     #[test]
     fn rotate_point_about_z_axis() {
-        use std::f64::consts::{PI, SQRT_2};
+        use crate::precision::consts::{PI, SQRT_2};
         let p = Tuple::point_new(0.0, 1.0, 0.0);
         let eigth_turn = Matrix::rotation_z(PI / 4.0);
         let quarter_turn = Matrix::rotation_z(PI / 2.0);
@@ -613,7 +784,7 @@ mod tests {
 
     #[test]
     fn transformation_sequence() {
-        use std::f64::consts::PI;
+        use crate::precision::consts::PI;
         let p = Tuple::point_new(1.0, 0.0, 1.0);
         let rot = Matrix::rotation_x(PI / 2.0);
         let scale = Matrix::scaling(5.0, 5.0, 5.0);
@@ -625,4 +796,34 @@ mod tests {
             .translate(10.0, 5.0, 7.0);
         assert_eq!(transform * &p, Tuple::point_new(15.0, 0.0, 7.0));
     }
+
+    #[test]
+    fn transforms_keep_their_inverse() {
+        let m = Matrix::translation(1.0, 2.0, 3.0).scale(2.0, 1.0, 1.0);
+        let t = Transform::new(m);
+        assert_eq!(*t.matrix(), m);
+        assert_eq!(*t.inverse(), m.inverse());
+        assert_eq!(*t.inverse_transpose(), m.inverse().transpose());
+        assert_eq!(t, m);
+        assert_eq!(Transform::default(), Matrix::identity());
+    }
+
+    #[test]
+    #[should_panic]
+    fn flattening_transforms_cant_be_used() {
+        Transform::new(Matrix::scaling(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn transforms_are_saved_as_their_matrix() {
+        let t = Transform::new(Matrix::translation(1.0, 2.0, 3.0));
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(
+            json,
+            r#"{"data":[[1.0,0.0,0.0,1.0],[0.0,1.0,0.0,2.0],[0.0,0.0,1.0,3.0],[0.0,0.0,0.0,1.0]]}"#
+        );
+        assert_eq!(serde_json::from_str::<Transform>(&json).unwrap(), t);
+        let flat = serde_json::to_string(&Matrix::scaling(1.0, 0.0, 1.0)).unwrap();
+        assert!(serde_json::from_str::<Transform>(&flat).is_err());
+    }
 }
@@ -0,0 +1,153 @@
+use crate::precision::Float;
+use crate::tuple::Tuple;
+
+// Ken Perlin's reference permutation table. The lookup wraps around at 256, so
+// the noise repeats every 256 units in each direction.
+const PERMUTATION: [u8; 256] = [
+    151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+    142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+    203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+    74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+    220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+    132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+    186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+    59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+    70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+    178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+    241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+    176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+    128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn perm(i: usize) -> usize {
+    PERMUTATION[i & 255] as usize
+}
+
+// Smooths the interpolation between lattice points: 6t^5 - 15t^4 + 10t^3
+fn fade(t: Float) -> Float {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: Float, a: Float, b: Float) -> Float {
+    a + t * (b - a)
+}
+
+// Dot product of (x, y, z) with one of twelve gradient directions, picked by
+// the low four bits of the hash.
+fn grad(hash: usize, x: Float, y: Float, z: Float) -> Float {
+    let h = hash & 15;
+    let u = if h < 8 { x } else { y };
+    let v = if h < 4 {
+        y
+    } else if h == 12 || h == 14 {
+        x
+    } else {
+        z
+    };
+    (if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+// Improved Perlin noise. Returns a value in the range [-1, 1], which varies
+// smoothly with the point and is zero at every integer lattice point.
+pub fn perlin(point: &Tuple) -> Float {
+    let (xf, yf, zf) = (point.x.floor(), point.y.floor(), point.z.floor());
+    // the lattice cell containing the point
+    let (xi, yi, zi) = (
+        xf.rem_euclid(256.0) as usize,
+        yf.rem_euclid(256.0) as usize,
+        zf.rem_euclid(256.0) as usize,
+    );
+    // position of the point within its cell
+    let (x, y, z) = (point.x - xf, point.y - yf, point.z - zf);
+    let (u, v, w) = (fade(x), fade(y), fade(z));
+
+    let a = perm(xi) + yi;
+    let aa = perm(a) + zi;
+    let ab = perm(a + 1) + zi;
+    let b = perm(xi + 1) + yi;
+    let ba = perm(b) + zi;
+    let bb = perm(b + 1) + zi;
+
+    lerp(
+        w,
+        lerp(
+            v,
+            lerp(u, grad(perm(aa), x, y, z), grad(perm(ba), x - 1.0, y, z)),
+            lerp(
+                u,
+                grad(perm(ab), x, y - 1.0, z),
+                grad(perm(bb), x - 1.0, y - 1.0, z),
+            ),
+        ),
+        lerp(
+            v,
+            lerp(
+                u,
+                grad(perm(aa + 1), x, y, z - 1.0),
+                grad(perm(ba + 1), x - 1.0, y, z - 1.0),
+            ),
+            lerp(
+                u,
+                grad(perm(ab + 1), x, y - 1.0, z - 1.0),
+                grad(perm(bb + 1), x - 1.0, y - 1.0, z - 1.0),
+            ),
+        ),
+    )
+}
+
+// Sums the magnitude of several octaves of noise, each at double the frequency
+// and half the amplitude of the last. Always non-negative.
+pub fn turbulence(point: &Tuple, octaves: usize) -> Float {
+    let mut out = 0.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves {
+        let p = Tuple::point_new(
+            point.x * frequency,
+            point.y * frequency,
+            point.z * frequency,
+        );
+        out += perlin(&p).abs() / frequency;
+        frequency *= 2.0;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn noise_is_zero_on_lattice_points() {
+        for (x, y, z) in [(0.0, 0.0, 0.0), (1.0, 2.0, 3.0), (-4.0, 7.0, -300.0)] {
+            assert_eq!(perlin(&Tuple::point_new(x, y, z)), 0.0);
+        }
+    }
+
+    #[test]
+    fn noise_is_bounded_and_repeatable() {
+        for i in 0..1000 {
+            let f = i as Float;
+            let p = Tuple::point_new(f * 0.173, f * -0.311, f * 0.057);
+            let n = perlin(&p);
+            assert!((-1.0..=1.0).contains(&n));
+            assert_eq!(n, perlin(&p));
+        }
+    }
+
+    #[test]
+    fn noise_varies_between_lattice_points() {
+        let n1 = perlin(&Tuple::point_new(0.5, 0.5, 0.5));
+        let n2 = perlin(&Tuple::point_new(1.5, 0.5, 0.5));
+        assert_ne!(n1, 0.0);
+        assert_ne!(n1, n2);
+    }
+
+    #[test]
+    fn turbulence_is_non_negative() {
+        for i in 0..100 {
+            let f = i as Float;
+            let p = Tuple::point_new(f * 0.37, f * 0.71, f * -0.13);
+            assert!(turbulence(&p, 6) >= 0.0);
+        }
+    }
+}
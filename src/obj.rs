@@ -0,0 +1,430 @@
+use crate::canvas::Colour;
+use crate::matrices::{Matrix, Transform};
+use crate::precision::Float;
+use crate::shapes::mesh::Mesh;
+use crate::shapes::{triangle, Material, Shape, ShapeType};
+use crate::tuple::Tuple;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+// A triangle read from an OBJ file, along with the group and material it was
+// declared under. The names are shared between all the triangles declared
+// under them.
+#[derive(Debug, PartialEq)]
+pub struct MeshTriangle {
+    pub points: [Tuple; 3],
+    pub group: Option<Arc<str>>,
+    pub material: Option<Arc<str>>,
+}
+
+#[derive(Debug, PartialEq, Default)]
+pub struct ObjMesh {
+    pub triangles: Vec<MeshTriangle>,
+    // the .mtl files named by mtllib statements, relative to the OBJ file
+    pub material_libraries: Vec<String>,
+    // lines that weren't understood, and so were skipped
+    pub ignored_lines: usize,
+}
+
+impl ObjMesh {
+    // Turns the mesh into separate triangles, ready to be added to a world.
+    // Each triangle gets the material named after its group if there is one,
+    // then the one named by its usemtl statement, and otherwise the default.
+    pub fn into_shapes(
+        self,
+        materials: &HashMap<String, Material>,
+        default: &Material,
+        transform: &Matrix<Float, 4, 4>,
+    ) -> Vec<Shape> {
+        // the inverse is worked out once for the whole mesh
+        let transform = Transform::new(*transform);
+        self.triangles
+            .into_iter()
+            .map(|tri| {
+                let [p1, p2, p3] = tri.points;
+                Shape {
+                    material: tri.material_in(materials, default).clone(),
+                    transform,
+                    ..triangle::new(p1, p2, p3)
+                }
+            })
+            .collect()
+    }
+
+    // As into_shapes, but the triangles with the same material are kept
+    // together as one mesh shape, with their corners shared, rather than
+    // each being a shape of its own. That takes much less memory for big
+    // meshes.
+    pub fn into_meshes(
+        self,
+        materials: &HashMap<String, Material>,
+        default: &Material,
+        transform: &Matrix<Float, 4, 4>,
+    ) -> Vec<Shape> {
+        let transform = Transform::new(*transform);
+        // in the order each material's first used
+        let mut by_material: Vec<(&Material, Vec<[Tuple; 3]>)> = Vec::new();
+        for tri in self.triangles {
+            let material = tri.material_in(materials, default);
+            match by_material
+                .iter_mut()
+                .find(|(m, _)| std::ptr::eq(*m, material))
+            {
+                Some((_, points)) => points.push(tri.points),
+                None => by_material.push((material, vec![tri.points])),
+            }
+        }
+        by_material
+            .into_iter()
+            .map(|(material, points)| Shape {
+                material: material.clone(),
+                transform,
+                shape: ShapeType::Mesh(Box::new(Mesh::new(points))),
+                ..Default::default()
+            })
+            .collect()
+    }
+}
+
+impl MeshTriangle {
+    // which of the materials the triangle gets, as described for into_shapes
+    fn material_in<'a>(
+        &self,
+        materials: &'a HashMap<String, Material>,
+        default: &'a Material,
+    ) -> &'a Material {
+        let named = |name: &Option<Arc<str>>| name.as_deref().and_then(|n| materials.get(n));
+        named(&self.group)
+            .or_else(|| named(&self.material))
+            .unwrap_or(default)
+    }
+}
+
+fn parse_numbers(args: &[&str]) -> Option<Vec<Float>> {
+    args.iter().map(|arg| arg.parse().ok()).collect()
+}
+
+// Face vertices can be written as v, v/vt, v//vn or v/vt/vn - only the vertex
+// index matters here. Indices start from 1, and negative ones count backwards
+// from the most recent vertex.
+fn vertex_index(arg: &str, vertex_count: usize) -> Option<usize> {
+    let index: i64 = arg.split('/').next()?.parse().ok()?;
+    let index = if index < 0 {
+        vertex_count as i64 + index
+    } else {
+        index - 1
+    };
+    if 0 <= index && (index as usize) < vertex_count {
+        Some(index as usize)
+    } else {
+        None
+    }
+}
+
+pub fn parse_obj(source: &str) -> ObjMesh {
+    let mut out = ObjMesh::default();
+    let mut vertices = Vec::new();
+    let mut group = None;
+    let mut material = None;
+    for line in source.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+        match command {
+            "v" => match parse_numbers(args) {
+                Some(xyz) if xyz.len() >= 3 => {
+                    vertices.push(Tuple::point_new(xyz[0], xyz[1], xyz[2]))
+                }
+                _ => out.ignored_lines += 1,
+            },
+            "f" => {
+                let indices: Option<Vec<usize>> = args
+                    .iter()
+                    .map(|arg| vertex_index(arg, vertices.len()))
+                    .collect();
+                match indices {
+                    // polygons are split into a fan of triangles around the first vertex
+                    Some(indices) if indices.len() >= 3 => {
+                        for pair in indices[1..].windows(2) {
+                            out.triangles.push(MeshTriangle {
+                                points: [
+                                    vertices[indices[0]],
+                                    vertices[pair[0]],
+                                    vertices[pair[1]],
+                                ],
+                                group: group.clone(),
+                                material: material.clone(),
+                            });
+                        }
+                    }
+                    _ => out.ignored_lines += 1,
+                }
+            }
+            "g" => {
+                group = if args.is_empty() {
+                    None
+                } else {
+                    Some(args.join(" ").into())
+                }
+            }
+            "usemtl" => material = args.first().map(|&name| name.into()),
+            "mtllib" => out
+                .material_libraries
+                .extend(args.iter().map(|lib| lib.to_string())),
+            // valid, but nothing we can use
+            "vt" | "vn" | "s" | "o" => {}
+            _ if command.starts_with('#') => {}
+            _ => out.ignored_lines += 1,
+        }
+    }
+    out
+}
+
+// Reads the materials in an .mtl file. Our materials only have a single
+// ambient and specular factor rather than a colour for each, so those are
+// taken as the average of the colour's components.
+pub fn parse_mtl(source: &str) -> HashMap<String, Material> {
+    let mut out = HashMap::new();
+    let mut current: Option<(String, Material)> = None;
+    for line in source.lines() {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (command, args) = match words.split_first() {
+            Some((command, args)) => (*command, args),
+            None => continue,
+        };
+        if command == "newmtl" {
+            if let Some((name, material)) = current.take() {
+                out.insert(name, material);
+            }
+            current = Some((args.join(" "), Material::default()));
+            continue;
+        }
+        let (material, values) = match (&mut current, parse_numbers(args)) {
+            (Some((_, material)), Some(values)) if !values.is_empty() => (material, values),
+            _ => continue,
+        };
+        let average = values.iter().sum::<Float>() / values.len() as Float;
+        match command {
+            "Kd" if values.len() >= 3 => {
+                material.colour = Colour::new(values[0], values[1], values[2])
+            }
+            "Ke" if values.len() >= 3 => {
+                material.emission = Colour::new(values[0], values[1], values[2])
+            }
+            "Ka" => material.ambient = average,
+            "Ks" => material.specular = average,
+            "Ns" => material.shininess = values[0],
+            "d" => material.transparency = 1.0 - values[0],
+            "Tr" => material.transparency = values[0],
+            "Ni" => material.refractive_index = values[0],
+            _ => {}
+        }
+    }
+    if let Some((name, material)) = current {
+        out.insert(name, material);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_unrecognised_lines() {
+        let mesh = parse_obj(
+            "There was a young lady named Bright
+who traveled much faster than light.
+v 1 2
+f 1 2 3",
+        );
+        assert_eq!(mesh.triangles, Vec::new());
+        assert_eq!(mesh.ignored_lines, 4);
+    }
+
+    #[test]
+    fn parses_faces_and_fans_polygons() {
+        let mesh = parse_obj(
+            "# a square and a triangle
+v -1 1 0
+v -1 0 0
+v 1 0 0
+v 1 1 0
+vn 0 0 1
+
+f 1/1/1 2/2/1 3/3/1 4/4/1
+f -4 -2 -1",
+        );
+        assert_eq!(mesh.ignored_lines, 0);
+        assert_eq!(mesh.triangles.len(), 3);
+        let points = |i: usize| mesh.triangles[i].points;
+        assert_eq!(
+            points(0),
+            [
+                Tuple::point_new(-1.0, 1.0, 0.0),
+                Tuple::point_new(-1.0, 0.0, 0.0),
+                Tuple::point_new(1.0, 0.0, 0.0)
+            ]
+        );
+        assert_eq!(
+            points(1),
+            [
+                Tuple::point_new(-1.0, 1.0, 0.0),
+                Tuple::point_new(1.0, 0.0, 0.0),
+                Tuple::point_new(1.0, 1.0, 0.0)
+            ]
+        );
+        assert_eq!(points(2), points(1));
+    }
+
+    #[test]
+    fn records_groups_and_materials_of_faces() {
+        let mesh = parse_obj(
+            "mtllib scene.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+g lid
+usemtl red
+f 1 2 3
+g
+f 1 2 3",
+        );
+        assert_eq!(mesh.material_libraries, vec!["scene.mtl".to_string()]);
+        let tags: Vec<_> = mesh
+            .triangles
+            .iter()
+            .map(|t| (t.group.as_deref(), t.material.as_deref()))
+            .collect();
+        assert_eq!(
+            tags,
+            vec![
+                (None, None),
+                (Some("lid"), Some("red")),
+                (None, Some("red"))
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_mtl_materials() {
+        let materials = parse_mtl(
+            "# two materials
+newmtl red
+Kd 1 0 0
+Ka 0.5 0.5 0.5
+Ks 0.3 0.6 0.9
+Ns 50
+
+newmtl glass
+Ke 0 0 0.5
+d 0.25
+Ni 1.5
+illum 4",
+        );
+        assert_eq!(materials.len(), 2);
+        // Ks is averaged, which f32 can only do to within a rounding error
+        let red = &materials["red"];
+        assert!(crate::float_eq(red.specular, 0.6));
+        assert_eq!(
+            *red,
+            Material {
+                colour: Colour::new(1.0, 0.0, 0.0),
+                ambient: 0.5,
+                specular: red.specular,
+                shininess: 50.0,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            materials["glass"],
+            Material {
+                emission: Colour::new(0.0, 0.0, 0.5),
+                transparency: 0.75,
+                refractive_index: 1.5,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn meshes_look_the_same_as_separate_triangles() {
+        use crate::rays::Ray;
+        use crate::world::World;
+        let source = "v -1 1 0
+v -1 -1 0
+v 1 -1 0
+v 1 1 0
+v 0 0 -1
+f 1 2 3 4
+f 1 2 5
+f 2 3 5
+";
+        let default = Material::default();
+        let transform = Matrix::rotation_y(0.5).translate(0.0, 0.0, 1.0);
+        let mut separate = World::new();
+        separate.objects = parse_obj(source).into_shapes(&HashMap::new(), &default, &transform);
+        separate.build_bvh();
+        let mut meshes = World::new();
+        meshes.objects = parse_obj(source).into_meshes(&HashMap::new(), &default, &transform);
+        assert_eq!(meshes.objects.len(), 1);
+        for y in -6..=6 {
+            for x in -6..=6 {
+                let r = Ray::new(
+                    Tuple::point_new(x as Float * 0.2, y as Float * 0.2, -5.0),
+                    Tuple::vector_new(0.0, 0.0, 1.0),
+                );
+                let seen = |w: &World| {
+                    let hit = r.intersects_world(w).into_iter().find(|i| i.t >= 0.0)?;
+                    Some((
+                        hit.t,
+                        w.object(hit.object)
+                            .normal_at_face(&r.position(hit.t), hit.face),
+                    ))
+                };
+                assert_eq!(seen(&separate), seen(&meshes), "at {}, {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn groups_take_priority_over_usemtl() {
+        let mesh = parse_obj(
+            "v 0 0 0
+v 1 0 0
+v 0 1 0
+usemtl red
+f 1 2 3
+g handle
+f 1 2 3
+usemtl unknown
+g
+f 1 2 3",
+        );
+        let mut materials = HashMap::new();
+        let red = Material {
+            colour: Colour::new(1.0, 0.0, 0.0),
+            ..Default::default()
+        };
+        let blue = Material {
+            colour: Colour::new(0.0, 0.0, 1.0),
+            ..Default::default()
+        };
+        materials.insert("red".to_string(), red.clone());
+        materials.insert("handle".to_string(), blue.clone());
+        let default = Material {
+            ambient: 1.0,
+            ..Default::default()
+        };
+        let transform = Matrix::scaling(2.0, 2.0, 2.0);
+        let shapes = mesh.into_shapes(&materials, &default, &transform);
+        assert_eq!(shapes.len(), 3);
+        assert_eq!(shapes[0].material, red);
+        assert_eq!(shapes[1].material, blue);
+        assert_eq!(shapes[2].material, default);
+        assert_eq!(shapes[2].transform, transform);
+    }
+}
@@ -0,0 +1,41 @@
+// Rendering is spread over every core with rayon, by default. Without the
+// parallel feature - e.g. for WebAssembly, where there aren't any threads to
+// spread it over - the same calls do the work one item after another instead,
+// so the rendering code doesn't need to know which it's getting.
+
+#[cfg(feature = "parallel")]
+pub use rayon::prelude::*;
+
+#[cfg(not(feature = "parallel"))]
+pub use serial::*;
+
+#[cfg(not(feature = "parallel"))]
+mod serial {
+    pub trait IntoParallelIterator: IntoIterator + Sized {
+        fn into_par_iter(self) -> Self::IntoIter {
+            self.into_iter()
+        }
+    }
+
+    impl<I: IntoIterator> IntoParallelIterator for I {}
+
+    // rayon's ways of giving each thread its own state, where there's only
+    // ever the one thread
+    pub trait ParallelIterator: Iterator + Sized {
+        fn map_init<T, R>(
+            self,
+            init: impl Fn() -> T,
+            mut map: impl FnMut(&mut T, Self::Item) -> R,
+        ) -> impl Iterator<Item = R> {
+            let mut state = init();
+            self.map(move |item| map(&mut state, item))
+        }
+
+        fn for_each_init<T>(self, init: impl Fn() -> T, mut f: impl FnMut(&mut T, Self::Item)) {
+            let mut state = init();
+            self.for_each(|item| f(&mut state, item));
+        }
+    }
+
+    impl<I: Iterator> ParallelIterator for I {}
+}
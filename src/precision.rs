@@ -0,0 +1,34 @@
+// The kind of float rendering is done with. It's f64 by default, but the f32
+// feature switches everything - tuples, matrices, colours and the scene itself
+// - over to f32, which is less accurate but quicker, with twice as many fitting
+// in each SIMD register and half as much memory to move about. That's good
+// enough for previews.
+//
+//     cargo build --release --features f32
+
+#[cfg(not(feature = "f32"))]
+pub use std::f64::consts;
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+
+#[cfg(feature = "f32")]
+pub use std::f32::consts;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+// How far off a surface the rays leaving it start, so that rounding errors
+// don't put them back inside it. f32's errors are much bigger, so it has to be
+// further.
+#[cfg(not(feature = "f32"))]
+pub const SURFACE_OFFSET: Float = 0.0000001;
+#[cfg(feature = "f32")]
+pub const SURFACE_OFFSET: Float = 0.0005;
+
+// Tuples, colours and matrices compare equal when they're within an epsilon of
+// each other. The tests check them against values worked out with f64, which
+// f32 can't match that closely, so when the tests are built with f32 their
+// epsilons are scaled up by this much. Renders never are.
+#[cfg(not(all(test, feature = "f32")))]
+pub const EPSILON_SCALE: Float = 1.0;
+#[cfg(all(test, feature = "f32"))]
+pub const EPSILON_SCALE: Float = 100.0;
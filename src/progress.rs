@@ -0,0 +1,183 @@
+// A progress bar for long renders, drawn on one line of the terminal with an
+// estimate of how long is left, e.g.
+//
+//     [==============>               ]  48% 13/27 tiles, about 1m 05s left
+//
+// Nothing is drawn when stderr isn't a terminal, so logs don't fill up with it,
+// or with -q, or when debug messages are being logged, which it'd get mixed up
+// with.
+
+use std::io::{IsTerminal, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const WIDTH: usize = 30;
+// how often the bar's redrawn, at most
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+pub struct ProgressBar {
+    // what's being counted, e.g. "tiles"
+    unit: &'static str,
+    enabled: bool,
+    start: Instant,
+    state: Mutex<State>,
+}
+
+struct State {
+    // how many were already done the first time the bar was updated, e.g. from
+    // a checkpoint - these don't count towards the estimate
+    already_done: Option<usize>,
+    // updates from different threads can arrive out of order
+    most_done: usize,
+    // whether the bar's been drawn, but not finished off with a new line
+    unfinished: bool,
+    last_drawn: Option<Instant>,
+}
+
+impl ProgressBar {
+    pub fn new(unit: &'static str) -> ProgressBar {
+        ProgressBar {
+            unit,
+            enabled: std::io::stderr().is_terminal()
+                && log::log_enabled!(log::Level::Warn)
+                && !log::log_enabled!(log::Level::Debug),
+            start: Instant::now(),
+            state: Mutex::new(State {
+                already_done: None,
+                most_done: 0,
+                unfinished: false,
+                last_drawn: None,
+            }),
+        }
+    }
+
+    // Called with how many are done so far, from any thread. Only redraws
+    // every so often, apart from when everything's done.
+    pub fn update(&self, done: usize, total: usize) {
+        if !self.enabled {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if done <= state.most_done {
+            return;
+        }
+        state.most_done = done;
+        let already_done = *state.already_done.get_or_insert(done.saturating_sub(1));
+        let now = Instant::now();
+        let due = state.last_drawn.is_none_or(|t| now - t >= REDRAW_INTERVAL);
+        if !due && done < total {
+            return;
+        }
+        state.last_drawn = Some(now);
+        let line = bar(
+            done,
+            total,
+            self.unit,
+            remaining(
+                done.saturating_sub(already_done),
+                total.saturating_sub(done),
+                now - self.start,
+            ),
+        );
+        let mut stderr = std::io::stderr();
+        let _ = write!(stderr, "\r{}", line);
+        state.unfinished = done < total;
+        if !state.unfinished {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
+
+// a render that's stopped early leaves the bar on its own line
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if self.state.lock().unwrap().unfinished {
+            eprintln!();
+        }
+    }
+}
+
+// how long the rest should take, going by how long the ones done took
+fn remaining(done: usize, left: usize, elapsed: Duration) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+    Some(elapsed.mul_f64(left as f64 / done as f64))
+}
+
+fn bar(done: usize, total: usize, unit: &str, remaining: Option<Duration>) -> String {
+    let fraction = if total == 0 {
+        1.0
+    } else {
+        done as f64 / total as f64
+    };
+    let filled = (fraction * WIDTH as f64) as usize;
+    let arrow = if filled < WIDTH { ">" } else { "" };
+    let bar = format!(
+        "{}{}{}",
+        "=".repeat(filled),
+        arrow,
+        " ".repeat(WIDTH.saturating_sub(filled + arrow.len()))
+    );
+    let eta = match remaining {
+        _ if done >= total => "done".to_string(),
+        Some(t) => format!("about {} left", duration(t)),
+        None => "working out how long is left".to_string(),
+    };
+    // padded out so a shorter line covers up a longer one from before
+    format!(
+        "[{}] {:>3}% {}/{} {}, {:<32}",
+        bar,
+        (fraction * 100.0) as usize,
+        done,
+        total,
+        unit,
+        eta
+    )
+}
+
+fn duration(t: Duration) -> String {
+    let seconds = t.as_secs();
+    match seconds {
+        0..=59 => format!("{}s", seconds),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_the_time_left() {
+        let elapsed = Duration::from_secs(10);
+        assert_eq!(remaining(0, 4, elapsed), None);
+        assert_eq!(remaining(2, 4, elapsed), Some(Duration::from_secs(20)));
+        assert_eq!(remaining(4, 0, elapsed), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn draws_the_bar() {
+        assert_eq!(
+            bar(13, 27, "tiles", Some(Duration::from_secs(65))).trim_end(),
+            "[==============>               ]  48% 13/27 tiles, about 1m 05s left"
+        );
+        assert_eq!(
+            bar(0, 10, "rows", None).trim_end(),
+            "[>                             ]   0% 0/10 rows, working out how long is left"
+        );
+        assert_eq!(
+            bar(10, 10, "rows", Some(Duration::from_secs(0))).trim_end(),
+            "[==============================] 100% 10/10 rows, done"
+        );
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(duration(Duration::from_secs(7)), "7s");
+        assert_eq!(duration(Duration::from_secs(125)), "2m 05s");
+        assert_eq!(duration(Duration::from_secs(7380)), "2h 03m");
+    }
+}
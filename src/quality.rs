@@ -0,0 +1,142 @@
+// Quality presets, for quick previews of a scene without editing it. Each one
+// turns down everything that makes a render slow together: the size of the
+// image, rays per pixel, how deep reflections and refractions go, and the
+// shadow rays for area lights and environment lighting. Settings are only
+// ever turned down - a scene that's already quicker than the preset is left
+// as it is.
+
+use crate::precision::Float;
+use crate::world::{Camera, World};
+
+#[derive(clap::ValueEnum, Debug, PartialEq, Clone, Copy)]
+pub enum Quality {
+    // a rough idea of the composition and lighting, in seconds
+    Draft,
+    // good enough to judge materials and soft shadows by
+    Medium,
+    // the scene exactly as written
+    Final,
+}
+
+struct Limits {
+    scale: Float,
+    samples: usize,
+    recursion_depth: usize,
+    area_steps: usize,
+    environment_samples: usize,
+}
+
+impl Quality {
+    fn limits(self) -> Option<Limits> {
+        match self {
+            Quality::Draft => Some(Limits {
+                scale: 0.25,
+                samples: 1,
+                recursion_depth: 2,
+                area_steps: 2,
+                environment_samples: 4,
+            }),
+            Quality::Medium => Some(Limits {
+                scale: 0.5,
+                samples: 4,
+                recursion_depth: 4,
+                area_steps: 4,
+                environment_samples: 16,
+            }),
+            Quality::Final => None,
+        }
+    }
+
+    // Turns the world down to this quality, returning the camera to render it
+    // with.
+    pub fn apply(self, world: &mut World, camera: Camera) -> Camera {
+        let limits = match self.limits() {
+            Some(limits) => limits,
+            None => return camera,
+        };
+        world.lights = std::mem::take(&mut world.lights)
+            .into_iter()
+            .map(|l| l.with_area_steps_at_most(limits.area_steps))
+            .collect();
+        world.environment_samples = world.environment_samples.min(limits.environment_samples);
+        let depth = camera
+            .recursion_depth
+            .unwrap_or(crate::REFLECTION_RECURSION_DEPTH);
+        Camera {
+            samples: camera.samples.min(limits.samples),
+            recursion_depth: Some(depth.min(limits.recursion_depth)),
+            ..camera.scaled(limits.scale)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::canvas::Colour;
+    use crate::lighting::{LightArea, PointLight};
+    use crate::matrices::Matrix;
+    use crate::tuple::Tuple;
+
+    fn scene() -> (World, Camera) {
+        let mut w = World::new();
+        w.lights.push(
+            PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0)).with_area(
+                LightArea {
+                    uvec: Tuple::vector_new(1.0, 0.0, 0.0),
+                    usteps: 8,
+                    vvec: Tuple::vector_new(0.0, 0.0, 1.0),
+                    vsteps: 3,
+                },
+            ),
+        );
+        w.environment_samples = 64;
+        let mut c = Camera::new(400, 200, 1.0, Matrix::identity());
+        c.samples = 16;
+        (w, c)
+    }
+
+    #[test]
+    fn draft_turns_everything_down() {
+        let (mut w, c) = scene();
+        let c = Quality::Draft.apply(&mut w, c);
+        assert_eq!((c.hsize, c.vsize), (100, 50));
+        assert_eq!(c.samples, 1);
+        assert_eq!(c.recursion_depth, Some(2));
+        assert_eq!(w.environment_samples, 4);
+        let area = |steps_u, steps_v| {
+            PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0)).with_area(
+                LightArea {
+                    uvec: Tuple::vector_new(1.0, 0.0, 0.0),
+                    usteps: steps_u,
+                    vvec: Tuple::vector_new(0.0, 0.0, 1.0),
+                    vsteps: steps_v,
+                },
+            )
+        };
+        assert_eq!(w.lights, vec![area(2, 2)]);
+        let (mut w, c) = scene();
+        Quality::Medium.apply(&mut w, c);
+        assert_eq!(w.lights, vec![area(4, 3)]);
+    }
+
+    #[test]
+    fn settings_are_never_turned_up() {
+        let (mut w, mut c) = scene();
+        c.samples = 2;
+        c.recursion_depth = Some(1);
+        w.environment_samples = 0;
+        let c = Quality::Medium.apply(&mut w, c);
+        assert_eq!((c.hsize, c.vsize), (200, 100));
+        assert_eq!(c.samples, 2);
+        assert_eq!(c.recursion_depth, Some(1));
+        assert_eq!(w.environment_samples, 0);
+    }
+
+    #[test]
+    fn final_leaves_the_scene_alone() {
+        let (mut w, c) = scene();
+        assert_eq!(Quality::Final.apply(&mut w, c.clone()), c);
+        assert_eq!(w.environment_samples, 64);
+    }
+}
@@ -1,25 +1,65 @@
+use crate::bvh::Packet;
 use crate::matrices::Matrix;
-use crate::shapes::Shape;
+use crate::precision::Float;
 use crate::tuple::Tuple;
-use crate::world::World;
+use crate::world::{ObjectId, World};
 use std::cmp::Ordering;
-use std::f64::EPSILON;
+use std::ops::Deref;
 
+// Rays made with Ray::new point along a unit vector, so t is the distance
+// along them. Rays taken into an object's space keep the same t for the same
+// point, so their directions are stretched along with the object instead.
 #[derive(Debug)]
 pub struct Ray {
     pub origin: Tuple,
     pub direction: Tuple,
+    pub differentials: Option<RayDifferentials>,
 }
 
+// The rays through the neighbouring pixels - one pixel across and one pixel
+// down - as (origin, direction) pairs. Camera rays carry these so textures
+// know how much of the image a single pixel covers.
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub struct Intersection<'a> {
-    pub t: f64,
-    pub object: &'a Shape,
+pub struct RayDifferentials {
+    pub dx: (Tuple, Tuple),
+    pub dy: (Tuple, Tuple),
 }
 
-impl<'a> Intersection<'a> {
+// How far a hit point moves across the surface between neighbouring pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Footprint {
+    pub dpdx: Tuple,
+    pub dpdy: Tuple,
+}
+
+impl RayDifferentials {
+    // intersects the neighbouring rays with the plane touching the surface at
+    // the hit, which is close enough to the real surface over one pixel
+    pub fn footprint(&self, point: &Tuple, normal: &Tuple) -> Footprint {
+        let offset = |(origin, direction): &(Tuple, Tuple)| {
+            let t = normal.dot(&(*point - *origin)) / normal.dot(direction);
+            *origin + *direction * t - *point
+        };
+        Footprint {
+            dpdx: offset(&self.dx),
+            dpdy: offset(&self.dy),
+        }
+    }
+}
+
+// Intersections say which object was hit by its id in the world, rather than
+// borrowing the shape, so they can be kept around while the world changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Intersection {
+    pub t: Float,
+    pub object: ObjectId,
+    // which of a mesh's triangles was hit - it's always 0 for other shapes
+    pub face: u32,
+}
+
+impl Intersection {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        let eq = (self.t - other.t).abs() < EPSILON;
+        let eq = (self.t - other.t).abs() < Float::EPSILON;
         if eq {
             Some(Ordering::Equal)
         } else {
@@ -30,11 +70,15 @@ impl<'a> Intersection<'a> {
         }
     }
 
-    pub fn new(t: f64, object: &'a Shape) -> Intersection<'a> {
-        Intersection { t, object }
+    pub fn new(t: Float, object: ObjectId) -> Intersection {
+        Intersection::on_face(t, object, 0)
     }
 
-    pub fn hit(intersections: &'a [Intersection<'a>]) -> Option<&'a Intersection<'a>> {
+    pub fn on_face(t: Float, object: ObjectId, face: u32) -> Intersection {
+        Intersection { t, object, face }
+    }
+
+    pub fn hit(intersections: &[Intersection]) -> Option<&Intersection> {
         intersections
             .iter()
             .filter(|x| x.t >= 0.0)
@@ -42,6 +86,93 @@ impl<'a> Intersection<'a> {
     }
 }
 
+// Where the intersections along a ray are collected. Rendering keeps one for
+// each thread and reuses it for every ray, so that tracing a ray doesn't need
+// new lists of intersections.
+#[derive(Debug, Default)]
+pub struct IntersectionBuffer {
+    intersections: Vec<Intersection>,
+    // for the BVH to keep track of the boxes it's still to look in
+    stack: Vec<usize>,
+    // the same for a mesh's own BVH, which is gone through while the world's
+    // is still using stack
+    mesh_stack: Vec<usize>,
+    // the opaque object that last blocked a shadow ray towards each of the
+    // world's lights. Neighbouring pixels tend to be in the shadow of the
+    // same thing, so it's worth trying first
+    occluders: Vec<Option<ObjectId>>,
+    // whether shadow rays are cast at all. Buffers last for a whole render on
+    // each thread, so this is where the renderer says to leave them out
+    shadows: bool,
+}
+
+impl IntersectionBuffer {
+    pub fn new() -> IntersectionBuffer {
+        IntersectionBuffer {
+            intersections: Vec::new(),
+            stack: Vec::new(),
+            mesh_stack: Vec::new(),
+            occluders: Vec::new(),
+            shadows: true,
+        }
+    }
+
+    pub fn with_shadows(mut self, shadows: bool) -> IntersectionBuffer {
+        self.shadows = shadows;
+        self
+    }
+
+    pub fn shadows(&self) -> bool {
+        self.shadows
+    }
+
+    pub fn push(&mut self, intersection: Intersection) {
+        self.intersections.push(intersection);
+    }
+
+    // empties the buffer, keeping the space it's using
+    pub fn clear(&mut self) {
+        self.intersections.clear();
+    }
+
+    pub fn occluder(&self, light: usize) -> Option<ObjectId> {
+        self.occluders.get(light).copied().flatten()
+    }
+
+    pub fn set_occluder(&mut self, light: usize, object: Option<ObjectId>) {
+        if self.occluders.len() <= light {
+            self.occluders.resize(light + 1, None);
+        }
+        self.occluders[light] = object;
+    }
+
+    // Lends out the stack for going through a mesh's BVH. It's handed back
+    // with put_mesh_stack, so its space can be reused for the next mesh.
+    pub fn take_mesh_stack(&mut self) -> Vec<usize> {
+        std::mem::take(&mut self.mesh_stack)
+    }
+
+    pub fn put_mesh_stack(&mut self, stack: Vec<usize>) {
+        self.mesh_stack = stack;
+    }
+
+    fn sort(&mut self) {
+        self.intersections.sort_by(|i, j| i.partial_cmp(j).unwrap());
+    }
+
+    pub fn into_vec(self) -> Vec<Intersection> {
+        self.intersections
+    }
+}
+
+impl Deref for IntersectionBuffer {
+    type Target = [Intersection];
+
+    fn deref(&self) -> &[Intersection] {
+        &self.intersections
+    }
+}
+
 impl Ray {
     pub fn new(point: Tuple, vector: Tuple) -> Ray {
         if point.is_vector() || vector.is_point() {
@@ -49,27 +180,186 @@ impl Ray {
         }
         Ray {
             origin: point,
-            direction: vector,
+            direction: vector.normalise(),
+            differentials: None,
         }
     }
 
-    pub fn position(&self, t: f64) -> Tuple {
+    pub fn with_differentials(mut self, dx: &Ray, dy: &Ray) -> Ray {
+        self.differentials = Some(RayDifferentials {
+            dx: (dx.origin, dx.direction),
+            dy: (dy.origin, dy.direction),
+        });
+        self
+    }
+
+    pub fn position(&self, t: Float) -> Tuple {
         self.origin + (t * &self.direction)
     }
 
-    pub fn intersects_world<'a>(&self, w: &'a World) -> Vec<Intersection<'a>> {
-        let mut out = Vec::new();
-        for shape in w.objects.iter() {
-            out.append(&mut shape.intersects(self))
+    pub fn intersects_world(&self, w: &World) -> Vec<Intersection> {
+        let mut buffer = IntersectionBuffer::new();
+        self.intersects_world_into(w, &mut buffer);
+        buffer.into_vec()
+    }
+
+    // replaces whatever's in the buffer with the ray's intersections with
+    // the world, sorted from nearest to furthest
+    pub fn intersects_world_into(&self, w: &World, buffer: &mut IntersectionBuffer) {
+        buffer.clear();
+        // anything behind the ray's origin matters too, for knowing which
+        // objects the ray starts inside
+        self.candidates(
+            w,
+            (Float::NEG_INFINITY, Float::INFINITY),
+            buffer,
+            |id, buffer, _| {
+                w.object(id).intersects_into(id, self, buffer);
+                false
+            },
+        );
+        buffer.sort();
+    }
+
+    // Calls visit with each object that the ray might hit between the given
+    // distances, until it returns true, and returns whether it did. With a
+    // BVH that's only the objects in the boxes the ray goes through,
+    // otherwise it's every object. visit is also given the far end of the
+    // range, which it can bring in to skip the boxes beyond.
+    fn candidates<F>(
+        &self,
+        w: &World,
+        (t_min, mut t_max): (Float, Float),
+        buffer: &mut IntersectionBuffer,
+        mut visit: F,
+    ) -> bool
+    where
+        F: FnMut(ObjectId, &mut IntersectionBuffer, &mut Float) -> bool,
+    {
+        match &w.bvh {
+            Some(bvh) => {
+                let mut stack = std::mem::take(&mut buffer.stack);
+                let found = bvh.visit_shrinking(self, (t_min, t_max), &mut stack, |i, t_max| {
+                    visit(ObjectId(i), buffer, t_max)
+                });
+                buffer.stack = stack;
+                found
+            }
+            None => w.ids().any(|id| visit(id, buffer, &mut t_max)),
         }
-        out.sort_by(|i, j| i.partial_cmp(j).unwrap());
-        out
     }
 
-    pub fn transform(&self, m: &Matrix<f64, 4, 4>) -> Ray {
+    // The nearest intersection in front of the ray's origin. Unlike
+    // intersects_world, nothing's kept or sorted besides the nearest so far,
+    // and objects that can only be further away than it aren't tested. That's
+    // enough for most hits - only refraction needs to know about the rest.
+    pub fn nearest_hit(&self, w: &World, buffer: &mut IntersectionBuffer) -> Option<Intersection> {
+        let mut nearest = None;
+        self.candidates(w, (0.0, Float::INFINITY), buffer, |id, buffer, t_max| {
+            self.try_nearer(w, id, buffer, t_max, &mut nearest);
+            false
+        });
+        nearest
+    }
+
+    // The nearest hits of four rays, as nearest_hit would find them one at a
+    // time. With a BVH, the rays go down it together as a packet, which saves
+    // work when they go through mostly the same boxes - as camera rays
+    // through pixels next to each other do.
+    pub fn nearest_hits(
+        rays: &[Ray; 4],
+        w: &World,
+        buffer: &mut IntersectionBuffer,
+    ) -> [Option<Intersection>; 4] {
+        let bvh = match &w.bvh {
+            Some(bvh) => bvh,
+            None => return rays.each_ref().map(|r| r.nearest_hit(w, buffer)),
+        };
+        let mut nearest = [None; 4];
+        let mut t_max = [Float::INFINITY; 4];
+        let mut stack = std::mem::take(&mut buffer.stack);
+        bvh.visit_packet(
+            &Packet::new(rays),
+            0.0,
+            &mut t_max,
+            &mut stack,
+            |ray, i, t_max| rays[ray].try_nearer(w, ObjectId(i), buffer, t_max, &mut nearest[ray]),
+        );
+        buffer.stack = stack;
+        nearest
+    }
+
+    // tests the ray against the object, keeping its hit as the nearest if
+    // it's nearer than t_max, and bringing t_max in to it
+    fn try_nearer(
+        &self,
+        w: &World,
+        id: ObjectId,
+        buffer: &mut IntersectionBuffer,
+        t_max: &mut Float,
+        nearest: &mut Option<Intersection>,
+    ) {
+        buffer.clear();
+        w.object(id).intersects_into(id, self, buffer);
+        if let Some(hit) = Intersection::hit(buffer).filter(|h| h.t < *t_max) {
+            *t_max = hit.t;
+            *nearest = Some(*hit);
+        }
+    }
+
+    // The first intersection found between the ray's origin and distance
+    // that matches. The shapes are checked one at a time and nothing's
+    // sorted, so it isn't necessarily the nearest, but the search stops as
+    // soon as there's a match - which is all shadow rays need.
+    pub fn find_before<F>(
+        &self,
+        w: &World,
+        distance: Float,
+        buffer: &mut IntersectionBuffer,
+        mut matches: F,
+    ) -> Option<Intersection>
+    where
+        F: FnMut(&Intersection) -> bool,
+    {
+        let mut found = None;
+        self.candidates(w, (0.0, distance), buffer, |id, buffer, _| {
+            buffer.clear();
+            w.object(id).intersects_into(id, self, buffer);
+            found = buffer
+                .iter()
+                .filter(|i| i.t >= 0.0 && i.t < distance)
+                .find(|i| matches(i))
+                .copied();
+            found.is_some()
+        });
+        found
+    }
+
+    // whether the one object is in the way between the ray's origin and
+    // distance
+    pub fn hits_before(
+        &self,
+        w: &World,
+        id: ObjectId,
+        distance: Float,
+        buffer: &mut IntersectionBuffer,
+    ) -> bool {
+        buffer.clear();
+        w.object(id).intersects_into(id, self, buffer);
+        buffer.iter().any(|i| i.t >= 0.0 && i.t < distance)
+    }
+
+    // whether anything's in the way between the ray's origin and distance
+    pub fn is_blocked(&self, w: &World, distance: Float, buffer: &mut IntersectionBuffer) -> bool {
+        self.find_before(w, distance, buffer, |_| true).is_some()
+    }
+
+    // differentials are only needed in world space, so they're dropped here
+    pub fn transform(&self, m: &Matrix<Float, 4, 4>) -> Ray {
         Ray {
             origin: m * &self.origin,
             direction: m * &self.direction,
+            differentials: None,
         }
     }
 }
@@ -78,6 +368,116 @@ impl Ray {
 mod tests {
     use super::*;
     use crate::shapes::sphere;
+    #[test]
+    fn directions_are_normalised() {
+        let r = Ray::new(
+            Tuple::point_new(1.0, 2.0, 3.0),
+            Tuple::vector_new(0.0, 3.0, 4.0),
+        );
+        assert_eq!(r.direction, Tuple::vector_new(0.0, 0.6, 0.8));
+        // so t is how far along the ray a point is
+        assert_eq!(r.position(5.0), Tuple::point_new(1.0, 5.0, 7.0));
+    }
+
+    #[test]
+    fn rays_are_blocked_by_anything_before_the_distance() {
+        let w = World::default();
+        let mut buffer = IntersectionBuffer::new();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        // the outer sphere's hit at 4
+        assert!(r.is_blocked(&w, 10.0, &mut buffer));
+        assert!(r.is_blocked(&w, 4.1, &mut buffer));
+        assert!(!r.is_blocked(&w, 3.9, &mut buffer));
+        let found = r.find_before(&w, 10.0, &mut buffer, |i| i.t > 5.0);
+        assert!(found.unwrap().t > 5.0);
+        let away = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, -1.0),
+        );
+        assert!(!away.is_blocked(&w, Float::INFINITY, &mut buffer));
+    }
+
+    #[test]
+    fn nearest_hit_is_the_same_as_sorting_every_hit() {
+        let mut w = World::default();
+        let rays = [
+            // from outside, then from inside both spheres, then missing
+            Ray::new(
+                Tuple::point_new(0.0, 0.0, -5.0),
+                Tuple::vector_new(0.0, 0.0, 1.0),
+            ),
+            Ray::new(
+                Tuple::point_new(0.0, 0.0, 0.0),
+                Tuple::vector_new(0.0, 1.0, 0.0),
+            ),
+            Ray::new(
+                Tuple::point_new(0.0, 5.0, -5.0),
+                Tuple::vector_new(0.0, 0.0, 1.0),
+            ),
+        ];
+        for _ in 0..2 {
+            let mut buffer = IntersectionBuffer::new();
+            for r in &rays {
+                let everything = r.intersects_world(&w);
+                let expected = Intersection::hit(&everything).copied();
+                assert_eq!(r.nearest_hit(&w, &mut buffer), expected);
+            }
+            // and again, going through a BVH
+            w.build_bvh();
+        }
+        let mut buffer = IntersectionBuffer::new();
+        assert_eq!(rays[1].nearest_hit(&w, &mut buffer).unwrap().t, 0.5);
+    }
+
+    #[test]
+    fn packets_find_the_same_hits_as_single_rays() {
+        use crate::shapes::plane;
+        let mut w = World::default();
+        for i in 0..20 {
+            let mut s = sphere::default();
+            s.set_transform(Matrix::translation(i as Float - 10.0, 2.0, 3.0).scale(0.4, 0.4, 0.4));
+            w.add_object(s);
+        }
+        w.add_object(plane::default());
+        w.build_bvh();
+        // fanned out from one place, some hitting the same things and some not
+        for packet in 0..10 {
+            let rays = [0, 1, 2, 3].map(|i| {
+                let angle = (packet * 4 + i) as Float * 0.05;
+                Ray::new(
+                    Tuple::point_new(0.0, 0.5, -5.0),
+                    Tuple::vector_new(angle.sin(), angle.cos() - 0.8, 1.0),
+                )
+            });
+            let mut buffer = IntersectionBuffer::new();
+            let hits = Ray::nearest_hits(&rays, &w, &mut buffer);
+            for (r, hit) in rays.iter().zip(hits) {
+                assert_eq!(hit, r.nearest_hit(&w, &mut buffer));
+            }
+        }
+    }
+
+    #[test]
+    fn buffers_are_reused_between_rays() {
+        let w = World::default();
+        let mut buffer = IntersectionBuffer::new();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        r.intersects_world_into(&w, &mut buffer);
+        assert_eq!(&buffer[..], &r.intersects_world(&w)[..]);
+        let miss = Ray::new(
+            Tuple::point_new(0.0, 5.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        miss.intersects_world_into(&w, &mut buffer);
+        assert!(buffer.is_empty());
+    }
+
     #[test]
     fn computing_point_from_distance() {
         let r = Ray::new(
@@ -96,7 +496,7 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let s = sphere::default();
-        let xs = s.intersects(&r);
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 2);
         assert_eq!(xs[0].t, 4.0);
         assert_eq!(xs[1].t, 6.0);
@@ -109,7 +509,7 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let s = sphere::default();
-        let xs = s.intersects(&r);
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 2);
         assert_eq!(xs[0].t, 5.0);
         assert_eq!(xs[1].t, 5.0);
@@ -122,7 +522,7 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let s = sphere::default();
-        let xs = s.intersects(&r);
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 0);
     }
 
@@ -133,7 +533,7 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let s = sphere::default();
-        let xs = s.intersects(&r);
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 2);
         assert_eq!(xs[0].t, -1.0);
         assert_eq!(xs[1].t, 1.0);
@@ -146,7 +546,7 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let s = sphere::default();
-        let xs = s.intersects(&r);
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 2);
         assert_eq!(xs[0].t, -6.0);
         assert_eq!(xs[1].t, -4.0);
@@ -159,36 +559,33 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let s = sphere::default();
-        let xs = s.intersects(&r);
-        assert_eq!(xs[0].object, &s);
-        assert_eq!(xs[1].object, &s);
+        let xs = s.intersects(ObjectId(3), &r);
+        assert_eq!(xs[0].object, ObjectId(3));
+        assert_eq!(xs[1].object, ObjectId(3));
     }
 
     #[test]
     fn hit_point_when_t_both_positive() {
-        let s = sphere::default();
-        let i1 = Intersection::new(1.0, &s);
-        let i2 = Intersection::new(2.0, &s);
+        let i1 = Intersection::new(1.0, ObjectId(0));
+        let i2 = Intersection::new(2.0, ObjectId(0));
         let xs = vec![i1, i2];
         let i = Intersection::hit(&xs).unwrap();
-        assert_eq!(*i, Intersection::new(1.0, &s));
+        assert_eq!(*i, Intersection::new(1.0, ObjectId(0)));
     }
 
     #[test]
     fn hit_point_when_one_t_negative() {
-        let s = sphere::default();
-        let i1 = Intersection::new(-1.0, &s);
-        let i2 = Intersection::new(1.0, &s);
+        let i1 = Intersection::new(-1.0, ObjectId(0));
+        let i2 = Intersection::new(1.0, ObjectId(0));
         let xs = vec![i1, i2];
         let i = Intersection::hit(&xs).unwrap();
-        assert_eq!(*i, Intersection::new(1.0, &s));
+        assert_eq!(*i, Intersection::new(1.0, ObjectId(0)));
     }
 
     #[test]
     fn hit_point_when_t_both_negative() {
-        let s = sphere::default();
-        let i1 = Intersection::new(-1.0, &s);
-        let i2 = Intersection::new(-2.0, &s);
+        let i1 = Intersection::new(-1.0, ObjectId(0));
+        let i2 = Intersection::new(-2.0, ObjectId(0));
         let xs = vec![i1, i2];
         let i = Intersection::hit(&xs);
         assert_eq!(i, Option::None);
@@ -221,7 +618,7 @@ mod tests {
     #[test]
     fn changing_a_spheres_transformation() {
         let mut s = sphere::default();
-        s.transform = Matrix::translation(2.0, 3.0, 4.0);
+        s.set_transform(Matrix::translation(2.0, 3.0, 4.0));
         assert_eq!(s.transform, Matrix::translation(2.0, 3.0, 4.0));
     }
 
@@ -232,8 +629,8 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let mut s = sphere::default();
-        s.transform = Matrix::scaling(2.0, 2.0, 2.0);
-        let xs = s.intersects(&r);
+        s.set_transform(Matrix::scaling(2.0, 2.0, 2.0));
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs[0].t, 3.0);
         assert_eq!(xs[1].t, 7.0);
     }
@@ -245,8 +642,36 @@ mod tests {
             Tuple::vector_new(0.0, 0.0, 1.0),
         );
         let mut s = sphere::default();
-        s.transform = Matrix::translation(5.0, 0.0, 0.0);
-        let xs = s.intersects(&r);
+        s.set_transform(Matrix::translation(5.0, 0.0, 0.0));
+        let xs = s.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 0);
     }
+
+    #[test]
+    fn footprint_of_differentials_on_a_plane() {
+        let origin = Tuple::point_new(0.0, 0.0, -5.0);
+        let r = Ray::new(origin, Tuple::vector_new(0.0, 0.0, 1.0)).with_differentials(
+            &Ray::new(origin, Tuple::vector_new(0.1, 0.0, 1.0)),
+            &Ray::new(origin, Tuple::vector_new(0.0, -0.1, 1.0)),
+        );
+        let footprint = r.differentials.unwrap().footprint(
+            &Tuple::point_new(0.0, 0.0, 5.0),
+            &Tuple::vector_new(0.0, 0.0, -1.0),
+        );
+        assert_eq!(footprint.dpdx, Tuple::vector_new(1.0, 0.0, 0.0));
+        assert_eq!(footprint.dpdy, Tuple::vector_new(0.0, -1.0, 0.0));
+    }
+
+    #[test]
+    fn transforming_a_ray_drops_its_differentials() {
+        let origin = Tuple::point_new(0.0, 0.0, 0.0);
+        let neighbour = Ray::new(origin, Tuple::vector_new(0.1, 0.0, 1.0));
+        let r = Ray::new(origin, Tuple::vector_new(0.0, 0.0, 1.0))
+            .with_differentials(&neighbour, &neighbour);
+        assert!(r.differentials.is_some());
+        assert!(r
+            .transform(&Matrix::scaling(2.0, 2.0, 2.0))
+            .differentials
+            .is_none());
+    }
 }
@@ -0,0 +1,229 @@
+// Ways of spreading a number of samples over the unit square, e.g. across the
+// surface of an area light. Every sequence is deterministic: the same seed and
+// key always give the same points, so renders can be reproduced exactly no
+// matter how the work is split between threads.
+
+use crate::precision::Float;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Sampler {
+    // the centre of each cell of a grid - no noise, but it can cause banding
+    Grid,
+    // a random point within each cell of a grid
+    Stratified { seed: u64 },
+    // points scattered so that no two are too close together, using Mitchell's
+    // best candidate algorithm
+    BlueNoise { seed: u64 },
+}
+
+impl Default for Sampler {
+    fn default() -> Sampler {
+        Sampler::Stratified { seed: 0 }
+    }
+}
+
+// A small, fast pseudo-random number generator (splitmix64). Not suitable for
+// anything but rendering.
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // uniformly distributed in [0, 1), using as many bits as fit exactly
+    pub fn next_float(&mut self) -> Float {
+        let bits = Float::MANTISSA_DIGITS;
+        (self.next_u64() >> (64 - bits)) as Float / (1u64 << bits) as Float
+    }
+}
+
+// Mixes a list of values into a single well-scrambled number, for deriving
+// seeds from things like the coordinates of a point.
+pub fn hash(values: &[u64]) -> u64 {
+    values.iter().fold(0, |acc, v| Rng::new(acc ^ v).next_u64())
+}
+
+impl Sampler {
+    // The same kind of sampler with another seed. A grid has nothing random
+    // about it to seed.
+    pub fn with_seed(self, seed: u64) -> Sampler {
+        match self {
+            Sampler::Grid => Sampler::Grid,
+            Sampler::Stratified { .. } => Sampler::Stratified { seed },
+            Sampler::BlueNoise { .. } => Sampler::BlueNoise { seed },
+        }
+    }
+
+    // Returns usteps * vsteps points in [0, 1) x [0, 1). The key picks out
+    // which of the sampler's sequences to use - pass something identifying the
+    // point being shaded, so that neighbouring points get different noise.
+    pub fn samples(&self, usteps: usize, vsteps: usize, key: u64) -> Vec<(Float, Float)> {
+        let cell = |i: usize| ((i % usteps) as Float, (i / usteps) as Float);
+        let count = usteps * vsteps;
+        match self {
+            Sampler::Grid => (0..count)
+                .map(|i| {
+                    let (u, v) = cell(i);
+                    ((u + 0.5) / usteps as Float, (v + 0.5) / vsteps as Float)
+                })
+                .collect(),
+            Sampler::Stratified { seed } => {
+                let mut rng = Rng::new(hash(&[*seed, key]));
+                (0..count)
+                    .map(|i| {
+                        let (u, v) = cell(i);
+                        (
+                            (u + rng.next_float()) / usteps as Float,
+                            (v + rng.next_float()) / vsteps as Float,
+                        )
+                    })
+                    .collect()
+            }
+            Sampler::BlueNoise { seed } => {
+                const CANDIDATES: usize = 8;
+                let mut rng = Rng::new(hash(&[*seed, key]));
+                // distance between points on a torus, so the edges don't clump
+                let distance = |(u1, v1): (Float, Float), (u2, v2): (Float, Float)| {
+                    let du = (u1 - u2).abs().min(1.0 - (u1 - u2).abs());
+                    let dv = (v1 - v2).abs().min(1.0 - (v1 - v2).abs());
+                    du * du + dv * dv
+                };
+                let mut out: Vec<(Float, Float)> = Vec::with_capacity(count);
+                for _ in 0..count {
+                    let mut best = (0.0, 0.0);
+                    let mut best_distance = -1.0;
+                    for _ in 0..CANDIDATES {
+                        let candidate = (rng.next_float(), rng.next_float());
+                        let nearest = out
+                            .iter()
+                            .map(|p| distance(*p, candidate))
+                            .fold(Float::INFINITY, Float::min);
+                        if nearest > best_distance {
+                            best = candidate;
+                            best_distance = nearest;
+                        }
+                    }
+                    out.push(best);
+                }
+                out
+            }
+        }
+    }
+}
+
+// Maps a point in the unit square onto the disc of radius 1, keeping samples
+// that were evenly spread over the square evenly spread over the disc. This is
+// Shirley and Chiu's concentric mapping - the centre of the square goes to the
+// centre of the disc.
+pub fn square_to_disc((u, v): (Float, Float)) -> (Float, Float) {
+    use crate::precision::consts::{FRAC_PI_2, FRAC_PI_4};
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    (r * theta.cos(), r * theta.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precision::EPSILON_SCALE;
+
+    #[test]
+    fn grid_samples_are_cell_centres() {
+        let samples = Sampler::Grid.samples(2, 2, 0);
+        assert_eq!(
+            samples,
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+    }
+
+    #[test]
+    fn stratified_samples_stay_in_their_cells() {
+        let samples = Sampler::Stratified { seed: 7 }.samples(4, 3, 12345);
+        assert_eq!(samples.len(), 12);
+        for (i, (u, v)) in samples.iter().enumerate() {
+            assert_eq!((u * 4.0).floor() as usize, i % 4);
+            assert_eq!((v * 3.0).floor() as usize, i / 4);
+        }
+    }
+
+    #[test]
+    fn samples_are_reproducible() {
+        for sampler in [
+            Sampler::Stratified { seed: 1 },
+            Sampler::BlueNoise { seed: 1 },
+        ] {
+            assert_eq!(sampler.samples(4, 4, 99), sampler.samples(4, 4, 99));
+            assert_ne!(sampler.samples(4, 4, 99), sampler.samples(4, 4, 100));
+        }
+        assert_ne!(
+            Sampler::BlueNoise { seed: 1 }.samples(4, 4, 99),
+            Sampler::BlueNoise { seed: 2 }.samples(4, 4, 99)
+        );
+    }
+
+    #[test]
+    fn reseeding_keeps_the_kind_of_sampler() {
+        assert_eq!(Sampler::Grid.with_seed(3), Sampler::Grid);
+        assert_eq!(
+            Sampler::Stratified { seed: 1 }.with_seed(3),
+            Sampler::Stratified { seed: 3 }
+        );
+        assert_eq!(
+            Sampler::BlueNoise { seed: 1 }.with_seed(3),
+            Sampler::BlueNoise { seed: 3 }
+        );
+    }
+
+    #[test]
+    fn blue_noise_samples_are_spread_out() {
+        let samples = Sampler::BlueNoise { seed: 3 }.samples(4, 4, 0);
+        assert_eq!(samples.len(), 16);
+        for (i, (u1, v1)) in samples.iter().enumerate() {
+            assert!((0.0..1.0).contains(u1) && (0.0..1.0).contains(v1));
+            for (u2, v2) in samples.iter().skip(i + 1) {
+                assert!((u1 - u2).hypot(v1 - v2) > 0.01);
+            }
+        }
+    }
+
+    #[test]
+    fn square_maps_onto_disc() {
+        const EPSILON: Float = 1e-9 * EPSILON_SCALE;
+        assert_eq!(square_to_disc((0.5, 0.5)), (0.0, 0.0));
+        let (x, y) = square_to_disc((1.0, 0.5));
+        assert!((x - 1.0).abs() < EPSILON && y.abs() < EPSILON);
+        let (x, y) = square_to_disc((0.5, 0.0));
+        assert!(x.abs() < EPSILON && (y + 1.0).abs() < EPSILON);
+        let (x, y) = square_to_disc((1.0, 1.0));
+        assert!((x.hypot(y) - 1.0).abs() < EPSILON);
+        for (u, v) in (Sampler::Stratified { seed: 5 }).samples(5, 5, 0) {
+            let (x, y) = square_to_disc((u, v));
+            assert!(x.hypot(y) <= 1.0);
+        }
+    }
+
+    #[test]
+    fn random_numbers_are_in_unit_interval() {
+        let mut rng = Rng::new(42);
+        for _ in 0..1000 {
+            assert!((0.0..1.0).contains(&rng.next_float()));
+        }
+    }
+}
@@ -0,0 +1,226 @@
+// A scene as plain data: the cameras, lights, objects and named materials in
+// it, and the world-wide settings. Scene files are read into one of these
+// before it's turned into a World and the Camera to render from, so a scene
+// can just as well be put together in code, e.g.
+//
+//     let (world, camera) = SceneDescription::new()
+//         .with_camera(Camera::default())
+//         .with_light(light)
+//         .with_object(sphere::default())
+//         .build(None)?;
+
+use crate::canvas::Colour;
+use crate::lighting::PointLight;
+use crate::sampling::Sampler;
+use crate::shapes::{Material, Pattern, Shape};
+use crate::world::{Camera, Fog, World};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, PartialEq, Default)]
+pub struct SceneDescription {
+    pub cameras: Vec<Camera>,
+    // the name of the camera to render from, if not the last one
+    pub selected_camera: Option<String>,
+    pub lights: Vec<PointLight>,
+    pub objects: Vec<Shape>,
+    // materials that can be looked up by name when making objects
+    pub materials: HashMap<String, Material>,
+    pub settings: WorldSettings,
+}
+
+// everything about a World besides what's in it
+#[derive(Debug, PartialEq, Clone)]
+pub struct WorldSettings {
+    pub environment: Option<Pattern>,
+    pub background: Colour,
+    pub environment_samples: usize,
+    pub shadow_sampler: Sampler,
+    pub shadow_cache: bool,
+    pub fog: Option<Fog>,
+}
+
+impl Default for WorldSettings {
+    fn default() -> WorldSettings {
+        WorldSettings {
+            environment: None,
+            background: Colour::black(),
+            environment_samples: 0,
+            shadow_sampler: Sampler::default(),
+            shadow_cache: true,
+            fog: None,
+        }
+    }
+}
+
+// the camera asked to be rendered from isn't in the scene
+#[derive(Debug, PartialEq, Clone)]
+pub struct NoSuchCamera {
+    pub name: String,
+}
+
+impl fmt::Display for NoSuchCamera {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "there's no camera called {}", self.name)
+    }
+}
+
+impl std::error::Error for NoSuchCamera {}
+
+impl SceneDescription {
+    pub fn new() -> SceneDescription {
+        SceneDescription::default()
+    }
+
+    pub fn with_camera(mut self, camera: Camera) -> SceneDescription {
+        self.cameras.push(camera);
+        self
+    }
+
+    pub fn with_light(mut self, light: PointLight) -> SceneDescription {
+        self.lights.push(light);
+        self
+    }
+
+    pub fn with_object(mut self, object: Shape) -> SceneDescription {
+        self.objects.push(object);
+        self
+    }
+
+    pub fn with_material(mut self, name: &str, material: Material) -> SceneDescription {
+        self.materials.insert(name.to_string(), material);
+        self
+    }
+
+    pub fn with_settings(mut self, settings: WorldSettings) -> SceneDescription {
+        self.settings = settings;
+        self
+    }
+
+    pub fn render_from(mut self, camera: &str) -> SceneDescription {
+        self.selected_camera = Some(camera.to_string());
+        self
+    }
+
+    pub fn material(&self, name: &str) -> Option<&Material> {
+        self.materials.get(name)
+    }
+
+    // The camera rendered from is the one named by the camera argument if
+    // given, otherwise the selected one, otherwise the last one. A scene
+    // without any cameras gets the default one.
+    pub fn build(self, camera: Option<&str>) -> Result<(World, Camera), NoSuchCamera> {
+        let mut cameras = self.cameras;
+        let c = match camera.or(self.selected_camera.as_deref()) {
+            Some(name) => match cameras
+                .into_iter()
+                .find(|c| c.name.as_deref() == Some(name))
+            {
+                Some(c) => c,
+                None => {
+                    return Err(NoSuchCamera {
+                        name: name.to_string(),
+                    })
+                }
+            },
+            None => cameras.pop().unwrap_or_default(),
+        };
+        let settings = self.settings;
+        let mut world = World {
+            objects: self.objects,
+            lights: self.lights,
+            environment: settings.environment,
+            background: settings.background,
+            environment_samples: settings.environment_samples,
+            shadow_sampler: settings.shadow_sampler,
+            shadow_cache: settings.shadow_cache,
+            fog: settings.fog,
+            bvh: None,
+        };
+        world.build_bvh();
+        Ok((world, c))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrices::Matrix;
+    use crate::shapes::sphere;
+    use crate::tuple::Tuple;
+
+    fn named_camera(name: &str, hsize: usize) -> Camera {
+        let mut c = Camera::new(hsize, 50, 1.0, Matrix::identity());
+        c.name = Some(name.to_string());
+        c
+    }
+
+    #[test]
+    fn building_a_scene_in_code() {
+        let light = PointLight::new(Colour::white(), Tuple::point_new(-10.0, 10.0, -10.0));
+        let glass = Material {
+            transparency: 1.0,
+            refractive_index: 1.5,
+            ..Material::default()
+        };
+        let scene = SceneDescription::new()
+            .with_camera(Camera::new(100, 50, 1.0, Matrix::identity()))
+            .with_light(light.clone())
+            .with_material("glass", glass.clone());
+        let ball = Shape {
+            material: scene.material("glass").unwrap().clone(),
+            ..sphere::default()
+        };
+        let (w, c) = scene.with_object(ball).build(None).unwrap();
+        assert_eq!(c.hsize, 100);
+        assert_eq!(w.lights, vec![light]);
+        assert_eq!(w.objects.len(), 1);
+        assert_eq!(w.objects[0].material, glass);
+        assert_eq!(w.background, Colour::black());
+    }
+
+    #[test]
+    fn world_settings_are_carried_over() {
+        let settings = WorldSettings {
+            background: Colour::new(0.2, 0.3, 0.4),
+            environment_samples: 8,
+            ..WorldSettings::default()
+        };
+        let (w, _) = SceneDescription::new()
+            .with_settings(settings)
+            .build(None)
+            .unwrap();
+        assert_eq!(w.background, Colour::new(0.2, 0.3, 0.4));
+        assert_eq!(w.environment_samples, 8);
+        assert!(w.objects.is_empty());
+    }
+
+    #[test]
+    fn choosing_the_camera() {
+        let scene = || {
+            SceneDescription::new()
+                .with_camera(named_camera("wide", 200))
+                .with_camera(named_camera("close", 100))
+        };
+        assert_eq!(scene().build(None).unwrap().1.hsize, 100);
+        assert_eq!(
+            scene().render_from("wide").build(None).unwrap().1.hsize,
+            200
+        );
+        assert_eq!(
+            scene()
+                .render_from("wide")
+                .build(Some("close"))
+                .unwrap()
+                .1
+                .hsize,
+            100
+        );
+        assert_eq!(
+            scene().build(Some("top")).err().unwrap(),
+            NoSuchCamera {
+                name: "top".to_string()
+            }
+        );
+    }
+}
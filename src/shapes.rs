@@ -1,54 +1,410 @@
-use crate::canvas::Colour;
-use crate::matrices::Matrix;
-use crate::rays::{Intersection, Ray};
+use crate::bvh::Bounds;
+use crate::canvas::{Canvas, Colour, MipMap};
+use crate::lighting::ShadeContext;
+use crate::matrices::{Matrix, Transform};
+use crate::noise;
+use crate::precision::Float;
+use crate::rays::{Footprint, Intersection, IntersectionBuffer, Ray};
+use crate::stats::{self, Counter};
 use crate::tuple::Tuple;
+use crate::uv::{self, UvMapping};
+use crate::world::ObjectId;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub enum ShapeType {
     Sphere,
     Plane,
+    // axis-aligned, spanning -1 to 1 on each axis
+    Cube,
+    // Both of these are truncated at y = minimum and y = maximum (exclusive).
+    // If closed is false, the ends are left open.
+    Cylinder {
+        minimum: Float,
+        maximum: Float,
+        closed: bool,
+    },
+    Cone {
+        minimum: Float,
+        maximum: Float,
+        closed: bool,
+    },
+    // A flat triangle. The edges and normal are precomputed from the points
+    // by triangle::new, so build triangles with that rather than by hand.
+    Triangle {
+        p1: Tuple,
+        p2: Tuple,
+        p3: Tuple,
+        e1: Tuple,
+        e2: Tuple,
+        normal: Tuple,
+    },
+    // many triangles that share their corners, as one shape
+    Mesh(Box<mesh::Mesh>),
 }
 
-#[derive(Debug, PartialEq)]
+impl ShapeType {
+    // An open shape is a truncated cylinder or cone without end caps. Rays can
+    // get inside one without crossing its surface, so it's treated as a thin
+    // shell rather than as a solid with an interior. A lone triangle has no
+    // interior either.
+    pub fn is_open(&self) -> bool {
+        match self {
+            ShapeType::Cylinder {
+                minimum,
+                maximum,
+                closed,
+            }
+            | ShapeType::Cone {
+                minimum,
+                maximum,
+                closed,
+            } => !closed && (minimum.is_finite() || maximum.is_finite()),
+            ShapeType::Triangle { .. } | ShapeType::Mesh(_) => true,
+            _ => false,
+        }
+    }
+
+    // The middle of the shape, before it's transformed. Shapes that go on
+    // forever, like planes, don't have one.
+    pub fn centre(&self) -> Option<Tuple> {
+        match self {
+            ShapeType::Plane => None,
+            ShapeType::Cylinder {
+                minimum, maximum, ..
+            }
+            | ShapeType::Cone {
+                minimum, maximum, ..
+            } => {
+                let y = (minimum + maximum) / 2.0;
+                if y.is_finite() {
+                    Some(Tuple::point_new(0.0, y, 0.0))
+                } else {
+                    None
+                }
+            }
+            ShapeType::Triangle { p1, p2, p3, .. } => Some(Tuple::point_new(
+                (p1.x + p2.x + p3.x) / 3.0,
+                (p1.y + p2.y + p3.y) / 3.0,
+                (p1.z + p2.z + p3.z) / 3.0,
+            )),
+            ShapeType::Mesh(mesh) => Some(mesh.bounds().centre()),
+            ShapeType::Sphere | ShapeType::Cube => Some(Tuple::point_new(0.0, 0.0, 0.0)),
+        }
+    }
+
+    // The box around the shape, before it's transformed. As with the centre,
+    // shapes that go on forever don't have one.
+    pub fn bounds(&self) -> Option<Bounds> {
+        let unit = |min_y: Float, max_y: Float, radius: Float| {
+            Bounds::new(
+                Tuple::point_new(-radius, min_y, -radius),
+                Tuple::point_new(radius, max_y, radius),
+            )
+        };
+        match self {
+            ShapeType::Plane => None,
+            ShapeType::Sphere | ShapeType::Cube => Some(unit(-1.0, 1.0, 1.0)),
+            ShapeType::Cylinder {
+                minimum, maximum, ..
+            } if minimum.is_finite() && maximum.is_finite() => Some(unit(*minimum, *maximum, 1.0)),
+            // the cone's as wide as it is far from its point
+            ShapeType::Cone {
+                minimum, maximum, ..
+            } if minimum.is_finite() && maximum.is_finite() => {
+                Some(unit(*minimum, *maximum, minimum.abs().max(maximum.abs())))
+            }
+            ShapeType::Cylinder { .. } | ShapeType::Cone { .. } => None,
+            ShapeType::Triangle { p1, p2, p3, .. } => Some(Bounds::around([*p1, *p2, *p3])),
+            ShapeType::Mesh(mesh) => Some(mesh.bounds()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
 pub struct Shape {
     pub material: Material,
-    pub transform: Matrix<f64, 4, 4>,
+    pub transform: Transform,
     pub shape: ShapeType,
+    // optional handle used to look the shape up in a World after it's been added
+    pub name: Option<String>,
+    // if set, only lights in one of these groups light up the shape. it still
+    // casts shadows from every light
+    pub light_links: Option<Vec<String>>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Material {
     pub colour: Colour,
-    pub ambient: f64,
-    pub diffuse: f64,
-    pub specular: f64,
-    pub shininess: f64,
-    pub reflectivity: f64,
-    pub transparency: f64,
-    pub refractive_index: f64,
+    pub ambient: Float,
+    pub diffuse: Float,
+    pub specular: Float,
+    pub shininess: Float,
+    pub reflectivity: Float,
+    pub transparency: Float,
+    pub refractive_index: Float,
     pub pattern: Option<Pattern>,
+    // light given off by the surface itself, regardless of any lights
+    pub emission: Colour,
+    // if set, this works out the colour of the surface instead of the usual
+    // lighting. Shaders can't be saved, so serialising one is an error
+    #[serde(default, skip_deserializing, skip_serializing_if = "Option::is_none")]
+    pub shader: Option<Shader>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub enum Pattern {
     Stripe {
         colour_a: Colour,
         colour_b: Colour,
-        transform: Matrix<f64, 4, 4>,
+        transform: Transform,
+    },
+    // Blends smoothly along x through its colour stops. Repeats every unit,
+    // and is flat before the first stop and after the last.
+    Gradient {
+        stops: ColourStops,
+        transform: Transform,
     },
     Check3D {
         colour_a: Colour,
         colour_b: Colour,
-        transform: Matrix<f64, 4, 4>,
+        transform: Transform,
+    },
+    // Alternating rings around the y axis, each one unit wide.
+    Ring {
+        colour_a: Colour,
+        colour_b: Colour,
+        transform: Transform,
+    },
+    // As Gradient, but blending outwards from the y axis, so it repeats in
+    // rings rather than in bands.
+    RadialGradient {
+        stops: ColourStops,
+        transform: Transform,
+    },
+    // The same colour everywhere. Mostly useful inside other patterns.
+    Solid {
+        colour: Colour,
+        transform: Transform,
+    },
+    // Solid textures built on noise. With a turbulence of 0, marble is a set
+    // of soft stripes along x, wood is a set of rings around the y axis, and
+    // granite is a flat colour_a.
+    Marble {
+        colour_a: Colour,
+        colour_b: Colour,
+        turbulence: Float,
+        transform: Transform,
+    },
+    Wood {
+        colour_a: Colour,
+        colour_b: Colour,
+        turbulence: Float,
+        transform: Transform,
+    },
+    Granite {
+        colour_a: Colour,
+        colour_b: Colour,
+        turbulence: Float,
+        transform: Transform,
+    },
+    // Wraps an image around the object, using the mapping to turn points into
+    // (u, v) coordinates on the image. The image is shared, so that textures
+    // can be reused between objects without loading them again.
+    Texture {
+        texture: Arc<MipMap>,
+        mapping: UvMapping,
+        transform: Transform,
+    },
+    // A checker pattern laid out in (u, v) space rather than 3D space, so the
+    // squares follow the surface instead of cutting through it. width and
+    // height are the number of squares across u and v respectively.
+    UvCheck {
+        colour_a: Colour,
+        colour_b: Colour,
+        width: Float,
+        height: Float,
+        mapping: UvMapping,
+        transform: Transform,
+    },
+    // Mixes two patterns, with weight being the proportion of pattern_b used.
+    // A weight of 0.5 gives an even average of the two.
+    Blend {
+        pattern_a: Box<Pattern>,
+        pattern_b: Box<Pattern>,
+        weight: Float,
+        transform: Transform,
+    },
+    // Uses one pattern to choose between two others: where the mask is black
+    // pattern_a is used, where it's white pattern_b, and in between they're
+    // mixed. This is how patterns are nested, e.g. stripes of checks.
+    Masked {
+        mask: Box<Pattern>,
+        pattern_a: Box<Pattern>,
+        pattern_b: Box<Pattern>,
+        transform: Transform,
+    },
+    // Moves each point by a small amount of noise before looking it up in
+    // the inner pattern, which makes straight edges wobbly. scale is the
+    // furthest a point can be moved.
+    Perturbed {
+        pattern: Box<Pattern>,
+        scale: Float,
+        transform: Transform,
+    },
+    // A separate image on each face of a cube, in the order left, right,
+    // front, back, up, down. Points are projected out onto the cube first, so
+    // this works as a skybox as well as on cubes themselves.
+    CubeMap {
+        faces: [Arc<Canvas>; 6],
+        transform: Transform,
+    },
+    // Any procedural pattern you can write as a function of a point in
+    // pattern space. Functions can't be saved, so serialising one is an error.
+    #[serde(skip)]
+    Function {
+        function: PatternFunction,
+        transform: Transform,
     },
     Test {
-        transform: Matrix<f64, 4, 4>,
+        transform: Transform,
     },
 }
 
+// A user-supplied pattern function. It has to be Sync so that worlds using it
+// can still be rendered in parallel.
+#[derive(Clone)]
+pub struct PatternFunction(Arc<dyn Fn(&Tuple) -> Colour + Send + Sync>);
+
+impl PatternFunction {
+    pub fn new<F>(function: F) -> PatternFunction
+    where
+        F: Fn(&Tuple) -> Colour + Send + Sync + 'static,
+    {
+        PatternFunction(Arc::new(function))
+    }
+}
+
+impl fmt::Debug for PatternFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("PatternFunction(..)")
+    }
+}
+
+// closures can't be compared, so a function is only equal to itself (or to
+// clones of itself)
+impl PartialEq for PatternFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+// A user-supplied shader, for lighting surfaces some other way, e.g. toon
+// shading. As with pattern functions, it has to be Sync so that worlds using
+// it can still be rendered in parallel.
+#[derive(Clone)]
+pub struct Shader(Arc<dyn Fn(&ShadeContext) -> Colour + Send + Sync>);
+
+impl Shader {
+    pub fn new<F>(shader: F) -> Shader
+    where
+        F: Fn(&ShadeContext) -> Colour + Send + Sync + 'static,
+    {
+        Shader(Arc::new(shader))
+    }
+
+    pub fn shade(&self, context: &ShadeContext) -> Colour {
+        (self.0)(context)
+    }
+}
+
+impl fmt::Debug for Shader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Shader(..)")
+    }
+}
+
+// like pattern functions, shaders are only equal to themselves
+impl PartialEq for Shader {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Serialize for Shader {
+    fn serialize<S: serde::Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("shaders can't be saved"))
+    }
+}
+
+// linearly interpolates between two colours, where t = 0 gives a and t = 1 gives b
+fn mix(a: &Colour, b: &Colour, t: Float) -> Colour {
+    *a * (1.0 - t) + *b * t
+}
+
+// The (position, colour) stops of a gradient, sorted by position. There's
+// always at least one, and every position is a finite number - new checks,
+// and serde goes through it too.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(try_from = "Vec<(Float, Colour)>", into = "Vec<(Float, Colour)>")]
+pub struct ColourStops(Vec<(Float, Colour)>);
+
+impl ColourStops {
+    pub fn new(mut stops: Vec<(Float, Colour)>) -> Result<ColourStops, String> {
+        if stops.is_empty() {
+            return Err("a gradient needs at least one colour stop".to_string());
+        }
+        if let Some((position, _)) = stops.iter().find(|(p, _)| !p.is_finite()) {
+            return Err(format!("a colour stop can't be at {}", position));
+        }
+        stops.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+        Ok(ColourStops(stops))
+    }
+
+    // from colour_a at 0 to colour_b at 1
+    pub fn between(colour_a: Colour, colour_b: Colour) -> ColourStops {
+        ColourStops(vec![(0.0, colour_a), (1.0, colour_b)])
+    }
+
+    pub fn stops(&self) -> &[(Float, Colour)] {
+        &self.0
+    }
+
+    // finds the colour at t by interpolating between the stops either side of it
+    fn colour_at(&self, t: Float) -> Colour {
+        let stops = &self.0;
+        match stops.iter().position(|(position, _)| *position > t) {
+            Some(0) => stops[0].1,
+            Some(i) => {
+                let (start, colour_a) = &stops[i - 1];
+                let (end, colour_b) = &stops[i];
+                mix(colour_a, colour_b, (t - start) / (end - start))
+            }
+            None => stops[stops.len() - 1].1,
+        }
+    }
+}
+
+impl std::convert::TryFrom<Vec<(Float, Colour)>> for ColourStops {
+    type Error = String;
+
+    fn try_from(stops: Vec<(Float, Colour)>) -> Result<ColourStops, String> {
+        ColourStops::new(stops)
+    }
+}
+
+impl From<ColourStops> for Vec<(Float, Colour)> {
+    fn from(stops: ColourStops) -> Vec<(Float, Colour)> {
+        stops.0
+    }
+}
+
 impl Pattern {
     fn pattern_at(&self, point: &Tuple) -> Colour {
-        const EPSILON: f64 = 0.00001;
+        const EPSILON: Float = 0.00001;
+        const NOISE_OCTAVES: usize = 6;
 
         match self {
             Pattern::Check3D {
@@ -85,41 +441,274 @@ impl Pattern {
                 }
             }
 
+            Pattern::Ring {
+                colour_a, colour_b, ..
+            } => {
+                let radius = (point.x.powi(2) + point.z.powi(2)).sqrt();
+                if radius.floor() as i64 % 2 == 0 {
+                    *colour_a
+                } else {
+                    *colour_b
+                }
+            }
+            Pattern::Gradient { stops, .. } => stops.colour_at(point.x - point.x.floor()),
+            Pattern::RadialGradient { stops, .. } => {
+                let radius = (point.x.powi(2) + point.z.powi(2)).sqrt();
+                stops.colour_at(radius - radius.floor())
+            }
+            Pattern::Solid { colour, .. } => *colour,
+            Pattern::Marble {
+                colour_a,
+                colour_b,
+                turbulence,
+                ..
+            } => {
+                let x = point.x + turbulence * noise::turbulence(point, NOISE_OCTAVES);
+                let t = 0.5 * (1.0 + (x * crate::precision::consts::PI).sin());
+                mix(colour_a, colour_b, t)
+            }
+            Pattern::Wood {
+                colour_a,
+                colour_b,
+                turbulence,
+                ..
+            } => {
+                let radius = (point.x.powi(2) + point.z.powi(2)).sqrt();
+                let t = (radius + turbulence * noise::perlin(point)).rem_euclid(1.0);
+                mix(colour_a, colour_b, t)
+            }
+            Pattern::Granite {
+                colour_a,
+                colour_b,
+                turbulence,
+                ..
+            } => {
+                let t = (turbulence * noise::turbulence(point, NOISE_OCTAVES)).min(1.0);
+                mix(colour_a, colour_b, t)
+            }
+            Pattern::Texture {
+                texture, mapping, ..
+            } => {
+                let (u, v) = mapping.map(point);
+                texture.uv_at(u, v)
+            }
+            Pattern::UvCheck {
+                colour_a,
+                colour_b,
+                width,
+                height,
+                mapping,
+                ..
+            } => {
+                let (u, v) = mapping.map(point);
+                let u2 = (u * width).floor() as i64;
+                let v2 = (v * height).floor() as i64;
+                if (u2 + v2) % 2 == 0 {
+                    *colour_a
+                } else {
+                    *colour_b
+                }
+            }
+            Pattern::Blend {
+                pattern_a,
+                pattern_b,
+                weight,
+                ..
+            } => mix(
+                &pattern_a.pattern_at_parent(point),
+                &pattern_b.pattern_at_parent(point),
+                *weight,
+            ),
+            Pattern::Masked {
+                mask,
+                pattern_a,
+                pattern_b,
+                ..
+            } => {
+                let [r, g, b] = mask.pattern_at_parent(point).components();
+                mix(
+                    &pattern_a.pattern_at_parent(point),
+                    &pattern_b.pattern_at_parent(point),
+                    (r + g + b) / 3.0,
+                )
+            }
+            Pattern::Perturbed { pattern, scale, .. } => {
+                // offset the noise for each axis so they don't all move together
+                let offset = |dx: Float, dy: Float, dz: Float| {
+                    noise::perlin(&Tuple::point_new(point.x + dx, point.y + dy, point.z + dz))
+                };
+                let jittered = Tuple::point_new(
+                    point.x + scale * offset(0.0, 0.0, 0.0),
+                    point.y + scale * offset(17.3, 5.1, 9.7),
+                    point.z + scale * offset(3.9, 21.2, 13.4),
+                );
+                pattern.pattern_at_parent(&jittered)
+            }
+            Pattern::CubeMap { faces, .. } => {
+                let largest = point.x.abs().max(point.y.abs()).max(point.z.abs());
+                let on_cube =
+                    Tuple::point_new(point.x / largest, point.y / largest, point.z / largest);
+                let (face, u, v) = uv::cube_map(&on_cube);
+                faces[face as usize].uv_at(u, v)
+            }
+            Pattern::Function { function, .. } => (function.0)(point),
+
             Pattern::Test { .. } => Colour::new(point.x, point.y, point.z),
         }
     }
 
-    pub fn pattern_at_object(&self, object: &Shape, point: &Tuple) -> Colour {
+    fn transform(&self) -> &Transform {
         match self {
             Pattern::Check3D { transform, .. }
             | Pattern::Stripe { transform, .. }
-            | Pattern::Test { transform } => {
-                let object_space_point = object.transform.inverse() * point;
-                let pattern_point = transform.inverse() * &object_space_point;
-                self.pattern_at(&pattern_point)
+            | Pattern::Ring { transform, .. }
+            | Pattern::Gradient { transform, .. }
+            | Pattern::RadialGradient { transform, .. }
+            | Pattern::Solid { transform, .. }
+            | Pattern::Masked { transform, .. }
+            | Pattern::Perturbed { transform, .. }
+            | Pattern::Marble { transform, .. }
+            | Pattern::Wood { transform, .. }
+            | Pattern::Granite { transform, .. }
+            | Pattern::Texture { transform, .. }
+            | Pattern::UvCheck { transform, .. }
+            | Pattern::Blend { transform, .. }
+            | Pattern::CubeMap { transform, .. }
+            | Pattern::Function { transform, .. }
+            | Pattern::Test { transform } => transform,
+        }
+    }
+
+    // Takes a point in the space of whatever contains this pattern (either an
+    // object or another pattern), and moves it into pattern space before sampling.
+    fn pattern_at_parent(&self, point: &Tuple) -> Colour {
+        let pattern_point = self.transform().inverse() * point;
+        self.pattern_at(&pattern_point)
+    }
+
+    pub fn pattern_at_object(&self, object: &Shape, point: &Tuple) -> Colour {
+        let object_space_point = object.transform.inverse() * point;
+        self.pattern_at_parent(&object_space_point)
+    }
+
+    // As pattern_at_object, but image textures are averaged over the area of
+    // the surface that one pixel covers, so they don't alias when seen from
+    // far away. Everything else is sampled at the point.
+    pub fn pattern_at_object_filtered(
+        &self,
+        object: &Shape,
+        point: &Tuple,
+        footprint: Option<&Footprint>,
+    ) -> Colour {
+        match (self, footprint) {
+            (
+                Pattern::Texture {
+                    texture,
+                    mapping,
+                    transform,
+                },
+                Some(f),
+            ) => {
+                let to_pattern =
+                    |p: &Tuple| transform.inverse() * &(object.transform.inverse() * p);
+                let (u, v) = mapping.map(&to_pattern(point));
+                let image = texture.level(0);
+                // how many texels the step to a neighbouring pixel crosses,
+                // going the short way round if it wraps across a seam
+                let texels = |p: Tuple| {
+                    let (pu, pv) = mapping.map(&to_pattern(&p));
+                    let (du, dv) = ((pu - u).abs(), (pv - v).abs());
+                    let du = du.min(1.0 - du) * image.width() as Float;
+                    let dv = dv.min(1.0 - dv) * image.height() as Float;
+                    du.max(dv)
+                };
+                let footprint = texels(*point + f.dpdx).max(texels(*point + f.dpdy));
+                texture.filtered_uv_at(u, v, footprint)
             }
+            _ => self.pattern_at_object(object, point),
         }
     }
+
+    // Samples the pattern as though it were painted on the inside of an
+    // infinitely large sphere around the scene, looking in the given direction.
+    pub fn pattern_at_direction(&self, direction: &Tuple) -> Colour {
+        let d = direction.normalise();
+        self.pattern_at_parent(&Tuple::point_new(d.x, d.y, d.z))
+    }
 }
 
 impl Shape {
+    // the middle of the shape in the world, if it has one
+    pub fn centre(&self) -> Option<Tuple> {
+        self.shape.centre().map(|c| self.transform.matrix() * &c)
+    }
+
+    // the box around the shape in the world, if it has one
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.shape
+            .bounds()
+            .map(|b| b.transformed(self.transform.matrix()))
+    }
+
+    // keeps the inverse transform in step
+    pub fn set_transform(&mut self, transform: Matrix<Float, 4, 4>) {
+        self.transform = Transform::new(transform);
+    }
+
     pub fn normal_at(&self, point: &Tuple) -> Tuple {
-        let transform_inverse = &self.transform.inverse();
-        let object_space_point = transform_inverse * point;
-        let object_space_normal = match self.shape {
+        self.normal_at_face(point, 0)
+    }
+
+    // as normal_at, on the given face of a mesh - other shapes only have the
+    // one, so it's ignored for them
+    pub fn normal_at_face(&self, point: &Tuple, face: u32) -> Tuple {
+        let object_space_point = self.transform.inverse() * point;
+        let object_space_normal = match &self.shape {
             ShapeType::Sphere => sphere::normal_at(&object_space_point),
             ShapeType::Plane => plane::normal_at(),
+            ShapeType::Cube => cube::normal_at(&object_space_point),
+            ShapeType::Cylinder {
+                minimum, maximum, ..
+            } => cylinder::normal_at(&object_space_point, *minimum, *maximum),
+            ShapeType::Cone {
+                minimum, maximum, ..
+            } => cone::normal_at(&object_space_point, *minimum, *maximum),
+            ShapeType::Triangle { normal, .. } => *normal,
+            ShapeType::Mesh(mesh) => mesh.normal(face),
         };
-        let world_space_normal = transform_inverse.transpose() * &object_space_normal;
+        let world_space_normal = self.transform.inverse_transpose() * &object_space_normal;
         world_space_normal.normalise()
     }
 
-    pub fn intersects<'a>(&'a self, r: &Ray) -> Vec<Intersection<'a>> {
-        let transform_inverse = &self.transform.inverse();
-        let object_space_ray = r.transform(transform_inverse);
-        match self.shape {
-            ShapeType::Sphere => sphere::intersects(self, &object_space_ray),
-            ShapeType::Plane => plane::intersects(self, &object_space_ray),
+    pub fn intersects(&self, id: ObjectId, r: &Ray) -> Vec<Intersection> {
+        let mut out = IntersectionBuffer::new();
+        self.intersects_into(id, r, &mut out);
+        out.into_vec()
+    }
+
+    // adds the ray's intersections with the shape, which is the object with
+    // the given id, to the end of out
+    pub fn intersects_into(&self, id: ObjectId, r: &Ray, out: &mut IntersectionBuffer) {
+        stats::count(Counter::IntersectionTests);
+        let object_space_ray = r.transform(self.transform.inverse());
+        match &self.shape {
+            ShapeType::Sphere => sphere::intersects(id, &object_space_ray, out),
+            ShapeType::Plane => plane::intersects(id, &object_space_ray, out),
+            ShapeType::Cube => cube::intersects(id, &object_space_ray, out),
+            ShapeType::Cylinder {
+                minimum,
+                maximum,
+                closed,
+            } => cylinder::intersects(id, &object_space_ray, *minimum, *maximum, *closed, out),
+            ShapeType::Cone {
+                minimum,
+                maximum,
+                closed,
+            } => cone::intersects(id, &object_space_ray, *minimum, *maximum, *closed, out),
+            ShapeType::Triangle { p1, e1, e2, .. } => {
+                triangle::intersects(id, &object_space_ray, p1, e1, e2, out)
+            }
+            ShapeType::Mesh(mesh) => mesh.intersects(id, &object_space_ray, out),
         }
     }
 }
@@ -137,15 +726,10 @@ pub mod plane {
         }
     }
 
-    pub(super) fn intersects<'a>(plane: &'a Shape, r: &Ray) -> Vec<Intersection<'a>> {
-        const EPSILON: f64 = 0.00001;
-        if r.direction.y.abs() < EPSILON {
-            vec![]
-        } else {
-            vec![Intersection::new(
-                -r.origin.y / r.direction.normalise().y,
-                plane,
-            )]
+    pub(super) fn intersects(id: ObjectId, r: &Ray, out: &mut IntersectionBuffer) {
+        const EPSILON: Float = 0.00001;
+        if r.direction.y.abs() >= EPSILON {
+            out.push(Intersection::new(-r.origin.y / r.direction.y, id));
         }
     }
 }
@@ -175,28 +759,457 @@ pub mod sphere {
         }
     }
 
-    pub(super) fn intersects<'a>(sphere: &'a Shape, r: &Ray) -> Vec<Intersection<'a>> {
+    pub(super) fn intersects(id: ObjectId, r: &Ray, out: &mut IntersectionBuffer) {
         let sphere_to_ray = r.origin - Tuple::point_new(0.0, 0.0, 0.0);
         let a = r.direction.dot(&r.direction);
         let b = 2.0 * r.direction.dot(&sphere_to_ray);
         let c = sphere_to_ray.dot(&sphere_to_ray) - 1.0;
         let discriminant = b.powi(2) - (4.0 * a * c);
-        match discriminant < 0.0 {
-            true => Vec::new(),
-            false => {
-                let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
-                let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
-                vec![Intersection::new(t1, sphere), Intersection::new(t2, sphere)]
+        if discriminant >= 0.0 {
+            let t1 = (-b - discriminant.sqrt()) / (2.0 * a);
+            let t2 = (-b + discriminant.sqrt()) / (2.0 * a);
+            out.push(Intersection::new(t1, id));
+            out.push(Intersection::new(t2, id));
+        }
+    }
+}
+
+pub mod cube {
+    use super::*;
+    const EPSILON: Float = 0.00001;
+
+    // the normal points along whichever axis the point is furthest out on
+    pub(super) fn normal_at(point: &Tuple) -> Tuple {
+        let max = point.x.abs().max(point.y.abs()).max(point.z.abs());
+        if max == point.x.abs() {
+            Tuple::vector_new(point.x, 0.0, 0.0)
+        } else if max == point.y.abs() {
+            Tuple::vector_new(0.0, point.y, 0.0)
+        } else {
+            Tuple::vector_new(0.0, 0.0, point.z)
+        }
+    }
+
+    pub fn default() -> Shape {
+        Shape {
+            shape: ShapeType::Cube,
+            ..Default::default()
+        }
+    }
+
+    // finds where the ray crosses the two planes bounding the cube on one axis
+    fn check_axis(origin: Float, direction: Float) -> (Float, Float) {
+        let tmin_numerator = -1.0 - origin;
+        let tmax_numerator = 1.0 - origin;
+        let (tmin, tmax) = if direction.abs() >= EPSILON {
+            (tmin_numerator / direction, tmax_numerator / direction)
+        } else {
+            (
+                tmin_numerator * Float::INFINITY,
+                tmax_numerator * Float::INFINITY,
+            )
+        };
+        if tmin > tmax {
+            (tmax, tmin)
+        } else {
+            (tmin, tmax)
+        }
+    }
+
+    pub(super) fn intersects(id: ObjectId, r: &Ray, out: &mut IntersectionBuffer) {
+        let (xtmin, xtmax) = check_axis(r.origin.x, r.direction.x);
+        let (ytmin, ytmax) = check_axis(r.origin.y, r.direction.y);
+        let (ztmin, ztmax) = check_axis(r.origin.z, r.direction.z);
+        let tmin = xtmin.max(ytmin).max(ztmin);
+        let tmax = xtmax.min(ytmax).min(ztmax);
+        if tmin <= tmax {
+            out.push(Intersection::new(tmin, id));
+            out.push(Intersection::new(tmax, id));
+        }
+    }
+}
+
+pub mod cylinder {
+    use super::*;
+    const EPSILON: Float = 0.00001;
+
+    pub(super) fn normal_at(point: &Tuple, minimum: Float, maximum: Float) -> Tuple {
+        let dist = point.x.powi(2) + point.z.powi(2);
+        if dist < 1.0 && point.y >= maximum - EPSILON {
+            Tuple::vector_new(0.0, 1.0, 0.0)
+        } else if dist < 1.0 && point.y <= minimum + EPSILON {
+            Tuple::vector_new(0.0, -1.0, 0.0)
+        } else {
+            Tuple::vector_new(point.x, 0.0, point.z)
+        }
+    }
+
+    // An infinitely long, uncapped cylinder of radius 1 around the y axis
+    pub fn default() -> Shape {
+        Shape {
+            shape: ShapeType::Cylinder {
+                minimum: Float::NEG_INFINITY,
+                maximum: Float::INFINITY,
+                closed: false,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn truncated(minimum: Float, maximum: Float, closed: bool) -> Shape {
+        Shape {
+            shape: ShapeType::Cylinder {
+                minimum,
+                maximum,
+                closed,
+            },
+            ..Default::default()
+        }
+    }
+
+    // checks to see if the intersection at t is within the radius of the cap
+    fn check_cap(r: &Ray, t: Float) -> bool {
+        let x = r.origin.x + t * r.direction.x;
+        let z = r.origin.z + t * r.direction.z;
+        x.powi(2) + z.powi(2) <= 1.0
+    }
+
+    pub(super) fn intersects(
+        id: ObjectId,
+        r: &Ray,
+        minimum: Float,
+        maximum: Float,
+        closed: bool,
+        out: &mut IntersectionBuffer,
+    ) {
+        let a = r.direction.x.powi(2) + r.direction.z.powi(2);
+        // rays parallel to the y axis can only hit the caps
+        if a.abs() > EPSILON {
+            let b = 2.0 * r.origin.x * r.direction.x + 2.0 * r.origin.z * r.direction.z;
+            let c = r.origin.x.powi(2) + r.origin.z.powi(2) - 1.0;
+            let discriminant = b.powi(2) - 4.0 * a * c;
+            if discriminant < 0.0 {
+                return;
+            }
+            let t0 = (-b - discriminant.sqrt()) / (2.0 * a);
+            let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
+            for t in [t0.min(t1), t0.max(t1)] {
+                let y = r.origin.y + t * r.direction.y;
+                if minimum < y && y < maximum {
+                    out.push(Intersection::new(t, id));
+                }
+            }
+        }
+        if closed && r.direction.y.abs() > EPSILON {
+            for t in [
+                (minimum - r.origin.y) / r.direction.y,
+                (maximum - r.origin.y) / r.direction.y,
+            ] {
+                if check_cap(r, t) {
+                    out.push(Intersection::new(t, id));
+                }
+            }
+        }
+    }
+}
+
+pub mod cone {
+    use super::*;
+    const EPSILON: Float = 0.00001;
+
+    pub(super) fn normal_at(point: &Tuple, minimum: Float, maximum: Float) -> Tuple {
+        let dist = point.x.powi(2) + point.z.powi(2);
+        if dist < maximum.powi(2) && point.y >= maximum - EPSILON {
+            Tuple::vector_new(0.0, 1.0, 0.0)
+        } else if dist < minimum.powi(2) && point.y <= minimum + EPSILON {
+            Tuple::vector_new(0.0, -1.0, 0.0)
+        } else {
+            let y = dist.sqrt();
+            let y = if point.y > 0.0 { -y } else { y };
+            Tuple::vector_new(point.x, y, point.z)
+        }
+    }
+
+    // A double-napped cone around the y axis, with its apex at the origin.
+    // Infinitely long and uncapped.
+    pub fn default() -> Shape {
+        Shape {
+            shape: ShapeType::Cone {
+                minimum: Float::NEG_INFINITY,
+                maximum: Float::INFINITY,
+                closed: false,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn truncated(minimum: Float, maximum: Float, closed: bool) -> Shape {
+        Shape {
+            shape: ShapeType::Cone {
+                minimum,
+                maximum,
+                closed,
+            },
+            ..Default::default()
+        }
+    }
+
+    // the radius of a cone's cap is the absolute value of y at the cap
+    fn check_cap(r: &Ray, t: Float, y: Float) -> bool {
+        let x = r.origin.x + t * r.direction.x;
+        let z = r.origin.z + t * r.direction.z;
+        x.powi(2) + z.powi(2) <= y.powi(2)
+    }
+
+    pub(super) fn intersects(
+        id: ObjectId,
+        r: &Ray,
+        minimum: Float,
+        maximum: Float,
+        closed: bool,
+        out: &mut IntersectionBuffer,
+    ) {
+        let (o, d) = (&r.origin, &r.direction);
+        let a = d.x.powi(2) - d.y.powi(2) + d.z.powi(2);
+        let b = 2.0 * o.x * d.x - 2.0 * o.y * d.y + 2.0 * o.z * d.z;
+        let c = o.x.powi(2) - o.y.powi(2) + o.z.powi(2);
+        // hits on the sides only count between the ends
+        let mut side = |t: Float| {
+            let y = o.y + t * d.y;
+            if minimum < y && y < maximum {
+                out.push(Intersection::new(t, id));
+            }
+        };
+        if a.abs() < EPSILON {
+            // ray is parallel to one of the cone's halves, so hits at most once
+            if b.abs() > EPSILON {
+                side(-c / (2.0 * b));
+            }
+        } else {
+            let discriminant = b.powi(2) - 4.0 * a * c;
+            if discriminant >= 0.0 {
+                let t0 = (-b - discriminant.sqrt()) / (2.0 * a);
+                let t1 = (-b + discriminant.sqrt()) / (2.0 * a);
+                side(t0.min(t1));
+                side(t0.max(t1));
+            }
+        }
+        if closed && d.y.abs() > EPSILON {
+            for cap in [minimum, maximum] {
+                let t = (cap - o.y) / d.y;
+                if check_cap(r, t, cap) {
+                    out.push(Intersection::new(t, id));
+                }
+            }
+        }
+    }
+}
+
+pub mod triangle {
+    use super::*;
+    const EPSILON: Float = 0.00001;
+
+    pub fn new(p1: Tuple, p2: Tuple, p3: Tuple) -> Shape {
+        let e1 = p2 - p1;
+        let e2 = p3 - p1;
+        let normal = e2.cross(&e1).normalise();
+        Shape {
+            shape: ShapeType::Triangle {
+                p1,
+                p2,
+                p3,
+                e1,
+                e2,
+                normal,
+            },
+            ..Default::default()
+        }
+    }
+
+    pub(super) fn intersects(
+        id: ObjectId,
+        r: &Ray,
+        p1: &Tuple,
+        e1: &Tuple,
+        e2: &Tuple,
+        out: &mut IntersectionBuffer,
+    ) {
+        if let Some(t) = hit(r, p1, e1, e2) {
+            out.push(Intersection::new(t, id));
+        }
+    }
+
+    // Moller-Trumbore: u and v are the barycentric coordinates of the hit,
+    // which is only inside the triangle if both are positive and sum to at
+    // most 1
+    pub(super) fn hit(r: &Ray, p1: &Tuple, e1: &Tuple, e2: &Tuple) -> Option<Float> {
+        let dir_cross_e2 = r.direction.cross(e2);
+        let det = e1.dot(&dir_cross_e2);
+        // ray is parallel to the triangle
+        if det.abs() < EPSILON {
+            return None;
+        }
+        let f = 1.0 / det;
+        let p1_to_origin = r.origin - *p1;
+        let u = f * p1_to_origin.dot(&dir_cross_e2);
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+        let origin_cross_e1 = p1_to_origin.cross(e1);
+        let v = f * r.direction.dot(&origin_cross_e1);
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+        Some(f * e2.dot(&origin_cross_e1))
+    }
+}
+
+pub mod mesh {
+    use super::*;
+    use crate::bvh::Bvh;
+    use std::collections::HashMap;
+
+    // Triangles that share their corners. Each corner's kept once, however
+    // many triangles meet at it, and the triangles refer to their corners by
+    // where they are in the list. The triangles have a BVH of their own, so
+    // the whole mesh can be one shape in the world - where separate triangles
+    // would each need a material and transform too, which for a mesh of
+    // millions of triangles adds up to far more memory.
+    pub struct Mesh {
+        vertices: Vec<Tuple>,
+        faces: Vec<[u32; 3]>,
+        bvh: Bvh,
+        bounds: Bounds,
+    }
+
+    // only the corners and triangles are saved - the rest is worked out again
+    // when the mesh is loaded
+    #[derive(Serialize, Deserialize)]
+    struct Saved<V, F> {
+        vertices: V,
+        faces: F,
+    }
+
+    impl Serialize for Mesh {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            Saved {
+                vertices: &self.vertices,
+                faces: &self.faces,
+            }
+            .serialize(serializer)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Mesh {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Mesh, D::Error> {
+            let saved: Saved<Vec<Tuple>, Vec<[u32; 3]>> = Saved::deserialize(deserializer)?;
+            let count = saved.vertices.len();
+            if saved.faces.iter().flatten().any(|&i| i as usize >= count) {
+                return Err(serde::de::Error::custom(format!(
+                    "a mesh's triangles can only use its {} corners",
+                    count
+                )));
+            }
+            Ok(Mesh::from_parts(saved.vertices, saved.faces))
+        }
+    }
+
+    impl Mesh {
+        // Corners of different triangles that are in exactly the same place
+        // are merged into one.
+        pub fn new(triangles: impl IntoIterator<Item = [Tuple; 3]>) -> Mesh {
+            let mut vertices = Vec::new();
+            let mut index = HashMap::new();
+            let faces: Vec<[u32; 3]> = triangles
+                .into_iter()
+                .map(|points| {
+                    points.map(|p| {
+                        *index
+                            .entry((p.x.to_bits(), p.y.to_bits(), p.z.to_bits()))
+                            .or_insert_with(|| {
+                                vertices.push(p);
+                                (vertices.len() - 1) as u32
+                            })
+                    })
+                })
+                .collect();
+            vertices.shrink_to_fit();
+            Mesh::from_parts(vertices, faces)
+        }
+
+        fn from_parts(vertices: Vec<Tuple>, faces: Vec<[u32; 3]>) -> Mesh {
+            let corners = |face: &[u32; 3]| face.map(|i| vertices[i as usize]);
+            Mesh {
+                bvh: Bvh::around(faces.iter().map(|f| Some(Bounds::around(corners(f))))),
+                bounds: Bounds::around(vertices.iter().copied()),
+                vertices,
+                faces,
             }
         }
+
+        pub fn vertex_count(&self) -> usize {
+            self.vertices.len()
+        }
+
+        pub fn face_count(&self) -> usize {
+            self.faces.len()
+        }
+
+        pub fn bounds(&self) -> Bounds {
+            self.bounds
+        }
+
+        fn corners(&self, face: u32) -> [Tuple; 3] {
+            self.faces[face as usize].map(|i| self.vertices[i as usize])
+        }
+
+        // facing the same way as a triangle with the same corners
+        pub(super) fn normal(&self, face: u32) -> Tuple {
+            let [p1, p2, p3] = self.corners(face);
+            (p3 - p1).cross(&(p2 - p1)).normalise()
+        }
+
+        pub(super) fn intersects(&self, id: ObjectId, r: &Ray, out: &mut IntersectionBuffer) {
+            let mut stack = out.take_mesh_stack();
+            let everywhere = (Float::NEG_INFINITY, Float::INFINITY);
+            self.bvh.visit(r, everywhere, &mut stack, |face| {
+                let [p1, p2, p3] = self.corners(face as u32);
+                if let Some(t) = triangle::hit(r, &p1, &(p2 - p1), &(p3 - p1)) {
+                    out.push(Intersection::on_face(t, id, face as u32));
+                }
+                false
+            });
+            out.put_mesh_stack(stack);
+        }
+    }
+
+    // printing every corner would be far too much
+    impl fmt::Debug for Mesh {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(
+                f,
+                "Mesh {{ {} vertices, {} faces }}",
+                self.vertices.len(),
+                self.faces.len()
+            )
+        }
+    }
+
+    impl PartialEq for Mesh {
+        fn eq(&self, other: &Mesh) -> bool {
+            self.vertices == other.vertices && self.faces == other.faces
+        }
     }
 }
+
 impl Default for Shape {
     fn default() -> Shape {
         Shape {
             material: Material::default(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
             shape: ShapeType::Sphere,
+            name: None,
+            light_links: None,
         }
     }
 }
@@ -213,6 +1226,8 @@ impl Default for Material {
             refractive_index: 1.0,
             transparency: 0.0,
             pattern: None,
+            emission: Colour::black(),
+            shader: None,
         }
     }
 }
@@ -225,120 +1240,420 @@ mod tests {
     use crate::lighting::{calculate_lighting, PointLight};
 
     #[test]
-    fn normal_of_sphere() {
-        let s = sphere::default();
-        let n = s.normal_at(&Tuple::point_new(1.0, 0.0, 0.0));
-        assert_eq!(n, Tuple::vector_new(1.0, 0.0, 0.0));
+    fn normal_of_sphere() {
+        let s = sphere::default();
+        let n = s.normal_at(&Tuple::point_new(1.0, 0.0, 0.0));
+        assert_eq!(n, Tuple::vector_new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn normal_of_translated_sphere() {
+        use crate::precision::consts::FRAC_1_SQRT_2;
+        let s = Shape {
+            transform: Matrix::translation(0.0, 1.0, 0.0).into(),
+            ..sphere::default()
+        };
+        let n = s.normal_at(&Tuple::point_new(0.0, 1.0 + FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
+        assert_eq!(n, Tuple::vector_new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
+    }
+
+    #[test]
+    fn normal_of_transformed_sphere() {
+        use crate::precision::consts::{FRAC_1_SQRT_2, PI};
+        let s = Shape {
+            transform: Matrix::rotation_z(PI / 5.0).scale(1.0, 0.5, 1.0).into(),
+            ..sphere::default()
+        };
+        let n = s.normal_at(&Tuple::point_new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
+        assert_eq!(n, Tuple::vector_new(0.0, 0.97014, -0.24254));
+    }
+
+    #[test]
+    fn normal_of_plane() {
+        let p = plane::default();
+        let n = p.normal_at(&Tuple::point_new(0.21, 0.543, 0.438294));
+        assert_eq!(n, Tuple::vector_new(0.0, 1.0, 0.0))
+    }
+
+    #[test]
+    fn normal_of_rotated_plane() {
+        let p = Shape {
+            shape: ShapeType::Plane,
+            transform: Matrix::rotation_x(crate::precision::consts::PI / 2.0).into(),
+            ..Default::default()
+        };
+        let n = p.normal_at(&Tuple::point_new(0.21, 0.543, 0.438294));
+        assert_eq!(n, Tuple::vector_new(0.0, 0.0, 1.0))
+    }
+
+    #[test]
+    fn intersection_with_ray_parallel_to_plane() {
+        let p = plane::default();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 10.0, 0.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        let xs = p.intersects(ObjectId(0), &r);
+        assert_eq!(xs, Vec::new());
+    }
+
+    #[test]
+    fn intersection_with_ray_coplanar_to_plane() {
+        let p = plane::default();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, 0.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        let xs = p.intersects(ObjectId(0), &r);
+        assert_eq!(xs, Vec::new());
+    }
+
+    #[test]
+    fn ray_intersecting_plane_from_above() {
+        let p = plane::default();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 1.0, 0.0),
+            Tuple::vector_new(0.0, -1.0, 0.0),
+        );
+        let xs = p.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.0);
+        assert_eq!(xs[0].object, ObjectId(0));
+    }
+
+    #[test]
+    fn ray_intersecting_plane_from_below() {
+        let p = plane::default();
+        let r = Ray::new(
+            Tuple::point_new(0.0, -1.0, 0.0),
+            Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let xs = p.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 1);
+        assert_eq!(xs[0].t, 1.0);
+        assert_eq!(xs[0].object, ObjectId(0));
+    }
+
+    #[test]
+    fn ray_intersecting_transformed_plane() {
+        let p = Shape {
+            transform: Matrix::rotation_x(crate::precision::consts::PI / 2.0).into(),
+            ..plane::default()
+        };
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -2.0),
+            Tuple::vector_new(0.0, 1.0, 1.0),
+        );
+        let xs = p.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 1);
+        assert!(float_eq(xs[0].t, 2.0 * crate::precision::consts::SQRT_2));
+        assert_eq!(xs[0].object, ObjectId(0));
+    }
+
+    #[test]
+    fn t_is_the_same_in_a_stretched_planes_space() {
+        let mut p = plane::default();
+        p.set_transform(Matrix::scaling(1.0, 2.0, 1.0));
+        let r = Ray::new(
+            Tuple::point_new(0.0, 1.0, 0.0),
+            Tuple::vector_new(0.0, -1.0, 1.0),
+        );
+        // it hits at (0, 0, 1), however the plane's stretched
+        let xs = p.intersects(ObjectId(0), &r);
+        assert!(float_eq(xs[0].t, crate::precision::consts::SQRT_2));
+    }
+    #[test]
+    fn ray_intersects_cube() {
+        let c = cube::default();
+        let cases = [
+            ((5.0, 0.5, 0.0), (-1.0, 0.0, 0.0), 4.0, 6.0),
+            ((-5.0, 0.5, 0.0), (1.0, 0.0, 0.0), 4.0, 6.0),
+            ((0.5, 5.0, 0.0), (0.0, -1.0, 0.0), 4.0, 6.0),
+            ((0.5, -5.0, 0.0), (0.0, 1.0, 0.0), 4.0, 6.0),
+            ((0.5, 0.0, 5.0), (0.0, 0.0, -1.0), 4.0, 6.0),
+            ((0.5, 0.0, -5.0), (0.0, 0.0, 1.0), 4.0, 6.0),
+            ((0.0, 0.5, 0.0), (0.0, 0.0, 1.0), -1.0, 1.0),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz), t1, t2) in cases {
+            let r = Ray::new(Tuple::point_new(ox, oy, oz), Tuple::vector_new(dx, dy, dz));
+            let xs = c.intersects(ObjectId(0), &r);
+            assert_eq!(xs.len(), 2);
+            assert_eq!(xs[0].t, t1);
+            assert_eq!(xs[1].t, t2);
+        }
+    }
+
+    #[test]
+    fn ray_misses_cube() {
+        let c = cube::default();
+        let cases = [
+            ((-2.0, 0.0, 0.0), (0.2673, 0.5345, 0.8018)),
+            ((0.0, -2.0, 0.0), (0.8018, 0.2673, 0.5345)),
+            ((0.0, 0.0, -2.0), (0.5345, 0.8018, 0.2673)),
+            ((2.0, 0.0, 2.0), (0.0, 0.0, -1.0)),
+            ((0.0, 2.0, 2.0), (0.0, -1.0, 0.0)),
+            ((2.0, 2.0, 0.0), (-1.0, 0.0, 0.0)),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz)) in cases {
+            let r = Ray::new(Tuple::point_new(ox, oy, oz), Tuple::vector_new(dx, dy, dz));
+            assert_eq!(c.intersects(ObjectId(0), &r).len(), 0);
+        }
+    }
+
+    #[test]
+    fn normal_of_cube() {
+        let c = cube::default();
+        let cases = [
+            ((1.0, 0.5, -0.8), (1.0, 0.0, 0.0)),
+            ((-1.0, -0.2, 0.9), (-1.0, 0.0, 0.0)),
+            ((-0.4, 1.0, -0.1), (0.0, 1.0, 0.0)),
+            ((0.3, -1.0, -0.7), (0.0, -1.0, 0.0)),
+            ((-0.6, 0.3, 1.0), (0.0, 0.0, 1.0)),
+            ((0.4, 0.4, -1.0), (0.0, 0.0, -1.0)),
+            ((1.0, 1.0, 1.0), (1.0, 0.0, 0.0)),
+            ((-1.0, -1.0, -1.0), (-1.0, 0.0, 0.0)),
+        ];
+        for ((px, py, pz), (nx, ny, nz)) in cases {
+            assert_eq!(
+                c.normal_at(&Tuple::point_new(px, py, pz)),
+                Tuple::vector_new(nx, ny, nz)
+            );
+        }
+    }
+
+    fn test_triangle() -> Shape {
+        triangle::new(
+            Tuple::point_new(0.0, 1.0, 0.0),
+            Tuple::point_new(-1.0, 0.0, 0.0),
+            Tuple::point_new(1.0, 0.0, 0.0),
+        )
+    }
+
+    #[test]
+    fn constructing_triangle() {
+        match test_triangle().shape {
+            ShapeType::Triangle { e1, e2, normal, .. } => {
+                assert_eq!(e1, Tuple::vector_new(-1.0, -1.0, 0.0));
+                assert_eq!(e2, Tuple::vector_new(1.0, -1.0, 0.0));
+                assert_eq!(normal, Tuple::vector_new(0.0, 0.0, -1.0));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn normal_of_triangle_is_constant() {
+        let t = test_triangle();
+        for (x, y) in [(0.0, 0.5), (-0.5, 0.75), (0.5, 0.25)] {
+            assert_eq!(
+                t.normal_at(&Tuple::point_new(x, y, 0.0)),
+                Tuple::vector_new(0.0, 0.0, -1.0)
+            );
+        }
+    }
+
+    #[test]
+    fn ray_misses_triangle() {
+        let t = test_triangle();
+        let cases = [
+            // parallel to the triangle
+            ((0.0, -1.0, -2.0), (0.0, 1.0, 0.0)),
+            // past each of the three edges
+            ((1.0, 1.0, -2.0), (0.0, 0.0, 1.0)),
+            ((-1.0, 1.0, -2.0), (0.0, 0.0, 1.0)),
+            ((0.0, -1.0, -2.0), (0.0, 0.0, 1.0)),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz)) in cases {
+            let r = Ray::new(Tuple::point_new(ox, oy, oz), Tuple::vector_new(dx, dy, dz));
+            assert_eq!(t.intersects(ObjectId(0), &r).len(), 0);
+        }
+    }
+
+    #[test]
+    fn ray_strikes_triangle() {
+        let t = test_triangle();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.5, -2.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        let xs = t.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 1);
+        assert!(float_eq(xs[0].t, 2.0));
+        assert!(t.shape.is_open());
+    }
+
+    #[test]
+    fn ray_misses_cylinder() {
+        let cyl = cylinder::default();
+        let rays = [
+            ((1.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+            ((0.0, 0.0, 0.0), (0.0, 1.0, 0.0)),
+            ((0.0, 0.0, -5.0), (1.0, 1.0, 1.0)),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz)) in rays {
+            let r = Ray::new(
+                Tuple::point_new(ox, oy, oz),
+                Tuple::vector_new(dx, dy, dz).normalise(),
+            );
+            assert_eq!(cyl.intersects(ObjectId(0), &r).len(), 0);
+        }
     }
 
     #[test]
-    fn normal_of_translated_sphere() {
-        let s = Shape {
-            transform: Matrix::translation(0.0, 1.0, 0.0),
-            ..sphere::default()
-        };
-        let n = s.normal_at(&Tuple::point_new(0.0, 1.70711, -0.70711));
-        assert_eq!(n, Tuple::vector_new(0.0, 0.70711, -0.70711));
+    fn ray_strikes_cylinder() {
+        let cyl = cylinder::default();
+        let r = Ray::new(
+            Tuple::point_new(0.5, 0.0, -5.0),
+            Tuple::vector_new(0.1, 1.0, 1.0).normalise(),
+        );
+        let xs = cyl.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - 6.80798).abs() < 0.0001);
+        assert!((xs[1].t - 7.08872).abs() < 0.0001);
     }
 
     #[test]
-    fn normal_of_transformed_sphere() {
-        use std::f64::consts::{FRAC_1_SQRT_2, PI};
-        let s = Shape {
-            transform: Matrix::rotation_z(PI / 5.0).scale(1.0, 0.5, 1.0),
-            ..sphere::default()
-        };
-        let n = s.normal_at(&Tuple::point_new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2));
-        assert_eq!(n, Tuple::vector_new(0.0, 0.97014, -0.24254));
+    fn normal_of_cylinder() {
+        let cyl = cylinder::default();
+        let n = cyl.normal_at(&Tuple::point_new(0.0, 5.0, -1.0));
+        assert_eq!(n, Tuple::vector_new(0.0, 0.0, -1.0));
+        let n = cyl.normal_at(&Tuple::point_new(-1.0, 1.0, 0.0));
+        assert_eq!(n, Tuple::vector_new(-1.0, 0.0, 0.0));
     }
 
     #[test]
-    fn normal_of_plane() {
-        let p = plane::default();
-        let n = p.normal_at(&Tuple::point_new(0.21, 0.543, 0.438294));
-        assert_eq!(n, Tuple::vector_new(0.0, 1.0, 0.0))
+    fn intersecting_truncated_cylinder() {
+        let cyl = cylinder::truncated(1.0, 2.0, false);
+        let cases = [
+            ((0.0, 1.5, 0.0), (0.1, 1.0, 0.0), 0),
+            ((0.0, 3.0, -5.0), (0.0, 0.0, 1.0), 0),
+            ((0.0, 0.0, -5.0), (0.0, 0.0, 1.0), 0),
+            ((0.0, 2.0, -5.0), (0.0, 0.0, 1.0), 0),
+            ((0.0, 1.0, -5.0), (0.0, 0.0, 1.0), 0),
+            ((0.0, 1.5, -2.0), (0.0, 0.0, 1.0), 2),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz), count) in cases {
+            let r = Ray::new(
+                Tuple::point_new(ox, oy, oz),
+                Tuple::vector_new(dx, dy, dz).normalise(),
+            );
+            assert_eq!(cyl.intersects(ObjectId(0), &r).len(), count);
+        }
     }
 
     #[test]
-    fn normal_of_rotated_plane() {
-        let p = Shape {
-            shape: ShapeType::Plane,
-            transform: Matrix::rotation_x(std::f64::consts::PI / 2.0),
-            ..Default::default()
-        };
-        let n = p.normal_at(&Tuple::point_new(0.21, 0.543, 0.438294));
-        assert_eq!(n, Tuple::vector_new(0.0, 0.0, 1.0))
+    fn intersecting_caps_of_closed_cylinder() {
+        let cyl = cylinder::truncated(1.0, 2.0, true);
+        let cases = [
+            ((0.0, 3.0, 0.0), (0.0, -1.0, 0.0), 2),
+            ((0.0, 3.0, -2.0), (0.0, -1.0, 2.0), 2),
+            ((0.0, 4.0, -2.0), (0.0, -1.0, 1.0), 2),
+            ((0.0, 0.0, -2.0), (0.0, 1.0, 2.0), 2),
+            ((0.0, -1.0, -2.0), (0.0, 1.0, 1.0), 2),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz), count) in cases {
+            let r = Ray::new(
+                Tuple::point_new(ox, oy, oz),
+                Tuple::vector_new(dx, dy, dz).normalise(),
+            );
+            assert_eq!(cyl.intersects(ObjectId(0), &r).len(), count);
+        }
     }
 
     #[test]
-    fn intersection_with_ray_parallel_to_plane() {
-        let p = plane::default();
-        let r = Ray::new(
-            Tuple::point_new(0.0, 10.0, 0.0),
-            Tuple::vector_new(0.0, 0.0, 1.0),
+    fn normal_on_cylinder_caps() {
+        let cyl = cylinder::truncated(1.0, 2.0, true);
+        assert_eq!(
+            cyl.normal_at(&Tuple::point_new(0.5, 1.0, 0.0)),
+            Tuple::vector_new(0.0, -1.0, 0.0)
+        );
+        assert_eq!(
+            cyl.normal_at(&Tuple::point_new(0.0, 2.0, 0.5)),
+            Tuple::vector_new(0.0, 1.0, 0.0)
         );
-        let xs = p.intersects(&r);
-        assert_eq!(xs, Vec::new());
     }
 
     #[test]
-    fn intersection_with_ray_coplanar_to_plane() {
-        let p = plane::default();
+    fn ray_through_open_end_of_cylinder_hits_wall_once() {
+        let cyl = cylinder::truncated(-1.0, 1.0, false);
         let r = Ray::new(
-            Tuple::point_new(0.0, 0.0, 0.0),
-            Tuple::vector_new(0.0, 0.0, 1.0),
+            Tuple::point_new(0.0, -2.0, 0.0),
+            Tuple::vector_new(0.0, 1.0, 0.5),
         );
-        let xs = p.intersects(&r);
-        assert_eq!(xs, Vec::new());
+        let xs = cyl.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 1);
+        // it hits at (0, 0, 1)
+        assert!(float_eq(xs[0].t, Float::sqrt(5.0)));
+        assert!(cyl.shape.is_open());
+        assert!(!cylinder::default().shape.is_open());
+        assert!(!cylinder::truncated(-1.0, 1.0, true).shape.is_open());
     }
 
     #[test]
-    fn ray_intersecting_plane_from_above() {
-        let p = plane::default();
+    fn intersecting_cone() {
+        let c = cone::default();
         let r = Ray::new(
-            Tuple::point_new(0.0, 1.0, 0.0),
-            Tuple::vector_new(0.0, -1.0, 0.0),
+            Tuple::point_new(1.0, 1.0, -5.0),
+            Tuple::vector_new(-0.5, -1.0, 1.0).normalise(),
         );
-        let xs = p.intersects(&r);
-        assert_eq!(xs.len(), 1);
-        assert_eq!(xs[0].t, 1.0);
-        assert_eq!(xs[0].object, &p);
+        let xs = c.intersects(ObjectId(0), &r);
+        assert_eq!(xs.len(), 2);
+        assert!((xs[0].t - 4.55006).abs() < 0.0001);
+        assert!((xs[1].t - 49.44994).abs() < 0.0001);
     }
 
     #[test]
-    fn ray_intersecting_plane_from_below() {
-        let p = plane::default();
+    fn intersecting_cone_parallel_to_one_half() {
+        let c = cone::default();
         let r = Ray::new(
-            Tuple::point_new(0.0, -1.0, 0.0),
-            Tuple::vector_new(0.0, 1.0, 0.0),
+            Tuple::point_new(0.0, 0.0, -1.0),
+            Tuple::vector_new(0.0, 1.0, 1.0).normalise(),
         );
-        let xs = p.intersects(&r);
+        let xs = c.intersects(ObjectId(0), &r);
         assert_eq!(xs.len(), 1);
-        assert_eq!(xs[0].t, 1.0);
-        assert_eq!(xs[0].object, &p);
+        assert!((xs[0].t - 0.35355).abs() < 0.0001);
     }
 
     #[test]
-    fn ray_intersecting_transformed_plane() {
-        let p = Shape {
-            transform: Matrix::rotation_x(std::f64::consts::PI / 2.0),
-            ..plane::default()
-        };
-        let r = Ray::new(
-            Tuple::point_new(0.0, 0.0, -2.0),
-            Tuple::vector_new(0.0, 1.0, 1.0),
+    fn intersecting_caps_of_closed_cone() {
+        let c = cone::truncated(-0.5, 0.5, true);
+        let cases = [
+            ((0.0, 0.0, -5.0), (0.0, 1.0, 0.0), 0),
+            ((0.0, 0.0, -0.25), (0.0, 1.0, 1.0), 2),
+            ((0.0, 0.0, -0.25), (0.0, 1.0, 0.0), 4),
+        ];
+        for ((ox, oy, oz), (dx, dy, dz), count) in cases {
+            let r = Ray::new(
+                Tuple::point_new(ox, oy, oz),
+                Tuple::vector_new(dx, dy, dz).normalise(),
+            );
+            assert_eq!(c.intersects(ObjectId(0), &r).len(), count);
+        }
+    }
+
+    #[test]
+    fn normal_of_cone() {
+        let c = cone::default();
+        assert_eq!(
+            cone::normal_at(
+                &Tuple::point_new(1.0, 1.0, 1.0),
+                Float::NEG_INFINITY,
+                Float::INFINITY
+            ),
+            Tuple::vector_new(1.0, -crate::precision::consts::SQRT_2, 1.0)
+        );
+        assert_eq!(
+            c.normal_at(&Tuple::point_new(-1.0, -1.0, 0.0)),
+            Tuple::vector_new(-1.0, 1.0, 0.0).normalise()
         );
-        let xs = p.intersects(&r);
-        assert_eq!(xs.len(), 1);
-        assert!(float_eq(xs[0].t, 2.0 * std::f64::consts::SQRT_2));
-        assert_eq!(xs[0].object, &p);
     }
+
     #[test]
     fn stripe_pattern_constant_in_y() {
         let pat = Pattern::Stripe {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         // default();
         let p1 = Tuple::point_new(0.0, 0.0, 0.0);
@@ -354,7 +1669,7 @@ mod tests {
         let pat = Pattern::Stripe {
             colour_a: Colour::black(),
             colour_b: Colour::white(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         let p1 = Tuple::point_new(0.0, 0.0, 0.0);
         let p2 = Tuple::point_new(0.0, 0.0, 1.0);
@@ -369,7 +1684,7 @@ mod tests {
         let pat = Pattern::Stripe {
             colour_a: Colour::black(),
             colour_b: Colour::white(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         let p1 = Tuple::point_new(0.0, 0.0, 0.0);
         let p2 = Tuple::point_new(1.01, 0.0, 0.0);
@@ -383,6 +1698,50 @@ mod tests {
         assert_eq!(pat.pattern_at(&p5), Colour::black());
     }
 
+    #[test]
+    fn gradient_interpolates_between_two_colours() {
+        let pattern = Pattern::Gradient {
+            stops: ColourStops::between(Colour::white(), Colour::black()),
+            transform: Transform::default(),
+        };
+        let cases = [
+            (0.0, Colour::white()),
+            (0.25, Colour::new(0.75, 0.75, 0.75)),
+            (0.5, Colour::new(0.5, 0.5, 0.5)),
+            (0.75, Colour::new(0.25, 0.25, 0.25)),
+            (1.25, Colour::new(0.75, 0.75, 0.75)),
+        ];
+        for (x, colour) in cases.iter() {
+            assert_eq!(
+                pattern.pattern_at(&Tuple::point_new(*x, 0.5, -3.0)),
+                *colour
+            );
+        }
+    }
+
+    #[test]
+    fn gradient_with_several_stops() {
+        let pattern = Pattern::Gradient {
+            stops: ColourStops::new(vec![
+                (0.2, Colour::new(1.0, 0.0, 0.0)),
+                (0.4, Colour::new(0.0, 1.0, 0.0)),
+                (0.8, Colour::new(0.0, 0.0, 1.0)),
+            ])
+            .unwrap(),
+            transform: Transform::default(),
+        };
+        let cases = [
+            (0.1, Colour::new(1.0, 0.0, 0.0)),
+            (0.3, Colour::new(0.5, 0.5, 0.0)),
+            (0.4, Colour::new(0.0, 1.0, 0.0)),
+            (0.7, Colour::new(0.0, 0.25, 0.75)),
+            (0.9, Colour::new(0.0, 0.0, 1.0)),
+        ];
+        for (x, colour) in cases.iter() {
+            assert_eq!(pattern.pattern_at(&Tuple::point_new(*x, 0.0, 0.0)), *colour);
+        }
+    }
+
     #[test]
     fn lighting_with_pattern() {
         let s = Shape::default();
@@ -390,7 +1749,7 @@ mod tests {
             pattern: Some(Pattern::Stripe {
                 colour_a: Colour::white(),
                 colour_b: Colour::black(),
-                transform: Matrix::identity(),
+                transform: Transform::default(),
             }),
             ambient: 1.0,
             diffuse: 0.0,
@@ -425,13 +1784,13 @@ mod tests {
     #[test]
     fn stripes_with_object_transformation() {
         let object = Shape {
-            transform: Matrix::scaling(2.0, 2.0, 2.0),
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
             ..sphere::default()
         };
         let pattern = Pattern::Stripe {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         let c = pattern.pattern_at_object(&object, &Tuple::point_new(1.5, 0.0, 0.0));
         assert_eq!(c, Colour::white());
@@ -445,7 +1804,7 @@ mod tests {
         let pattern = Pattern::Stripe {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::scaling(2.0, 2.0, 2.0),
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
         };
         let c = pattern.pattern_at_object(&object, &Tuple::point_new(1.5, 0.0, 0.0));
         assert_eq!(c, Colour::white());
@@ -454,24 +1813,458 @@ mod tests {
     #[test]
     fn stripes_with_pattern_and_object_transformation() {
         let object = Shape {
-            transform: Matrix::scaling(2.0, 2.0, 2.0),
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
             ..sphere::default()
         };
         let pattern = Pattern::Stripe {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::translation(0.5, 0.0, 0.0),
+            transform: Matrix::translation(0.5, 0.0, 0.0).into(),
         };
         let c = pattern.pattern_at_object(&object, &Tuple::point_new(2.5, 0.0, 0.0));
         assert_eq!(c, Colour::white());
     }
 
+    #[test]
+    fn ring_pattern_extends_in_x_and_z() {
+        let pattern = Pattern::Ring {
+            colour_a: Colour::white(),
+            colour_b: Colour::black(),
+            transform: Transform::default(),
+        };
+        let at = |x, y, z| pattern.pattern_at(&Tuple::point_new(x, y, z));
+        assert_eq!(at(0.0, 0.0, 0.0), Colour::white());
+        assert_eq!(at(1.0, 0.0, 0.0), Colour::black());
+        assert_eq!(at(0.0, 0.0, 1.0), Colour::black());
+        // 0.708 = just slightly more than √2/2
+        assert_eq!(at(0.708, 0.0, 0.708), Colour::black());
+        assert_eq!(at(0.0, 5.0, 0.5), Colour::white());
+    }
+
+    #[test]
+    fn colour_stops_are_checked_and_sorted() {
+        assert!(ColourStops::new(Vec::new()).is_err());
+        assert!(ColourStops::new(vec![(Float::NAN, Colour::white())]).is_err());
+        let stops = ColourStops::new(vec![(1.0, Colour::black()), (0.0, Colour::white())]);
+        assert_eq!(
+            stops.unwrap(),
+            ColourStops::between(Colour::white(), Colour::black())
+        );
+        // loading goes through the same checks
+        assert!(serde_json::from_str::<ColourStops>("[]").is_err());
+        let json = serde_json::to_string(&ColourStops::between(Colour::white(), Colour::black()));
+        assert!(serde_json::from_str::<ColourStops>(&json.unwrap()).is_ok());
+    }
+
+    #[test]
+    fn radial_gradient_blends_outwards() {
+        let pattern = Pattern::RadialGradient {
+            stops: ColourStops::between(Colour::white(), Colour::black()),
+            transform: Transform::default(),
+        };
+        let at = |x, y, z| pattern.pattern_at(&Tuple::point_new(x, y, z));
+        assert_eq!(at(0.0, 0.0, 0.0), Colour::white());
+        assert_eq!(at(0.0, 3.0, 0.25), Colour::new(0.75, 0.75, 0.75));
+        assert_eq!(at(0.3, 0.0, 0.4), Colour::new(0.5, 0.5, 0.5));
+        assert_eq!(at(1.25, 0.0, 0.0), Colour::new(0.75, 0.75, 0.75));
+    }
+
+    #[test]
+    fn masked_pattern_nests_patterns() {
+        // stripes alternating between a solid colour and a checker
+        let pattern = Pattern::Masked {
+            mask: Box::new(Pattern::Stripe {
+                colour_a: Colour::black(),
+                colour_b: Colour::white(),
+                transform: Transform::default(),
+            }),
+            pattern_a: Box::new(Pattern::Solid {
+                colour: Colour::new(1.0, 0.0, 0.0),
+                transform: Transform::default(),
+            }),
+            pattern_b: Box::new(Pattern::Check3D {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Matrix::scaling(0.5, 0.5, 0.5).into(),
+            }),
+            transform: Transform::default(),
+        };
+        let at = |x, y, z| pattern.pattern_at(&Tuple::point_new(x, y, z));
+        assert_eq!(at(0.25, 0.0, 0.25), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(at(0.75, 0.0, 0.75), Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(at(1.25, 0.0, 0.25), Colour::white());
+        assert_eq!(at(1.75, 0.0, 0.25), Colour::black());
+    }
+
+    #[test]
+    fn perturbed_pattern_moves_points() {
+        let stripes = Pattern::Stripe {
+            colour_a: Colour::white(),
+            colour_b: Colour::black(),
+            transform: Transform::default(),
+        };
+        let perturbed = |scale| Pattern::Perturbed {
+            pattern: Box::new(stripes.clone()),
+            scale,
+            transform: Transform::default(),
+        };
+        let points: Vec<Tuple> = (0..50)
+            .map(|i| {
+                Tuple::point_new(
+                    0.95 + 0.001 * i as Float,
+                    0.37 * i as Float,
+                    0.21 * i as Float,
+                )
+            })
+            .collect();
+        // with no noise it's just the stripes
+        let still = perturbed(0.0);
+        assert!(points
+            .iter()
+            .all(|p| still.pattern_at(p) == stripes.pattern_at(p)));
+        // near the edge of a stripe, some points get moved across it
+        let wobbly = perturbed(0.2);
+        assert!(points
+            .iter()
+            .any(|p| wobbly.pattern_at(p) != stripes.pattern_at(p)));
+    }
+
+    #[test]
+    fn blend_averages_two_patterns() {
+        let pattern = Pattern::Blend {
+            pattern_a: Box::new(Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            }),
+            pattern_b: Box::new(Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Matrix::rotation_y(crate::precision::consts::FRAC_PI_2).into(),
+            }),
+            weight: 0.5,
+            transform: Transform::default(),
+        };
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.5, 0.0, -0.5)),
+            Colour::white()
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(1.5, 0.0, -0.5)),
+            Colour::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(1.5, 0.0, -1.5)),
+            Colour::black()
+        );
+    }
+
+    #[test]
+    fn blend_weight_favours_second_pattern() {
+        let pattern = Pattern::Blend {
+            pattern_a: Box::new(Pattern::Test {
+                transform: Transform::default(),
+            }),
+            pattern_b: Box::new(Pattern::Test {
+                transform: Matrix::translation(1.0, 0.0, 0.0).into(),
+            }),
+            weight: 0.25,
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
+        };
+        let c = pattern.pattern_at_object(&Shape::default(), &Tuple::point_new(2.0, 1.0, 0.0));
+        assert_eq!(c, Colour::new(0.75, 0.5, 0.0));
+    }
+
+    #[test]
+    fn marble_without_turbulence_is_soft_stripes() {
+        let pattern = Pattern::Marble {
+            colour_a: Colour::white(),
+            colour_b: Colour::black(),
+            turbulence: 0.0,
+            transform: Transform::default(),
+        };
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.0, 3.0, 1.0)),
+            Colour::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.5, 0.0, 0.0)),
+            Colour::black()
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(1.5, 0.0, 0.0)),
+            Colour::white()
+        );
+    }
+
+    #[test]
+    fn marble_turbulence_disturbs_stripes() {
+        let pattern = Pattern::Marble {
+            colour_a: Colour::white(),
+            colour_b: Colour::black(),
+            turbulence: 2.0,
+            transform: Transform::default(),
+        };
+        let p = Tuple::point_new(0.5, 0.3, 0.7);
+        assert_ne!(pattern.pattern_at(&p), Colour::black());
+    }
+
+    #[test]
+    fn wood_without_turbulence_is_rings() {
+        let pattern = Pattern::Wood {
+            colour_a: Colour::white(),
+            colour_b: Colour::black(),
+            turbulence: 0.0,
+            transform: Transform::default(),
+        };
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.0, 5.0, 0.0)),
+            Colour::white()
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.0, 0.0, 1.5)),
+            Colour::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.6, 0.0, 0.8)),
+            Colour::white()
+        );
+    }
+
+    #[test]
+    fn granite_saturates_with_turbulence() {
+        let granite = |turbulence| Pattern::Granite {
+            colour_a: Colour::new(0.2, 0.2, 0.2),
+            colour_b: Colour::new(0.8, 0.8, 0.8),
+            turbulence,
+            transform: Transform::default(),
+        };
+        let p = Tuple::point_new(0.3, 0.4, 0.5);
+        assert_eq!(granite(0.0).pattern_at(&p), Colour::new(0.2, 0.2, 0.2));
+        assert_eq!(granite(1000.0).pattern_at(&p), Colour::new(0.8, 0.8, 0.8));
+    }
+
+    #[test]
+    fn texture_pattern_with_spherical_mapping() {
+        let mut image = Canvas::new(2, 2);
+        // top row is the northern hemisphere, left column the -z side
+        image.write_pixel((0, 0), Colour::new(1.0, 0.0, 0.0));
+        image.write_pixel((1, 0), Colour::new(0.0, 1.0, 0.0));
+        image.write_pixel((0, 1), Colour::new(0.0, 0.0, 1.0));
+        image.write_pixel((1, 1), Colour::white());
+        let pattern = Pattern::Texture {
+            texture: Arc::new(MipMap::new(image)),
+            mapping: UvMapping::Spherical,
+            transform: Transform::default(),
+        };
+        let s = sphere::default();
+        let c = pattern.pattern_at_object(&s, &Tuple::point_new(0.0, 0.9, -0.1));
+        assert_eq!(c, Colour::new(1.0, 0.0, 0.0));
+        let c = pattern.pattern_at_object(&s, &Tuple::point_new(0.0, -0.9, 0.1));
+        assert_eq!(c, Colour::white());
+    }
+
+    #[test]
+    fn texture_pattern_with_planar_mapping() {
+        let mut image = Canvas::new(2, 1);
+        image.write_pixel((1, 0), Colour::white());
+        let pattern = Pattern::Texture {
+            texture: Arc::new(MipMap::new(image)),
+            mapping: UvMapping::Planar,
+            transform: Transform::default(),
+        };
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.1, 0.0, 0.5)),
+            Colour::black()
+        );
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(2.9, 0.0, 0.5)),
+            Colour::white()
+        );
+    }
+
+    #[test]
+    fn filtered_texture_is_averaged_over_the_footprint() {
+        let mut image = Canvas::new(4, 4);
+        for y in 0..4 {
+            for x in 0..4 {
+                if (x + y) % 2 == 0 {
+                    image.write_pixel((x, y), Colour::white());
+                }
+            }
+        }
+        let pattern = Pattern::Texture {
+            texture: Arc::new(MipMap::new(image)),
+            mapping: UvMapping::Planar,
+            transform: Transform::default(),
+        };
+        let p = plane::default();
+        // lands exactly on a texel, so the filtered and nearest lookups agree
+        let point = Tuple::point_new(1.0 / 3.0, 0.0, 1.0 / 3.0);
+        let small = Footprint {
+            dpdx: Tuple::vector_new(0.01, 0.0, 0.0),
+            dpdy: Tuple::vector_new(0.0, 0.0, 0.01),
+        };
+        let large = Footprint {
+            dpdx: Tuple::vector_new(0.5, 0.0, 0.0),
+            dpdy: Tuple::vector_new(0.0, 0.0, 0.5),
+        };
+        assert_eq!(
+            pattern.pattern_at_object_filtered(&p, &point, Some(&small)),
+            pattern.pattern_at_object(&p, &point)
+        );
+        assert_eq!(
+            pattern.pattern_at_object_filtered(&p, &point, Some(&large)),
+            Colour::new(0.5, 0.5, 0.5)
+        );
+        assert_eq!(
+            pattern.pattern_at_object_filtered(&p, &point, None),
+            pattern.pattern_at_object(&p, &point)
+        );
+    }
+
+    fn cube_map_faces() -> [Arc<Canvas>; 6] {
+        // each face is a single pixel of a different colour
+        let colours = [
+            Colour::new(1.0, 0.0, 0.0),
+            Colour::new(0.0, 1.0, 0.0),
+            Colour::new(0.0, 0.0, 1.0),
+            Colour::new(1.0, 1.0, 0.0),
+            Colour::new(0.0, 1.0, 1.0),
+            Colour::new(1.0, 0.0, 1.0),
+        ];
+        colours.map(|c| {
+            let mut image = Canvas::new(1, 1);
+            image.write_pixel((0, 0), c);
+            Arc::new(image)
+        })
+    }
+
+    #[test]
+    fn cube_map_picks_face_image() {
+        let pattern = Pattern::CubeMap {
+            faces: cube_map_faces(),
+            transform: Transform::default(),
+        };
+        let cases = [
+            ((-1.0, 0.3, 0.2), Colour::new(1.0, 0.0, 0.0)),
+            ((1.0, -0.3, 0.2), Colour::new(0.0, 1.0, 0.0)),
+            ((0.1, 0.3, 1.0), Colour::new(0.0, 0.0, 1.0)),
+            ((0.1, 0.3, -1.0), Colour::new(1.0, 1.0, 0.0)),
+            ((0.1, 1.0, 0.2), Colour::new(0.0, 1.0, 1.0)),
+            ((0.1, -1.0, 0.2), Colour::new(1.0, 0.0, 1.0)),
+        ];
+        for ((x, y, z), colour) in cases.iter() {
+            assert_eq!(pattern.pattern_at(&Tuple::point_new(*x, *y, *z)), *colour);
+        }
+    }
+
+    #[test]
+    fn cube_map_projects_points_onto_cube() {
+        let mut front = Canvas::new(4, 1);
+        front.write_pixel((3, 0), Colour::white());
+        let mut faces = cube_map_faces();
+        faces[2] = Arc::new(front);
+        let pattern = Pattern::CubeMap {
+            faces,
+            transform: Transform::default(),
+        };
+        // (0.75, 0, 5) is projected to x = 0.15 on the front face
+        assert_eq!(
+            pattern.pattern_at(&Tuple::point_new(0.75, 0.0, 5.0)),
+            Colour::black()
+        );
+        assert_eq!(
+            pattern.pattern_at_direction(&Tuple::vector_new(1.0, 0.0, 1.5)),
+            Colour::white()
+        );
+    }
+
+    #[test]
+    fn function_pattern_is_sampled_in_pattern_space() {
+        let pattern = Pattern::Function {
+            function: PatternFunction::new(|p| {
+                if p.x * p.x + p.z * p.z < 1.0 {
+                    Colour::white()
+                } else {
+                    Colour::black()
+                }
+            }),
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
+        };
+        let object = Shape {
+            transform: Matrix::translation(5.0, 0.0, 0.0).into(),
+            ..sphere::default()
+        };
+        let c = pattern.pattern_at_object(&object, &Tuple::point_new(6.5, 0.0, 0.0));
+        assert_eq!(c, Colour::white());
+        let c = pattern.pattern_at_object(&object, &Tuple::point_new(7.5, 0.0, 0.0));
+        assert_eq!(c, Colour::black());
+    }
+
+    #[test]
+    fn function_patterns_are_only_equal_to_themselves() {
+        let f1 = PatternFunction::new(|_| Colour::white());
+        let f2 = PatternFunction::new(|_| Colour::white());
+        assert_eq!(f1, f1);
+        assert_ne!(f1, f2);
+    }
+
+    #[test]
+    fn uv_checkers_in_2d() {
+        let pattern = Pattern::UvCheck {
+            colour_a: Colour::black(),
+            colour_b: Colour::white(),
+            width: 2.0,
+            height: 2.0,
+            mapping: UvMapping::Planar,
+            transform: Transform::default(),
+        };
+        let cases = [
+            ((0.0, 0.0), Colour::black()),
+            ((0.5, 0.0), Colour::white()),
+            ((0.0, 0.5), Colour::white()),
+            ((0.5, 0.5), Colour::black()),
+            ((1.0, 1.0), Colour::black()),
+        ];
+        for ((x, z), colour) in cases.iter() {
+            assert_eq!(pattern.pattern_at(&Tuple::point_new(*x, 0.0, *z)), *colour);
+        }
+    }
+
+    #[test]
+    fn uv_checkers_on_sphere() {
+        let pattern = Pattern::UvCheck {
+            colour_a: Colour::black(),
+            colour_b: Colour::white(),
+            width: 16.0,
+            height: 8.0,
+            mapping: UvMapping::Spherical,
+            transform: Transform::default(),
+        };
+        let cases = [
+            ((0.4315, 0.4670, 0.7719), Colour::white()),
+            ((-0.9654, 0.2552, -0.0534), Colour::black()),
+            ((0.1039, 0.7090, 0.6975), Colour::white()),
+            ((-0.4986, -0.7856, -0.3663), Colour::black()),
+            ((-0.0317, -0.9395, 0.3411), Colour::black()),
+            ((0.4809, -0.7721, 0.4154), Colour::black()),
+            ((0.0285, -0.9612, -0.2745), Colour::black()),
+            ((-0.5734, -0.2162, -0.7903), Colour::white()),
+            ((0.7688, -0.1470, 0.6223), Colour::black()),
+            ((-0.7652, 0.2175, 0.6060), Colour::black()),
+        ];
+        for ((x, y, z), colour) in cases.iter() {
+            assert_eq!(pattern.pattern_at(&Tuple::point_new(*x, *y, *z)), *colour);
+        }
+    }
+
     #[test]
     fn checks_repeat_in_x() {
         let pattern = Pattern::Check3D {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         assert_eq!(
             pattern.pattern_at(&Tuple::point_new(0.0, 0.0, 0.0)),
@@ -492,7 +2285,7 @@ mod tests {
         let pattern = Pattern::Check3D {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         assert_eq!(
             pattern.pattern_at(&Tuple::point_new(0.0, 0.0, 0.0)),
@@ -513,7 +2306,7 @@ mod tests {
         let pattern = Pattern::Check3D {
             colour_a: Colour::white(),
             colour_b: Colour::black(),
-            transform: Matrix::identity(),
+            transform: Transform::default(),
         };
         assert_eq!(
             pattern.pattern_at(&Tuple::point_new(0.0, 0.0, 0.0)),
@@ -0,0 +1,212 @@
+// Counts of the work done while rendering, for --stats. Each thread counts
+// on its own, and only adds its counts to the totals once it's finished a
+// piece of the render - so threads never wait on each other to count, and
+// counting's cheap enough to always be on.
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Counter {
+    // from the camera, through the pixels
+    PrimaryRays,
+    // towards lights, to see whether they're blocked
+    ShadowRays,
+    ReflectionRays,
+    RefractionRays,
+    // out into the environment, to light a point by it
+    EnvironmentRays,
+    // rays checked against single objects
+    IntersectionTests,
+}
+
+const COUNTERS: usize = 6;
+
+static TOTALS: [AtomicU64; COUNTERS] = [const { AtomicU64::new(0) }; COUNTERS];
+
+thread_local! {
+    // what this thread's counted since it last added to the totals
+    static COUNTS: [Cell<u64>; COUNTERS] = const { [const { Cell::new(0) }; COUNTERS] };
+}
+
+pub fn count(counter: Counter) {
+    add(counter, 1);
+}
+
+pub fn add(counter: Counter, n: usize) {
+    COUNTS.with(|counts| {
+        let count = &counts[counter as usize];
+        count.set(count.get() + n as u64);
+    });
+}
+
+// adds what this thread's counted to the totals, and starts it counting again
+// from 0
+pub fn flush() {
+    COUNTS.with(|counts| {
+        for (count, total) in counts.iter().zip(&TOTALS) {
+            let n = count.replace(0);
+            if n > 0 {
+                total.fetch_add(n, Ordering::Relaxed);
+            }
+        }
+    });
+}
+
+// Flushes the thread's counts when it's dropped. Rendering threads hold one
+// for as long as they're working on a part of the render.
+pub struct Flush;
+
+impl Drop for Flush {
+    fn drop(&mut self) {
+        flush();
+    }
+}
+
+// the counts so far, since the start or since they were last reset
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Stats {
+    pub primary_rays: u64,
+    pub shadow_rays: u64,
+    pub reflection_rays: u64,
+    pub refraction_rays: u64,
+    pub environment_rays: u64,
+    pub intersection_tests: u64,
+}
+
+impl Stats {
+    // The totals, along with what this thread's counted. Other threads'
+    // counts aren't in it until they've flushed them.
+    pub fn now() -> Stats {
+        flush();
+        let total = |counter: Counter| TOTALS[counter as usize].load(Ordering::Relaxed);
+        Stats {
+            primary_rays: total(Counter::PrimaryRays),
+            shadow_rays: total(Counter::ShadowRays),
+            reflection_rays: total(Counter::ReflectionRays),
+            refraction_rays: total(Counter::RefractionRays),
+            environment_rays: total(Counter::EnvironmentRays),
+            intersection_tests: total(Counter::IntersectionTests),
+        }
+    }
+
+    pub fn reset() {
+        COUNTS.with(|counts| counts.iter().for_each(|count| count.set(0)));
+        for total in TOTALS.iter() {
+            total.store(0, Ordering::Relaxed);
+        }
+    }
+
+    pub fn rays(&self) -> u64 {
+        self.primary_rays
+            + self.shadow_rays
+            + self.reflection_rays
+            + self.refraction_rays
+            + self.environment_rays
+    }
+}
+
+// what's printed for --stats after a render
+pub struct Report {
+    pub stats: Stats,
+    pub elapsed: Duration,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = &self.stats;
+        let pixels = (self.width * self.height).max(1);
+        writeln!(
+            f,
+            "Rendered {}x{} pixels in {:.2}s, {:.1}µs per pixel",
+            self.width,
+            self.height,
+            self.elapsed.as_secs_f64(),
+            self.elapsed.as_secs_f64() * 1e6 / pixels as f64
+        )?;
+        writeln!(
+            f,
+            "Rays: {} in all - {} primary, {} shadow, {} reflection, {} refraction, {} environment",
+            s.rays(),
+            s.primary_rays,
+            s.shadow_rays,
+            s.reflection_rays,
+            s.refraction_rays,
+            s.environment_rays
+        )?;
+        write!(
+            f,
+            "Intersection tests: {}, {:.1} per ray",
+            s.intersection_tests,
+            s.intersection_tests as f64 / s.rays().max(1) as f64
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precision::consts::FRAC_PI_2;
+    use crate::world::{render, Camera, World};
+
+    #[test]
+    fn counts_the_rays_in_a_render() {
+        let before = Stats::now();
+        let w = World::default();
+        let mut c = Camera::new(10, 8, FRAC_PI_2, crate::matrices::Matrix::identity());
+        c.samples = 4;
+        render(&mut c, &w);
+        let after = Stats::now();
+        // other tests may be counting at the same time
+        assert!(after.primary_rays - before.primary_rays >= 10 * 8 * 4);
+        assert!(after.shadow_rays > before.shadow_rays);
+        assert!(after.intersection_tests - before.intersection_tests >= 10 * 8 * 4 * 2);
+    }
+
+    #[test]
+    fn counts_from_every_thread_are_added_up() {
+        let before = Stats::now();
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    let _flush = Flush;
+                    add(Counter::EnvironmentRays, 1000);
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        // this thread's own counts don't need flushing first
+        count(Counter::EnvironmentRays);
+        let after = Stats::now();
+        assert!(after.environment_rays - before.environment_rays >= 4001);
+    }
+
+    #[test]
+    fn reports_the_stats() {
+        let report = Report {
+            stats: Stats {
+                primary_rays: 100,
+                shadow_rays: 60,
+                reflection_rays: 30,
+                refraction_rays: 10,
+                environment_rays: 0,
+                intersection_tests: 1000,
+            },
+            elapsed: Duration::from_millis(2500),
+            width: 10,
+            height: 10,
+        };
+        assert_eq!(
+            report.to_string(),
+            "Rendered 10x10 pixels in 2.50s, 25000.0µs per pixel\n\
+             Rays: 200 in all - 100 primary, 60 shadow, 30 reflection, 10 refraction, 0 environment\n\
+             Intersection tests: 1000, 5.0 per ray"
+        );
+    }
+}
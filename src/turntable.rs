@@ -0,0 +1,154 @@
+// Turntables, for showing a scene off from all the way round without writing
+// out an animation. Over the frames, the camera goes once round a vertical
+// line through the middle of the scene - or it stays put, and the objects
+// with a chosen name spin round their own middle instead.
+
+use crate::matrices::Matrix;
+use crate::precision::consts::PI;
+use crate::precision::Float;
+use crate::shapes::Shape;
+use crate::tuple::Tuple;
+use crate::world::{Camera, World};
+
+pub struct Turntable {
+    frames: usize,
+    centre: Tuple,
+    // the objects that spin, with their transforms from before - if there
+    // aren't any, the camera goes round instead
+    spinning: Vec<(usize, Matrix<Float, 4, 4>)>,
+}
+
+impl Turntable {
+    // Goes round everything in the world, or spins just the objects called
+    // object. Groups and obj files pass their name on to everything in them.
+    pub fn new(world: &World, frames: usize, object: Option<&str>) -> Result<Turntable, String> {
+        let (centre, spinning) = match object {
+            None => {
+                let centre = centre(&world.objects)
+                    .ok_or("there's nothing in the scene with a middle to go round")?;
+                (centre, Vec::new())
+            }
+            Some(name) => {
+                let spinning: Vec<_> = world
+                    .objects
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, o)| o.name.as_deref() == Some(name))
+                    .map(|(i, o)| (i, *o.transform.matrix()))
+                    .collect();
+                if spinning.is_empty() {
+                    return Err(format!("there's no object called {}", name));
+                }
+                let centre = centre(spinning.iter().map(|&(i, _)| &world.objects[i]))
+                    .ok_or_else(|| format!("{} goes on forever, so can't be spun", name))?;
+                (centre, spinning)
+            }
+        };
+        Ok(Turntable {
+            frames,
+            centre,
+            spinning,
+        })
+    }
+
+    // Sets the world up for the given frame, counting from 0, and returns
+    // the camera to render it with.
+    pub fn frame(&self, frame: usize, world: &mut World, camera: &Camera) -> Camera {
+        let spin = spin(
+            &self.centre,
+            2.0 * PI * frame as Float / self.frames as Float,
+        );
+        if self.spinning.is_empty() {
+            // turning the camera the other way looks just like the scene
+            // spinning, apart from the lights staying where they are
+            return Camera {
+                transform: (*camera.transform.matrix() * spin).into(),
+                ..camera.clone()
+            };
+        }
+        for (i, transform) in &self.spinning {
+            world.objects[*i].set_transform(spin * transform);
+        }
+        if world.bvh.is_some() {
+            world.build_bvh();
+        }
+        camera.clone()
+    }
+}
+
+// The middle of the objects, going by each one's own middle. Anything that
+// goes on forever, like a plane, is left out.
+pub fn centre<'a>(objects: impl IntoIterator<Item = &'a Shape>) -> Option<Tuple> {
+    let centres: Vec<Tuple> = objects.into_iter().filter_map(|o| o.centre()).collect();
+    if centres.is_empty() {
+        return None;
+    }
+    let n = centres.len() as Float;
+    let (x, y, z) = centres
+        .iter()
+        .fold((0.0, 0.0, 0.0), |(x, y, z), c| (x + c.x, y + c.y, z + c.z));
+    Some(Tuple::point_new(x / n, y / n, z / n))
+}
+
+// turns things round the vertical line through centre
+fn spin(centre: &Tuple, angle: Float) -> Matrix<Float, 4, 4> {
+    Matrix::translation(-centre.x, -centre.y, -centre.z)
+        .rotate_y(angle)
+        .translate(centre.x, centre.y, centre.z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes::{plane, sphere};
+    use crate::world::view_transform;
+
+    fn scene() -> World {
+        let mut w = World::new();
+        let mut a = sphere::default();
+        a.set_transform(Matrix::translation(2.0, 1.0, 0.0));
+        a.name = Some("ball".to_string());
+        let mut b = sphere::default();
+        b.set_transform(Matrix::translation(4.0, 1.0, 0.0));
+        w.objects = vec![a, b, plane::default()];
+        w
+    }
+
+    #[test]
+    fn the_centre_leaves_out_planes() {
+        let w = scene();
+        assert_eq!(centre(&w.objects), Some(Tuple::point_new(3.0, 1.0, 0.0)));
+        assert_eq!(centre(&w.objects[2..]), None);
+    }
+
+    #[test]
+    fn the_camera_goes_round_the_centre() {
+        let mut w = scene();
+        let from = Tuple::point_new(3.0, 1.0, -5.0);
+        let to = Tuple::point_new(3.0, 1.0, 0.0);
+        let up = Tuple::vector_new(0.0, 1.0, 0.0);
+        let c = Camera::new(10, 10, 1.0, view_transform(&from, &to, &up));
+        let t = Turntable::new(&w, 4, None).unwrap();
+        assert_eq!(t.frame(0, &mut w, &c), c);
+        // a quarter of the way round, it's looking along x from the side
+        let quarter = t.frame(1, &mut w, &c);
+        let eye = quarter.transform.inverse() * &Tuple::point_new(0.0, 0.0, 0.0);
+        assert_eq!(eye, Tuple::point_new(8.0, 1.0, 0.0));
+        // and the objects haven't moved
+        assert_eq!(w.objects[0].transform, Matrix::translation(2.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn named_objects_spin_on_their_own() {
+        let mut w = scene();
+        let c = Camera::new(10, 10, 1.0, Matrix::identity());
+        let t = Turntable::new(&w, 8, Some("ball")).unwrap();
+        for frame in 0..8 {
+            assert_eq!(t.frame(frame, &mut w, &c), c);
+            assert_eq!(w.objects[0].centre(), Some(Tuple::point_new(2.0, 1.0, 0.0)));
+            assert_eq!(w.objects[1].transform, Matrix::translation(4.0, 1.0, 0.0));
+        }
+        assert!(Turntable::new(&w, 8, Some("cube")).is_err());
+        assert!(Turntable::new(&World::new(), 8, None).is_err());
+    }
+}
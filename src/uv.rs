@@ -0,0 +1,253 @@
+use crate::precision::consts::PI;
+use crate::precision::Float;
+use crate::tuple::Tuple;
+use serde::{Deserialize, Serialize};
+
+// Ways of flattening a 3D point on an object's surface into 2D (u, v)
+// coordinates, each in the range [0, 1). Each suits a particular shape type:
+// spherical for spheres, planar for planes, cylindrical for cylinders, and
+// cube for cubes (or anything else roughly box-shaped). Equirectangular is
+// for wrapping panoramas around the whole scene as an environment.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum UvMapping {
+    Spherical,
+    Planar,
+    Cylindrical,
+    Cube,
+    Equirectangular,
+}
+
+// The faces of an axis-aligned cube, named as seen from a camera looking down
+// the positive z axis.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum CubeFace {
+    Left,
+    Right,
+    Front,
+    Back,
+    Up,
+    Down,
+}
+
+impl UvMapping {
+    pub fn map(&self, point: &Tuple) -> (Float, Float) {
+        match self {
+            UvMapping::Spherical => spherical_map(point),
+            UvMapping::Planar => planar_map(point),
+            UvMapping::Cylindrical => cylindrical_map(point),
+            UvMapping::Cube => {
+                let (_, u, v) = cube_map(point);
+                (u, v)
+            }
+            UvMapping::Equirectangular => equirectangular_map(point),
+        }
+    }
+}
+
+// Maps a point on a unit sphere centred on the origin. u wraps around the
+// equator, and v runs from the south pole (0) to the north pole (1).
+pub fn spherical_map(point: &Tuple) -> (Float, Float) {
+    let theta = point.x.atan2(point.z);
+    let radius = Tuple::vector_new(point.x, point.y, point.z).magnitude();
+    let phi = (point.y / radius).acos();
+    let raw_u = theta / (2.0 * PI);
+    // flip u so that it increases anticlockwise when viewed from above
+    let u = 1.0 - (raw_u + 0.5);
+    let v = 1.0 - phi / PI;
+    (u, v)
+}
+
+// The spherical mapping as seen from inside the sphere rather than outside,
+// which is how panoramas are laid out: u increases as the view turns to the
+// right, starting from behind at -z.
+pub fn equirectangular_map(point: &Tuple) -> (Float, Float) {
+    let (u, v) = spherical_map(point);
+    ((1.0 - u).rem_euclid(1.0), v)
+}
+
+// Maps a point on the xz plane, repeating every unit.
+pub fn planar_map(point: &Tuple) -> (Float, Float) {
+    (point.x.rem_euclid(1.0), point.z.rem_euclid(1.0))
+}
+
+// Maps a point on a unit cylinder around the y axis. u wraps around the
+// cylinder, and v repeats every unit along it.
+pub fn cylindrical_map(point: &Tuple) -> (Float, Float) {
+    let theta = point.x.atan2(point.z);
+    let raw_u = theta / (2.0 * PI);
+    let u = 1.0 - (raw_u + 0.5);
+    (u, point.y.rem_euclid(1.0))
+}
+
+// The face is decided by whichever coordinate has the largest magnitude.
+pub fn cube_face(point: &Tuple) -> CubeFace {
+    let coord = point.x.abs().max(point.y.abs()).max(point.z.abs());
+    if coord == point.x {
+        CubeFace::Right
+    } else if coord == -point.x {
+        CubeFace::Left
+    } else if coord == point.y {
+        CubeFace::Up
+    } else if coord == -point.y {
+        CubeFace::Down
+    } else if coord == point.z {
+        CubeFace::Front
+    } else {
+        CubeFace::Back
+    }
+}
+
+// Maps a point on the cube spanning -1 to 1 on each axis onto one of its faces,
+// with (u, v) running across that face as seen from outside the cube.
+pub fn cube_map(point: &Tuple) -> (CubeFace, Float, Float) {
+    let face = cube_face(point);
+    let along = |a: Float| (a + 1.0).rem_euclid(2.0) / 2.0;
+    let against = |a: Float| (1.0 - a).rem_euclid(2.0) / 2.0;
+    let (u, v) = match face {
+        CubeFace::Front => (along(point.x), along(point.y)),
+        CubeFace::Back => (against(point.x), along(point.y)),
+        CubeFace::Left => (along(point.z), along(point.y)),
+        CubeFace::Right => (against(point.z), along(point.y)),
+        CubeFace::Up => (along(point.x), against(point.z)),
+        CubeFace::Down => (along(point.x), along(point.z)),
+    };
+    (face, u, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::float_eq;
+    use crate::precision::consts::FRAC_1_SQRT_2;
+
+    fn uv_eq((u1, v1): (Float, Float), (u2, v2): (Float, Float)) -> bool {
+        float_eq(u1, u2) && float_eq(v1, v2)
+    }
+
+    #[test]
+    fn spherical_mapping_on_sphere() {
+        let cases = [
+            (Tuple::point_new(0.0, 0.0, -1.0), (0.0, 0.5)),
+            (Tuple::point_new(1.0, 0.0, 0.0), (0.25, 0.5)),
+            (Tuple::point_new(0.0, 0.0, 1.0), (0.5, 0.5)),
+            (Tuple::point_new(-1.0, 0.0, 0.0), (0.75, 0.5)),
+            (Tuple::point_new(0.0, 1.0, 0.0), (0.5, 1.0)),
+            (Tuple::point_new(0.0, -1.0, 0.0), (0.5, 0.0)),
+            (
+                Tuple::point_new(FRAC_1_SQRT_2, FRAC_1_SQRT_2, 0.0),
+                (0.25, 0.75),
+            ),
+        ];
+        for (point, expected) in cases.iter() {
+            assert!(uv_eq(spherical_map(point), *expected), "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn equirectangular_mapping_is_spherical_seen_from_inside() {
+        let cases = [
+            (Tuple::point_new(0.0, 0.0, -1.0), (0.0, 0.5)),
+            (Tuple::point_new(-1.0, 0.0, 0.0), (0.25, 0.5)),
+            (Tuple::point_new(0.0, 0.0, 1.0), (0.5, 0.5)),
+            (Tuple::point_new(1.0, 0.0, 0.0), (0.75, 0.5)),
+            (Tuple::point_new(0.0, 1.0, 0.0), (0.5, 1.0)),
+        ];
+        for (point, expected) in cases.iter() {
+            assert!(uv_eq(equirectangular_map(point), *expected), "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn planar_mapping_on_plane() {
+        let cases = [
+            (Tuple::point_new(0.25, 0.0, 0.5), (0.25, 0.5)),
+            (Tuple::point_new(0.25, 0.0, -0.25), (0.25, 0.75)),
+            (Tuple::point_new(0.25, 0.5, -0.25), (0.25, 0.75)),
+            (Tuple::point_new(1.25, 0.0, 0.5), (0.25, 0.5)),
+            (Tuple::point_new(0.25, 0.0, -1.75), (0.25, 0.25)),
+            (Tuple::point_new(0.0, 0.0, 0.0), (0.0, 0.0)),
+        ];
+        for (point, expected) in cases.iter() {
+            assert!(uv_eq(planar_map(point), *expected), "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn cylindrical_mapping_on_cylinder() {
+        let cases = [
+            (Tuple::point_new(0.0, 0.0, -1.0), (0.0, 0.0)),
+            (Tuple::point_new(0.0, 0.5, -1.0), (0.0, 0.5)),
+            (Tuple::point_new(0.0, 1.0, -1.0), (0.0, 0.0)),
+            (
+                Tuple::point_new(FRAC_1_SQRT_2, 0.5, -FRAC_1_SQRT_2),
+                (0.125, 0.5),
+            ),
+            (Tuple::point_new(1.0, 0.5, 0.0), (0.25, 0.5)),
+            (Tuple::point_new(-1.0, 1.25, 0.0), (0.75, 0.25)),
+        ];
+        for (point, expected) in cases.iter() {
+            assert!(uv_eq(cylindrical_map(point), *expected), "{:?}", point);
+        }
+    }
+
+    #[test]
+    fn identifying_cube_faces() {
+        let cases = [
+            (Tuple::point_new(-1.0, 0.5, -0.25), CubeFace::Left),
+            (Tuple::point_new(1.1, -0.75, 0.8), CubeFace::Right),
+            (Tuple::point_new(0.1, 0.6, 0.9), CubeFace::Front),
+            (Tuple::point_new(-0.7, 0.0, -2.0), CubeFace::Back),
+            (Tuple::point_new(0.5, 1.0, 0.9), CubeFace::Up),
+            (Tuple::point_new(-0.2, -1.3, 1.1), CubeFace::Down),
+        ];
+        for (point, face) in cases.iter() {
+            assert_eq!(cube_face(point), *face);
+        }
+    }
+
+    #[test]
+    fn cube_mapping_on_faces() {
+        let cases = [
+            (
+                Tuple::point_new(-0.5, 0.5, 1.0),
+                CubeFace::Front,
+                (0.25, 0.75),
+            ),
+            (
+                Tuple::point_new(0.5, -0.5, 1.0),
+                CubeFace::Front,
+                (0.75, 0.25),
+            ),
+            (
+                Tuple::point_new(0.5, 0.5, -1.0),
+                CubeFace::Back,
+                (0.25, 0.75),
+            ),
+            (
+                Tuple::point_new(-1.0, 0.5, -0.5),
+                CubeFace::Left,
+                (0.25, 0.75),
+            ),
+            (
+                Tuple::point_new(1.0, 0.5, 0.5),
+                CubeFace::Right,
+                (0.25, 0.75),
+            ),
+            (
+                Tuple::point_new(-0.5, 1.0, -0.5),
+                CubeFace::Up,
+                (0.25, 0.75),
+            ),
+            (
+                Tuple::point_new(-0.5, -1.0, 0.5),
+                CubeFace::Down,
+                (0.25, 0.75),
+            ),
+        ];
+        for (point, face, expected) in cases.iter() {
+            let (f, u, v) = cube_map(point);
+            assert_eq!(f, *face);
+            assert!(uv_eq((u, v), *expected), "{:?}", point);
+        }
+    }
+}
@@ -0,0 +1,76 @@
+// Keeps an eye on a file by checking when it was last modified every so often,
+// for re-rendering a scene whenever it's saved.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+pub struct Watcher {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    interval: Duration,
+}
+
+impl Watcher {
+    pub fn new(path: &Path, interval: Duration) -> Watcher {
+        Watcher {
+            path: path.to_path_buf(),
+            modified: modified(path),
+            interval,
+        }
+    }
+
+    // Whether the file's been modified since the last time this said so (or
+    // since the watcher was made). A file that's gone missing hasn't changed -
+    // editors often delete a file just before writing the new one.
+    pub fn changed(&mut self) -> bool {
+        match modified(&self.path) {
+            Some(time) if Some(time) != self.modified => {
+                self.modified = Some(time);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // waits until the file changes
+    pub fn wait(&mut self) {
+        while !self.changed() {
+            std::thread::sleep(self.interval);
+        }
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+
+    #[test]
+    fn notices_when_a_file_is_modified() {
+        let path = std::env::temp_dir().join("rusrat_watched.yaml");
+        std::fs::write(&path, "- add: sphere").unwrap();
+        let start = SystemTime::now() - Duration::from_secs(60);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(start)
+            .unwrap();
+        let mut watcher = Watcher::new(&path, Duration::from_millis(1));
+        assert!(!watcher.changed());
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(start + Duration::from_secs(1))
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+        std::fs::remove_file(&path).unwrap();
+        assert!(!watcher.changed());
+    }
+}
@@ -0,0 +1,61 @@
+// What a browser front-end needs, for rusrat built for WebAssembly
+// (wasm32-unknown-unknown, without the default features). There aren't any
+// files there, so scenes come in as YAML text and images go back out as bytes
+// ready to be put on a <canvas>.
+
+use crate::world::Renderer;
+use crate::yaml::{self, ParseError};
+
+// Renders a scene, given as YAML, at the width and height given rather than
+// the camera's own, returning four bytes per pixel (RGBA, with alpha always
+// 255) a row at a time from the top - what ImageData expects. The scene can't
+// include other files, or use textures or OBJ models.
+pub fn render_to_rgba_buffer(
+    scene_yaml: &str,
+    width: usize,
+    height: usize,
+) -> Result<Vec<u8>, ParseError> {
+    let (world, camera) = yaml::parse_scene(scene_yaml, None)?;
+    let camera = camera.resized(width, height);
+    let image = Renderer::default().render(&camera, &world);
+    Ok(image.to_rgba(camera.dither))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCENE: &str = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 1.0
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- add: light
+  at: [-10, 10, -10]
+  intensity: [1, 1, 1]
+- add: sphere
+  material:
+    color: [1, 0, 0]
+";
+
+    #[test]
+    fn renders_to_rgba_at_the_size_asked_for() {
+        let buffer = render_to_rgba_buffer(SCENE, 8, 6).unwrap();
+        assert_eq!(buffer.len(), 8 * 6 * 4);
+        assert!(buffer.chunks(4).all(|pixel| pixel[3] == 255));
+        // the sphere's in the middle, and red
+        let middle = &buffer[(3 * 8 + 4) * 4..(3 * 8 + 5) * 4];
+        assert!(middle[0] > 0);
+        assert_eq!(middle[1..3], [0, 0]);
+        // with nothing behind it
+        assert_eq!(buffer[..4], [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bad_scenes_are_an_error() {
+        assert!(render_to_rgba_buffer("- add: nonsense", 8, 6).is_err());
+    }
+}
@@ -1,44 +1,243 @@
-use crate::canvas::{Canvas, Colour};
-use crate::lighting::{colour_at, PointLight};
-use crate::matrices::Matrix;
-use crate::rays::Ray;
-use crate::shapes::{sphere, Material, Shape};
+use crate::bvh::Bvh;
+use crate::canvas::{Bloom, Canvas, Colour, Dither, PostProcess};
+use crate::lighting::{colour_of_hit, PointLight};
+use crate::matrices::{Matrix, Transform};
+use crate::precision::Float;
+use crate::rays::{IntersectionBuffer, Ray};
+use crate::sampling::{self, Sampler};
+use crate::shapes::{sphere, Material, Pattern, Shape};
+use crate::stats::{self, Counter};
 use crate::tuple::Tuple;
 use crate::REFLECTION_RECURSION_DEPTH;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
+// the width and height of the squares the image is rendered in
+pub const TILE_SIZE: usize = 32;
+
+// Worlds can be saved and loaded with serde, e.g. as JSON. The BVH isn't
+// saved, so build_bvh has to be called again after loading.
+#[derive(Serialize, Deserialize)]
 pub struct World {
     pub objects: Vec<Shape>,
     pub lights: Vec<PointLight>,
+    // seen by any ray that misses every object, e.g. a cube-mapped sky. If
+    // there isn't one, those rays see the plain background colour instead.
+    pub environment: Option<Pattern>,
+    pub background: Colour,
+    // how many rays to cast at each hit to light it by the environment -
+    // 0 means the environment is only seen, and doesn't light anything
+    pub environment_samples: usize,
+    // decides where the shadow rays towards area lights go
+    pub shadow_sampler: Sampler,
+    // whether shadow rays try whatever blocked the last one towards the same
+    // light before anything else. It doesn't change the image, only how long
+    // it takes
+    pub shadow_cache: bool,
+    pub fog: Option<Fog>,
+    // speeds up finding what rays hit. Without one, every ray's tested
+    // against every object. It has to be built again after the objects change
+    #[serde(skip)]
+    pub bvh: Option<Bvh>,
+}
+
+// Which of a world's objects something is - its place in the world's list of
+// objects. Intersections carry these rather than borrowing the shapes, so
+// worlds can still be changed while they're about.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct ObjectId(pub usize);
+
+// Fog fades objects towards its colour the further away they are. Rays that
+// don't hit anything aren't fogged, so the background is usually set to match.
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub enum Fog {
+    // no fog up to start, fading to solid fog at end
+    Linear {
+        colour: Colour,
+        start: Float,
+        end: Float,
+    },
+    // a fraction 1 - e^(-density * d) of the colour is fog at distance d
+    Exponential {
+        colour: Colour,
+        density: Float,
+    },
+}
+
+impl Fog {
+    // how much of what's seen at the given distance is fog, from 0 to 1
+    fn amount(&self, distance: Float) -> Float {
+        match self {
+            Fog::Linear { start, end, .. } => ((distance - start) / (end - start)).clamp(0.0, 1.0),
+            Fog::Exponential { density, .. } => 1.0 - (-density * distance).exp(),
+        }
+    }
+
+    pub fn apply(&self, colour: Colour, distance: Float) -> Colour {
+        let fog_colour = match self {
+            Fog::Linear { colour, .. } | Fog::Exponential { colour, .. } => *colour,
+        };
+        let amount = self.amount(distance);
+        colour * (1.0 - amount) + fog_colour * amount
+    }
 }
 
-#[derive(Default, Debug, PartialEq)]
+#[derive(Default, Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub hsize: usize,
     pub vsize: usize,
-    pub field_of_view: f64,
-    pub transform: Matrix<f64, 4, 4>,
+    pub field_of_view: Float,
+    pub transform: Transform,
     // cache/memoise these values
-    pub pixel_size: f64,
-    pub half_width: f64,
-    pub half_height: f64,
+    pub pixel_size: Float,
+    pub half_width: Float,
+    pub half_height: Float,
+    // rays per pixel, averaged together to smooth jagged edges
+    pub samples: usize,
+    // where in each pixel those rays go - a single ray always goes through
+    // the centre
+    pub sampler: Sampler,
+    // if set, only pixels that differ from a neighbour by more than this get
+    // all of their samples - the rest just get one
+    pub adaptive_threshold: Option<Float>,
+    // radius of the lens - 0 makes a pinhole camera, with everything in focus
+    pub aperture: Float,
+    // how far from the camera things are perfectly in focus
+    pub focal_distance: Float,
+    pub projection: Projection,
+    // if set, only this part of the image is rendered
+    pub region: Option<Region>,
+    // whether the image is cut down to just the region, rather than leaving
+    // the rest of it black
+    pub crop: bool,
+    // used to pick the camera to render from when a scene has several
+    pub name: Option<String>,
+    // applied to the finished image, after any bloom
+    pub post_process: PostProcess,
+    pub bloom: Option<Bloom>,
+    // how the finished image is rounded when it's written out
+    pub dither: Dither,
+    // if set, the image is a side by side pair, left eye then right eye, each
+    // hsize pixels wide
+    pub stereo: Option<Stereo>,
+    // how many times a ray can be reflected or refracted, if not
+    // REFLECTION_RECURSION_DEPTH
+    pub recursion_depth: Option<usize>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Stereo {
+    // the distance between the eyes
+    pub interocular: Float,
+    // how far from the camera the eyes' views line up - things nearer than
+    // this seem to stand out of the screen, and things further away behind it
+    pub convergence: Float,
+}
+
+// A rectangle of pixels, from (x0, y0) at the top left up to but not
+// including (x1, y1).
+#[derive(Debug, PartialEq, Clone, Copy, Serialize, Deserialize)]
+pub struct Region {
+    pub x0: usize,
+    pub y0: usize,
+    pub x1: usize,
+    pub y1: usize,
+}
+
+impl Region {
+    pub fn width(&self) -> usize {
+        self.x1 - self.x0
+    }
+
+    pub fn height(&self) -> usize {
+        self.y1 - self.y0
+    }
+
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x0..self.x1).contains(&x) && (self.y0..self.y1).contains(&y)
+    }
+
+    // every pixel in the region, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (usize, usize)> {
+        let (x0, x1) = (self.x0, self.x1);
+        (self.y0..self.y1).flat_map(move |y| (x0..x1).map(move |x| (x, y)))
+    }
+
+    // Splits the region into squares of the given size, row by row. Those on
+    // the right and bottom edges are cut short to fit.
+    pub fn tiles(&self, size: usize) -> Vec<Region> {
+        let mut out = Vec::new();
+        for y0 in (self.y0..self.y1).step_by(size) {
+            for x0 in (self.x0..self.x1).step_by(size) {
+                out.push(Region {
+                    x0,
+                    y0,
+                    x1: (x0 + size).min(self.x1),
+                    y1: (y0 + size).min(self.y1),
+                });
+            }
+        }
+        out
+    }
+}
+
+// How the camera's view is flattened into an image.
+#[derive(Debug, PartialEq, Clone, Copy, Default, Serialize, Deserialize)]
+pub enum Projection {
+    // an ordinary camera, where straight lines stay straight
+    #[default]
+    Perspective,
+    // the angle away from the centre of the image grows steadily with the
+    // distance from it, reaching half the field of view at the edge of the
+    // image's longer side. straight lines bend, but a much wider view fits in
+    Fisheye,
+    // the whole sphere of directions around the camera, with longitude across
+    // the image and latitude up it - the field of view is ignored
+    Equirectangular,
 }
 
 impl Camera {
-    pub fn new(hsize: usize, vsize: usize, fov: f64, t: Matrix<f64, 4, 4>) -> Camera {
+    pub fn new(hsize: usize, vsize: usize, fov: Float, t: Matrix<Float, 4, 4>) -> Camera {
         Camera {
             hsize,
             vsize,
             field_of_view: fov,
-            transform: t,
+            transform: t.into(),
             half_width: Self::half_width(hsize, vsize, fov),
             half_height: Self::half_height(hsize, vsize, fov),
             pixel_size: Self::pixel_size(hsize, vsize, fov),
+            samples: 1,
+            sampler: Sampler::default(),
+            adaptive_threshold: None,
+            aperture: 0.0,
+            focal_distance: 1.0,
+            projection: Projection::Perspective,
+            region: None,
+            crop: false,
+            name: None,
+            post_process: PostProcess::default(),
+            bloom: None,
+            dither: Dither::None,
+            stereo: None,
+            recursion_depth: None,
         }
     }
 
-    fn half_width(hsize: usize, vsize: usize, fov: f64) -> f64 {
+    // for setting a camera up a piece at a time, e.g.
+    //
+    //     Camera::builder().size(800, 600).fov_degrees(60.0).look_at(from, to, up).build()
+    pub fn builder() -> CameraBuilder {
+        CameraBuilder::default()
+    }
+
+    fn half_width(hsize: usize, vsize: usize, fov: Float) -> Float {
         let half_view = (fov / 2.0).tan();
-        let aspect = hsize as f64 / vsize as f64;
+        let aspect = hsize as Float / vsize as Float;
 
         if aspect >= 1.0 {
             half_view
@@ -47,9 +246,9 @@ impl Camera {
         }
     }
 
-    fn half_height(hsize: usize, vsize: usize, fov: f64) -> f64 {
+    fn half_height(hsize: usize, vsize: usize, fov: Float) -> Float {
         let half_view = (fov / 2.0).tan();
-        let aspect = hsize as f64 / vsize as f64;
+        let aspect = hsize as Float / vsize as Float;
 
         if aspect >= 1.0 {
             half_view / aspect
@@ -58,19 +257,314 @@ impl Camera {
         }
     }
 
-    fn pixel_size(hsize: usize, vsize: usize, fov: f64) -> f64 {
-        Self::half_width(hsize, vsize, fov) * 2.0 / hsize as f64
+    fn pixel_size(hsize: usize, vsize: usize, fov: Float) -> Float {
+        Self::half_width(hsize, vsize, fov) * 2.0 / hsize as Float
     }
 
     pub fn ray_for_pixel(&self, x: usize, y: usize) -> Ray {
-        let x_offset = (x as f64 + 0.5) * self.pixel_size;
-        let y_offset = (y as f64 + 0.5) * self.pixel_size;
+        self.ray_for_subpixel(x, y, (0.5, 0.5))
+    }
+
+    // offset is the position within the pixel, from (0, 0) at the top left to
+    // (1, 1) at the bottom right
+    pub fn ray_for_subpixel(&self, x: usize, y: usize, offset: (Float, Float)) -> Ray {
+        self.ray_through_lens(x, y, offset, (0.0, 0.0))
+    }
+
+    // lens is the point the ray leaves the lens from, relative to its centre.
+    // every ray through the same point in a pixel meets at the focal distance,
+    // so only things that far away are sharp
+    pub fn ray_through_lens(
+        &self,
+        x: usize,
+        y: usize,
+        offset: (Float, Float),
+        lens: (Float, Float),
+    ) -> Ray {
+        let (x, eye) = self.eye_for_column(x);
+        let eye = Tuple::point_new(eye, 0.0, 0.0);
+        let (xf, yf) = (x as Float + offset.0, y as Float + offset.1);
+        // the neighbouring rays use the same eye and lens position, so they
+        // only differ in where they cross the image
+        let dx = self.ray_through_image(xf + 1.0, yf, eye, lens);
+        let dy = self.ray_through_image(xf, yf + 1.0, eye, lens);
+        self.ray_through_image(xf, yf, eye, lens)
+            .with_differentials(&dx, &dy)
+    }
+
+    fn ray_through_image(&self, xf: Float, yf: Float, eye: Tuple, lens: (Float, Float)) -> Ray {
+        if self.projection != Projection::Perspective {
+            let origin = self.transform.inverse() * &eye;
+            let direction = self.transform.inverse() * &self.panoramic_direction(xf, yf);
+            return Ray::new(origin, direction);
+        }
+        let x_offset = xf * self.pixel_size;
+        let y_offset = yf * self.pixel_size;
         let world_x = self.half_width - x_offset;
         let world_y = self.half_height - y_offset;
-        let px = self.transform.inverse() * &Tuple::point_new(world_x, world_y, -1.0);
-        let origin = self.transform.inverse() * &Tuple::point_new(0.0, 0.0, 0.0);
-        let direction = (px - origin).normalise();
-        Ray::new(origin, direction)
+        // each eye looks through the same window onto the convergence plane,
+        // and is focused at the same depth as the camera
+        let c = self.stereo.map_or(1.0, |s| s.convergence);
+        let target = Tuple::point_new(world_x * c, world_y * c, -c);
+        let focus = eye + (target - eye) * (self.focal_distance / c);
+        let px = self.transform.inverse() * &focus;
+        let origin = self.transform.inverse() * &(eye + Tuple::vector_new(lens.0, lens.1, 0.0));
+        Ray::new(origin, px - origin)
+    }
+
+    // Stereo images are twice as wide as one view. Returns the column within
+    // the view, and how far the eye for that view is to the left (+x) of the
+    // camera's centre.
+    fn eye_for_column(&self, x: usize) -> (usize, Float) {
+        match self.stereo {
+            Some(s) if x < self.hsize => (x, s.interocular / 2.0),
+            Some(s) => (x - self.hsize, -s.interocular / 2.0),
+            None => (x, 0.0),
+        }
+    }
+
+    // The same camera, making an image the given fraction of the size - e.g.
+    // 0.5 for half the width and height. Everything measured in pixels is
+    // scaled to match.
+    pub fn scaled(&self, factor: Float) -> Camera {
+        let scale = |n: usize| ((n as Float * factor).round() as usize).max(1);
+        self.resized(scale(self.hsize), scale(self.vsize))
+    }
+
+    // The same camera, making an image of the given width and height, which
+    // needn't be the same shape as before. The region is stretched to match.
+    pub fn resized(&self, hsize: usize, vsize: usize) -> Camera {
+        let x_factor = hsize as Float / self.hsize as Float;
+        let y_factor = vsize as Float / self.vsize as Float;
+        let scale = |n: usize, factor: Float| (n as Float * factor).round() as usize;
+        Camera {
+            hsize,
+            vsize,
+            half_width: Self::half_width(hsize, vsize, self.field_of_view),
+            half_height: Self::half_height(hsize, vsize, self.field_of_view),
+            pixel_size: Self::pixel_size(hsize, vsize, self.field_of_view),
+            region: self.region.map(|r| Region {
+                x0: scale(r.x0, x_factor),
+                y0: scale(r.y0, y_factor),
+                x1: scale(r.x1, x_factor).max(1),
+                y1: scale(r.y1, y_factor).max(1),
+            }),
+            bloom: self.bloom.map(|b| Bloom {
+                radius: b.radius * x_factor,
+                ..b
+            }),
+            ..self.clone()
+        }
+    }
+
+    // The width of the whole image, including both views for stereo.
+    pub fn image_width(&self) -> usize {
+        match self.stereo {
+            Some(_) => self.hsize * 2,
+            None => self.hsize,
+        }
+    }
+
+    // The width and height of the finished image - just the region if it's
+    // cropped.
+    pub fn output_size(&self) -> (usize, usize) {
+        if self.crop {
+            let region = self.render_region();
+            (region.width(), region.height())
+        } else {
+            (self.image_width(), self.vsize)
+        }
+    }
+
+    // The part of the image to render - the region cut down to fit inside the
+    // image, or the whole image if there isn't one.
+    pub fn render_region(&self) -> Region {
+        match self.region {
+            Some(r) => Region {
+                x0: r.x0.min(self.image_width()),
+                y0: r.y0.min(self.vsize),
+                x1: r.x1.min(self.image_width()),
+                y1: r.y1.min(self.vsize),
+            },
+            None => Region {
+                x0: 0,
+                y0: 0,
+                x1: self.image_width(),
+                y1: self.vsize,
+            },
+        }
+    }
+
+    // The direction, relative to the camera, seen at position (x, y) on the
+    // image for the projections other than perspective. As with perspective,
+    // the camera looks down -z, with +x to the left of the image.
+    fn panoramic_direction(&self, x: Float, y: Float) -> Tuple {
+        match self.projection {
+            Projection::Fisheye => {
+                let half_size = self.hsize.max(self.vsize) as Float / 2.0;
+                let cx = (self.hsize as Float / 2.0 - x) / half_size;
+                let cy = (self.vsize as Float / 2.0 - y) / half_size;
+                let r = cx.hypot(cy);
+                if r == 0.0 {
+                    return Tuple::vector_new(0.0, 0.0, -1.0);
+                }
+                let theta = r * self.field_of_view / 2.0;
+                Tuple::vector_new(theta.sin() * cx / r, theta.sin() * cy / r, -theta.cos())
+            }
+            Projection::Equirectangular => {
+                use crate::precision::consts::PI;
+                let longitude = (x / self.hsize as Float - 0.5) * 2.0 * PI;
+                let latitude = (0.5 - y / self.vsize as Float) * PI;
+                Tuple::vector_new(
+                    -latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    -latitude.cos() * longitude.cos(),
+                )
+            }
+            Projection::Perspective => unreachable!(),
+        }
+    }
+
+    // The positions within pixel (x, y) to send rays through. The samples are
+    // laid out in a grid, so their number is rounded up to fill it.
+    pub fn subpixel_offsets(&self, x: usize, y: usize) -> Vec<(Float, Float)> {
+        self.square_samples(self.samples, &[x as u64, y as u64])
+    }
+
+    fn square_samples(&self, count: usize, key: &[u64]) -> Vec<(Float, Float)> {
+        if count <= 1 {
+            return vec![(0.5, 0.5)];
+        }
+        let usteps = (count as Float).sqrt().ceil() as usize;
+        let vsteps = count.div_ceil(usteps);
+        self.sampler.samples(usteps, vsteps, sampling::hash(key))
+    }
+
+    // The rays to average together for pixel (x, y), spread over both the
+    // pixel and the lens. A single ray goes through the centre of each.
+    pub fn rays_for_pixel(&self, x: usize, y: usize, samples: usize) -> Vec<Ray> {
+        let offsets = self.square_samples(samples, &[x as u64, y as u64]);
+        // panoramas are always pinhole cameras
+        if self.aperture == 0.0 || self.projection != Projection::Perspective {
+            return offsets
+                .iter()
+                .map(|offset| self.ray_for_subpixel(x, y, *offset))
+                .collect();
+        }
+        // a different key, so the lens and pixel samples don't line up
+        let lens = self.square_samples(samples, &[x as u64, y as u64, 1]);
+        offsets
+            .iter()
+            .zip(lens)
+            .map(|(offset, lens)| {
+                let (u, v) = sampling::square_to_disc(lens);
+                self.ray_through_lens(x, y, *offset, (u * self.aperture, v * self.aperture))
+            })
+            .collect()
+    }
+}
+
+// Builds a camera without having to work out its view transform. Anything not
+// set is left as Camera::new has it, and by default the camera makes a 100x100
+// image with a 60 degree field of view, looking down -z from the origin.
+#[derive(Debug, Clone)]
+pub struct CameraBuilder {
+    hsize: usize,
+    vsize: usize,
+    field_of_view: Float,
+    transform: Matrix<Float, 4, 4>,
+    samples: usize,
+    aperture: Float,
+    // if not set, it's how far away whatever the camera looks at is
+    focal_distance: Option<Float>,
+    look_distance: Option<Float>,
+    projection: Projection,
+    name: Option<String>,
+}
+
+impl Default for CameraBuilder {
+    fn default() -> CameraBuilder {
+        CameraBuilder {
+            hsize: 100,
+            vsize: 100,
+            field_of_view: crate::precision::consts::FRAC_PI_3,
+            transform: Matrix::identity(),
+            samples: 1,
+            aperture: 0.0,
+            focal_distance: None,
+            look_distance: None,
+            projection: Projection::Perspective,
+            name: None,
+        }
+    }
+}
+
+impl CameraBuilder {
+    pub fn size(mut self, hsize: usize, vsize: usize) -> CameraBuilder {
+        self.hsize = hsize;
+        self.vsize = vsize;
+        self
+    }
+
+    // in radians
+    pub fn fov(mut self, field_of_view: Float) -> CameraBuilder {
+        self.field_of_view = field_of_view;
+        self
+    }
+
+    pub fn fov_degrees(self, degrees: Float) -> CameraBuilder {
+        self.fov(degrees.to_radians())
+    }
+
+    pub fn look_at(mut self, from: Tuple, to: Tuple, up: Tuple) -> CameraBuilder {
+        self.transform = view_transform(&from, &to, &up);
+        self.look_distance = Some((to - from).magnitude());
+        self
+    }
+
+    pub fn transform(mut self, transform: Matrix<Float, 4, 4>) -> CameraBuilder {
+        self.transform = transform;
+        self.look_distance = None;
+        self
+    }
+
+    pub fn samples(mut self, samples: usize) -> CameraBuilder {
+        self.samples = samples;
+        self
+    }
+
+    pub fn aperture(mut self, aperture: Float) -> CameraBuilder {
+        self.aperture = aperture;
+        self
+    }
+
+    pub fn focal_distance(mut self, distance: Float) -> CameraBuilder {
+        self.focal_distance = Some(distance);
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> CameraBuilder {
+        self.projection = projection;
+        self
+    }
+
+    pub fn name(mut self, name: &str) -> CameraBuilder {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn build(self) -> Camera {
+        let mut cam = Camera::new(self.hsize, self.vsize, self.field_of_view, self.transform);
+        cam.samples = self.samples;
+        cam.aperture = self.aperture;
+        // like in scene files, the camera focuses on what it's looking at
+        // unless told otherwise
+        if let Some(distance) = self.focal_distance.or(self.look_distance) {
+            cam.focal_distance = distance;
+        }
+        cam.projection = self.projection;
+        cam.name = self.name;
+        cam
     }
 }
 
@@ -79,6 +573,67 @@ impl World {
         World {
             objects: Vec::new(),
             lights: Vec::new(),
+            environment: None,
+            background: Colour::black(),
+            environment_samples: 0,
+            shadow_sampler: Sampler::default(),
+            shadow_cache: true,
+            fog: None,
+            bvh: None,
+        }
+    }
+
+    // for putting a world together a piece at a time, e.g.
+    //
+    //     World::builder().object(sphere::default()).light(light).build()
+    pub fn builder() -> WorldBuilder {
+        WorldBuilder {
+            world: World::new(),
+        }
+    }
+
+    // sorts the objects into a BVH, replacing any there was already
+    pub fn build_bvh(&mut self) {
+        self.bvh = Some(Bvh::new(&self.objects));
+    }
+
+    // adds the object to the world, returning the id it can be found by
+    pub fn add_object(&mut self, object: Shape) -> ObjectId {
+        self.objects.push(object);
+        ObjectId(self.objects.len() - 1)
+    }
+
+    pub fn object(&self, id: ObjectId) -> &Shape {
+        &self.objects[id.0]
+    }
+
+    pub fn object_mut(&mut self, id: ObjectId) -> &mut Shape {
+        &mut self.objects[id.0]
+    }
+
+    // the ids of every object in the world
+    pub fn ids(&self) -> impl Iterator<Item = ObjectId> {
+        (0..self.objects.len()).map(ObjectId)
+    }
+
+    // Returns the first object with the given name, if there is one.
+    pub fn get_object(&self, name: &str) -> Option<&Shape> {
+        self.objects
+            .iter()
+            .find(|obj| obj.name.as_deref() == Some(name))
+    }
+
+    pub fn get_object_mut(&mut self, name: &str) -> Option<&mut Shape> {
+        self.objects
+            .iter_mut()
+            .find(|obj| obj.name.as_deref() == Some(name))
+    }
+
+    // The colour seen looking off into the distance in the given direction.
+    pub fn environment_colour(&self, direction: &Tuple) -> Colour {
+        match &self.environment {
+            Some(pattern) => pattern.pattern_at_direction(direction),
+            None => self.background,
         }
     }
 }
@@ -92,11 +647,11 @@ impl Default for World {
                 specular: 0.2,
                 ..Material::default()
             },
-            transform: Matrix::identity(),
+            transform: Transform::default(),
             ..sphere::default()
         };
         let s2 = Shape {
-            transform: Matrix::scaling(0.5, 0.5, 0.5),
+            transform: Matrix::scaling(0.5, 0.5, 0.5).into(),
             ..sphere::default()
         };
         let light = PointLight::new(
@@ -107,15 +662,86 @@ impl Default for World {
         World {
             objects: vec![s1, s2],
             lights: vec![light],
+            environment: None,
+            background: Colour::black(),
+            environment_samples: 0,
+            shadow_sampler: Sampler::default(),
+            shadow_cache: true,
+            fog: None,
+            bvh: None,
         }
     }
 }
 
-pub fn view_transform(from: &Tuple, to: &Tuple, up: &Tuple) -> Matrix<f64, 4, 4> {
+// Builds up a world, starting from an empty one. Its BVH is built at the end, so
+// it's ready to render.
+pub struct WorldBuilder {
+    world: World,
+}
+
+impl WorldBuilder {
+    pub fn object(mut self, object: Shape) -> WorldBuilder {
+        self.world.objects.push(object);
+        self
+    }
+
+    pub fn objects<I: IntoIterator<Item = Shape>>(mut self, objects: I) -> WorldBuilder {
+        self.world.objects.extend(objects);
+        self
+    }
+
+    pub fn light(mut self, light: PointLight) -> WorldBuilder {
+        self.world.lights.push(light);
+        self
+    }
+
+    pub fn background(mut self, colour: Colour) -> WorldBuilder {
+        self.world.background = colour;
+        self
+    }
+
+    pub fn environment(mut self, environment: Pattern) -> WorldBuilder {
+        self.world.environment = Some(environment);
+        self
+    }
+
+    pub fn environment_samples(mut self, samples: usize) -> WorldBuilder {
+        self.world.environment_samples = samples;
+        self
+    }
+
+    pub fn shadow_sampler(mut self, sampler: Sampler) -> WorldBuilder {
+        self.world.shadow_sampler = sampler;
+        self
+    }
+
+    pub fn shadow_cache(mut self, cache: bool) -> WorldBuilder {
+        self.world.shadow_cache = cache;
+        self
+    }
+
+    pub fn fog(mut self, fog: Fog) -> WorldBuilder {
+        self.world.fog = Some(fog);
+        self
+    }
+
+    pub fn build(mut self) -> World {
+        self.world.build_bvh();
+        self.world
+    }
+}
+
+// If up is (nearly) parallel to the direction the camera looks in, it can't
+// say which way up the camera is, so a direction at right angles to the view is
+// used instead - otherwise the transform would be full of NaNs.
+pub fn view_transform(from: &Tuple, to: &Tuple, up: &Tuple) -> Matrix<Float, 4, 4> {
     let forward = (*to - *from).normalise();
-    let left = forward.cross(&up.normalise());
+    let mut left = forward.cross(&up.normalise());
+    if left.magnitude() < 1e-6 {
+        left = forward.cross(&level_up(&forward)).normalise();
+    }
     let true_up = left.cross(&forward);
-    let orientation = Matrix::from_array(&[
+    let orientation = Matrix::from_array([
         [left.x, left.y, left.z, 0.0],
         [true_up.x, true_up.y, true_up.z, 0.0],
         [-forward.x, -forward.y, -forward.z, 0.0],
@@ -124,136 +750,904 @@ pub fn view_transform(from: &Tuple, to: &Tuple, up: &Tuple) -> Matrix<f64, 4, 4>
     orientation * Matrix::translation(-from.x, -from.y, -from.z)
 }
 
-use rayon::prelude::*;
-pub fn render(cam: &mut Camera, world: &World) -> Canvas {
-    let mut image = Canvas::new(cam.hsize, cam.vsize);
-    let mut colour_vec: Vec<(Colour, (usize, usize))> = vec![];
+// A direction at right angles to forward that's as close to straight up (+y)
+// as possible, or to +z when looking straight up or down.
+fn level_up(forward: &Tuple) -> Tuple {
+    let world_up = if forward.x.abs() < 1e-6 && forward.z.abs() < 1e-6 {
+        Tuple::vector_new(0.0, 0.0, 1.0)
+    } else {
+        Tuple::vector_new(0.0, 1.0, 0.0)
+    };
+    (world_up - *forward * forward.dot(&world_up)).normalise()
+}
 
-    (0..cam.hsize * cam.vsize)
-        .into_par_iter()
-        .map(|i| {
-            let (x, y) = (i % cam.hsize, i / cam.hsize);
-            let ray = cam.ray_for_pixel(x, y);
-            (colour_at(world, &ray, REFLECTION_RECURSION_DEPTH), (x, y))
-        })
-        .collect_into_vec(&mut colour_vec);
+// The up vector for a camera looking from one point to another, rolled
+// clockwise around the direction it looks in by the given angle in radians.
+// With no roll, the camera is level.
+pub fn up_from_roll(from: &Tuple, to: &Tuple, roll: Float) -> Tuple {
+    let forward = (*to - *from).normalise();
+    let up = level_up(&forward);
+    // rodrigues' rotation formula - up is at right angles to the axis, so the
+    // last term drops out
+    up * roll.cos() + forward.cross(&up) * roll.sin()
+}
 
-    for (c, (x, y)) in colour_vec {
-        image.write_pixel((x, y), c);
+use crate::parallel::*;
+// The average colour seen through each of the pixels' subpixel offsets,
+// using the rendering thread's buffer for the intersections. The camera rays
+// are traced through the BVH in packets of four, so the pixels should be next
+// to each other - what they hit is then shaded a ray at a time.
+fn render_pixels(
+    cam: &Camera,
+    world: &World,
+    pixels: &[(usize, usize)],
+    samples: usize,
+    buffer: &mut IntersectionBuffer,
+) -> Vec<Colour> {
+    let mut rays = Vec::new();
+    let mut rays_per_pixel = Vec::with_capacity(pixels.len());
+    for &(x, y) in pixels {
+        let pixel_rays = cam.rays_for_pixel(x, y, samples);
+        rays_per_pixel.push(pixel_rays.len());
+        rays.extend(pixel_rays);
+    }
+    stats::add(Counter::PrimaryRays, rays.len());
+    let (packets, rest) = rays.as_chunks::<4>();
+    let mut hits = Vec::with_capacity(rays.len());
+    for packet in packets {
+        hits.extend(Ray::nearest_hits(packet, world, buffer));
     }
+    hits.extend(rest.iter().map(|r| r.nearest_hit(world, buffer)));
+    let depth = cam.recursion_depth.unwrap_or(REFLECTION_RECURSION_DEPTH);
+    let mut colours = rays
+        .iter()
+        .zip(hits)
+        .map(|(ray, hit)| colour_of_hit(world, ray, hit, depth, buffer));
+    rays_per_pixel
+        .iter()
+        .map(|&n| {
+            let total = colours
+                .by_ref()
+                .take(n)
+                .fold(Colour::black(), |acc, c| acc + c);
+            total * (1.0 / n as Float)
+        })
+        .collect()
+}
 
-    image
+// whether pixel (x, y) of a rough render, width by height pixels, differs too
+// much from any of the pixels next to it
+fn high_contrast(
+    rough: &[Colour],
+    (width, height): (usize, usize),
+    (x, y): (usize, usize),
+    threshold: Float,
+) -> bool {
+    let colour = &rough[y * width + x];
+    let neighbours = [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ];
+    neighbours
+        .iter()
+        .filter(|(nx, ny)| *nx < width && *ny < height)
+        .any(|(nx, ny)| colour.max_difference(&rough[ny * width + nx]) > threshold)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    fn float_close(x: f64, y: f64) -> bool {
-        const EPSILON: f64 = 0.0001;
-        (x - y).abs() < EPSILON
+// A finished tile, as passed to render_with_progress's callback.
+pub struct Progress<'a> {
+    pub tile: Region,
+    // the tile's finished colours, row by row - with bloom, these are from
+    // before bloom and post-processing, as those need the whole image
+    pub colours: &'a [Colour],
+    pub tiles_done: usize,
+    pub tiles_total: usize,
+}
+
+// Settings for a Renderer. Those left as None are taken from the camera, so
+// the default settings render it just as the scene says.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct RenderSettings {
+    // how many times a ray can be reflected or refracted
+    pub recursion_depth: Option<usize>,
+    // rays per pixel, for anti-aliasing
+    pub samples: Option<usize>,
+    // only give pixels on edges all their samples - see Camera
+    pub adaptive_threshold: Option<Float>,
+    // without shadows every light reaches everything, which is quicker
+    pub shadows: bool,
+    // if set, no more tiles are started once the render's taken this long
+    pub timeout: Option<Duration>,
+}
+
+impl Default for RenderSettings {
+    fn default() -> RenderSettings {
+        RenderSettings {
+            recursion_depth: None,
+            samples: None,
+            adaptive_threshold: None,
+            shadows: true,
+            timeout: None,
+        }
     }
+}
 
-    #[test]
-    fn intersect_world_with_ray() {
-        let w = World::default();
-        let r = Ray::new(
-            Tuple::point_new(0.0, 0.0, -5.0),
-            Tuple::vector_new(0.0, 0.0, 1.0),
-        );
-        let intersections = r.intersects_world(&w);
-        assert_eq!(intersections.len(), 4);
-        assert_eq!(intersections[0].t, 4.0);
-        assert_eq!(intersections[1].t, 4.5);
-        assert_eq!(intersections[2].t, 5.5);
-        assert_eq!(intersections[3].t, 6.0);
+// Stops a render from elsewhere - another thread, or a GUI's stop button.
+// Clones share the same flag, so one can be kept to cancel with while another
+// is given to the renderer.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> CancellationToken {
+        CancellationToken::default()
     }
 
-    #[test]
-    fn default_view_transformation() {
-        let t = view_transform(
-            &Tuple::point_new(0.0, 0.0, 0.0),
-            &Tuple::point_new(0.0, 0.0, -1.0),
-            &Tuple::vector_new(0.0, 1.0, 0.0),
-        );
-        assert_eq!(t, Matrix::identity());
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::SeqCst);
     }
 
-    #[test]
-    fn view_transform_positive_z_direction() {
-        let t = view_transform(
-            &Tuple::point_new(0.0, 0.0, 0.0),
-            &Tuple::point_new(0.0, 0.0, 1.0),
-            &Tuple::vector_new(0.0, 1.0, 0.0),
-        );
-        assert_eq!(t, Matrix::scaling(-1.0, 1.0, -1.0));
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::SeqCst)
     }
+}
 
-    #[test]
-    fn view_transform_moves_world() {
-        let t = view_transform(
-            &Tuple::point_new(0.0, 0.0, 8.0),
-            &Tuple::point_new(0.0, 0.0, 0.0),
-            &Tuple::vector_new(0.0, 1.0, 0.0),
-        );
-        assert_eq!(t, Matrix::translation(0.0, 0.0, -8.0));
+// shares a flag that something else sets, like a signal handler
+impl From<Arc<AtomicBool>> for CancellationToken {
+    fn from(flag: Arc<AtomicBool>) -> CancellationToken {
+        CancellationToken(flag)
     }
+}
 
-    #[test]
-    fn arbitrary_view_transform() {
-        let t = view_transform(
-            &Tuple::point_new(1.0, 3.0, 2.0),
-            &Tuple::point_new(4.0, -2.0, 8.0),
-            &Tuple::vector_new(1.0, 1.0, 0.0),
-        );
-        let expected = Matrix::from_array(&[
-            [-0.50709, 0.50709, 0.67612, -2.36643],
-            [0.76772, 0.60609, 0.12122, -2.82843],
-            [-0.35857, 0.59761, -0.71714, 0.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]);
-        assert_eq!(t, expected);
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum RenderStatus {
+    Finished,
+    Cancelled,
+    TimedOut,
+}
+
+// An image from Renderer::render_cancellable. Unless it's finished, the tiles
+// that weren't rendered are left black.
+#[derive(Debug)]
+pub struct Rendered {
+    pub image: Canvas,
+    pub status: RenderStatus,
+}
+
+// Renders cameras' views of worlds, e.g.
+//
+//     let settings = RenderSettings { samples: Some(4), ..RenderSettings::default() };
+//     let image = Renderer::new(settings).render(&camera, &world);
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
+pub struct Renderer {
+    pub settings: RenderSettings,
+}
+
+impl Renderer {
+    pub fn new(settings: RenderSettings) -> Renderer {
+        Renderer { settings }
     }
 
-    #[test]
-    fn camera_pixel_size_horizontal() {
-        use std::f64::consts::FRAC_PI_2;
-        let c = Camera::new(200, 125, FRAC_PI_2, Matrix::identity());
-        assert!(float_close(c.pixel_size, 0.01));
+    pub fn render(&self, cam: &Camera, world: &World) -> Canvas {
+        self.render_with_progress(cam, world, |_| {})
     }
 
-    #[test]
-    fn camera_pixel_size_vertical() {
-        use std::f64::consts::FRAC_PI_2;
-        let c = Camera::new(125, 200, FRAC_PI_2, Matrix::identity());
-        assert!(float_close(c.pixel_size, 0.01));
+    // see render_with_progress below
+    pub fn render_with_progress<F>(&self, cam: &Camera, world: &World, progress: F) -> Canvas
+    where
+        F: Fn(&Progress) + Sync,
+    {
+        self.render_cancellable(cam, world, &CancellationToken::new(), progress)
+            .image
     }
 
-    #[test]
-    fn ray_through_centre_of_canvas() {
-        use std::f64::consts::FRAC_PI_2;
-        let c = Camera::new(201, 101, FRAC_PI_2, Matrix::identity());
-        println!("{}", c.pixel_size);
-        let r = c.ray_for_pixel(100, 50);
-        assert_eq!(r.origin, Tuple::point_new(0.0, 0.0, 0.0));
-        assert_eq!(r.direction, Tuple::vector_new(0.0, 0.0, -1.0));
+    // Tiles are checked for cancellation and the timeout before they're
+    // started, so the render stops once those already going are finished.
+    pub fn render_cancellable<F>(
+        &self,
+        cam: &Camera,
+        world: &World,
+        cancel: &CancellationToken,
+        progress: F,
+    ) -> Rendered
+    where
+        F: Fn(&Progress) + Sync,
+    {
+        let settings = &self.settings;
+        let cam = Camera {
+            recursion_depth: settings.recursion_depth.or(cam.recursion_depth),
+            samples: settings.samples.unwrap_or(cam.samples),
+            adaptive_threshold: settings.adaptive_threshold.or(cam.adaptive_threshold),
+            ..cam.clone()
+        };
+        let deadline = settings.timeout.map(|timeout| Instant::now() + timeout);
+        let stop = || cancel.is_cancelled() || deadline.is_some_and(|d| Instant::now() >= d);
+        let (image, finished) =
+            render_tiles(&cam, world, settings.shadows, Vec::new(), progress, stop);
+        let status = match (finished, cancel.is_cancelled()) {
+            (true, _) => RenderStatus::Finished,
+            (false, true) => RenderStatus::Cancelled,
+            (false, false) => RenderStatus::TimedOut,
+        };
+        Rendered { image, status }
     }
 
-    #[test]
-    fn ray_through_corner_of_canvas() {
-        use std::f64::consts::FRAC_PI_2;
-        let c = Camera::new(201, 101, FRAC_PI_2, Matrix::identity());
-        let r = c.ray_for_pixel(0, 0);
-        assert_eq!(r.origin, Tuple::point_new(0.0, 0.0, 0.0));
-        assert_eq!(r.direction, Tuple::vector_new(0.66519, 0.33259, -0.66851));
+    // The image a row at a time, top to bottom, for showing or saving it as
+    // it's rendered. It's rendered a band of rows at a time, as with
+    // --stream, so with adaptive sampling pixels are only compared with the
+    // neighbours in their band.
+    pub fn render_rows<'a>(&self, cam: &Camera, world: &'a World) -> Rows<'a> {
+        self.render_rows_cancellable(cam, world, &CancellationToken::new())
     }
 
-    #[test]
-    fn ray_when_camera_transformed() {
-        use std::f64::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4};
-        let c = Camera::new(
-            201,
-            101,
-            FRAC_PI_2,
+    // Once cancel is set, the rest of the rows are handed over black.
+    pub fn render_rows_cancellable<'a>(
+        &self,
+        cam: &Camera,
+        world: &'a World,
+        cancel: &CancellationToken,
+    ) -> Rows<'a> {
+        Rows {
+            renderer: *self,
+            cam: cam.clone(),
+            world,
+            cancel: cancel.clone(),
+            deadline: self.settings.timeout.map(|t| Instant::now() + t),
+            bands: band_tops(cam),
+            next_row: 0,
+            rows: VecDeque::new(),
+        }
+    }
+}
+
+// the same as Renderer::default().render - the camera isn't changed
+pub fn render(cam: &mut Camera, world: &World) -> Canvas {
+    Renderer::default().render(cam, world)
+}
+
+// Renders the image in square tiles, calling progress as each one is finished.
+// Tiles are finished in no particular order, possibly on several threads at
+// once.
+pub fn render_with_progress<F>(cam: &mut Camera, world: &World, progress: F) -> Canvas
+where
+    F: Fn(&Progress) + Sync,
+{
+    Renderer::default().render_with_progress(cam, world, progress)
+}
+
+// As render_with_progress, but tiles that have already been rendered (e.g.
+// by an earlier run that was stopped) are used as they are rather than being
+// rendered again. They count towards tiles_done, but aren't passed to progress.
+// Once cancel is set, no more tiles are started, and those not finished are
+// left black.
+pub fn render_resuming<F>(
+    cam: &Camera,
+    world: &World,
+    done: Vec<(Region, Vec<Colour>)>,
+    progress: F,
+    cancel: &AtomicBool,
+) -> Canvas
+where
+    F: Fn(&Progress) + Sync,
+{
+    let stop = || cancel.load(Ordering::SeqCst);
+    render_tiles(cam, world, true, done, progress, stop).0
+}
+
+// Returns the image, and whether every tile was rendered - once stop returns
+// true, no more are started.
+fn render_tiles<F, S>(
+    cam: &Camera,
+    world: &World,
+    shadows: bool,
+    done: Vec<(Region, Vec<Colour>)>,
+    progress: F,
+    stop: S,
+) -> (Canvas, bool)
+where
+    F: Fn(&Progress) + Sync,
+    S: Fn() -> bool + Sync,
+{
+    let region = cam.render_region();
+    let size = (region.width(), region.height());
+
+    // with adaptive sampling, a rough pass with one ray per pixel finds the
+    // edges that need smoothing
+    let rough: Option<(Vec<Colour>, Float)> = match cam.adaptive_threshold {
+        Some(threshold) if cam.samples > 1 => {
+            let rough = (0..size.0 * size.1)
+                .into_par_iter()
+                .map_init(
+                    || {
+                        (
+                            IntersectionBuffer::new().with_shadows(shadows),
+                            stats::Flush,
+                        )
+                    },
+                    |(buffer, _), i| {
+                        let pixel = (region.x0 + i % size.0, region.y0 + i / size.0);
+                        render_pixels(cam, world, &[pixel], 1, buffer)[0]
+                    },
+                )
+                .collect();
+            Some((rough, threshold))
+        }
+        _ => None,
+    };
+    // the colours of a block of pixels, row by row
+    let shade = |block: &Region, buffer: &mut IntersectionBuffer| -> Vec<Colour> {
+        let pixels: Vec<_> = block.pixels().collect();
+        let (rough, threshold) = match &rough {
+            Some((rough, threshold)) => (rough, *threshold),
+            None => return render_pixels(cam, world, &pixels, cam.samples, buffer),
+        };
+        let local = |(x, y): (usize, usize)| (x - region.x0, y - region.y0);
+        let edges: Vec<_> = pixels
+            .iter()
+            .copied()
+            .filter(|&p| high_contrast(rough, size, local(p), threshold))
+            .collect();
+        let mut smoothed = render_pixels(cam, world, &edges, cam.samples, buffer).into_iter();
+        pixels
+            .iter()
+            .map(|&p| match edges.contains(&p) {
+                true => smoothed.next().unwrap(),
+                false => rough[local(p).1 * size.0 + local(p).0],
+            })
+            .collect()
+    };
+
+    let tiles = region.tiles(TILE_SIZE);
+    let tiles_total = tiles.len();
+    let done: Vec<(Region, Vec<Colour>)> = done
+        .into_iter()
+        .filter(|(tile, colours)| tiles.contains(tile) && colours.len() == tile.pixels().count())
+        .collect();
+    let tiles_done = AtomicUsize::new(done.len());
+
+    // each tile's copied into the image as soon as it's finished, so only
+    // the tiles being worked on are ever held apart from it
+    let (width, height) = cam.output_size();
+    let place = |image: &mut Canvas, tile: &Region, colours: &[Colour]| {
+        for ((x, y), c) in tile.pixels().zip(colours) {
+            if cam.crop {
+                image.write_pixel((x - region.x0, y - region.y0), *c);
+            } else {
+                image.write_pixel((x, y), *c);
+            }
+        }
+    };
+    let mut image = Canvas::new(width, height);
+    for (tile, colours) in &done {
+        place(&mut image, tile, colours);
+    }
+    let image = Mutex::new(image);
+    tiles
+        .into_par_iter()
+        .filter(|tile| !done.iter().any(|(d, _)| d == tile))
+        .for_each_init(
+            || {
+                let buffer = IntersectionBuffer::new().with_shadows(shadows);
+                (buffer, Vec::new(), stats::Flush)
+            },
+            |(buffer, colours, _), tile| {
+                if stop() {
+                    return;
+                }
+                // the colours are gathered in a list that's reused for every
+                // tile the thread renders. they're shaded in 2x2 blocks, so
+                // each block's camera rays can be traced together
+                colours.clear();
+                colours.resize(tile.width() * tile.height(), Colour::black());
+                for block in tile.tiles(2) {
+                    for ((x, y), c) in block.pixels().zip(shade(&block, buffer)) {
+                        colours[(y - tile.y0) * tile.width() + x - tile.x0] = match cam.bloom {
+                            Some(_) => c,
+                            None => cam.post_process.apply(c),
+                        };
+                    }
+                }
+                place(&mut image.lock().unwrap(), &tile, colours);
+                progress(&Progress {
+                    tile,
+                    colours,
+                    tiles_done: tiles_done.fetch_add(1, Ordering::SeqCst) + 1,
+                    tiles_total,
+                });
+            },
+        );
+
+    let mut image = image.into_inner().unwrap();
+    if let Some(bloom) = cam.bloom {
+        image = bloom.apply(&image);
+        image.post_process(&cam.post_process);
+    }
+
+    (image, tiles_done.into_inner() == tiles_total)
+}
+
+// Extra images rendered alongside the main one ("arbitrary output
+// variables"), for compositing and for tracking down lighting problems. Each
+// pixel comes from the first thing hit by the ray through its centre, and is
+// None where that ray hits nothing or the pixel is outside the render region.
+#[derive(Debug)]
+pub struct Aovs {
+    width: usize,
+    height: usize,
+    // the distance from the camera to the hit
+    pub depth: Vec<Option<Float>>,
+    // the world space normal at the hit, facing back towards the camera
+    pub normal: Vec<Option<Tuple>>,
+    // where the object that was hit is in the world's list of objects
+    pub object_id: Vec<Option<usize>>,
+}
+
+impl Aovs {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    // Nearby hits are white, fading to dark grey for the furthest one.
+    pub fn depth_image(&self) -> Canvas {
+        let furthest = self
+            .depth
+            .iter()
+            .flatten()
+            .fold(0.0, |a: Float, &d| a.max(d));
+        self.image(&self.depth, |d| {
+            let shade = 1.0 - 0.9 * d / furthest.max(Float::EPSILON);
+            Colour::new(shade, shade, shade)
+        })
+    }
+
+    // Each component of the normal is moved from -1..1 to 0..1.
+    pub fn normal_image(&self) -> Canvas {
+        self.image(&self.normal, |n| {
+            Colour::new(n.x * 0.5 + 0.5, n.y * 0.5 + 0.5, n.z * 0.5 + 0.5)
+        })
+    }
+
+    // Every object gets its own made up colour.
+    pub fn object_id_image(&self) -> Canvas {
+        self.image(&self.object_id, |id| {
+            let mut rng = sampling::Rng::new(sampling::hash(&[id as u64]));
+            Colour::new(rng.next_float(), rng.next_float(), rng.next_float())
+        })
+    }
+
+    fn image<T: Copy>(&self, values: &[Option<T>], colour: impl Fn(T) -> Colour) -> Canvas {
+        let mut out = Canvas::new(self.width, self.height);
+        for (i, value) in values.iter().enumerate() {
+            if let Some(v) = value {
+                out.write_pixel((i % self.width, i / self.width), colour(*v));
+            }
+        }
+        out
+    }
+
+    // Writes each pass next to the main image, in the same format, with the
+    // name of the pass added: render.png gives render-depth.png and so on.
+    #[cfg(feature = "files")]
+    pub fn save<P: AsRef<Path>>(&self, output: P) -> io::Result<()> {
+        let output = output.as_ref();
+        self.depth_image().save(aov_path(output, "depth"))?;
+        self.normal_image().save(aov_path(output, "normal"))?;
+        self.object_id_image().save(aov_path(output, "id"))
+    }
+}
+
+pub fn aov_path(output: &Path, pass: &str) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}-{}", stem, pass);
+    if let Some(extension) = output.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    output.with_file_name(name)
+}
+
+// Animation frames are numbered from 1: render.png gives render-0001.png,
+// render-0002.png and so on.
+pub fn frame_path(output: &Path, frame: usize) -> PathBuf {
+    aov_path(output, &format!("{:04}", frame))
+}
+
+// the distance to, normal at and id of the first object seen through a pixel
+fn first_hit(cam: &Camera, world: &World, x: usize, y: usize) -> Option<(Float, Tuple, usize)> {
+    let r = cam.ray_for_pixel(x, y);
+    let hit = r.nearest_hit(world, &mut IntersectionBuffer::new())?;
+    let point = r.position(hit.t);
+    let mut normal = world.object(hit.object).normal_at_face(&point, hit.face);
+    if normal.dot(&r.direction) > 0.0 {
+        normal = normal.negate();
+    }
+    Some((hit.t, normal, hit.object.0))
+}
+
+// Renders the auxiliary passes for the same pixels that render would produce.
+pub fn render_aovs(cam: &Camera, world: &World) -> Aovs {
+    let region = cam.render_region();
+    let (width, height) = cam.output_size();
+    let (x0, y0) = if cam.crop {
+        (region.x0, region.y0)
+    } else {
+        (0, 0)
+    };
+    let hits: Vec<Option<(Float, Tuple, usize)>> = (0..width * height)
+        .into_par_iter()
+        .map(|i| {
+            let (x, y) = (x0 + i % width, y0 + i / width);
+            if region.contains(x, y) {
+                first_hit(cam, world, x, y)
+            } else {
+                None
+            }
+        })
+        .collect();
+    Aovs {
+        width,
+        height,
+        depth: hits.iter().map(|h| h.map(|(d, _, _)| d)).collect(),
+        normal: hits.iter().map(|h| h.map(|(_, n, _)| n)).collect(),
+        object_id: hits.iter().map(|h| h.map(|(_, _, id)| id)).collect(),
+    }
+}
+
+// Hands each row of Renderer::default().render_rows_cancellable to `row`, from
+// top to bottom, so that only one band is ever held in memory.
+pub fn render_rows<F>(
+    cam: &Camera,
+    world: &World,
+    cancel: &CancellationToken,
+    mut row: F,
+) -> io::Result<()>
+where
+    F: FnMut(&[Colour]) -> io::Result<()>,
+{
+    for (_, r) in Renderer::default().render_rows_cancellable(cam, world, cancel) {
+        row(&r)?;
+    }
+    Ok(())
+}
+
+// bloom spreads light between bands, so with bloom the whole image is one band
+fn band_height(cam: &Camera) -> usize {
+    match cam.bloom {
+        Some(_) => cam.output_size().1.max(1),
+        None => TILE_SIZE,
+    }
+}
+
+// where each band of rows starts, in the whole image
+fn band_tops(cam: &Camera) -> std::iter::StepBy<std::ops::Range<usize>> {
+    let top = if cam.crop { cam.render_region().y0 } else { 0 };
+    (top..top + cam.output_size().1).step_by(band_height(cam))
+}
+
+// The rows of the output image in the band starting at band_top, rendered
+// with render - or all black, if skip is set.
+fn band_rows<R>(cam: &Camera, band_top: usize, skip: bool, render: R) -> Vec<Vec<Colour>>
+where
+    R: FnOnce(&Camera) -> Canvas,
+{
+    let region = cam.render_region();
+    let (width, height) = cam.output_size();
+    let top = if cam.crop { region.y0 } else { 0 };
+    let blank = vec![Colour::black(); width];
+    let band_bottom = (band_top + band_height(cam)).min(top + height);
+    if skip {
+        return vec![blank; band_bottom - band_top];
+    }
+    let band = Region {
+        y0: band_top.max(region.y0),
+        y1: band_bottom.min(region.y1).max(band_top.max(region.y0)),
+        ..region
+    };
+    let image = render(&Camera {
+        region: Some(band),
+        crop: true,
+        ..cam.clone()
+    });
+    (band_top..band_bottom)
+        .map(|y| {
+            if !(band.y0..band.y1).contains(&y) {
+                blank.clone()
+            } else if cam.crop {
+                image.row(y - band.y0).to_vec()
+            } else {
+                let mut full = blank.clone();
+                full[region.x0..region.x1].copy_from_slice(image.row(y - band.y0));
+                full
+            }
+        })
+        .collect()
+}
+
+// The rows of an image from Renderer::render_rows, numbered from 0 at the top.
+// Each band of rows is only rendered when the first of its rows is asked for,
+// so a render can be stopped by not asking for any more.
+pub struct Rows<'a> {
+    renderer: Renderer,
+    cam: Camera,
+    world: &'a World,
+    cancel: CancellationToken,
+    // when the render has to stop, if the renderer has a timeout
+    deadline: Option<Instant>,
+    bands: std::iter::StepBy<std::ops::Range<usize>>,
+    // the output row the next band starts at
+    next_row: usize,
+    // rendered, but not yet handed out
+    rows: VecDeque<(usize, Vec<Colour>)>,
+}
+
+impl Iterator for Rows<'_> {
+    type Item = (usize, Vec<Colour>);
+
+    fn next(&mut self) -> Option<(usize, Vec<Colour>)> {
+        if self.rows.is_empty() {
+            let band_top = self.bands.next()?;
+            let timeout = self
+                .deadline
+                .map(|d| d.saturating_duration_since(Instant::now()));
+            let renderer = Renderer::new(RenderSettings {
+                timeout,
+                ..self.renderer.settings
+            });
+            let (world, cancel) = (self.world, &self.cancel);
+            let skip = timeout == Some(Duration::ZERO) || cancel.is_cancelled();
+            let render = |c: &Camera| renderer.render_cancellable(c, world, cancel, |_| {}).image;
+            // with bloom, the one band is rendered as a whole image rather
+            // than cropped to the region, so the glow can spread outside it
+            let rows = match self.cam.bloom {
+                Some(_) if !skip => {
+                    let image = render(&self.cam);
+                    (0..image.height()).map(|y| image.row(y).to_vec()).collect()
+                }
+                _ => band_rows(&self.cam, band_top, skip, render),
+            };
+            let first = self.next_row;
+            self.next_row += rows.len();
+            self.rows.extend((first..).zip(rows));
+        }
+        self.rows.pop_front()
+    }
+}
+
+pub fn render_with_aovs(cam: &mut Camera, world: &World) -> (Canvas, Aovs) {
+    let aovs = render_aovs(cam, world);
+    (render(cam, world), aovs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::precision::EPSILON_SCALE;
+    fn float_close(x: Float, y: Float) -> bool {
+        const EPSILON: Float = 0.0001 * EPSILON_SCALE;
+        (x - y).abs() < EPSILON
+    }
+
+    #[test]
+    fn intersect_world_with_ray() {
+        let w = World::default();
+        let r = Ray::new(
+            Tuple::point_new(0.0, 0.0, -5.0),
+            Tuple::vector_new(0.0, 0.0, 1.0),
+        );
+        let intersections = r.intersects_world(&w);
+        assert_eq!(intersections.len(), 4);
+        assert_eq!(intersections[0].t, 4.0);
+        assert_eq!(intersections[1].t, 4.5);
+        assert_eq!(intersections[2].t, 5.5);
+        assert_eq!(intersections[3].t, 6.0);
+    }
+
+    #[test]
+    fn objects_are_found_by_their_ids() {
+        let mut w = World::default();
+        let floor = w.add_object(crate::shapes::plane::default());
+        assert_eq!(floor, ObjectId(2));
+        assert_eq!(w.object(floor).shape, crate::shapes::ShapeType::Plane);
+        w.object_mut(floor).material.reflectivity = 0.5;
+        assert_eq!(w.objects[2].material.reflectivity, 0.5);
+        assert_eq!(w.ids().collect::<Vec<_>>().len(), 3);
+        // hits say which object they're on, not borrowing it
+        let r = Ray::new(
+            Tuple::point_new(0.0, 5.0, 0.0),
+            Tuple::vector_new(0.0, -1.0, 0.0),
+        );
+        let hit = r.nearest_hit(&w, &mut IntersectionBuffer::new()).unwrap();
+        w.object_mut(hit.object).material.reflectivity = 0.0;
+        assert_eq!(hit.object, ObjectId(0));
+    }
+
+    #[test]
+    fn looking_up_named_object() {
+        let mut w = World::default();
+        w.objects.push(Shape {
+            name: Some("floor".to_string()),
+            ..crate::shapes::plane::default()
+        });
+        let floor = w.get_object("floor").unwrap();
+        assert_eq!(floor.shape, crate::shapes::ShapeType::Plane);
+        assert!(w.get_object("ceiling").is_none());
+    }
+
+    #[test]
+    fn modifying_named_object() {
+        let mut w = World::default();
+        w.objects[1].name = Some("inner".to_string());
+        w.get_object_mut("inner")
+            .unwrap()
+            .set_transform(Matrix::translation(0.0, 1.0, 0.0));
+        assert_eq!(w.objects[1].transform, Matrix::translation(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn default_view_transformation() {
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::point_new(0.0, 0.0, -1.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(t, Matrix::identity());
+    }
+
+    #[test]
+    fn view_transform_positive_z_direction() {
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::point_new(0.0, 0.0, 1.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(t, Matrix::scaling(-1.0, 1.0, -1.0));
+    }
+
+    #[test]
+    fn view_transform_moves_world() {
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, 8.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(t, Matrix::translation(0.0, 0.0, -8.0));
+    }
+
+    #[test]
+    fn arbitrary_view_transform() {
+        let t = view_transform(
+            &Tuple::point_new(1.0, 3.0, 2.0),
+            &Tuple::point_new(4.0, -2.0, 8.0),
+            &Tuple::vector_new(1.0, 1.0, 0.0),
+        );
+        let expected = Matrix::from_array([
+            [-0.50709, 0.50709, 0.67612, -2.36643],
+            [0.76772, 0.60609, 0.12122, -2.82843],
+            [-0.35857, 0.59761, -0.71714, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_eq!(t, expected);
+    }
+
+    #[test]
+    fn view_transform_with_up_parallel_to_view() {
+        let from = Tuple::point_new(0.0, 5.0, 0.0);
+        let to = Tuple::point_new(0.0, 0.0, 0.0);
+        let t = view_transform(&from, &to, &Tuple::vector_new(0.0, 1.0, 0.0));
+        let expected = view_transform(&from, &to, &Tuple::vector_new(0.0, 0.0, 1.0));
+        assert_eq!(t, expected);
+        assert!(!t.inverse()[0][0].is_nan());
+    }
+
+    #[test]
+    fn up_vectors_from_roll() {
+        use crate::precision::consts::FRAC_PI_2;
+        let from = Tuple::point_new(0.0, 2.0, -5.0);
+        let to = Tuple::point_new(0.0, 2.0, 0.0);
+        assert_eq!(
+            up_from_roll(&from, &to, 0.0),
+            Tuple::vector_new(0.0, 1.0, 0.0)
+        );
+        // rolling clockwise a quarter turn, looking down +z, tips up over to -x
+        assert_eq!(
+            up_from_roll(&from, &to, FRAC_PI_2),
+            Tuple::vector_new(-1.0, 0.0, 0.0)
+        );
+        // looking down at an angle, up stays at right angles to the view
+        let to = Tuple::point_new(0.0, 0.0, 0.0);
+        let up = up_from_roll(&from, &to, 0.3);
+        assert!(float_close(up.dot(&(to - from)), 0.0));
+        assert!(float_close(up.magnitude(), 1.0));
+        // and straight down is level too
+        let down = Tuple::point_new(0.0, -3.0, -5.0);
+        assert_eq!(
+            up_from_roll(&from, &down, 0.0),
+            Tuple::vector_new(0.0, 0.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn camera_pixel_size_horizontal() {
+        use crate::precision::consts::FRAC_PI_2;
+        let c = Camera::new(200, 125, FRAC_PI_2, Matrix::identity());
+        assert!(float_close(c.pixel_size, 0.01));
+    }
+
+    #[test]
+    fn camera_pixel_size_vertical() {
+        use crate::precision::consts::FRAC_PI_2;
+        let c = Camera::new(125, 200, FRAC_PI_2, Matrix::identity());
+        assert!(float_close(c.pixel_size, 0.01));
+    }
+
+    #[test]
+    fn scaling_a_camera() {
+        use crate::precision::consts::FRAC_PI_2;
+        let mut c = Camera::new(200, 125, FRAC_PI_2, Matrix::translation(0.0, 1.0, 0.0));
+        c.samples = 4;
+        c.region = Some(Region {
+            x0: 10,
+            y0: 20,
+            x1: 110,
+            y1: 120,
+        });
+        let half = c.scaled(0.5);
+        assert_eq!((half.hsize, half.vsize), (100, 63));
+        assert!(float_close(half.pixel_size, 0.02));
+        assert_eq!(half.transform, c.transform);
+        assert_eq!(half.samples, 4);
+        assert_eq!(
+            half.region,
+            Some(Region {
+                x0: 5,
+                y0: 10,
+                x1: 55,
+                y1: 60
+            })
+        );
+    }
+
+    #[test]
+    fn resizing_a_camera() {
+        use crate::precision::consts::FRAC_PI_2;
+        let c = Camera::new(200, 125, FRAC_PI_2, Matrix::identity());
+        let wide = c.resized(400, 100);
+        assert_eq!((wide.hsize, wide.vsize), (400, 100));
+        assert!(float_close(wide.pixel_size, 0.005));
+        assert_eq!(wide, Camera::new(400, 100, FRAC_PI_2, Matrix::identity()));
+    }
+
+    #[test]
+    fn ray_through_centre_of_canvas() {
+        use crate::precision::consts::FRAC_PI_2;
+        let c = Camera::new(201, 101, FRAC_PI_2, Matrix::identity());
+        println!("{}", c.pixel_size);
+        let r = c.ray_for_pixel(100, 50);
+        assert_eq!(r.origin, Tuple::point_new(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector_new(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn ray_through_corner_of_canvas() {
+        use crate::precision::consts::FRAC_PI_2;
+        let c = Camera::new(201, 101, FRAC_PI_2, Matrix::identity());
+        let r = c.ray_for_pixel(0, 0);
+        assert_eq!(r.origin, Tuple::point_new(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector_new(0.66519, 0.33259, -0.66851));
+    }
+
+    #[test]
+    fn ray_when_camera_transformed() {
+        use crate::precision::consts::{FRAC_1_SQRT_2, FRAC_PI_2, FRAC_PI_4};
+        let c = Camera::new(
+            201,
+            101,
+            FRAC_PI_2,
             Matrix::translation(0.0, -2.0, 5.0).rotate_y(FRAC_PI_4),
         );
         let r = c.ray_for_pixel(100, 50);
@@ -264,9 +1658,73 @@ mod tests {
         );
     }
 
+    #[test]
+    fn camera_rays_carry_the_rays_through_the_next_pixels() {
+        use crate::precision::consts::FRAC_PI_2;
+        let c = Camera::new(201, 101, FRAC_PI_2, Matrix::translation(0.0, -2.0, 5.0));
+        let d = c.ray_for_pixel(10, 20).differentials.unwrap();
+        let across = c.ray_for_pixel(11, 20);
+        let down = c.ray_for_pixel(10, 21);
+        assert_eq!(d.dx, (across.origin, across.direction));
+        assert_eq!(d.dy, (down.origin, down.direction));
+    }
+
+    #[test]
+    fn worlds_and_cameras_can_be_saved_and_loaded() {
+        use crate::shapes::mesh::Mesh;
+        use crate::shapes::{ColourStops, PatternFunction, ShapeType};
+        let mut w = World::default();
+        w.objects[1].material.pattern = Some(Pattern::Gradient {
+            stops: ColourStops::between(Colour::white(), Colour::black()),
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
+        });
+        w.add_object(Shape {
+            shape: ShapeType::Mesh(Box::new(Mesh::new([[
+                Tuple::point_new(0.0, 1.0, 2.0),
+                Tuple::point_new(-1.0, 0.0, 2.0),
+                Tuple::point_new(1.0, 0.0, 2.0),
+            ]]))),
+            ..sphere::default()
+        });
+        w.fog = Some(Fog::Exponential {
+            colour: Colour::white(),
+            density: 0.1,
+        });
+        let json = serde_json::to_string(&w).unwrap();
+        let mut loaded: World = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.objects, w.objects);
+        assert_eq!(loaded.lights, w.lights);
+        assert_eq!(loaded.fog, w.fog);
+        assert!(loaded.bvh.is_none());
+        loaded.build_bvh();
+
+        let c = Camera::builder()
+            .size(11, 11)
+            .samples(4)
+            .look_at(
+                Tuple::point_new(0.0, 0.0, -5.0),
+                Tuple::point_new(0.0, 0.0, 0.0),
+                Tuple::vector_new(0.0, 1.0, 0.0),
+            )
+            .build();
+        let loaded_c: Camera = serde_json::from_str(&serde_json::to_string(&c).unwrap()).unwrap();
+        assert_eq!(loaded_c, c);
+        assert_eq!(
+            Renderer::default().render(&loaded_c, &loaded),
+            Renderer::default().render(&c, &w)
+        );
+
+        // functions can't be saved
+        w.objects[0].material.pattern = Some(Pattern::Function {
+            function: PatternFunction::new(|_| Colour::white()),
+            transform: Transform::default(),
+        });
+        assert!(serde_json::to_string(&w).is_err());
+    }
+
     #[test]
     fn rendering_world_with_camera() {
-        use std::f64::consts::FRAC_PI_2;
+        use crate::precision::consts::FRAC_PI_2;
         let w = World::default();
         let t = view_transform(
             &Tuple::point_new(0.0, 0.0, -5.0),
@@ -277,4 +1735,692 @@ mod tests {
         let image = render(&mut c, &w);
         assert_eq!(*image.pixel_at(5, 5), Colour::new(0.38066, 0.47583, 0.2855));
     }
+
+    #[test]
+    fn builders_make_the_same_world_and_camera() {
+        let default = World::default();
+        let w = World::builder()
+            .objects(World::default().objects)
+            .light(default.lights[0].clone())
+            .build();
+        assert_eq!(w.objects, default.objects);
+        assert!(w.bvh.is_some());
+        let from = Tuple::point_new(0.0, 0.0, -5.0);
+        let to = Tuple::point_new(0.0, 0.0, 0.0);
+        let up = Tuple::vector_new(0.0, 1.0, 0.0);
+        let mut c = Camera::builder()
+            .size(11, 11)
+            .fov_degrees(90.0)
+            .look_at(from, to, up)
+            .build();
+        assert_eq!(
+            c.transform,
+            Transform::from(view_transform(&from, &to, &up))
+        );
+        assert!(float_close(
+            c.field_of_view,
+            crate::precision::consts::FRAC_PI_2
+        ));
+        // it's focused on what it's looking at
+        assert_eq!(c.focal_distance, 5.0);
+        let image = render(&mut c, &w);
+        assert_eq!(*image.pixel_at(5, 5), Colour::new(0.38066, 0.47583, 0.2855));
+    }
+
+    #[test]
+    fn renderer_settings_change_the_render() {
+        // a ball over a floor, looking at a point in its shadow
+        let mut ball = sphere::default();
+        ball.set_transform(Matrix::translation(0.0, 1.0, 0.0));
+        let w = World::builder()
+            .object(crate::shapes::plane::default())
+            .object(ball)
+            .light(PointLight::new(
+                Colour::white(),
+                Tuple::point_new(0.0, 10.0, 0.0),
+            ))
+            .build();
+        let c = Camera::builder()
+            .size(1, 1)
+            .samples(16)
+            .look_at(
+                Tuple::point_new(0.0, 0.5, -5.0),
+                Tuple::point_new(0.0, 0.0, -0.9),
+                Tuple::vector_new(0.0, 1.0, 0.0),
+            )
+            .build();
+        let shadowed = Renderer::default().render(&c, &w);
+        let lit = Renderer::new(RenderSettings {
+            shadows: false,
+            ..RenderSettings::default()
+        })
+        .render(&c, &w);
+        assert!(lit.pixel_at(0, 0).components()[0] > shadowed.pixel_at(0, 0).components()[0]);
+        // the camera's own settings are used unless they're overridden
+        let one_sample = Renderer::new(RenderSettings {
+            samples: Some(1),
+            ..RenderSettings::default()
+        })
+        .render(&c, &w);
+        let mut c1 = Camera {
+            samples: 1,
+            ..c.clone()
+        };
+        assert_eq!(one_sample, render(&mut c1, &w));
+        assert_eq!(shadowed, render(&mut c.clone(), &w));
+    }
+
+    #[test]
+    fn renders_can_be_cancelled_or_time_out() {
+        let w = World::default();
+        let c = Camera::builder()
+            .size(40, 40)
+            .look_at(
+                Tuple::point_new(0.0, 0.0, -5.0),
+                Tuple::point_new(0.0, 0.0, 0.0),
+                Tuple::vector_new(0.0, 1.0, 0.0),
+            )
+            .build();
+        let token = CancellationToken::new();
+        let finished = Renderer::default().render_cancellable(&c, &w, &token, |_| {});
+        assert_eq!(finished.status, RenderStatus::Finished);
+        assert_eq!(finished.image, Renderer::default().render(&c, &w));
+
+        // a clone stops the render just the same
+        token.clone().cancel();
+        let cancelled = Renderer::default().render_cancellable(&c, &w, &token, |_| {});
+        assert_eq!(cancelled.status, RenderStatus::Cancelled);
+        assert_eq!(cancelled.image, Canvas::new(40, 40));
+
+        let out_of_time = Renderer::new(RenderSettings {
+            timeout: Some(Duration::ZERO),
+            ..RenderSettings::default()
+        })
+        .render_cancellable(&c, &w, &CancellationToken::new(), |_| {});
+        assert_eq!(out_of_time.status, RenderStatus::TimedOut);
+        assert_eq!(out_of_time.image, Canvas::new(40, 40));
+    }
+
+    #[test]
+    fn bloom_is_applied_to_the_whole_image() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        let plain = render(&mut c, &w);
+        c.bloom = Some(Bloom {
+            threshold: 0.0,
+            radius: 1.0,
+            strength: 1.0,
+        });
+        let bloomed = render(&mut c, &w);
+        // the corners are background, but pick up glow from the sphere
+        assert_eq!(*plain.pixel_at(2, 2), Colour::black());
+        assert!(bloomed.pixel_at(2, 2).max_difference(&Colour::black()) > 0.0);
+        let mut rows = Vec::new();
+        render_rows(&c, &w, &CancellationToken::new(), |row| {
+            rows.push(row.to_vec());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rows[2].as_slice(), bloomed.row(2));
+        let (y, row) = Renderer::default().render_rows(&c, &w).nth(2).unwrap();
+        assert_eq!((y, row.as_slice()), (2, bloomed.row(2)));
+    }
+
+    #[test]
+    fn rendering_aovs_alongside_the_image() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        let (image, aovs) = render_with_aovs(&mut c, &w);
+        assert_eq!(
+            (aovs.width(), aovs.height()),
+            (image.width(), image.height())
+        );
+        let centre = 5 * 11 + 5;
+        assert!(float_close(aovs.depth[centre].unwrap(), 4.0));
+        assert_eq!(aovs.normal[centre], Some(Tuple::vector_new(0.0, 0.0, -1.0)));
+        assert_eq!(aovs.object_id[centre], Some(0));
+        assert_eq!(aovs.depth[0], None);
+        assert_eq!(*aovs.depth_image().pixel_at(0, 0), Colour::black());
+        assert_eq!(
+            *aovs.normal_image().pixel_at(5, 5),
+            Colour::new(0.5, 0.5, 0.0)
+        );
+        assert_ne!(*aovs.object_id_image().pixel_at(5, 5), Colour::black());
+    }
+
+    #[test]
+    fn aovs_only_cover_the_render_region() {
+        let w = World::default();
+        let mut c = Camera::new(20, 10, 1.0, Matrix::translation(0.0, 0.0, -5.0));
+        c.region = Some(Region {
+            x0: 0,
+            y0: 0,
+            x1: 5,
+            y1: 10,
+        });
+        let aovs = render_aovs(&c, &w);
+        assert_eq!(aovs.width(), 20);
+        assert_eq!(aovs.object_id[5 * 20 + 10], None);
+        c.crop = true;
+        assert_eq!(render_aovs(&c, &w).width(), 5);
+    }
+
+    #[test]
+    fn rendering_row_by_row_matches_rendering_all_at_once() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(9, 40, FRAC_PI_2, t);
+        let regions = [
+            None,
+            Some(Region {
+                x0: 2,
+                y0: 5,
+                x1: 7,
+                y1: 37,
+            }),
+        ];
+        for region in regions {
+            for crop in [false, true] {
+                c.region = region;
+                c.crop = crop;
+                let image = render(&mut c, &w);
+                let mut rows = Vec::new();
+                render_rows(&c, &w, &CancellationToken::new(), |row| {
+                    rows.push(row.to_vec());
+                    Ok(())
+                })
+                .unwrap();
+                assert_eq!(rows.len(), image.height());
+                for (y, row) in rows.iter().enumerate() {
+                    assert_eq!(row.as_slice(), image.row(y));
+                }
+                // the same rows come out of the renderer's iterator, numbered
+                let numbered: Vec<_> = Renderer::default().render_rows(&c, &w).collect();
+                assert_eq!(numbered, rows.into_iter().enumerate().collect::<Vec<_>>());
+            }
+        }
+    }
+
+    #[test]
+    fn aov_files_are_named_after_the_output() {
+        assert_eq!(
+            aov_path(Path::new("renders/scene.png"), "depth"),
+            PathBuf::from("renders/scene-depth.png")
+        );
+        assert_eq!(
+            aov_path(Path::new("scene"), "id"),
+            PathBuf::from("scene-id")
+        );
+        assert_eq!(
+            frame_path(Path::new("renders/scene.png"), 12),
+            PathBuf::from("renders/scene-0012.png")
+        );
+    }
+
+    #[test]
+    fn subpixel_offsets_fill_a_grid() {
+        let mut c = Camera::new(10, 10, 1.0, Matrix::identity());
+        assert_eq!(c.subpixel_offsets(3, 4), vec![(0.5, 0.5)]);
+        c.samples = 4;
+        c.sampler = Sampler::Grid;
+        assert_eq!(
+            c.subpixel_offsets(3, 4),
+            vec![(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+        );
+        c.samples = 5;
+        c.sampler = Sampler::Stratified { seed: 0 };
+        assert_eq!(c.subpixel_offsets(3, 4).len(), 6);
+        assert_eq!(c.subpixel_offsets(3, 4), c.subpixel_offsets(3, 4));
+        assert_ne!(c.subpixel_offsets(3, 4), c.subpixel_offsets(4, 3));
+    }
+
+    #[test]
+    fn fisheye_rays_spread_out_from_the_centre() {
+        use crate::precision::consts::{FRAC_1_SQRT_2, PI};
+        let mut c = Camera::new(100, 50, PI, Matrix::identity());
+        c.projection = Projection::Fisheye;
+        let r = c.ray_for_subpixel(50, 25, (0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector_new(0.0, 0.0, -1.0));
+        // the left edge is half of the 180 degree field of view away
+        let r = c.ray_for_subpixel(0, 25, (0.0, 0.0));
+        assert_eq!(r.direction, Tuple::vector_new(1.0, 0.0, 0.0));
+        // and the top edge is half as far from the centre
+        let r = c.ray_for_subpixel(50, 0, (0.0, 0.0));
+        assert_eq!(
+            r.direction,
+            Tuple::vector_new(0.0, FRAC_1_SQRT_2, -FRAC_1_SQRT_2)
+        );
+    }
+
+    #[test]
+    fn equirectangular_rays_cover_every_direction() {
+        let mut c = Camera::new(200, 100, 1.0, Matrix::translation(0.0, -2.0, 0.0));
+        c.projection = Projection::Equirectangular;
+        let direction = |x, y| c.ray_for_subpixel(x, y, (0.0, 0.0)).direction;
+        assert_eq!(
+            c.ray_for_pixel(0, 0).origin,
+            Tuple::point_new(0.0, 2.0, 0.0)
+        );
+        assert_eq!(direction(100, 50), Tuple::vector_new(0.0, 0.0, -1.0));
+        assert_eq!(direction(150, 50), Tuple::vector_new(-1.0, 0.0, 0.0));
+        assert_eq!(direction(0, 50), Tuple::vector_new(0.0, 0.0, 1.0));
+        assert_eq!(direction(50, 0), Tuple::vector_new(0.0, 1.0, 0.0));
+
+        // looking down +z, the panorama lines up with an equirectangular environment
+        let mut c = Camera::new(
+            200,
+            100,
+            1.0,
+            view_transform(
+                &Tuple::point_new(0.0, 0.0, 0.0),
+                &Tuple::point_new(0.0, 0.0, 1.0),
+                &Tuple::vector_new(0.0, 1.0, 0.0),
+            ),
+        );
+        c.projection = Projection::Equirectangular;
+        for (x, y) in [(30, 20), (120, 70), (190, 45)] {
+            let d = c.ray_for_subpixel(x, y, (0.0, 0.0)).direction;
+            let (u, v) = crate::uv::equirectangular_map(&d);
+            assert!(float_close(u, x as Float / 200.0));
+            assert!(float_close(v, 1.0 - y as Float / 100.0));
+        }
+    }
+
+    #[test]
+    fn stereo_eyes_converge() {
+        use crate::precision::consts::FRAC_PI_2;
+        let mut c = Camera::new(201, 101, FRAC_PI_2, Matrix::identity());
+        c.stereo = Some(Stereo {
+            interocular: 0.2,
+            convergence: 4.0,
+        });
+        assert_eq!(c.image_width(), 402);
+        // the middle of each view looks at the same point on the convergence plane
+        let left = c.ray_for_pixel(100, 50);
+        let right = c.ray_for_pixel(301, 50);
+        assert_eq!(left.origin, Tuple::point_new(0.1, 0.0, 0.0));
+        assert_eq!(right.origin, Tuple::point_new(-0.1, 0.0, 0.0));
+        let meet = Tuple::point_new(0.0, 0.0, -4.0);
+        assert_eq!(left.position(4.0 / -left.direction.z), meet);
+        assert_eq!(right.position(4.0 / -right.direction.z), meet);
+    }
+
+    #[test]
+    fn rendering_in_stereo() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        c.stereo = Some(Stereo {
+            interocular: 0.5,
+            convergence: 5.0,
+        });
+        let image = render(&mut c, &w);
+        assert_eq!((image.width(), image.height()), (22, 11));
+        // the views differ, but both see the sphere in the middle
+        assert_ne!(*image.pixel_at(5, 5), *image.pixel_at(16, 5));
+        assert_ne!(*image.pixel_at(16, 5), Colour::black());
+        assert_ne!(*image.pixel_at(5, 5), Colour::black());
+    }
+
+    #[test]
+    fn rays_through_the_lens_meet_at_the_focal_distance() {
+        use crate::precision::consts::FRAC_PI_2;
+        let mut c = Camera::new(201, 101, FRAC_PI_2, Matrix::translation(0.0, -2.0, 5.0));
+        c.focal_distance = 4.0;
+        let r = c.ray_through_lens(100, 50, (0.5, 0.5), (0.5, -0.25));
+        assert_eq!(r.origin, Tuple::point_new(0.5, 1.75, -5.0));
+        // the ray passes through the focal point, 4 units in front of the lens centre
+        let t = 4.0 / (-r.direction.z);
+        assert_eq!(r.position(t), Tuple::point_new(0.0, 2.0, -9.0));
+    }
+
+    #[test]
+    fn pinhole_cameras_ignore_the_lens() {
+        use crate::precision::consts::FRAC_PI_2;
+        let mut c = Camera::new(201, 101, FRAC_PI_2, Matrix::identity());
+        c.samples = 4;
+        for r in c.rays_for_pixel(10, 20, 4) {
+            assert_eq!(r.origin, Tuple::point_new(0.0, 0.0, 0.0));
+        }
+        c.aperture = 0.5;
+        c.focal_distance = 3.0;
+        let rays = c.rays_for_pixel(10, 20, 4);
+        assert_eq!(rays.len(), 4);
+        for r in &rays {
+            assert!(r.origin.z == 0.0 && r.origin.x.hypot(r.origin.y) <= 0.5);
+        }
+        assert_ne!(rays[0].origin, rays[1].origin);
+        // one ray goes straight through the middle of the lens
+        let r = &c.rays_for_pixel(10, 20, 1)[0];
+        assert_eq!(r.origin, Tuple::point_new(0.0, 0.0, 0.0));
+        assert_eq!(r.direction, c.ray_for_pixel(10, 20).direction);
+    }
+
+    #[test]
+    fn rendering_with_several_samples_per_pixel() {
+        use crate::lighting::colour_at;
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        c.samples = 4;
+        c.sampler = Sampler::Grid;
+        let image = render(&mut c, &w);
+        // a pixel on the edge of the sphere is a blend of it and the background
+        let expected = [(0.25, 0.25), (0.75, 0.25), (0.25, 0.75), (0.75, 0.75)]
+            .iter()
+            .fold(Colour::black(), |acc, offset| {
+                acc + colour_at(&w, &c.ray_for_subpixel(4, 5, *offset), 5)
+            })
+            * 0.25;
+        assert_eq!(*image.pixel_at(4, 5), expected);
+    }
+
+    #[test]
+    fn adaptive_sampling_only_smooths_edges() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        let rough = render(&mut c, &w);
+        c.samples = 4;
+        c.sampler = Sampler::Grid;
+        let full = render(&mut c, &w);
+        c.adaptive_threshold = Some(0.1);
+        let adaptive = render(&mut c, &w);
+        // the corner only sees background, and the edge of the sphere is smoothed
+        assert_eq!(*adaptive.pixel_at(0, 0), *rough.pixel_at(0, 0));
+        assert_eq!(*adaptive.pixel_at(4, 5), *full.pixel_at(4, 5));
+        assert_ne!(*adaptive.pixel_at(4, 5), *rough.pixel_at(4, 5));
+    }
+
+    #[test]
+    fn rendering_only_a_region() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        let whole = render(&mut c, &w);
+        c.region = Some(Region {
+            x0: 4,
+            y0: 3,
+            x1: 7,
+            y1: 20,
+        });
+        assert_eq!(
+            c.render_region(),
+            Region {
+                x0: 4,
+                y0: 3,
+                x1: 7,
+                y1: 11
+            }
+        );
+        let part = render(&mut c, &w);
+        assert_eq!((part.width(), part.height()), (11, 11));
+        assert_eq!(*part.pixel_at(5, 5), *whole.pixel_at(5, 5));
+        assert_eq!(*part.pixel_at(5, 1), Colour::black());
+        assert_ne!(*whole.pixel_at(5, 4), Colour::black());
+        assert_eq!(*part.pixel_at(3, 5), Colour::black());
+
+        c.crop = true;
+        let cropped = render(&mut c, &w);
+        assert_eq!((cropped.width(), cropped.height()), (3, 8));
+        assert_eq!(*cropped.pixel_at(1, 2), *whole.pixel_at(5, 5));
+    }
+
+    #[test]
+    fn regions_split_into_tiles() {
+        let r = Region {
+            x0: 10,
+            y0: 0,
+            x1: 80,
+            y1: 40,
+        };
+        let tiles = r.tiles(32);
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(
+            tiles[2],
+            Region {
+                x0: 74,
+                y0: 0,
+                x1: 80,
+                y1: 32
+            }
+        );
+        assert_eq!(
+            tiles.iter().map(|t| t.pixels().count()).sum::<usize>(),
+            70 * 40
+        );
+        let small = Region {
+            x0: 1,
+            y0: 2,
+            x1: 3,
+            y1: 4,
+        };
+        assert_eq!(
+            small.pixels().collect::<Vec<_>>(),
+            vec![(1, 2), (2, 2), (1, 3), (2, 3)]
+        );
+    }
+
+    #[test]
+    fn rendering_reports_progress_per_tile() {
+        use crate::precision::consts::FRAC_PI_2;
+        use std::sync::Mutex;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(70, 40, FRAC_PI_2, t);
+        let reports = Mutex::new(Vec::new());
+        let finished = Mutex::new(Vec::new());
+        let image = render_with_progress(&mut c, &w, |p| {
+            assert_eq!(p.colours.len(), p.tile.width() * p.tile.height());
+            let mut finished = finished.lock().unwrap();
+            finished.extend(p.tile.pixels().zip(p.colours.iter().cloned()));
+            reports.lock().unwrap().push((p.tiles_done, p.tiles_total));
+        });
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort();
+        assert_eq!(reports, (1..=6).map(|n| (n, 6)).collect::<Vec<_>>());
+        // the tiles cover the whole image, with the same colours as the result
+        let finished = finished.into_inner().unwrap();
+        assert_eq!(finished.len(), 70 * 40);
+        for ((x, y), colour) in finished {
+            assert_eq!(*image.pixel_at(x, y), colour);
+        }
+        assert_eq!(image, render(&mut c, &w));
+    }
+
+    #[test]
+    fn cancelled_renders_stop_early() {
+        use crate::precision::consts::FRAC_PI_2;
+        let w = World::default();
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        // 256 tiles, so there's plenty left once the first is finished
+        let c = Camera::new(512, 512, FRAC_PI_2, t);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let finished = AtomicUsize::new(0);
+        render_resuming(
+            &c,
+            &w,
+            Vec::new(),
+            |_| {
+                finished.fetch_add(1, Ordering::SeqCst);
+                cancel.store(true, Ordering::SeqCst);
+            },
+            &cancel,
+        );
+        let finished = finished.load(Ordering::SeqCst);
+        assert!((1..256).contains(&finished));
+
+        // streamed rows are still all handed over, but black
+        let mut rows = 0;
+        let token = CancellationToken::from(Arc::clone(&cancel));
+        render_rows(&c, &w, &token, |row| {
+            assert!(row.iter().all(|c| *c == Colour::black()));
+            rows += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(rows, 512);
+    }
+
+    #[test]
+    #[cfg(feature = "parallel")]
+    fn renders_are_the_same_on_any_number_of_threads() {
+        use crate::lighting::LightArea;
+        let mut w = World::default();
+        w.lights[0] = w.lights[0].clone().with_area(LightArea {
+            uvec: Tuple::vector_new(2.0, 0.0, 0.0),
+            usteps: 2,
+            vvec: Tuple::vector_new(0.0, 0.0, 2.0),
+            vsteps: 2,
+        });
+        w.shadow_sampler = Sampler::BlueNoise { seed: 9 };
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        // a few tiles, with everything random that a camera has
+        let mut c = Camera::new(40, 34, 1.0, t);
+        c.samples = 4;
+        c.aperture = 0.1;
+        c.focal_distance = 4.0;
+        c.adaptive_threshold = Some(0.05);
+        let render_on = |threads| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            pool.install(|| render(&mut c.clone(), &w))
+        };
+        assert_eq!(render_on(1), render_on(3));
+    }
+
+    #[test]
+    fn a_bvh_doesnt_change_the_render() {
+        use crate::shapes::{cone, cube, cylinder, plane, triangle};
+        let mut w = World::default();
+        let mut glass = sphere::glass_sphere();
+        glass.set_transform(Matrix::scaling(0.5, 0.5, 0.5).translate(-1.0, 0.5, -1.5));
+        let mut box_ = cube::default();
+        box_.set_transform(Matrix::rotation_y(0.5).translate(1.5, -0.5, 0.5));
+        let mut pipe = cylinder::truncated(-1.0, 1.0, true);
+        pipe.set_transform(Matrix::scaling(0.3, 1.0, 0.3).translate(-1.5, 0.0, 1.0));
+        let mut funnel = cone::truncated(-1.0, 0.0, false);
+        funnel.set_transform(Matrix::translation(0.0, 1.5, 0.0));
+        let mut floor = plane::default();
+        floor.set_transform(Matrix::translation(0.0, -1.0, 0.0));
+        floor.material.reflectivity = 0.3;
+        w.objects.extend(vec![
+            glass,
+            box_,
+            pipe,
+            funnel,
+            floor,
+            triangle::new(
+                Tuple::point_new(1.0, 1.0, -1.0),
+                Tuple::point_new(2.0, 1.0, -1.0),
+                Tuple::point_new(1.5, 2.0, -1.0),
+            ),
+        ]);
+        let t = view_transform(
+            &Tuple::point_new(0.0, 1.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(30, 20, 1.2, t);
+        let without = render(&mut c, &w);
+        w.build_bvh();
+        assert_eq!(render(&mut c, &w), without);
+    }
+
+    #[test]
+    fn linear_fog_is_clamped_between_its_distances() {
+        let fog = Fog::Linear {
+            colour: Colour::white(),
+            start: 10.0,
+            end: 20.0,
+        };
+        let c = Colour::new(0.2, 0.4, 0.6);
+        assert_eq!(fog.apply(c, 5.0), c);
+        assert_eq!(fog.apply(c, 15.0), Colour::new(0.6, 0.7, 0.8));
+        assert_eq!(fog.apply(c, 50.0), Colour::white());
+    }
+
+    #[test]
+    fn exponential_fog_thickens_with_distance() {
+        let fog = Fog::Exponential {
+            colour: Colour::white(),
+            density: 0.1,
+        };
+        let c = Colour::black();
+        assert_eq!(fog.apply(c, 0.0), Colour::black());
+        let amount = 1.0 - Float::exp(-1.0);
+        assert_eq!(fog.apply(c, 10.0), Colour::new(amount, amount, amount));
+    }
+
+    #[test]
+    fn rendering_world_with_function_pattern() {
+        use crate::precision::consts::FRAC_PI_2;
+        use crate::shapes::PatternFunction;
+        let w = World {
+            environment: Some(Pattern::Function {
+                function: PatternFunction::new(|p| Colour::new(p.x.abs(), p.y.abs(), p.z.abs())),
+                transform: Transform::default(),
+            }),
+            ..World::new()
+        };
+        let t = view_transform(
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::point_new(0.0, 0.0, 1.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        let mut c = Camera::new(11, 11, FRAC_PI_2, t);
+        let image = render(&mut c, &w);
+        assert_eq!(*image.pixel_at(5, 5), Colour::new(0.0, 0.0, 1.0));
+    }
 }
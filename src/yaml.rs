@@ -1,16 +1,37 @@
-use crate::canvas::Colour;
-use crate::lighting::PointLight;
-use crate::matrices::Matrix;
-use crate::shapes::{Material, Pattern, Shape, ShapeType};
+use crate::canvas::{Bloom, Canvas, Colour, Dither, MipMap, ToneMap};
+use crate::expression;
+use crate::lighting::{Attenuation, LightArea, LightKind, PointLight};
+use crate::matrices::{Matrix, Transform};
+use crate::obj;
+use crate::precision::Float;
+use crate::sampling::Sampler;
+use crate::scene::{SceneDescription, WorldSettings};
+use crate::shapes::{ColourStops, Material, Pattern, Shape, ShapeType};
 use crate::tuple::Tuple;
-use crate::world::{self, Camera, World};
-use yaml_rust::{yaml, Yaml};
+use crate::uv::UvMapping;
+use crate::world::{self, Camera, Fog, Projection, Region, Stereo, World};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use yaml_rust::{yaml, Yaml, YamlLoader};
 
 enum EntityKind {
     Camera,
     Light,
     Plane,
     Sphere,
+    Cylinder,
+    Cone,
+    Cube,
+    Obj,
+    Group,
+    World,
+    Render,
+    Define,
+    Include,
+    Frames,
 }
 
 enum TupleKind {
@@ -20,414 +41,3884 @@ enum TupleKind {
 
 #[derive(Debug, PartialEq)]
 enum TransformType {
-    RotateX(f64),
-    RotateY(f64),
-    RotateZ(f64),
-    Translate(f64, f64, f64),
-    Scale(f64, f64, f64),
-}
-
-pub fn parse_config(config: &yaml::Yaml) -> (World, Camera) {
-    let mut w = World::new();
-    let mut c = Camera::default();
-    // iterate over the structures
-    if let Yaml::Array(entities) = config {
-        for node in entities {
-            if let Yaml::Hash(entity) = node {
-                match entity_kind(entity) {
-                    EntityKind::Camera => c = camera_from_config(node),
-                    EntityKind::Light => w.lights.push(light_from_config(node)),
-                    EntityKind::Plane | EntityKind::Sphere => {
-                        w.objects.push(shape_from_config(node))
-                    }
-                };
-            }
-        }
-    }
-    (w, c)
+    RotateX(Float),
+    RotateY(Float),
+    RotateZ(Float),
+    Translate(Float, Float, Float),
+    Scale(Float, Float, Float),
+    Matrix(Matrix<Float, 4, 4>),
 }
 
-// this function assumes that it's being given a Yaml::Hash whose "add" field is "camera"
-// it will panic otherwise
-
-fn camera_from_config(cam_yaml: &yaml::Yaml) -> world::Camera {
-    if let Yaml::Hash(_cam_config) = cam_yaml {
-        let from = destructure_yaml_array_into_tuple(&cam_yaml["from"], TupleKind::Point);
-        let to = destructure_yaml_array_into_tuple(&cam_yaml["to"], TupleKind::Point);
-        let up = destructure_yaml_array_into_tuple(&cam_yaml["up"], TupleKind::Vector);
-        world::Camera::new(
-            cam_yaml["width"].as_i64().unwrap() as usize,
-            cam_yaml["height"].as_i64().unwrap() as usize,
-            cam_yaml["field-of-view"].as_f64().unwrap(),
-            world::view_transform(&from, &to, &up),
-        )
-    } else {
-        unreachable!()
-    }
+// Everything that can be wrong with a scene file. The key says where the
+// problem is, as the path of keys down from the entity it's in, e.g.
+// "entity 3 (sphere).material.colour[1]". yaml-rust doesn't keep track of
+// where values are in the file, so only syntax errors have line numbers.
+#[derive(Debug, PartialEq, Clone)]
+pub enum ParseError {
+    // the file isn't valid YAML
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    Missing {
+        key: String,
+    },
+    // a value of the wrong kind, e.g. a string where a number should be
+    Invalid {
+        key: String,
+        expected: String,
+        found: String,
+    },
+    // an image, obj or mtl file that couldn't be read
+    File {
+        key: String,
+        path: String,
+        message: String,
+    },
+    NoSuchCamera {
+        name: String,
+    },
+    // something wrong in an included file
+    Included {
+        path: String,
+        error: Box<ParseError>,
+    },
+    // something wrong in one frame of an animation, counting from 1
+    InFrame {
+        frame: usize,
+        error: Box<ParseError>,
+    },
+    // a key that doesn't mean anything where it is, which is usually a typo.
+    // these are only warnings, unless parsing strictly
+    UnknownKey {
+        key: String,
+        suggestion: Option<String>,
+    },
+    // a material or transform referring to something that hasn't been defined
+    Undefined {
+        key: String,
+        name: String,
+    },
 }
 
-fn shape_from_config(shape_yaml: &yaml::Yaml) -> Shape {
-    if let Yaml::Hash(_) = shape_yaml {
-        let mut out = Shape::default();
-        if let Yaml::Array(_) = shape_yaml["transform"] {
-            out.transform = parse_transforms(&shape_yaml["transform"]);
-        };
-        if let Yaml::Hash(_) = shape_yaml["material"] {
-            out.material = parse_material(&shape_yaml["material"]);
-        };
-        out.shape = match &shape_yaml["add"] {
-            Yaml::String(kind) if kind == "sphere" => ShapeType::Sphere,
-            Yaml::String(kind) if kind == "plane" => ShapeType::Plane,
-            _ => panic!(),
-        };
-        out
-    } else {
-        unreachable!()
+impl ParseError {
+    // Where a syntax error is, as the line and column, along with the
+    // included file it's in - None if it's in the scene file itself.
+    pub fn syntax_position(&self) -> Option<(Option<&str>, usize, usize)> {
+        match self {
+            ParseError::Syntax { line, column, .. } => Some((None, *line, *column)),
+            ParseError::InFrame { error, .. } => error.syntax_position(),
+            ParseError::Included { path, error } => error
+                .syntax_position()
+                .map(|(included, line, column)| (Some(included.unwrap_or(path)), line, column)),
+            _ => None,
+        }
     }
-}
-
-// assume that it's being given a Yaml::Hash whose "add" field is "light"
 
-fn light_from_config(light_yaml: &yaml::Yaml) -> PointLight {
-    if let Yaml::Hash(_) = light_yaml {
-        let at = destructure_yaml_array_into_tuple(&light_yaml["at"], TupleKind::Point);
-        let intensity = destructure_yaml_array_into_colour(&light_yaml["intensity"]);
-        PointLight::new(intensity, at)
-    } else {
-        unreachable!()
+    // Adds the key (or list index, like "[2]") the error was found under to
+    // the front of its path.
+    fn within(self, parent: &str) -> ParseError {
+        let join = |key: String| match key.as_str() {
+            "" => parent.to_string(),
+            k if k.starts_with('[') => format!("{}{}", parent, k),
+            k => format!("{}.{}", parent, k),
+        };
+        match self {
+            ParseError::Missing { key } => ParseError::Missing { key: join(key) },
+            ParseError::Invalid {
+                key,
+                expected,
+                found,
+            } => ParseError::Invalid {
+                key: join(key),
+                expected,
+                found,
+            },
+            ParseError::File { key, path, message } => ParseError::File {
+                key: join(key),
+                path,
+                message,
+            },
+            ParseError::Undefined { key, name } => ParseError::Undefined {
+                key: join(key),
+                name,
+            },
+            ParseError::UnknownKey { key, suggestion } => ParseError::UnknownKey {
+                key: join(key),
+                suggestion,
+            },
+            e => e,
+        }
     }
 }
 
-fn parse_transforms(transform_array: &yaml::Yaml) -> Matrix<f64, 4, 4> {
-    if let Yaml::Array(ts) = transform_array {
-        let mut out = Matrix::identity();
-        for transform in ts.iter().rev() {
-            out = out
-                * match transform_type_and_data(transform) {
-                    TransformType::RotateX(a) => Matrix::rotation_x(a),
-                    TransformType::RotateY(a) => Matrix::rotation_y(a),
-                    TransformType::RotateZ(a) => Matrix::rotation_z(a),
-                    TransformType::Scale(x, y, z) => Matrix::scaling(x, y, z),
-                    TransformType::Translate(x, y, z) => Matrix::translation(x, y, z),
-                };
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            ParseError::Missing { key } => write!(f, "{} is missing", key),
+            ParseError::Invalid {
+                key,
+                expected,
+                found,
+            } => write!(f, "{} should be {}, but is {}", key, expected, found),
+            ParseError::File { key, path, message } => {
+                write!(f, "{}: couldn't load {}: {}", key, path, message)
+            }
+            ParseError::NoSuchCamera { name } => write!(f, "there's no camera named {}", name),
+            ParseError::Undefined { key, name } => {
+                write!(f, "{} uses {}, which hasn't been defined", key, name)
+            }
+            ParseError::Included { path, error } => write!(f, "in {}, {}", path, error),
+            ParseError::InFrame { frame, error } => write!(f, "in frame {}, {}", frame, error),
+            ParseError::UnknownKey {
+                key,
+                suggestion: Some(suggestion),
+            } => write!(
+                f,
+                "{} isn't a known setting - did you mean {}?",
+                key, suggestion
+            ),
+            ParseError::UnknownKey {
+                key,
+                suggestion: None,
+            } => write!(f, "{} isn't a known setting", key),
         }
-        out
-    } else {
-        unreachable!()
     }
 }
 
-// should be given a &Yaml::Array, which looks like ["rotate-x", 1]
+impl std::error::Error for ParseError {}
 
-fn transform_type_and_data(transform: &yaml::Yaml) -> TransformType {
-    match &transform[0] {
-        Yaml::String(s) if s == "rotate-x" => TransformType::RotateX(parse_number(&transform[1])),
-        Yaml::String(s) if s == "rotate-y" => TransformType::RotateY(parse_number(&transform[1])),
-        Yaml::String(s) if s == "rotate-z" => TransformType::RotateZ(parse_number(&transform[1])),
-        Yaml::String(s) if s == "translate" => TransformType::Translate(
-            parse_number(&transform[1]),
-            parse_number(&transform[2]),
-            parse_number(&transform[3]),
-        ),
-        Yaml::String(s) if s == "scale" => TransformType::Scale(
-            parse_number(&transform[1]),
-            parse_number(&transform[2]),
-            parse_number(&transform[3]),
-        ),
-        Yaml::String(s) => panic!("String {} is not a valid transform", s),
-        _ => {
-            println!(
-                "Value {:?} is not a valid transform. Please check the yaml file for errors.",
-                &transform[0]
-            );
-            unreachable!()
-        }
+fn invalid(expected: &str, found: &Yaml) -> ParseError {
+    ParseError::Invalid {
+        key: String::new(),
+        expected: expected.to_string(),
+        found: describe(found),
     }
 }
 
-// must only be passed a Yaml::Integer or Yaml::Real.
-// returns the number within as an f64
+fn missing(key: &str) -> ParseError {
+    ParseError::Missing {
+        key: key.to_string(),
+    }
+}
 
-fn parse_number(num: &yaml::Yaml) -> f64 {
-    match num {
-        Yaml::Integer(x) => *x as f64,
-        Yaml::Real(x) => x.parse().unwrap(),
-        _ => unreachable!(),
+// how a value is shown in error messages
+fn describe(value: &Yaml) -> String {
+    match value {
+        Yaml::Real(x) => x.clone(),
+        Yaml::Integer(x) => x.to_string(),
+        Yaml::String(s) => format!("\"{}\"", s),
+        Yaml::Boolean(b) => b.to_string(),
+        Yaml::Array(_) => "a list".to_string(),
+        Yaml::Hash(_) => "a map".to_string(),
+        Yaml::Alias(_) => "an alias".to_string(),
+        Yaml::Null | Yaml::BadValue => "empty".to_string(),
     }
 }
 
-// expects to be given a Yaml::Hash, which maps the properties of the material
-// e.g "colour" onto their appropriate yaml::Yaml variants.
+// parses map[key], adding the key to any error
+fn field<'a, T>(
+    map: &'a Yaml,
+    key: &str,
+    parse: impl FnOnce(&'a Yaml) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    parse(&map[key]).map_err(|e| e.within(key))
+}
 
-fn parse_material(material: &yaml::Yaml) -> Material {
-    let mut out = Material::default();
-    if material["colour"] != Yaml::BadValue {
-        out.colour = destructure_yaml_array_into_colour(&material["colour"]);
-    } else if material["color"] != Yaml::BadValue {
-        out.colour = destructure_yaml_array_into_colour(&material["color"]);
-    }
-    if material["ambient"] != Yaml::BadValue {
-        out.ambient = parse_number(&material["ambient"]);
-    }
-    if material["diffuse"] != Yaml::BadValue {
-        out.diffuse = parse_number(&material["diffuse"]);
+// an optional number, which takes the default if it's left out
+fn optional_number(map: &Yaml, key: &str, default: Float) -> Result<Float, ParseError> {
+    match map[key] {
+        Yaml::BadValue => Ok(default),
+        _ => field(map, key, parse_number),
     }
-    if material["specular"] != Yaml::BadValue {
-        out.specular = parse_number(&material["specular"]);
+}
+
+fn optional_transform(map: &Yaml) -> Result<Matrix<Float, 4, 4>, ParseError> {
+    match map["transform"] {
+        Yaml::BadValue => Ok(Matrix::identity()),
+        _ => invertible_transform(map),
     }
-    if material["shininess"] != Yaml::BadValue {
-        out.shininess = parse_number(&material["shininess"]);
+}
+
+// rays are taken into the space of whatever's transformed by undoing the
+// transform, so it has to be possible to undo it
+fn invertible_transform(map: &Yaml) -> Result<Matrix<Float, 4, 4>, ParseError> {
+    let transform = field(map, "transform", parse_transforms)?;
+    if !transform.is_invertible() {
+        return Err(invalid(
+            "a transform that can be undone, without a scale of 0",
+            &map["transform"],
+        )
+        .within("transform"));
     }
-    if material["reflectivity"] != Yaml::BadValue {
-        out.reflectivity = parse_number(&material["reflectivity"]);
+    Ok(transform)
+}
+
+// picks the value whose name matches a string, e.g. for a projection
+fn parse_choice<T: Copy>(value: &Yaml, choices: &[(&str, T)]) -> Result<T, ParseError> {
+    let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+    let expected = format!("one of {}", names.join(", "));
+    match value {
+        Yaml::String(s) => match choices.iter().find(|(name, _)| name == s) {
+            Some((_, choice)) => Ok(*choice),
+            None => Err(invalid(&expected, value)),
+        },
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid(&expected, value)),
     }
-    if material["transparency"] != Yaml::BadValue {
-        out.transparency = parse_number(&material["transparency"]);
+}
+
+// Reads a whole scene file. Syntax errors come with the line they're on.
+pub fn parse_scene(source: &str, camera: Option<&str>) -> Result<(World, Camera), ParseError> {
+    parse_scene_in(source, Path::new(""), camera)
+}
+
+// The same, for a scene file in the given directory, which included files
+// are relative to.
+pub fn parse_scene_in(
+    source: &str,
+    directory: &Path,
+    camera: Option<&str>,
+) -> Result<(World, Camera), ParseError> {
+    parse_scene_with_warnings(source, SceneFormat::Yaml, directory, camera).map(|(w, c, _)| (w, c))
+}
+
+// Scenes can be written in JSON as well as YAML, laid out in exactly the same
+// way. They're read into the same Yaml tree, so the rest of the parsing
+// doesn't need to know which it was.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SceneFormat {
+    Yaml,
+    Json,
+}
+
+impl SceneFormat {
+    // .json files are JSON, and anything else is YAML
+    pub fn from_path(path: &Path) -> SceneFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("json") => SceneFormat::Json,
+            _ => SceneFormat::Yaml,
+        }
     }
-    if material["refractive_index"] != Yaml::BadValue {
-        out.refractive_index = parse_number(&material["refractive_index"]);
+}
+
+fn load_document(source: &str, format: SceneFormat) -> Result<Yaml, ParseError> {
+    match format {
+        SceneFormat::Yaml => load_yaml(source),
+        SceneFormat::Json => load_json(source),
     }
-    if material["pattern"] != Yaml::BadValue {
-        out.pattern = Some(parse_pattern(&material["pattern"]));
+}
+
+fn load_json(source: &str) -> Result<Yaml, ParseError> {
+    let value: serde_json::Value = serde_json::from_str(source).map_err(|e| {
+        let (line, column) = (e.line(), e.column());
+        let full = e.to_string();
+        let suffix = format!(" at line {} column {}", line, column);
+        ParseError::Syntax {
+            line,
+            column,
+            message: full.strip_suffix(&suffix).unwrap_or(&full).to_string(),
+        }
+    })?;
+    Ok(json_to_yaml(value))
+}
+
+fn json_to_yaml(value: serde_json::Value) -> Yaml {
+    use serde_json::Value;
+    match value {
+        Value::Null => Yaml::Null,
+        Value::Bool(b) => Yaml::Boolean(b),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Yaml::Integer(i),
+            None => Yaml::Real(n.to_string()),
+        },
+        Value::String(s) => Yaml::String(s),
+        Value::Array(values) => Yaml::Array(values.into_iter().map(json_to_yaml).collect()),
+        Value::Object(map) => Yaml::Hash(
+            map.into_iter()
+                .map(|(k, v)| (Yaml::String(k), json_to_yaml(v)))
+                .collect(),
+        ),
     }
-    out
 }
 
-// expects to be given a Yaml::Hash, which contains the type of pattern and
-// the relevant colours and transform etc
+// As parse_scene_in, but for either format, and also returns any keys in the
+// scene that weren't understood, as UnknownKey errors. These are ignored
+// otherwise.
+pub fn parse_scene_with_warnings(
+    source: &str,
+    format: SceneFormat,
+    directory: &Path,
+    camera: Option<&str>,
+) -> Result<(World, Camera, Vec<ParseError>), ParseError> {
+    let (description, warnings) = parse_description(source, format, directory)?;
+    let (w, c) = build(description, camera)?;
+    Ok((w, c, warnings))
+}
 
-fn parse_pattern(pattern_map: &yaml::Yaml) -> Pattern {
-    match &pattern_map["type"] {
-        Yaml::String(s) if s == "3d-check" => parse_check_pattern(pattern_map),
-        Yaml::String(s) if s == "stripe" => parse_stripe_pattern(pattern_map),
-        _ => unreachable!(),
+// Reads a scene file into a SceneDescription, without choosing a camera or
+// making the World, along with any warnings. Defined maps that are valid
+// materials are in its materials, under the names they were defined as. For
+// an animation, this is the first frame.
+pub fn parse_description(
+    source: &str,
+    format: SceneFormat,
+    directory: &Path,
+) -> Result<(SceneDescription, Vec<ParseError>), ParseError> {
+    let (mut frames, warnings) = parse_frames(source, format, directory)?;
+    Ok((frames.swap_remove(0), warnings))
+}
+
+// Reads every frame of an animation, for a flip-book. Each YAML document in
+// the file (separated by ---) is a frame. A document can also have a frames
+// entity, which is a list of frames, each a list of entities: each of those
+// frames is everything else in the document plus its own entities. A file
+// that isn't animated is just one frame.
+pub fn parse_frames(
+    source: &str,
+    format: SceneFormat,
+    directory: &Path,
+) -> Result<(Vec<SceneDescription>, Vec<ParseError>), ParseError> {
+    let documents = load_documents(source, format)?;
+    let mut frames = Vec::new();
+    let mut warnings = Vec::new();
+    for document in &documents {
+        // errors in the document itself only need a frame number if there
+        // are several documents
+        let first_frame = frames.len() + 1;
+        let in_document = |e: ParseError| {
+            if documents.len() > 1 {
+                ParseError::InFrame {
+                    frame: first_frame,
+                    error: Box::new(e),
+                }
+            } else {
+                e
+            }
+        };
+        let frame_lists = match frame_lists(document).map_err(in_document)? {
+            Some(lists) => lists,
+            None => {
+                let mut scene = SceneBuilder::new();
+                scene
+                    .add_entities(document, directory)
+                    .map_err(in_document)?;
+                warnings.extend(scene.warnings.into_iter().map(in_document));
+                frames.push(scene.scene);
+                continue;
+            }
+        };
+        for (i, list) in frame_lists.iter().enumerate() {
+            let frame = frames.len() + 1;
+            let in_frame = |e: ParseError| ParseError::InFrame {
+                frame,
+                error: Box::new(e),
+            };
+            let mut scene = SceneBuilder::new();
+            scene
+                .add_entities(document, directory)
+                .map_err(in_document)?;
+            // the rest of the document is the same in every frame, so its
+            // warnings only need giving once
+            if i == 0 {
+                warnings.extend(scene.warnings.drain(..).map(in_document));
+            } else {
+                scene.warnings.clear();
+            }
+            scene.in_frame = true;
+            scene.add_entities(list, directory).map_err(in_frame)?;
+            warnings.extend(scene.warnings.into_iter().map(in_frame));
+            frames.push(scene.scene);
+        }
     }
+    Ok((frames, warnings))
 }
 
-fn parse_check_pattern(pattern_map: &yaml::Yaml) -> Pattern {
-    let colour_a = if pattern_map["colour-a"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["colour-a"])
-    } else if pattern_map["color-a"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["color-a"])
-    } else {
-        unreachable!();
-    };
+// the world and camera for one frame of an animation
+pub type Frame = (World, Camera);
 
-    let colour_b = if pattern_map["colour-b"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["colour-b"])
-    } else if pattern_map["color-a"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["color-b"])
-    } else {
-        unreachable!();
-    };
+// As parse_scene_with_warnings, but for every frame of an animation.
+pub fn parse_animation_with_warnings(
+    source: &str,
+    format: SceneFormat,
+    directory: &Path,
+    camera: Option<&str>,
+) -> Result<(Vec<Frame>, Vec<ParseError>), ParseError> {
+    let (frames, warnings) = parse_frames(source, format, directory)?;
+    let count = frames.len();
+    let scenes = frames
+        .into_iter()
+        .enumerate()
+        .map(|(i, frame)| {
+            build(frame, camera).map_err(|e| match count {
+                1 => e,
+                _ => ParseError::InFrame {
+                    frame: i + 1,
+                    error: Box::new(e),
+                },
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((scenes, warnings))
+}
 
-    let transform = if pattern_map["transform"] != Yaml::BadValue {
-        parse_transforms(&pattern_map["transform"])
-    } else {
-        Matrix::identity()
-    };
-    Pattern::Check3D {
-        colour_a,
-        colour_b,
-        transform,
+// the lists of entities in a document's frames entity, if it has one
+fn frame_lists(document: &yaml::Yaml) -> Result<Option<Vec<Yaml>>, ParseError> {
+    let mut found = None;
+    for (i, node) in document.as_vec().into_iter().flatten().enumerate() {
+        if node["frames"] == Yaml::BadValue {
+            continue;
+        }
+        let context = entity_context(i, node);
+        if found.is_some() {
+            return Err(
+                invalid("left out, as there's already a frames entity", node).within(&context),
+            );
+        }
+        let expected = "a list of frames, each a list of entities";
+        match &node["frames"] {
+            Yaml::Array(lists) if lists.iter().all(|l| l.as_vec().is_some()) => {
+                found = Some(lists.clone())
+            }
+            other => return Err(invalid(expected, other).within(&format!("{}.frames", context))),
+        }
     }
+    Ok(found)
 }
 
-fn parse_stripe_pattern(pattern_map: &yaml::Yaml) -> Pattern {
-    let colour_a = if pattern_map["colour-a"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["colour-a"])
-    } else if pattern_map["color-a"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["color-a"])
-    } else {
-        unreachable!();
-    };
+fn build(scene: SceneDescription, camera: Option<&str>) -> Result<(World, Camera), ParseError> {
+    scene
+        .build(camera)
+        .map_err(|e| ParseError::NoSuchCamera { name: e.name })
+}
 
-    let colour_b = if pattern_map["colour-b"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["colour-b"])
-    } else if pattern_map["color-a"] != Yaml::BadValue {
-        destructure_yaml_array_into_colour(&pattern_map["color-b"])
-    } else {
-        unreachable!();
+// every document in the file - a JSON file only ever has one
+fn load_documents(source: &str, format: SceneFormat) -> Result<Vec<Yaml>, ParseError> {
+    let documents = match format {
+        SceneFormat::Yaml => load_yaml_documents(source)?,
+        SceneFormat::Json => vec![load_json(source)?],
     };
+    if documents.is_empty() {
+        Ok(vec![Yaml::Array(Vec::new())])
+    } else {
+        Ok(documents)
+    }
+}
 
-    let transform = if pattern_map["transform"] != Yaml::BadValue {
-        parse_transforms(&pattern_map["transform"])
+fn load_yaml(source: &str) -> Result<Yaml, ParseError> {
+    let mut documents = load_yaml_documents(source)?;
+    if documents.is_empty() {
+        Ok(Yaml::Array(Vec::new()))
     } else {
-        Matrix::identity()
-    };
-    Pattern::Stripe {
-        colour_a,
-        colour_b,
-        transform,
+        Ok(documents.swap_remove(0))
     }
 }
 
-fn destructure_yaml_array_into_tuple(array: &yaml::Yaml, kind: TupleKind) -> Tuple {
-    if let Yaml::Array(a) = array {
-        let mut tuple_as_array: [f64; 3] = [0.0; 3];
-        for i in 0..3 {
-            tuple_as_array[i] = match &a[i] {
-                Yaml::Integer(val) => *val as f64,
-                Yaml::Real(val) => val.parse().unwrap(),
+fn load_yaml_documents(source: &str) -> Result<Vec<Yaml>, ParseError> {
+    YamlLoader::load_from_str(source).map_err(|e| {
+        let (line, column) = (e.marker().line(), e.marker().col() + 1);
+        let full = e.to_string();
+        let suffix = format!(" at line {} column {}", line, column);
+        ParseError::Syntax {
+            line,
+            column,
+            message: full.strip_suffix(&suffix).unwrap_or(&full).to_string(),
+        }
+    })
+}
+
+pub fn parse_config(config: &yaml::Yaml) -> Result<(World, Camera), ParseError> {
+    parse_config_with_camera(config, None)
+}
+
+// Scenes can have several cameras. The one rendered from is the one named by
+// the camera argument if given, otherwise the one named by a "render" entry,
+// otherwise the last one.
+pub fn parse_config_with_camera(
+    config: &yaml::Yaml,
+    camera: Option<&str>,
+) -> Result<(World, Camera), ParseError> {
+    let mut scene = SceneBuilder::new();
+    scene.add_entities(config, Path::new(""))?;
+    build(scene.scene, camera)
+}
+
+// everything read from the scene so far, including from any included files
+struct SceneBuilder {
+    scene: SceneDescription,
+    definitions: HashMap<String, Yaml>,
+    // the files currently being included, to catch any that include themselves
+    including: Vec<PathBuf>,
+    // whether the entities being added are one frame of an animation
+    in_frame: bool,
+    warnings: Vec<ParseError>,
+}
+
+impl SceneBuilder {
+    fn new() -> SceneBuilder {
+        SceneBuilder {
+            scene: SceneDescription::new(),
+            definitions: HashMap::new(),
+            including: Vec::new(),
+            in_frame: false,
+            warnings: Vec::new(),
+        }
+    }
+
+    fn add_entities(&mut self, config: &yaml::Yaml, directory: &Path) -> Result<(), ParseError> {
+        // done once for each file, before anything in it is defined or used,
+        // so that definitions keep pointing at the right files wherever
+        // they're used
+        let config = &resolve_paths(config, directory);
+        let entities = match config {
+            Yaml::Array(entities) => entities,
+            _ => return Err(invalid("a list of entities", config).within("the scene")),
+        };
+        for (i, node) in entities.iter().enumerate() {
+            let context = entity_context(i, node);
+            let entity = match node {
+                Yaml::Hash(entity) => entity,
                 _ => {
-                    println!("Value {:?} is not a valid number!", &a[i]);
-                    panic!()
+                    let expected = "a map, like { add: sphere }";
+                    self.warnings.push(invalid(expected, node).within(&context));
+                    continue;
                 }
+            };
+            let first_warning = self.warnings.len();
+            self.add_entity(entity, node, directory)
+                .map_err(|e| e.within(&context))?;
+            for warning in &mut self.warnings[first_warning..] {
+                *warning = warning.clone().within(&context);
+            }
+        }
+        Ok(())
+    }
+
+    fn add_entity(
+        &mut self,
+        entity: &yaml::Hash,
+        node: &yaml::Yaml,
+        directory: &Path,
+    ) -> Result<(), ParseError> {
+        let instance = instantiate_template(entity, &self.definitions);
+        let (entity, node) = match &instance {
+            Some(Yaml::Hash(merged)) => (merged, instance.as_ref().unwrap()),
+            _ => (entity, node),
+        };
+        let kind = entity_kind(entity)?;
+        // a template's settings are checked where it's used instead
+        if let (EntityKind::Define, true) = (&kind, node["add"] != Yaml::BadValue) {
+            let (name, template) = parse_template(entity, &self.definitions)?;
+            self.definitions.insert(name, template);
+            return Ok(());
+        }
+        if let EntityKind::Define | EntityKind::Include | EntityKind::Frames = kind {
+            self.warnings.extend(unknown_entity_keys(&kind, node));
+        }
+        // the frames themselves are read by parse_frames
+        if let EntityKind::Frames = kind {
+            if !self.including.is_empty() || self.in_frame {
+                return Err(invalid(
+                    "only in the scene file itself, not in an included file or another frame",
+                    &node["frames"],
+                )
+                .within("frames"));
+            }
+            return Ok(());
+        }
+        if let EntityKind::Define = kind {
+            let (name, value) = parse_definition(node, &self.definitions)?;
+            if let Some(material) = definition_as_material(&value) {
+                self.scene.materials.insert(name.clone(), material);
             }
+            self.definitions.insert(name, value);
+            return Ok(());
+        }
+        if let EntityKind::Include = kind {
+            return self.include(node, directory);
         }
-        let [x, y, z] = tuple_as_array;
+        let node = &expand_definitions(node, &self.definitions)?;
+        self.warnings.extend(unknown_entity_keys(&kind, node));
+        let s = &mut self.scene;
         match kind {
-            TupleKind::Vector => Tuple::vector_new(x, y, z),
-            TupleKind::Point => Tuple::point_new(x, y, z),
+            EntityKind::Define | EntityKind::Include | EntityKind::Frames => unreachable!(),
+            EntityKind::Camera => s.cameras.push(camera_from_config(node)?),
+            EntityKind::Render => {
+                s.selected_camera = Some(field(node, "render", parse_str)?.to_string());
+            }
+            EntityKind::Light => s.lights.push(light_from_config(node)?),
+            EntityKind::World => {
+                let settings = &mut s.settings;
+                field(node, "world", |w| world_settings_from_config(w, settings))?
+            }
+            EntityKind::Obj => s.objects.extend(obj_from_config(node)?),
+            EntityKind::Group => s.objects.extend(group_from_config(node)?),
+            EntityKind::Plane
+            | EntityKind::Sphere
+            | EntityKind::Cylinder
+            | EntityKind::Cone
+            | EntityKind::Cube => s.objects.push(shape_from_config(node)?),
+        };
+        Ok(())
+    }
+
+    // the included file's entities are added as if they were in this file,
+    // and can use (and make) the same definitions
+    fn include(&mut self, node: &yaml::Yaml, directory: &Path) -> Result<(), ParseError> {
+        let path = directory.join(field(node, "include", parse_str)?);
+        let source = std::fs::read_to_string(&path).map_err(|e| ParseError::File {
+            key: "include".to_string(),
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })?;
+        let canonical = path.canonicalize().unwrap_or_else(|_| path.clone());
+        if self.including.contains(&canonical) {
+            return Err(ParseError::Invalid {
+                key: "include".to_string(),
+                expected: "a file that isn't already being included".to_string(),
+                found: path.display().to_string(),
+            });
         }
-    } else {
-        unreachable!()
+        self.including.push(canonical);
+        let inner = path.parent().unwrap_or_else(|| Path::new(""));
+        let first_warning = self.warnings.len();
+        let result = load_document(&source, SceneFormat::from_path(&path))
+            .and_then(|config| self.add_entities(&config, inner));
+        self.including.pop();
+        for warning in &mut self.warnings[first_warning..] {
+            *warning = ParseError::Included {
+                path: path.display().to_string(),
+                error: Box::new(warning.clone()),
+            };
+        }
+        result.map_err(|e| ParseError::Included {
+            path: path.display().to_string(),
+            error: Box::new(e),
+        })
     }
 }
 
-fn destructure_yaml_array_into_colour(array: &yaml::Yaml) -> Colour {
-    if let Yaml::Array(a) = array {
-        let mut colour_as_array: [f64; 3] = [0.0; 3];
-        for i in 0..3 {
-            colour_as_array[i] = match &a[i] {
-                Yaml::Integer(val) => *val as f64,
-                Yaml::Real(val) => val.parse().unwrap(),
-                _ => panic!(),
+// e.g. "entity 3 (sphere)", counting from 1
+fn entity_context(index: usize, node: &yaml::Yaml) -> String {
+    // templates have an add as well as a define
+    let kind = if node["define"] != Yaml::BadValue {
+        "define"
+    } else if let Some(kind) = node["add"].as_str() {
+        kind
+    } else if node["world"] != Yaml::BadValue {
+        "world"
+    } else if node["render"] != Yaml::BadValue {
+        "render"
+    } else if node["include"] != Yaml::BadValue {
+        "include"
+    } else if node["frames"] != Yaml::BadValue {
+        "frames"
+    } else {
+        return format!("entity {}", index + 1);
+    };
+    format!("entity {} ({})", index + 1, kind)
+}
+
+// a define gives a name to a value, usually a material or a list of
+// transforms, so it can be used by name afterwards. it can extend an earlier
+// definition, in which case its value is added to (and overrides) that one's.
+fn parse_definition(
+    define_yaml: &yaml::Yaml,
+    definitions: &HashMap<String, Yaml>,
+) -> Result<(String, Yaml), ParseError> {
+    let name = field(define_yaml, "define", parse_str)?;
+    let value = field(define_yaml, "value", |v| match v {
+        Yaml::BadValue => Err(missing("")),
+        Yaml::Array(list) => splice_definitions(list, definitions),
+        _ => expand_definitions(v, definitions),
+    })?;
+    if define_yaml["extend"] == Yaml::BadValue {
+        return Ok((name.to_string(), value));
+    }
+    let base = field(define_yaml, "extend", |e| {
+        let base = parse_str(e)?;
+        match definitions
+            .get(base)
+            .cloned()
+            .or_else(|| material_preset(base))
+        {
+            Some(Yaml::Hash(base)) => Ok(base),
+            Some(_) => Err(invalid("the name of a defined map, like a material", e)),
+            None => Err(ParseError::Undefined {
+                key: String::new(),
+                name: base.to_string(),
+            }),
+        }
+    })?;
+    match value {
+        Yaml::Hash(extra) => {
+            let mut merged = base;
+            for (key, value) in extra {
+                merged.insert(key, value);
             }
+            Ok((name.to_string(), Yaml::Hash(merged)))
         }
-        let [r, g, b] = colour_as_array;
-        Colour::new(r, g, b)
-    } else {
-        unreachable!()
+        _ => Err(invalid(
+            "a map, as it extends another definition",
+            &define_yaml["value"],
+        )
+        .within("value")),
     }
 }
 
-fn entity_kind(entity: &yaml::Hash) -> EntityKind {
-    let s = entity.get(&Yaml::String("add".to_string())).unwrap();
-    match s {
-        Yaml::String(kind) if kind == "sphere" => EntityKind::Sphere,
-        Yaml::String(kind) if kind == "plane" => EntityKind::Plane,
-        Yaml::String(kind) if kind == "camera" => EntityKind::Camera,
-        Yaml::String(kind) if kind == "light" => EntityKind::Light,
-        _ => panic!(),
-    }
+// Materials for common looks that can be used by name without being defined,
+// e.g. material: glass. A definition with the same name takes their place.
+fn material_preset(name: &str) -> Option<Yaml> {
+    let preset = match name {
+        "glass" => "{colour: black, ambient: 0, diffuse: 0.1, specular: 1, shininess: 300, reflectivity: 0.9, transparency: 0.9, refractive-index: 1.5}",
+        "mirror" => "{colour: black, ambient: 0, diffuse: 0.1, specular: 1, shininess: 300, reflectivity: 1}",
+        "matte" => "{specular: 0}",
+        _ => return None,
+    };
+    YamlLoader::load_from_str(preset).ok()?.pop()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::shapes;
+// a defined map is also a material if it's made only of material settings
+fn definition_as_material(value: &yaml::Yaml) -> Option<Material> {
+    match value {
+        Yaml::Hash(map) if !map.is_empty() && unknown_material_keys(value).is_empty() => {
+            parse_material(value).ok()
+        }
+        _ => None,
+    }
+}
 
-    #[test]
-    fn reads_in_camera() {
-        let yaml_file = "
-- add: camera
-  width: 100
-  height: 100
-  field-of-view: 0.785
-  from: [ 1, 3, 2 ]
-  to: [4, -2, 8]
-  up: [1, 1, 0]
-";
-        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
-        let cam = camera_from_config(config);
-        let expected = world::Camera::new(
-            100,
-            100,
-            0.785,
-            Matrix::from_array(&[
-                [-0.50709, 0.50709, 0.67612, -2.36643],
-                [0.76772, 0.60609, 0.12122, -2.82843],
-                [-0.35857, 0.59761, -0.71714, 0.0],
-                [0.0, 0.0, 0.0, 1.0],
-            ]),
+// A define with an add in it is a template for an entity, which is added by
+// using its name in place of the kind of entity. The entity's own settings
+// are laid over the template's, so each copy can be moved about or have its
+// material changed. A template can be based on another template.
+fn parse_template(
+    entity: &yaml::Hash,
+    definitions: &HashMap<String, Yaml>,
+) -> Result<(String, Yaml), ParseError> {
+    let node = Yaml::Hash(entity.clone());
+    let name = field(&node, "define", parse_str)?;
+    let mut template = entity.clone();
+    template.remove(&Yaml::String("define".to_string()));
+    let template = match instantiate_template(&template, definitions) {
+        Some(based_on) => based_on,
+        None => Yaml::Hash(template),
+    };
+    if let Yaml::Hash(map) = &template {
+        entity_kind(map)?;
+        let mut as_entity = yaml::Hash::new();
+        as_entity.insert(
+            Yaml::String("add".to_string()),
+            Yaml::String(name.to_string()),
         );
-        assert_eq!(cam, expected);
+        if entity_kind(&as_entity).is_ok() {
+            return Err(invalid(
+                "a name that isn't already a kind of entity",
+                &node["define"],
+            )
+            .within("define"));
+        }
     }
+    Ok((
+        name.to_string(),
+        expand_definitions(&template, definitions)?,
+    ))
+}
 
-    #[test]
-    fn reads_in_light() {
-        let yaml_file = "
-- add: light
-  at: [50, 100, -50]
-  intensity: [1, 1, 0.2]
-";
-        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
-        let light = light_from_config(config);
-        let expected = PointLight::new(
-            Colour::new(1.0, 1.0, 0.2),
-            Tuple::point_new(50.0, 100.0, -50.0),
-        );
-        assert_eq!(light, expected);
+// the template named by the entity's add, with the entity's settings on top,
+// if it names one. materials given as maps in both are merged, setting by
+// setting
+fn instantiate_template(entity: &yaml::Hash, definitions: &HashMap<String, Yaml>) -> Option<Yaml> {
+    let name = entity.get(&Yaml::String("add".to_string()))?.as_str()?;
+    let template = match definitions.get(name) {
+        Some(Yaml::Hash(template)) if template.contains_key(&Yaml::String("add".to_string())) => {
+            template
+        }
+        _ => return None,
+    };
+    let mut out = template.clone();
+    for (key, value) in entity {
+        let merged = match (key.as_str(), out.get(key), value) {
+            (Some("add"), _, _) => continue,
+            (Some("material"), Some(Yaml::Hash(base)), Yaml::Hash(extra)) => {
+                let mut material = base.clone();
+                for (k, v) in extra {
+                    material.insert(k.clone(), v.clone());
+                }
+                Yaml::Hash(material)
+            }
+            _ => value.clone(),
+        };
+        out.insert(key.clone(), merged);
     }
+    Some(Yaml::Hash(out))
+}
 
-    #[test]
-    fn reads_in_a_rotation() {
-        let yaml_transform = "
-[rotate-x, 0.345]
-    ";
-        let config = &yaml::YamlLoader::load_from_str(yaml_transform).unwrap()[0];
-        let transform = transform_type_and_data(config);
-        assert_eq!(transform, TransformType::RotateX(0.345));
+// Swaps names for what they were defined as: a material given by name, and
+// names in lists of transforms, which are replaced by the transforms they
+// stand for. This goes all the way down, so patterns' transforms work too.
+fn expand_definitions(
+    node: &yaml::Yaml,
+    definitions: &HashMap<String, Yaml>,
+) -> Result<Yaml, ParseError> {
+    let lookup = |name: &str| {
+        definitions
+            .get(name)
+            .cloned()
+            .or_else(|| material_preset(name))
+            .ok_or(ParseError::Undefined {
+                key: String::new(),
+                name: name.to_string(),
+            })
+    };
+    match node {
+        Yaml::Hash(map) => {
+            if let Some(instance) = instantiate_template(map, definitions) {
+                return expand_definitions(&instance, definitions);
+            }
+            let mut out = yaml::Hash::new();
+            for (key, value) in map {
+                let expanded = match (key.as_str(), value) {
+                    (Some("material"), Yaml::String(name)) => lookup(name),
+                    (Some("transform"), Yaml::Array(list)) => splice_definitions(list, definitions),
+                    _ => expand_definitions(value, definitions),
+                };
+                let expanded = match key.as_str() {
+                    Some(k) => expanded.map_err(|e| e.within(k))?,
+                    None => expanded?,
+                };
+                out.insert(key.clone(), expanded);
+            }
+            Ok(Yaml::Hash(out))
+        }
+        Yaml::Array(list) => Ok(Yaml::Array(
+            list.iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    expand_definitions(v, definitions).map_err(|e| e.within(&format!("[{}]", i)))
+                })
+                .collect::<Result<Vec<Yaml>, ParseError>>()?,
+        )),
+        _ => Ok(node.clone()),
     }
+}
 
-    #[test]
-    fn reads_in_a_translation() {
-        let yaml_transform = "
-[translate, 0.345, 5, 7.5]
-    ";
-        let config = &yaml::YamlLoader::load_from_str(yaml_transform).unwrap()[0];
-        let transform = transform_type_and_data(config);
-        assert_eq!(transform, TransformType::Translate(0.345, 5.0, 7.5));
+// Files named in a scene are relative to the scene file's directory, not to
+// wherever rusrat is run from. These are OBJ models and the images used by
+// texture and cube-map patterns, wherever they are. Included files are
+// handled when they're included.
+fn resolve_paths(node: &yaml::Yaml, directory: &Path) -> Yaml {
+    match node {
+        Yaml::Hash(map) => {
+            let path_keys: &[&str] = match (node["add"].as_str(), node["type"].as_str()) {
+                (Some("obj"), _) | (_, Some("texture")) => &["file"],
+                (_, Some("cube-map")) => &["left", "right", "front", "back", "up", "down"],
+                _ => &[],
+            };
+            let mut out = yaml::Hash::new();
+            for (key, value) in map {
+                let resolved = match (key.as_str(), value) {
+                    (Some(k), Yaml::String(path)) if path_keys.contains(&k) => {
+                        Yaml::String(directory.join(path).display().to_string())
+                    }
+                    _ => resolve_paths(value, directory),
+                };
+                out.insert(key.clone(), resolved);
+            }
+            Yaml::Hash(out)
+        }
+        Yaml::Array(list) => {
+            Yaml::Array(list.iter().map(|v| resolve_paths(v, directory)).collect())
+        }
+        _ => node.clone(),
     }
+}
 
-    #[test]
-    fn reads_in_several_transforms() {
-        let yaml_transforms = "
+// a list where some of the entries name other lists, which get spliced in
+fn splice_definitions(
+    list: &[Yaml],
+    definitions: &HashMap<String, Yaml>,
+) -> Result<Yaml, ParseError> {
+    let mut out = Vec::new();
+    for (i, entry) in list.iter().enumerate() {
+        match entry {
+            Yaml::String(name) => match definitions.get(name) {
+                Some(Yaml::Array(defined)) => out.extend(defined.iter().cloned()),
+                Some(_) => {
+                    return Err(
+                        invalid("the name of a defined list", entry).within(&format!("[{}]", i))
+                    )
+                }
+                None => {
+                    return Err(ParseError::Undefined {
+                        key: format!("[{}]", i),
+                        name: name.clone(),
+                    })
+                }
+            },
+            _ => out.push(
+                expand_definitions(entry, definitions)
+                    .map_err(|e| e.within(&format!("[{}]", i)))?,
+            ),
+        }
+    }
+    Ok(Yaml::Array(out))
+}
+
+// The keys each kind of entity (and each map inside one) understands, so
+// that any others can be pointed out. Maps that aren't maps are left for the
+// parser to complain about.
+
+const SHAPE_KEYS: &[&str] = &["add", "transform", "material", "name", "light-links"];
+const MATERIAL_KEYS: &[&str] = &[
+    "colour",
+    "color",
+    "ambient",
+    "diffuse",
+    "specular",
+    "shininess",
+    "reflectivity",
+    "reflective",
+    "transparency",
+    "refractive_index",
+    "refractive-index",
+    "pattern",
+    "emission",
+];
+const PATTERN_COLOUR_KEYS: &[&str] = &[
+    "colour-a", "color-a", "colour-b", "color-b", "colours", "colors",
+];
+
+fn unknown_entity_keys(kind: &EntityKind, node: &yaml::Yaml) -> Vec<ParseError> {
+    let truncated = [SHAPE_KEYS, &["minimum", "maximum", "closed"]].concat();
+    let mut found = Vec::new();
+    match kind {
+        EntityKind::Camera => {
+            found.extend(unknown_keys(
+                node,
+                &[
+                    "add",
+                    "name",
+                    "width",
+                    "height",
+                    "field-of-view",
+                    "field_of_view",
+                    "from",
+                    "to",
+                    "up",
+                    "roll",
+                    "transform",
+                    "samples",
+                    "sampler",
+                    "seed",
+                    "adaptive-threshold",
+                    "projection",
+                    "aperture",
+                    "focal-distance",
+                    "stereo",
+                    "region",
+                    "crop",
+                    "exposure",
+                    "gamma",
+                    "tone-map",
+                    "bloom",
+                    "dither",
+                ],
+            ));
+            found.extend(nested_keys(node, "stereo", &["interocular", "convergence"]));
+            found.extend(nested_keys(
+                node,
+                "bloom",
+                &["threshold", "radius", "strength"],
+            ));
+        }
+        EntityKind::Light => {
+            found.extend(unknown_keys(
+                node,
+                &[
+                    "add",
+                    "type",
+                    "at",
+                    "intensity",
+                    "attenuation",
+                    "pattern",
+                    "group",
+                    "specular",
+                    "area",
+                    "corner",
+                    "u",
+                    "u-steps",
+                    "v",
+                    "v-steps",
+                    "direction",
+                    "inner-angle",
+                    "outer-angle",
+                ],
+            ));
+            found.extend(nested_keys(
+                node,
+                "attenuation",
+                &["constant", "linear", "quadratic"],
+            ));
+            found.extend(nested_keys(node, "area", &["u", "u-steps", "v", "v-steps"]));
+            found.extend(
+                unknown_pattern_keys(&node["pattern"])
+                    .into_iter()
+                    .map(|e| e.within("pattern")),
+            );
+        }
+        EntityKind::World => {
+            let world = &node["world"];
+            let mut inner = unknown_keys(
+                world,
+                &[
+                    "environment",
+                    "background",
+                    "environment-samples",
+                    "fog",
+                    "shadow-sampler",
+                    "seed",
+                ],
+            );
+            inner.extend(nested_keys(
+                world,
+                "fog",
+                &["type", "colour", "color", "start", "end", "density"],
+            ));
+            inner.extend(
+                unknown_pattern_keys(&world["environment"])
+                    .into_iter()
+                    .map(|e| e.within("environment")),
+            );
+            found.extend(unknown_keys(node, &["world"]));
+            found.extend(inner.into_iter().map(|e| e.within("world")));
+        }
+        EntityKind::Render => found.extend(unknown_keys(node, &["render"])),
+        EntityKind::Define => found.extend(unknown_keys(node, &["define", "value", "extend"])),
+        EntityKind::Include => found.extend(unknown_keys(node, &["include"])),
+        EntityKind::Frames => found.extend(unknown_keys(node, &["frames"])),
+        EntityKind::Obj => {
+            found.extend(unknown_keys(
+                node,
+                &[SHAPE_KEYS, &["file", "materials", "indexed"]].concat(),
+            ));
+            if let Yaml::Hash(materials) = &node["materials"] {
+                for (name, material) in materials {
+                    let name = format!("materials.{}", name.as_str().unwrap_or("?"));
+                    found.extend(
+                        unknown_material_keys(material)
+                            .into_iter()
+                            .map(|e| e.within(&name)),
+                    );
+                }
+            }
+        }
+        EntityKind::Group => {
+            found.extend(unknown_keys(node, &[SHAPE_KEYS, &["children"]].concat()));
+            if let Yaml::Array(children) = &node["children"] {
+                for (i, child) in children.iter().enumerate() {
+                    if let Yaml::Hash(entity) = child {
+                        if let Ok(kind) = entity_kind(entity) {
+                            found.extend(
+                                unknown_entity_keys(&kind, child)
+                                    .into_iter()
+                                    .map(|e| e.within(&format!("children[{}]", i))),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+        EntityKind::Cylinder | EntityKind::Cone => found.extend(unknown_keys(node, &truncated)),
+        EntityKind::Plane | EntityKind::Sphere | EntityKind::Cube => {
+            found.extend(unknown_keys(node, SHAPE_KEYS))
+        }
+    }
+    if let EntityKind::Plane
+    | EntityKind::Sphere
+    | EntityKind::Cube
+    | EntityKind::Cylinder
+    | EntityKind::Cone
+    | EntityKind::Obj
+    | EntityKind::Group = kind
+    {
+        found.extend(
+            unknown_material_keys(&node["material"])
+                .into_iter()
+                .map(|e| e.within("material")),
+        );
+    }
+    found
+}
+
+fn unknown_material_keys(material: &yaml::Yaml) -> Vec<ParseError> {
+    let mut found = unknown_keys(material, MATERIAL_KEYS);
+    found.extend(
+        unknown_pattern_keys(&material["pattern"])
+            .into_iter()
+            .map(|e| e.within("pattern")),
+    );
+    found
+}
+
+fn unknown_pattern_keys(pattern: &yaml::Yaml) -> Vec<ParseError> {
+    let mut nested: Vec<&str> = Vec::new();
+    let extra: Vec<&str> = match pattern["type"].as_str() {
+        Some("3d-check") | Some("checkers") | Some("stripe") | Some("stripes") | Some("ring")
+        | Some("rings") => {
+            nested.extend(PATTERN_COLOUR_KEYS);
+            PATTERN_COLOUR_KEYS.to_vec()
+        }
+        Some("gradient") | Some("radial-gradient") => {
+            nested.extend(PATTERN_COLOUR_KEYS);
+            [PATTERN_COLOUR_KEYS, &["stops"]].concat()
+        }
+        Some("marble") | Some("wood") | Some("granite") => {
+            nested.extend(PATTERN_COLOUR_KEYS);
+            [PATTERN_COLOUR_KEYS, &["turbulence"]].concat()
+        }
+        Some("uv-check") => {
+            nested.extend(PATTERN_COLOUR_KEYS);
+            [PATTERN_COLOUR_KEYS, &["width", "height", "mapping"]].concat()
+        }
+        Some("texture") => vec!["file", "mapping"],
+        Some("blend") => {
+            nested.extend(["pattern-a", "pattern-b"]);
+            vec!["pattern-a", "pattern-b", "weight"]
+        }
+        Some("perturbed") => {
+            nested.push("pattern");
+            vec!["pattern", "scale"]
+        }
+        Some("solid") => vec!["colour", "color"],
+        Some("cube-map") => vec!["left", "right", "front", "back", "up", "down"],
+        // the parser will say what's wrong with the type
+        _ => return Vec::new(),
+    };
+    let mut found = unknown_keys(pattern, &[&["type", "transform"], &extra[..]].concat());
+    for key in nested {
+        found.extend(
+            unknown_pattern_keys(&pattern[key])
+                .into_iter()
+                .map(|e| e.within(key)),
+        );
+    }
+    found
+}
+
+fn nested_keys(map: &yaml::Yaml, key: &str, known: &[&str]) -> Vec<ParseError> {
+    unknown_keys(&map[key], known)
+        .into_iter()
+        .map(|e| e.within(key))
+        .collect()
+}
+
+fn unknown_keys(map: &yaml::Yaml, known: &[&str]) -> Vec<ParseError> {
+    let mut found = Vec::new();
+    if let Yaml::Hash(map) = map {
+        for key in map.keys() {
+            let name = match key.as_str() {
+                Some(name) => name.to_string(),
+                None => describe(key),
+            };
+            if !known.contains(&name.as_str()) {
+                found.push(ParseError::UnknownKey {
+                    suggestion: closest_key(&name, known),
+                    key: name,
+                });
+            }
+        }
+    }
+    found
+}
+
+// the known key that's the fewest edits away from a typo, if any are close
+fn closest_key(key: &str, known: &[&str]) -> Option<String> {
+    known
+        .iter()
+        .map(|k| (edit_distance(key, k), k))
+        .filter(|(distance, k)| *distance <= 2.max(k.len() / 4))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k.to_string())
+}
+
+// the number of single letter insertions, deletions and changes needed to
+// turn a into b
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let change = previous[j] + if ca == *cb { 0 } else { 1 };
+            current.push(change.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// this function assumes that it's being given a Yaml::Hash whose "add" field is "camera"
+fn camera_from_config(cam_yaml: &yaml::Yaml) -> Result<world::Camera, ParseError> {
+    // the view transform, and how far away the camera is looking, if it's
+    // been told
+    let (view, distance) = if cam_yaml["transform"] != Yaml::BadValue {
+        camera_transform(cam_yaml)?
+    } else {
+        camera_from_and_to(cam_yaml)?
+    };
+    // focus and converge on what the camera's looking at, unless told otherwise
+    let distance_or = |map: &yaml::Yaml, key: &str| match distance {
+        Some(d) => optional_number(map, key, d),
+        None => field(map, key, parse_number),
+    };
+    let mut cam = world::Camera::new(
+        field(cam_yaml, "width", parse_positive_whole_number)?,
+        field(cam_yaml, "height", parse_positive_whole_number)?,
+        // the book's scene files spell it field_of_view
+        if cam_yaml["field_of_view"] != Yaml::BadValue {
+            field(cam_yaml, "field_of_view", parse_number)?
+        } else {
+            field(cam_yaml, "field-of-view", parse_number)?
+        },
+        view,
+    );
+    if cam_yaml["samples"] != Yaml::BadValue {
+        cam.samples = field(cam_yaml, "samples", parse_positive_whole_number)?;
+    }
+    if cam_yaml["projection"] != Yaml::BadValue {
+        cam.projection = field(cam_yaml, "projection", |p| {
+            parse_choice(
+                p,
+                &[
+                    ("perspective", Projection::Perspective),
+                    ("fisheye", Projection::Fisheye),
+                    ("equirectangular", Projection::Equirectangular),
+                ],
+            )
+        })?;
+    }
+    if cam_yaml["aperture"] != Yaml::BadValue {
+        cam.aperture = field(cam_yaml, "aperture", parse_number)?;
+        cam.focal_distance = distance_or(cam_yaml, "focal-distance")?;
+    }
+    if cam_yaml["name"] != Yaml::BadValue {
+        cam.name = Some(field(cam_yaml, "name", parse_str)?.to_string());
+    }
+    if cam_yaml["stereo"] != Yaml::BadValue {
+        // the eyes can't cross over, and they have to converge in front of
+        // the camera
+        cam.stereo = Some(field(cam_yaml, "stereo", |stereo| {
+            let interocular = field(stereo, "interocular", parse_number)?;
+            if interocular < 0.0 {
+                return Err(invalid("0 or more", &stereo["interocular"]).within("interocular"));
+            }
+            let convergence = distance_or(stereo, "convergence")?;
+            if convergence <= 0.0 {
+                return Err(invalid("more than 0", &stereo["convergence"]).within("convergence"));
+            }
+            Ok(Stereo {
+                interocular,
+                convergence,
+            })
+        })?);
+    }
+    if cam_yaml["region"] != Yaml::BadValue {
+        cam.region = Some(field(cam_yaml, "region", parse_region)?);
+    }
+    if cam_yaml["crop"] != Yaml::BadValue {
+        cam.crop = field(cam_yaml, "crop", parse_bool)?;
+    }
+    // a negative exposure would turn every colour negative, and gamma is a
+    // power that the colours are raised to the inverse of
+    if cam_yaml["exposure"] != Yaml::BadValue {
+        cam.post_process.exposure = field(cam_yaml, "exposure", parse_number)?;
+        if cam.post_process.exposure < 0.0 {
+            return Err(invalid("0 or more", &cam_yaml["exposure"]).within("exposure"));
+        }
+    }
+    if cam_yaml["gamma"] != Yaml::BadValue {
+        cam.post_process.gamma = field(cam_yaml, "gamma", parse_number)?;
+        if cam.post_process.gamma <= 0.0 {
+            return Err(invalid("more than 0", &cam_yaml["gamma"]).within("gamma"));
+        }
+    }
+    if cam_yaml["tone-map"] != Yaml::BadValue {
+        cam.post_process.tone_map = field(cam_yaml, "tone-map", |t| {
+            parse_choice(
+                t,
+                &[
+                    ("clamp", ToneMap::Clamp),
+                    ("reinhard", ToneMap::Reinhard),
+                    ("aces", ToneMap::Aces),
+                ],
+            )
+        })?;
+    }
+    if cam_yaml["bloom"] != Yaml::BadValue {
+        cam.bloom = Some(field(cam_yaml, "bloom", |bloom| {
+            Ok(Bloom {
+                threshold: optional_number(bloom, "threshold", 1.0)?,
+                radius: optional_number(bloom, "radius", 4.0)?,
+                strength: optional_number(bloom, "strength", 0.5)?,
+            })
+        })?);
+    }
+    if cam_yaml["dither"] != Yaml::BadValue {
+        cam.dither = field(cam_yaml, "dither", |d| {
+            parse_choice(
+                d,
+                &[
+                    ("none", Dither::None),
+                    ("ordered", Dither::Ordered),
+                    ("error-diffusion", Dither::ErrorDiffusion),
+                ],
+            )
+        })?;
+    }
+    if cam_yaml["adaptive-threshold"] != Yaml::BadValue {
+        cam.adaptive_threshold = Some(field(cam_yaml, "adaptive-threshold", parse_number)?);
+    }
+    if cam_yaml["sampler"] != Yaml::BadValue {
+        cam.sampler = parse_sampler(cam_yaml, "sampler")?;
+    }
+    Ok(cam)
+}
+
+fn camera_from_and_to(
+    cam_yaml: &yaml::Yaml,
+) -> Result<(Matrix<Float, 4, 4>, Option<Float>), ParseError> {
+    let from = field(cam_yaml, "from", parse_point)?;
+    let to = field(cam_yaml, "to", parse_point)?;
+    // either an up vector, or a roll in degrees from level (the default)
+    let up = match (&cam_yaml["up"], &cam_yaml["roll"]) {
+        (Yaml::BadValue, Yaml::BadValue) => world::up_from_roll(&from, &to, 0.0),
+        (_, Yaml::BadValue) => field(cam_yaml, "up", parse_vector)?,
+        (Yaml::BadValue, _) => {
+            let roll = field(cam_yaml, "roll", parse_number)?;
+            world::up_from_roll(&from, &to, roll.to_radians())
+        }
+        (_, roll) => {
+            return Err(invalid("left out, as the camera has an up vector", roll).within("roll"))
+        }
+    };
+    Ok((
+        world::view_transform(&from, &to, &up),
+        Some((to - from).magnitude()),
+    ))
+}
+
+// A camera can be placed with a transform instead, just like a shape. Before
+// it's transformed, it's at the origin looking towards -z, with +y up.
+fn camera_transform(
+    cam_yaml: &yaml::Yaml,
+) -> Result<(Matrix<Float, 4, 4>, Option<Float>), ParseError> {
+    for key in &["from", "to", "up", "roll"] {
+        if cam_yaml[*key] != Yaml::BadValue {
+            return Err(
+                invalid("left out, as the camera has a transform", &cam_yaml[*key]).within(key),
+            );
+        }
+    }
+    let transform = invertible_transform(cam_yaml)?;
+    Ok((transform.inverse(), None))
+}
+
+fn shape_from_config(shape_yaml: &yaml::Yaml) -> Result<Shape, ParseError> {
+    let mut out = Shape::default();
+    if shape_yaml["transform"] != Yaml::BadValue {
+        out.set_transform(invertible_transform(shape_yaml)?);
+    };
+    if shape_yaml["material"] != Yaml::BadValue {
+        out.material = field(shape_yaml, "material", parse_material)?;
+    };
+    if shape_yaml["name"] != Yaml::BadValue {
+        out.name = Some(field(shape_yaml, "name", parse_str)?.to_string());
+    };
+    out.light_links = parse_light_links(shape_yaml)?;
+    out.shape = match field(shape_yaml, "add", parse_str)? {
+        "sphere" => ShapeType::Sphere,
+        "plane" => ShapeType::Plane,
+        "cube" => ShapeType::Cube,
+        "cylinder" => {
+            let (minimum, maximum, closed) = parse_truncation(shape_yaml)?;
+            ShapeType::Cylinder {
+                minimum,
+                maximum,
+                closed,
+            }
+        }
+        "cone" => {
+            let (minimum, maximum, closed) = parse_truncation(shape_yaml)?;
+            ShapeType::Cone {
+                minimum,
+                maximum,
+                closed,
+            }
+        }
+        _ => return Err(invalid("a kind of shape", &shape_yaml["add"]).within("add")),
+    };
+    Ok(out)
+}
+
+// an obj file is flattened into separate triangles, which all share the
+// transform - or with "indexed", into one mesh for each material. materials
+// come from any .mtl files the obj file uses, with those under "materials"
+// overriding them by name (either a usemtl name or a group name). triangles
+// without a material get the one under "material".
+fn obj_from_config(obj_yaml: &yaml::Yaml) -> Result<Vec<Shape>, ParseError> {
+    let path = field(obj_yaml, "file", parse_str)?;
+    let load = |path: &Path| {
+        std::fs::read_to_string(path).map_err(|e| ParseError::File {
+            key: "file".to_string(),
+            path: path.display().to_string(),
+            message: e.to_string(),
+        })
+    };
+    let mesh = obj::parse_obj(&load(Path::new(path))?);
+
+    let mut materials = HashMap::new();
+    let directory = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+    for library in mesh.material_libraries.iter() {
+        materials.extend(obj::parse_mtl(&load(&directory.join(library))?));
+    }
+    if let Yaml::Hash(overrides) = &obj_yaml["materials"] {
+        for (name, material) in overrides {
+            let name = parse_str(name).map_err(|e| e.within("materials"))?;
+            let material =
+                parse_material(material).map_err(|e| e.within(&format!("materials.{}", name)))?;
+            materials.insert(name.to_string(), material);
+        }
+    }
+
+    let default = if obj_yaml["material"] != Yaml::BadValue {
+        field(obj_yaml, "material", parse_material)?
+    } else {
+        Material::default()
+    };
+    let transform = optional_transform(obj_yaml)?;
+    let light_links = parse_light_links(obj_yaml)?;
+    let name = if obj_yaml["name"] != Yaml::BadValue {
+        Some(field(obj_yaml, "name", parse_str)?.to_string())
+    } else {
+        None
+    };
+    let indexed = if obj_yaml["indexed"] != Yaml::BadValue {
+        field(obj_yaml, "indexed", parse_bool)?
+    } else {
+        false
+    };
+    let mut shapes = match indexed {
+        true => mesh.into_meshes(&materials, &default, &transform),
+        false => mesh.into_shapes(&materials, &default, &transform),
+    };
+    for shape in &mut shapes {
+        shape.light_links = light_links.clone();
+        shape.name = name.clone();
+    }
+    Ok(shapes)
+}
+
+// like an obj file, a group is flattened into its children, with the group's
+// transform applied on top of each of theirs. children without a material,
+// light links or name of their own take the group's. groups can be nested.
+fn group_from_config(group_yaml: &yaml::Yaml) -> Result<Vec<Shape>, ParseError> {
+    let transform = optional_transform(group_yaml)?;
+    let children = match &group_yaml["children"] {
+        Yaml::Array(children) => children,
+        Yaml::BadValue => return Err(missing("children")),
+        children => return Err(invalid("a list of objects", children).within("children")),
+    };
+    let mut shapes = Vec::new();
+    for (i, child) in children.iter().enumerate() {
+        let mut child = match child {
+            Yaml::Hash(child) => child.clone(),
+            _ => {
+                return Err(invalid("an object", child).within(&format!("children[{}]", i)));
+            }
+        };
+        for key in ["material", "light-links", "name"] {
+            let inherited = &group_yaml[key];
+            let key = Yaml::String(key.to_string());
+            if !child.contains_key(&key) && *inherited != Yaml::BadValue {
+                child.insert(key, inherited.clone());
+            }
+        }
+        let kind = entity_kind(&child);
+        let child = Yaml::Hash(child);
+        let children = match kind {
+            Ok(EntityKind::Group) => group_from_config(&child),
+            Ok(EntityKind::Obj) => obj_from_config(&child),
+            Ok(EntityKind::Plane)
+            | Ok(EntityKind::Sphere)
+            | Ok(EntityKind::Cylinder)
+            | Ok(EntityKind::Cone)
+            | Ok(EntityKind::Cube) => shape_from_config(&child).map(|s| vec![s]),
+            Ok(_) => Err(invalid("a kind of object", &child["add"]).within("add")),
+            Err(e) => Err(e),
+        }
+        .map_err(|e| e.within(&format!("children[{}]", i)))?;
+        for mut shape in children {
+            shape.set_transform(transform * shape.transform.matrix());
+            shapes.push(shape);
+        }
+    }
+    Ok(shapes)
+}
+
+// reads the optional minimum, maximum and closed fields of a cylinder or cone.
+// missing bounds leave the shape infinitely long, and it is open by default.
+fn parse_truncation(shape_yaml: &yaml::Yaml) -> Result<(Float, Float, bool), ParseError> {
+    let minimum = optional_number(shape_yaml, "minimum", Float::NEG_INFINITY)?;
+    let maximum = optional_number(shape_yaml, "maximum", Float::INFINITY)?;
+    let closed = if shape_yaml["closed"] != Yaml::BadValue {
+        field(shape_yaml, "closed", parse_bool)?
+    } else {
+        false
+    };
+    Ok((minimum, maximum, closed))
+}
+
+// settings for the world as a whole, rather than anything added to it. the
+// environment is a pattern seen by rays that don't hit anything, which can
+// also light the scene if it's given a number of samples.
+fn world_settings_from_config(
+    world_yaml: &yaml::Yaml,
+    w: &mut WorldSettings,
+) -> Result<(), ParseError> {
+    if world_yaml["environment"] != Yaml::BadValue {
+        w.environment = Some(field(world_yaml, "environment", parse_pattern)?);
+    }
+    if world_yaml["background"] != Yaml::BadValue {
+        w.background = field(world_yaml, "background", parse_colour)?;
+    }
+    if world_yaml["environment-samples"] != Yaml::BadValue {
+        w.environment_samples = field(world_yaml, "environment-samples", parse_whole_number)?;
+    }
+    if world_yaml["fog"] != Yaml::BadValue {
+        w.fog = Some(field(world_yaml, "fog", parse_fog)?);
+    }
+    if world_yaml["shadow-sampler"] != Yaml::BadValue {
+        w.shadow_sampler = parse_sampler(world_yaml, "shadow-sampler")?;
+    }
+    if world_yaml["shadow-cache"] != Yaml::BadValue {
+        w.shadow_cache = field(world_yaml, "shadow-cache", parse_bool)?;
+    }
+    Ok(())
+}
+
+fn parse_region(region: &yaml::Yaml) -> Result<Region, ParseError> {
+    let expected = "[x0, y0, x1, y1], with x0 < x1 and y0 < y1";
+    let corners = match region.as_vec() {
+        Some(values) => values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| parse_whole_number(v).map_err(|e| e.within(&format!("[{}]", i))))
+            .collect::<Result<Vec<usize>, ParseError>>()?,
+        None => return Err(invalid(expected, region)),
+    };
+    match corners[..] {
+        [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok(Region { x0, y0, x1, y1 }),
+        _ => Err(invalid(expected, region)),
+    }
+}
+
+// the sampler is under the given key, with an optional seed alongside it
+fn parse_sampler(map: &yaml::Yaml, key: &str) -> Result<Sampler, ParseError> {
+    let seed = if map["seed"] != Yaml::BadValue {
+        field(map, "seed", parse_whole_number)? as u64
+    } else {
+        0
+    };
+    field(map, key, |s| {
+        parse_choice(
+            s,
+            &[
+                ("grid", Sampler::Grid),
+                ("stratified", Sampler::Stratified { seed }),
+                ("blue-noise", Sampler::BlueNoise { seed }),
+            ],
+        )
+    })
+}
+
+// assume that it's being given a Yaml::Hash whose "add" field is "light"
+fn light_from_config(light_yaml: &yaml::Yaml) -> Result<PointLight, ParseError> {
+    let intensity = field(light_yaml, "intensity", parse_colour)?;
+    let light_type = if light_yaml["type"] != Yaml::BadValue {
+        field(light_yaml, "type", |t| {
+            parse_choice(
+                t,
+                &[
+                    ("point", "point"),
+                    ("area", "area"),
+                    ("spot", "spot"),
+                    ("directional", "directional"),
+                ],
+            )
+        })?
+    } else {
+        "point"
+    };
+    let mut light = match light_type {
+        "area" => area_light_from_config(light_yaml, intensity)?,
+        "spot" => PointLight::new(intensity, field(light_yaml, "at", parse_point)?)
+            .with_kind(parse_spot(light_yaml)?),
+        // directional lights don't have a position
+        "directional" => PointLight::new(intensity, Tuple::point_new(0.0, 0.0, 0.0)).with_kind(
+            LightKind::Directional {
+                direction: field(light_yaml, "direction", parse_vector)?,
+            },
+        ),
+        _ => PointLight::new(intensity, field(light_yaml, "at", parse_point)?),
+    };
+    if light_yaml["attenuation"] != Yaml::BadValue {
+        light = light.with_attenuation(field(light_yaml, "attenuation", parse_attenuation)?);
+    }
+    if light_yaml["pattern"] != Yaml::BadValue {
+        light = light.with_pattern(field(light_yaml, "pattern", parse_pattern)?);
+    }
+    if light_yaml["group"] != Yaml::BadValue {
+        light = light.with_group(field(light_yaml, "group", parse_str)?);
+    }
+    if light_yaml["specular"] != Yaml::BadValue {
+        light = light.with_specular(field(light_yaml, "specular", parse_bool)?);
+    }
+    if light_yaml["area"] != Yaml::BadValue {
+        light = light.with_area(field(light_yaml, "area", parse_light_area)?);
+    }
+    Ok(light)
+}
+
+// An area light is given by its corner, like in The Ray Tracer Challenge,
+// rather than its centre, and is lit from the middle of the rectangle.
+fn area_light_from_config(
+    light_yaml: &yaml::Yaml,
+    intensity: Colour,
+) -> Result<PointLight, ParseError> {
+    let corner = field(light_yaml, "corner", parse_point)?;
+    let area = parse_light_area(light_yaml)?;
+    let centre = corner + area.uvec * 0.5 + area.vvec * 0.5;
+    Ok(PointLight::new(intensity, centre).with_area(area))
+}
+
+// the angles are in radians, from the middle of the cone out to its edge.
+// without an inner angle, the edge of the cone is sharp
+fn parse_spot(light_yaml: &yaml::Yaml) -> Result<LightKind, ParseError> {
+    let outer_angle = field(light_yaml, "outer-angle", parse_number)?;
+    let inner_angle = optional_number(light_yaml, "inner-angle", outer_angle)?;
+    if inner_angle > outer_angle {
+        return Err(
+            invalid("no bigger than the outer-angle", &light_yaml["inner-angle"])
+                .within("inner-angle"),
+        );
+    }
+    Ok(LightKind::Spot {
+        direction: field(light_yaml, "direction", parse_vector)?,
+        inner_angle,
+        outer_angle,
+    })
+}
+
+// any of the three coefficients can be left out, taking their default values
+// of no falloff
+fn parse_attenuation(attenuation_map: &yaml::Yaml) -> Result<Attenuation, ParseError> {
+    let default = Attenuation::default();
+    Ok(Attenuation {
+        constant: optional_number(attenuation_map, "constant", default.constant)?,
+        linear: optional_number(attenuation_map, "linear", default.linear)?,
+        quadratic: optional_number(attenuation_map, "quadratic", default.quadratic)?,
+    })
+}
+
+fn parse_fog(fog_map: &yaml::Yaml) -> Result<Fog, ParseError> {
+    let colour = if fog_map["colour"] != Yaml::BadValue {
+        field(fog_map, "colour", parse_colour)?
+    } else if fog_map["color"] != Yaml::BadValue {
+        field(fog_map, "color", parse_colour)?
+    } else {
+        Colour::white()
+    };
+    // backwards fog would hide what's near and show what's far
+    match field(fog_map, "type", parse_str)? {
+        "linear" => {
+            let start = field(fog_map, "start", parse_number)?;
+            let end = field(fog_map, "end", parse_number)?;
+            if end <= start {
+                return Err(invalid("further than the start", &fog_map["end"]).within("end"));
+            }
+            Ok(Fog::Linear { colour, start, end })
+        }
+        "exponential" => {
+            let density = field(fog_map, "density", parse_number)?;
+            if density < 0.0 {
+                return Err(invalid("0 or more", &fog_map["density"]).within("density"));
+            }
+            Ok(Fog::Exponential { colour, density })
+        }
+        _ => Err(invalid("linear or exponential", &fog_map["type"]).within("type")),
+    }
+}
+
+fn parse_light_links(shape_yaml: &yaml::Yaml) -> Result<Option<Vec<String>>, ParseError> {
+    match &shape_yaml["light-links"] {
+        Yaml::Array(groups) => Ok(Some(
+            groups
+                .iter()
+                .enumerate()
+                .map(|(i, g)| match g.as_str() {
+                    Some(group) => Ok(group.to_string()),
+                    None => Err(invalid("the name of a light group", g)
+                        .within(&format!("light-links[{}]", i))),
+                })
+                .collect::<Result<Vec<String>, ParseError>>()?,
+        )),
+        Yaml::BadValue => Ok(None),
+        links => Err(invalid("a list of light groups", links).within("light-links")),
+    }
+}
+
+fn parse_light_area(area_map: &yaml::Yaml) -> Result<LightArea, ParseError> {
+    Ok(LightArea {
+        uvec: field(area_map, "u", parse_vector)?,
+        usteps: field(area_map, "u-steps", parse_positive_whole_number)?,
+        vvec: field(area_map, "v", parse_vector)?,
+        vsteps: field(area_map, "v-steps", parse_positive_whole_number)?,
+    })
+}
+
+fn parse_transforms(transform_array: &yaml::Yaml) -> Result<Matrix<Float, 4, 4>, ParseError> {
+    if let Yaml::Array(ts) = transform_array {
+        let mut out = Matrix::identity();
+        for (i, transform) in ts.iter().enumerate().rev() {
+            let transform =
+                transform_type_and_data(transform).map_err(|e| e.within(&format!("[{}]", i)))?;
+            out = out
+                * match transform {
+                    TransformType::RotateX(a) => Matrix::rotation_x(a),
+                    TransformType::RotateY(a) => Matrix::rotation_y(a),
+                    TransformType::RotateZ(a) => Matrix::rotation_z(a),
+                    TransformType::Scale(x, y, z) => Matrix::scaling(x, y, z),
+                    TransformType::Translate(x, y, z) => Matrix::translation(x, y, z),
+                    TransformType::Matrix(m) => m,
+                };
+        }
+        Ok(out)
+    } else {
+        Err(invalid("a list of transforms", transform_array))
+    }
+}
+
+// should be given a &Yaml::Array, which looks like ["rotate-x", 1]
+fn transform_type_and_data(transform: &yaml::Yaml) -> Result<TransformType, ParseError> {
+    check_transform_length(transform)?;
+    let argument =
+        |i: usize| parse_number(&transform[i]).map_err(|e| e.within(&format!("[{}]", i)));
+    match &transform[0] {
+        Yaml::String(s) if s == "rotate-x" => Ok(TransformType::RotateX(argument(1)?)),
+        Yaml::String(s) if s == "rotate-y" => Ok(TransformType::RotateY(argument(1)?)),
+        Yaml::String(s) if s == "rotate-z" => Ok(TransformType::RotateZ(argument(1)?)),
+        Yaml::String(s) if s == "rotate-x-deg" => {
+            Ok(TransformType::RotateX(argument(1)?.to_radians()))
+        }
+        Yaml::String(s) if s == "rotate-y-deg" => {
+            Ok(TransformType::RotateY(argument(1)?.to_radians()))
+        }
+        Yaml::String(s) if s == "rotate-z-deg" => {
+            Ok(TransformType::RotateZ(argument(1)?.to_radians()))
+        }
+        Yaml::String(s) if s == "translate" => Ok(TransformType::Translate(
+            argument(1)?,
+            argument(2)?,
+            argument(3)?,
+        )),
+        Yaml::String(s) if s == "scale" => Ok(TransformType::Scale(
+            argument(1)?,
+            argument(2)?,
+            argument(3)?,
+        )),
+        Yaml::String(s) if s == "matrix" => Ok(TransformType::Matrix(
+            parse_matrix(&transform[1]).map_err(|e| e.within("[1]"))?,
+        )),
+        Yaml::BadValue => Err(invalid("a transform, like [scale, 1, 2, 3]", transform)),
+        kind => Err(invalid(
+            "one of rotate-x, rotate-y, rotate-z, rotate-x-deg, rotate-y-deg, rotate-z-deg, translate, scale, matrix",
+            kind,
+        )
+        .within("[0]")),
+    }
+}
+
+// each kind of transform needs exactly the right number of values after its
+// name - a missing one is as likely to be a typo as a default
+fn check_transform_length(transform: &yaml::Yaml) -> Result<(), ParseError> {
+    let name = transform[0].as_str().unwrap_or_default();
+    let (count, example) = match name {
+        "rotate-x" | "rotate-y" | "rotate-z" => (1, "pi/2"),
+        "rotate-x-deg" | "rotate-y-deg" | "rotate-z-deg" => (1, "90"),
+        "translate" | "scale" => (3, "1, 2, 3"),
+        "matrix" => (1, "[16 numbers]"),
+        _ => return Ok(()),
+    };
+    let given = transform.as_vec().map_or(0, |t| t.len() - 1);
+    if given == count {
+        return Ok(());
+    }
+    let values = |n: usize| match n {
+        1 => "1 value".to_string(),
+        n => format!("{} values", n),
+    };
+    Err(ParseError::Invalid {
+        key: String::new(),
+        expected: format!(
+            "{} followed by {}, like [{}, {}]",
+            name,
+            values(count),
+            name,
+            example
+        ),
+        found: format!("{} followed by {}", name, values(given)),
+    })
+}
+
+// sixteen numbers, a row at a time, e.g. exported from another program
+fn parse_matrix(values: &yaml::Yaml) -> Result<Matrix<Float, 4, 4>, ParseError> {
+    match values.as_vec() {
+        Some(list) if list.len() == 16 => {
+            let mut out = Matrix::new();
+            for (i, value) in list.iter().enumerate() {
+                out[i / 4][i % 4] =
+                    parse_number(value).map_err(|e| e.within(&format!("[{}]", i)))?;
+            }
+            Ok(out)
+        }
+        _ => Err(invalid(
+            "a list of sixteen numbers, a row at a time",
+            values,
+        )),
+    }
+}
+
+// an integer or a real, as a float. numbers can also be written as sums,
+// like pi/2 or deg(30), which are worked out here
+fn parse_number(num: &yaml::Yaml) -> Result<Float, ParseError> {
+    match num {
+        Yaml::Integer(x) => Ok(*x as Float),
+        Yaml::Real(_) => num
+            .as_f64()
+            .map(|x| x as Float)
+            .ok_or_else(|| invalid("a number", num)),
+        Yaml::String(sum) => expression::evaluate(sum)
+            .map_err(|e| invalid(&format!("a number or a sum like pi/2 ({})", e), num)),
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid("a number", num)),
+    }
+}
+
+fn parse_whole_number(num: &yaml::Yaml) -> Result<usize, ParseError> {
+    match num {
+        Yaml::Integer(n) if *n >= 0 => Ok(*n as usize),
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid("a whole number", num)),
+    }
+}
+
+fn parse_positive_whole_number(num: &yaml::Yaml) -> Result<usize, ParseError> {
+    match num {
+        Yaml::Integer(n) if *n > 0 => Ok(*n as usize),
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid("a positive whole number", num)),
+    }
+}
+
+fn parse_str(value: &yaml::Yaml) -> Result<&str, ParseError> {
+    match value {
+        Yaml::String(s) => Ok(s),
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid("a string", value)),
+    }
+}
+
+fn parse_bool(value: &yaml::Yaml) -> Result<bool, ParseError> {
+    match value {
+        Yaml::Boolean(b) => Ok(*b),
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid("true or false", value)),
+    }
+}
+
+// expects to be given a Yaml::Hash, which maps the properties of the material
+// e.g "colour" onto their appropriate yaml::Yaml variants.
+fn parse_material(material: &yaml::Yaml) -> Result<Material, ParseError> {
+    let mut out = Material::default();
+    if material["colour"] != Yaml::BadValue {
+        out.colour = field(material, "colour", parse_colour)?;
+    } else if material["color"] != Yaml::BadValue {
+        out.colour = field(material, "color", parse_colour)?;
+    }
+    if material["ambient"] != Yaml::BadValue {
+        out.ambient = field(material, "ambient", parse_number)?;
+    }
+    if material["diffuse"] != Yaml::BadValue {
+        out.diffuse = field(material, "diffuse", parse_number)?;
+    }
+    if material["specular"] != Yaml::BadValue {
+        out.specular = field(material, "specular", parse_number)?;
+    }
+    if material["shininess"] != Yaml::BadValue {
+        out.shininess = field(material, "shininess", parse_number)?;
+    }
+    if material["reflectivity"] != Yaml::BadValue {
+        out.reflectivity = field(material, "reflectivity", parse_number)?;
+    } else if material["reflective"] != Yaml::BadValue {
+        out.reflectivity = field(material, "reflective", parse_number)?;
+    }
+    if material["transparency"] != Yaml::BadValue {
+        out.transparency = field(material, "transparency", parse_number)?;
+    }
+    if material["refractive_index"] != Yaml::BadValue {
+        out.refractive_index = field(material, "refractive_index", parse_number)?;
+    } else if material["refractive-index"] != Yaml::BadValue {
+        out.refractive_index = field(material, "refractive-index", parse_number)?;
+    }
+    if material["pattern"] != Yaml::BadValue {
+        out.pattern = Some(field(material, "pattern", parse_pattern)?);
+    }
+    if material["emission"] != Yaml::BadValue {
+        out.emission = field(material, "emission", parse_colour)?;
+    }
+    Ok(out)
+}
+
+// expects to be given a Yaml::Hash, which contains the type of pattern and
+// the relevant colours and transform etc
+fn parse_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    match field(pattern_map, "type", parse_str)? {
+        "3d-check" | "checkers" => parse_check_pattern(pattern_map),
+        "stripe" | "stripes" => parse_stripe_pattern(pattern_map),
+        "ring" | "rings" => parse_ring_pattern(pattern_map),
+        "gradient" | "radial-gradient" => parse_gradient_pattern(pattern_map),
+        "marble" | "wood" | "granite" => parse_noise_pattern(pattern_map),
+        "texture" => parse_texture_pattern(pattern_map),
+        "uv-check" => parse_uv_check_pattern(pattern_map),
+        "blend" => parse_blend_pattern(pattern_map),
+        "perturbed" => parse_perturbed_pattern(pattern_map),
+        "solid" => parse_solid_pattern(pattern_map),
+        "cube-map" => parse_cube_map_pattern(pattern_map),
+        _ => Err(invalid("a kind of pattern", &pattern_map["type"]).within("type")),
+    }
+}
+
+// marble, wood and granite all share the same fields: two colours, plus an
+// optional turbulence controlling how much noise is added
+fn parse_noise_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    let turbulence = optional_number(pattern_map, "turbulence", 1.0)?;
+    let kind = field(pattern_map, "type", parse_str)?;
+    two_colour_pattern(pattern_map, |colour_a, colour_b, transform| match kind {
+        "marble" => Pattern::Marble {
+            colour_a,
+            colour_b,
+            turbulence,
+            transform: transform.into(),
+        },
+        "wood" => Pattern::Wood {
+            colour_a,
+            colour_b,
+            turbulence,
+            transform: transform.into(),
+        },
+        "granite" => Pattern::Granite {
+            colour_a,
+            colour_b,
+            turbulence,
+            transform: transform.into(),
+        },
+        _ => unreachable!(),
+    })
+}
+
+// either of a pattern's two colours can be a whole pattern of its own
+enum PatternColour {
+    Flat(Colour),
+    Nested(Box<Pattern>),
+}
+
+impl PatternColour {
+    fn into_pattern(self) -> Pattern {
+        match self {
+            PatternColour::Flat(colour) => Pattern::Solid {
+                colour,
+                transform: Transform::default(),
+            },
+            PatternColour::Nested(pattern) => *pattern,
+        }
+    }
+}
+
+fn parse_pattern_colour(value: &yaml::Yaml) -> Result<PatternColour, ParseError> {
+    match value {
+        Yaml::Hash(_) => Ok(PatternColour::Nested(Box::new(parse_pattern(value)?))),
+        _ => Ok(PatternColour::Flat(parse_colour(value)?)),
+    }
+}
+
+// reads the two colours of a pattern, accepting either spelling of colour
+fn parse_pattern_colours(
+    pattern_map: &yaml::Yaml,
+) -> Result<(PatternColour, PatternColour), ParseError> {
+    // the book's scene files give both colours in one list
+    for key in ["colours", "colors"] {
+        if pattern_map[key] != Yaml::BadValue {
+            return field(pattern_map, key, |colours| match colours.as_vec() {
+                Some(pair) if pair.len() == 2 => Ok((
+                    parse_pattern_colour(&pair[0]).map_err(|e| e.within("[0]"))?,
+                    parse_pattern_colour(&pair[1]).map_err(|e| e.within("[1]"))?,
+                )),
+                _ => Err(invalid("a list of two colours", colours)),
+            });
+        }
+    }
+    let colour_a = if pattern_map["colour-a"] != Yaml::BadValue {
+        field(pattern_map, "colour-a", parse_pattern_colour)?
+    } else if pattern_map["color-a"] != Yaml::BadValue {
+        field(pattern_map, "color-a", parse_pattern_colour)?
+    } else {
+        return Err(missing("colour-a"));
+    };
+
+    let colour_b = if pattern_map["colour-b"] != Yaml::BadValue {
+        field(pattern_map, "colour-b", parse_pattern_colour)?
+    } else if pattern_map["color-b"] != Yaml::BadValue {
+        field(pattern_map, "color-b", parse_pattern_colour)?
+    } else {
+        return Err(missing("colour-b"));
+    };
+    Ok((colour_a, colour_b))
+}
+
+// Builds a pattern with two colours and a transform. If either colour is a
+// pattern, the pattern is built in black and white instead, and used as the
+// mask to choose between the two.
+fn two_colour_pattern(
+    pattern_map: &yaml::Yaml,
+    build: impl Fn(Colour, Colour, Matrix<Float, 4, 4>) -> Pattern,
+) -> Result<Pattern, ParseError> {
+    let transform = optional_transform(pattern_map)?;
+    match parse_pattern_colours(pattern_map)? {
+        (PatternColour::Flat(colour_a), PatternColour::Flat(colour_b)) => {
+            Ok(build(colour_a, colour_b, transform))
+        }
+        (pattern_a, pattern_b) => Ok(Pattern::Masked {
+            mask: Box::new(build(Colour::black(), Colour::white(), Matrix::identity())),
+            pattern_a: Box::new(pattern_a.into_pattern()),
+            pattern_b: Box::new(pattern_b.into_pattern()),
+            transform: transform.into(),
+        }),
+    }
+}
+
+// the mapping is optional, and defaults to spherical
+fn parse_uv_mapping(pattern_map: &yaml::Yaml) -> Result<UvMapping, ParseError> {
+    match &pattern_map["mapping"] {
+        Yaml::BadValue => Ok(UvMapping::Spherical),
+        _ => field(pattern_map, "mapping", |m| {
+            parse_choice(
+                m,
+                &[
+                    ("spherical", UvMapping::Spherical),
+                    ("planar", UvMapping::Planar),
+                    ("cylindrical", UvMapping::Cylindrical),
+                    ("cube", UvMapping::Cube),
+                    ("equirectangular", UvMapping::Equirectangular),
+                ],
+            )
+        }),
+    }
+}
+
+// the number of squares across u and v default to 2 each
+fn parse_uv_check_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    let width = optional_number(pattern_map, "width", 2.0)?;
+    let height = optional_number(pattern_map, "height", 2.0)?;
+    let mapping = parse_uv_mapping(pattern_map)?;
+    two_colour_pattern(pattern_map, |colour_a, colour_b, transform| {
+        Pattern::UvCheck {
+            colour_a,
+            colour_b,
+            width,
+            height,
+            mapping,
+            transform: transform.into(),
+        }
+    })
+}
+
+fn load_image(map: &yaml::Yaml, key: &str) -> Result<Canvas, ParseError> {
+    let path = field(map, key, parse_str)?;
+    #[cfg(feature = "files")]
+    let image = Canvas::load(path).map_err(|e| e.to_string());
+    // there's no image decoding without the files feature, e.g. in a browser
+    #[cfg(not(feature = "files"))]
+    let image = Err("images can't be loaded in this build of rusrat".to_string());
+    image.map_err(|message| ParseError::File {
+        key: key.to_string(),
+        path: path.to_string(),
+        message,
+    })
+}
+
+// a texture needs the path of an image file, and optionally the uv mapping
+// to use
+fn parse_texture_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    let texture = Arc::new(MipMap::new(load_image(pattern_map, "file")?));
+    Ok(Pattern::Texture {
+        texture,
+        mapping: parse_uv_mapping(pattern_map)?,
+        transform: optional_transform(pattern_map)?.into(),
+    })
+}
+
+// a blend contains two full pattern definitions, and optionally a weight
+// giving the proportion of the second pattern to use
+fn parse_blend_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    Ok(Pattern::Blend {
+        pattern_a: Box::new(field(pattern_map, "pattern-a", parse_pattern)?),
+        pattern_b: Box::new(field(pattern_map, "pattern-b", parse_pattern)?),
+        weight: optional_number(pattern_map, "weight", 0.5)?,
+        transform: optional_transform(pattern_map)?.into(),
+    })
+}
+
+// a gradient either has a list of stops, each of which is a position and a
+// colour, or just two colours which go at positions 0 and 1. a radial
+// gradient is the same, but goes outwards from the y axis rather than along x.
+fn parse_gradient_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    let radial = field(pattern_map, "type", parse_str)? == "radial-gradient";
+    let build = |stops, transform| {
+        if radial {
+            Pattern::RadialGradient { stops, transform }
+        } else {
+            Pattern::Gradient { stops, transform }
+        }
+    };
+    if let Yaml::Array(stop_list) = &pattern_map["stops"] {
+        let stops = stop_list
+            .iter()
+            .enumerate()
+            .map(|(i, stop)| {
+                let at = |e: ParseError| e.within(&format!("stops[{}]", i));
+                let position = parse_number(&stop[0]).map_err(|e| at(e.within("[0]")))?;
+                if !position.is_finite() {
+                    return Err(at(invalid("a number", &stop[0]).within("[0]")));
+                }
+                Ok((
+                    position,
+                    parse_colour(&stop[1]).map_err(|e| at(e.within("[1]")))?,
+                ))
+            })
+            .collect::<Result<Vec<(Float, Colour)>, ParseError>>()?;
+        // the positions are all numbers, so this can only fail for no stops
+        let stops = ColourStops::new(stops).map_err(|_| {
+            invalid("at least one colour stop", &pattern_map["stops"]).within("stops")
+        })?;
+        Ok(build(stops, optional_transform(pattern_map)?.into()))
+    } else {
+        two_colour_pattern(pattern_map, |colour_a, colour_b, transform| {
+            build(ColourStops::between(colour_a, colour_b), transform.into())
+        })
+    }
+}
+
+// a perturbed pattern wraps another pattern, with an optional scale for how
+// far the noise can move points
+fn parse_perturbed_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    Ok(Pattern::Perturbed {
+        pattern: Box::new(field(pattern_map, "pattern", parse_pattern)?),
+        scale: optional_number(pattern_map, "scale", 0.2)?,
+        transform: optional_transform(pattern_map)?.into(),
+    })
+}
+
+fn parse_solid_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    let colour = if pattern_map["color"] != Yaml::BadValue {
+        field(pattern_map, "color", parse_colour)?
+    } else {
+        field(pattern_map, "colour", parse_colour)?
+    };
+    Ok(Pattern::Solid {
+        colour,
+        transform: optional_transform(pattern_map)?.into(),
+    })
+}
+
+// a cube map needs an image file for each of its six faces
+fn parse_cube_map_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    let mut faces = Vec::new();
+    for face in ["left", "right", "front", "back", "up", "down"] {
+        faces.push(Arc::new(load_image(pattern_map, face)?));
+    }
+    Ok(Pattern::CubeMap {
+        faces: faces.try_into().unwrap(),
+        transform: optional_transform(pattern_map)?.into(),
+    })
+}
+
+fn parse_check_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    two_colour_pattern(pattern_map, |colour_a, colour_b, transform| {
+        Pattern::Check3D {
+            colour_a,
+            colour_b,
+            transform: transform.into(),
+        }
+    })
+}
+
+fn parse_stripe_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    two_colour_pattern(pattern_map, |colour_a, colour_b, transform| {
+        Pattern::Stripe {
+            colour_a,
+            colour_b,
+            transform: transform.into(),
+        }
+    })
+}
+
+fn parse_ring_pattern(pattern_map: &yaml::Yaml) -> Result<Pattern, ParseError> {
+    two_colour_pattern(pattern_map, |colour_a, colour_b, transform| Pattern::Ring {
+        colour_a,
+        colour_b,
+        transform: transform.into(),
+    })
+}
+
+// three numbers in a list, e.g. [1, 0.5, 0]
+fn parse_three_numbers(array: &yaml::Yaml) -> Result<[Float; 3], ParseError> {
+    match array {
+        Yaml::Array(a) if a.len() == 3 => {
+            let mut out = [0.0; 3];
+            for (i, value) in a.iter().enumerate() {
+                out[i] = parse_number(value).map_err(|e| e.within(&format!("[{}]", i)))?;
+            }
+            Ok(out)
+        }
+        Yaml::BadValue => Err(missing("")),
+        _ => Err(invalid("a list of three numbers", array)),
+    }
+}
+
+fn destructure_yaml_array_into_tuple(
+    array: &yaml::Yaml,
+    kind: TupleKind,
+) -> Result<Tuple, ParseError> {
+    let [x, y, z] = parse_three_numbers(array)?;
+    Ok(match kind {
+        TupleKind::Vector => Tuple::vector_new(x, y, z),
+        TupleKind::Point => Tuple::point_new(x, y, z),
+    })
+}
+
+fn parse_point(array: &yaml::Yaml) -> Result<Tuple, ParseError> {
+    destructure_yaml_array_into_tuple(array, TupleKind::Point)
+}
+
+fn parse_vector(array: &yaml::Yaml) -> Result<Tuple, ParseError> {
+    destructure_yaml_array_into_tuple(array, TupleKind::Vector)
+}
+
+// a colour can be a list of red, green and blue, a name like "white", or a
+// hex code like "#ff8800"
+fn parse_colour(colour: &yaml::Yaml) -> Result<Colour, ParseError> {
+    let expected = "a list of three numbers, a colour name, or a hex colour like \"#ff8800\"";
+    match colour {
+        Yaml::String(s) => {
+            let parsed = if s.starts_with('#') {
+                Colour::from_hex(s)
+            } else {
+                Colour::named(s)
+            };
+            parsed.ok_or_else(|| invalid(expected, colour))
+        }
+        Yaml::Hash(_) | Yaml::Integer(_) | Yaml::Real(_) | Yaml::Boolean(_) => {
+            Err(invalid(expected, colour))
+        }
+        _ => {
+            let [r, g, b] = parse_three_numbers(colour)?;
+            Ok(Colour::new(r, g, b))
+        }
+    }
+}
+
+fn entity_kind(entity: &yaml::Hash) -> Result<EntityKind, ParseError> {
+    if entity.contains_key(&Yaml::String("world".to_string())) {
+        return Ok(EntityKind::World);
+    }
+    if entity.contains_key(&Yaml::String("render".to_string())) {
+        return Ok(EntityKind::Render);
+    }
+    if entity.contains_key(&Yaml::String("define".to_string())) {
+        return Ok(EntityKind::Define);
+    }
+    if entity.contains_key(&Yaml::String("include".to_string())) {
+        return Ok(EntityKind::Include);
+    }
+    if entity.contains_key(&Yaml::String("frames".to_string())) {
+        return Ok(EntityKind::Frames);
+    }
+    let s = match entity.get(&Yaml::String("add".to_string())) {
+        Some(s) => s,
+        None => return Err(missing("add")),
+    };
+    match s {
+        Yaml::String(kind) if kind == "sphere" => Ok(EntityKind::Sphere),
+        Yaml::String(kind) if kind == "plane" => Ok(EntityKind::Plane),
+        Yaml::String(kind) if kind == "cylinder" => Ok(EntityKind::Cylinder),
+        Yaml::String(kind) if kind == "cone" => Ok(EntityKind::Cone),
+        Yaml::String(kind) if kind == "cube" => Ok(EntityKind::Cube),
+        Yaml::String(kind) if kind == "obj" => Ok(EntityKind::Obj),
+        Yaml::String(kind) if kind == "group" => Ok(EntityKind::Group),
+        Yaml::String(kind) if kind == "camera" => Ok(EntityKind::Camera),
+        Yaml::String(kind) if kind == "light" => Ok(EntityKind::Light),
+        _ => Err(invalid(
+            "one of sphere, plane, cylinder, cone, cube, obj, group, camera, light, or the name of a template",
+            s,
+        )
+        .within("add")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shapes;
+
+    #[test]
+    fn reads_in_camera() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [ 1, 3, 2 ]
+  to: [4, -2, 8]
+  up: [1, 1, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        let expected = world::Camera::new(
+            100,
+            100,
+            0.785,
+            Matrix::from_array([
+                [-0.50709, 0.50709, 0.67612, -2.36643],
+                [0.76772, 0.60609, 0.12122, -2.82843],
+                [-0.35857, 0.59761, -0.71714, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ]),
+        );
+        assert_eq!(cam, expected);
+    }
+
+    #[test]
+    fn reads_in_camera_samples() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+  samples: 16
+  sampler: blue-noise
+  seed: 3
+  adaptive-threshold: 0.05
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        assert_eq!(cam.samples, 16);
+        assert_eq!(cam.adaptive_threshold, Some(0.05));
+        assert_eq!(cam.sampler, Sampler::BlueNoise { seed: 3 });
+        assert_eq!(cam.aperture, 0.0);
+    }
+
+    #[test]
+    fn reads_in_camera_post_processing() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  exposure: 1.5
+  gamma: 2.2
+  tone-map: aces
+  dither: ordered
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        assert_eq!(
+            cam.post_process,
+            crate::canvas::PostProcess {
+                exposure: 1.5,
+                tone_map: ToneMap::Aces,
+                gamma: 2.2
+            }
+        );
+        assert_eq!(cam.bloom, None);
+        assert_eq!(cam.dither, Dither::Ordered);
+    }
+
+    #[test]
+    fn camera_post_processing_has_to_keep_colours_positive() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  exposure: -1
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  gamma: 0
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let error = |i: usize| camera_from_config(&config[i]).err().unwrap().to_string();
+        assert_eq!(error(0), "exposure should be 0 or more, but is -1");
+        assert_eq!(error(1), "gamma should be more than 0, but is 0");
+    }
+
+    #[test]
+    fn reads_in_camera_bloom() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  bloom:
+    threshold: 0.8
+    radius: 2
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        assert_eq!(
+            cam.bloom,
+            Some(Bloom {
+                threshold: 0.8,
+                radius: 2.0,
+                strength: 0.5
+            })
+        );
+    }
+
+    #[test]
+    fn reads_in_stereo_camera() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  stereo:
+    interocular: 0.1
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        assert_eq!(
+            cam.stereo,
+            Some(Stereo {
+                interocular: 0.1,
+                convergence: 5.0
+            })
+        );
+    }
+
+    #[test]
+    fn stereo_eyes_have_to_converge_in_front_of_the_camera() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  stereo:
+    interocular: -0.1
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  stereo:
+    interocular: 0.1
+    convergence: 0
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let error = |i: usize| camera_from_config(&config[i]).err().unwrap().to_string();
+        assert_eq!(
+            error(0),
+            "stereo.interocular should be 0 or more, but is -0.1"
+        );
+        assert_eq!(
+            error(1),
+            "stereo.convergence should be more than 0, but is 0"
+        );
+    }
+
+    #[test]
+    fn reads_in_camera_roll() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  roll: 90
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let from = Tuple::point_new(0.0, 0.0, -5.0);
+        let to = Tuple::point_new(0.0, 0.0, 0.0);
+        let rolled = camera_from_config(&config[0]).unwrap();
+        let expected = world::view_transform(&from, &to, &Tuple::vector_new(-1.0, 0.0, 0.0));
+        assert_eq!(rolled.transform, expected);
+        let level = camera_from_config(&config[1]).unwrap();
+        let expected = world::view_transform(&from, &to, &Tuple::vector_new(0.0, 1.0, 0.0));
+        assert_eq!(level.transform, expected);
+    }
+
+    #[test]
+    fn reads_in_camera_transform() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  transform:
+    - [rotate-y-deg, 180]
+    - [translate, 0, 0, -5]
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  transform:
+    - [translate, 0, 0, -5]
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  aperture: 0.1
+  transform:
+    - [translate, 0, 0, -5]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let cam = camera_from_config(&config[0]).unwrap();
+        let expected = world::view_transform(
+            &Tuple::point_new(0.0, 0.0, -5.0),
+            &Tuple::point_new(0.0, 0.0, 0.0),
+            &Tuple::vector_new(0.0, 1.0, 0.0),
+        );
+        assert_eq!(cam.transform, expected);
+        assert_eq!(
+            camera_from_config(&config[1]).err().unwrap().to_string(),
+            "from should be left out, as the camera has a transform, but is a list"
+        );
+        // there's nothing to focus on by default
+        assert_eq!(
+            camera_from_config(&config[2]).err().unwrap(),
+            missing("focal-distance")
+        );
+    }
+
+    #[test]
+    fn selects_named_camera() {
+        let yaml_file = "
+- add: camera
+  name: wide
+  width: 200
+  height: 100
+  field-of-view: 1.5
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- add: camera
+  name: close-up
+  width: 100
+  height: 100
+  field-of-view: 0.5
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- add: camera
+  width: 50
+  height: 50
+  field-of-view: 0.5
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        // the last camera by default
+        let (_, c) = parse_config(config).unwrap();
+        assert_eq!((c.hsize, c.name), (50, None));
+        let (_, c) = parse_config_with_camera(config, Some("wide")).unwrap();
+        assert_eq!(c.name.as_deref(), Some("wide"));
+
+        let with_render = format!("{}- render: close-up\n", yaml_file);
+        let config = &yaml::YamlLoader::load_from_str(&with_render).unwrap()[0];
+        let (_, c) = parse_config(config).unwrap();
+        assert_eq!(c.name.as_deref(), Some("close-up"));
+        // asking for a camera overrides the scene's choice
+        let (_, c) = parse_config_with_camera(config, Some("wide")).unwrap();
+        assert_eq!(c.name.as_deref(), Some("wide"));
+    }
+
+    #[test]
+    fn selecting_missing_camera_is_an_error() {
+        let yaml_file = "
+- add: camera
+  name: wide
+  width: 200
+  height: 100
+  field-of-view: 1.5
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+- render: missing
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        assert_eq!(
+            parse_config(config).err(),
+            Some(ParseError::NoSuchCamera {
+                name: "missing".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn missing_field_error_has_its_key() {
+        let yaml_file = "
+- add: light
+  at: [0, 0, -5]
+- add: sphere
+  material:
+    pattern:
+      type: stripe
+      colour-a: [1, 1, 1]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error,
+            ParseError::Missing {
+                key: "entity 1 (light).intensity".to_string()
+            }
+        );
+        assert_eq!(error.to_string(), "entity 1 (light).intensity is missing");
+    }
+
+    #[test]
+    fn invalid_value_error_has_its_key_and_value() {
+        let yaml_file = "
+- add: sphere
+  material:
+    pattern:
+      type: stripe
+      colour-a: [1, 1, 1]
+      colour-b: [1, [0.5], 1]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "entity 1 (sphere).material.pattern.colour-b[1] should be a number, but is a list"
+        );
+    }
+
+    #[test]
+    fn transform_errors_point_at_the_transform() {
+        let yaml_file = "
+- add: cube
+  transform:
+    - [translate, 1, 2, 3]
+    - [squash, 2]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error,
+            ParseError::Invalid {
+                key: "entity 1 (cube).transform[1][0]".to_string(),
+                expected: "one of rotate-x, rotate-y, rotate-z, rotate-x-deg, rotate-y-deg, rotate-z-deg, translate, scale, matrix".to_string(),
+                found: "\"squash\"".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn transforms_that_flatten_things_are_errors() {
+        let yaml_file = "
+- add: sphere
+  transform:
+    - [scale, 1, 1, 0]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "entity 1 (sphere).transform should be a transform that can be undone, without a scale of 0, but is a list"
+        );
+    }
+
+    #[test]
+    fn transforms_need_the_right_number_of_values() {
+        let yaml_file = "
+- add: cube
+  transform:
+    - [translate, 1, 2, 3]
+    - [scale, 2, 2]
+- add: cube
+  transform:
+    - [rotate-x-deg, 90, 0]
+- add: sphere
+  transform:
+    - [matrix]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let error = |i: usize| {
+            parse_config(&Yaml::Array(vec![config[i].clone()]))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error(0),
+            "entity 1 (cube).transform[1] should be scale followed by 3 values, like \
+             [scale, 1, 2, 3], but is scale followed by 2 values"
+        );
+        assert_eq!(
+            error(1),
+            "entity 1 (cube).transform[0] should be rotate-x-deg followed by 1 value, like \
+             [rotate-x-deg, 90], but is rotate-x-deg followed by 2 values"
+        );
+        assert_eq!(
+            error(2),
+            "entity 1 (sphere).transform[0] should be matrix followed by 1 value, like \
+             [matrix, [16 numbers]], but is matrix followed by 0 values"
+        );
+    }
+
+    #[test]
+    fn missing_file_is_an_error() {
+        let yaml_file = "
+- add: obj
+  file: no/such/file.obj
+";
+        match parse_scene(yaml_file, None) {
+            Err(ParseError::File { key, path, .. }) => {
+                assert_eq!(key, "entity 1 (obj).file");
+                assert_eq!(path, "no/such/file.obj");
+            }
+            other => panic!("expected a file error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn syntax_error_has_its_line() {
+        let yaml_file = "- add: sphere\n  material: [1, 2\n- add: cube\n";
+        match parse_scene(yaml_file, None) {
+            Err(ParseError::Syntax { line, .. }) => assert_eq!(line, 3),
+            other => panic!("expected a syntax error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn reads_in_camera_region() {
+        let yaml_file = "
+- add: camera
+  width: 200
+  height: 100
+  field-of-view: 1.0
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+  region: [10, 20, 50, 60]
+  crop: true
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        assert_eq!(
+            cam.region,
+            Some(Region {
+                x0: 10,
+                y0: 20,
+                x1: 50,
+                y1: 60
+            })
+        );
+        assert!(cam.crop);
+    }
+
+    #[test]
+    fn reads_in_camera_projection() {
+        let yaml_file = "
+- add: camera
+  width: 200
+  height: 100
+  field-of-view: 3.14
+  from: [0, 0, 0]
+  to: [0, 0, 1]
+  up: [0, 1, 0]
+  projection: equirectangular
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let cam = camera_from_config(config).unwrap();
+        assert_eq!(cam.projection, Projection::Equirectangular);
+    }
+
+    #[test]
+    fn reads_in_camera_lens() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+  aperture: 0.1
+- add: camera
+  width: 100
+  height: 100
+  field-of-view: 0.785
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+  aperture: 0.2
+  focal-distance: 8
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let cam = camera_from_config(&config[0]).unwrap();
+        assert_eq!((cam.aperture, cam.focal_distance), (0.1, 5.0));
+        let cam = camera_from_config(&config[1]).unwrap();
+        assert_eq!((cam.aperture, cam.focal_distance), (0.2, 8.0));
+    }
+
+    #[test]
+    fn reads_in_light() {
+        let yaml_file = "
+- add: light
+  at: [50, 100, -50]
+  intensity: [1, 1, 0.2]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let light = light_from_config(config).unwrap();
+        let expected = PointLight::new(
+            Colour::new(1.0, 1.0, 0.2),
+            Tuple::point_new(50.0, 100.0, -50.0),
+        );
+        assert_eq!(light, expected);
+    }
+
+    #[test]
+    fn reads_in_light_with_attenuation() {
+        let yaml_file = "
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  attenuation:
+    linear: 0.1
+    quadratic: 0.01
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let light = light_from_config(config).unwrap();
+        let expected = PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0))
+            .with_attenuation(Attenuation {
+                constant: 1.0,
+                linear: 0.1,
+                quadratic: 0.01,
+            });
+        assert_eq!(light, expected);
+    }
+
+    #[test]
+    fn reads_in_light_without_specular() {
+        let yaml_file = "
+- add: light
+  at: [0, 10, 0]
+  intensity: [0.3, 0.3, 0.3]
+  specular: false
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let light = light_from_config(config).unwrap();
+        let expected =
+            PointLight::new(Colour::new(0.3, 0.3, 0.3), Tuple::point_new(0.0, 10.0, 0.0))
+                .with_specular(false);
+        assert_eq!(light, expected);
+    }
+
+    #[test]
+    fn reads_in_light_links() {
+        let yaml_file = "
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  group: rim
+- add: sphere
+  light-links: [rim, key]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert_eq!(
+            w.lights[0],
+            PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0)).with_group("rim")
+        );
+        assert_eq!(
+            w.objects[0].light_links,
+            Some(vec!["rim".to_string(), "key".to_string()])
+        );
+    }
+
+    #[test]
+    fn reads_in_area_light() {
+        let yaml_file = "
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  area:
+    u: [2, 0, 0]
+    u-steps: 4
+    v: [0, 0, 1]
+    v-steps: 2
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let light = light_from_config(config).unwrap();
+        let expected = PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0))
+            .with_area(LightArea {
+                uvec: Tuple::vector_new(2.0, 0.0, 0.0),
+                usteps: 4,
+                vvec: Tuple::vector_new(0.0, 0.0, 1.0),
+                vsteps: 2,
+            });
+        assert_eq!(light, expected);
+    }
+
+    #[test]
+    fn reads_in_lights_of_each_type() {
+        let yaml_file = "
+- add: light
+  type: area
+  corner: [-1, 10, -1]
+  intensity: [1, 1, 1]
+  u: [2, 0, 0]
+  u-steps: 4
+  v: [0, 0, 2]
+  v-steps: 2
+- add: light
+  type: spot
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  direction: [0, -1, 0]
+  inner-angle: 0.25
+  outer-angle: 0.5
+- add: light
+  type: directional
+  intensity: [1, 1, 1]
+  direction: [1, -1, 0]
+- add: light
+  type: spot
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  direction: [0, -1, 0]
+  outer-angle: 0.5
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let white = Colour::white();
+        assert_eq!(
+            light_from_config(&config[0]).unwrap(),
+            PointLight::new(white, Tuple::point_new(0.0, 10.0, 0.0)).with_area(LightArea {
+                uvec: Tuple::vector_new(2.0, 0.0, 0.0),
+                usteps: 4,
+                vvec: Tuple::vector_new(0.0, 0.0, 2.0),
+                vsteps: 2,
+            })
+        );
+        let spot = |inner_angle| LightKind::Spot {
+            direction: Tuple::vector_new(0.0, -1.0, 0.0),
+            inner_angle,
+            outer_angle: 0.5,
+        };
+        assert_eq!(
+            light_from_config(&config[1]).unwrap(),
+            PointLight::new(white, Tuple::point_new(0.0, 10.0, 0.0)).with_kind(spot(0.25))
+        );
+        assert_eq!(
+            light_from_config(&config[2]).unwrap(),
+            PointLight::new(white, Tuple::point_new(0.0, 0.0, 0.0)).with_kind(
+                LightKind::Directional {
+                    direction: Tuple::vector_new(1.0, -1.0, 0.0)
+                }
+            )
+        );
+        // a hard-edged cone
+        assert_eq!(
+            light_from_config(&config[3]).unwrap(),
+            PointLight::new(white, Tuple::point_new(0.0, 10.0, 0.0)).with_kind(spot(0.5))
+        );
+    }
+
+    #[test]
+    fn light_types_need_their_settings() {
+        let yaml_file = "
+- add: light
+  type: laser
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+- add: light
+  type: spot
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  direction: [0, -1, 0]
+- add: light
+  type: spot
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  direction: [0, -1, 0]
+  inner-angle: 0.5
+  outer-angle: 0.25
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let error = |i: usize| light_from_config(&config[i]).err().unwrap().to_string();
+        assert_eq!(
+            error(0),
+            "type should be one of point, area, spot, directional, but is \"laser\""
+        );
+        assert_eq!(error(1), "outer-angle is missing");
+        assert_eq!(
+            error(2),
+            "inner-angle should be no bigger than the outer-angle, but is 0.5"
+        );
+    }
+
+    #[test]
+    fn reads_in_fog() {
+        let yaml_file = "
+- world:
+    fog:
+      type: linear
+      colour: [0.5, 0.5, 0.6]
+      start: 5
+      end: 30
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert_eq!(
+            w.fog,
+            Some(Fog::Linear {
+                colour: Colour::new(0.5, 0.5, 0.6),
+                start: 5.0,
+                end: 30.0
+            })
+        );
+
+        let yaml_file = "
+- world:
+    fog:
+      type: exponential
+      density: 0.05
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert_eq!(
+            w.fog,
+            Some(Fog::Exponential {
+                colour: Colour::white(),
+                density: 0.05
+            })
+        );
+    }
+
+    #[test]
+    fn fog_has_to_thicken_with_distance() {
+        let yaml_file = "
+- type: linear
+  start: 30
+  end: 5
+- type: linear
+  start: 5
+  end: 5
+- type: exponential
+  density: -0.05
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let error = |i: usize| parse_fog(&config[i]).err().unwrap().to_string();
+        assert_eq!(error(0), "end should be further than the start, but is 5");
+        assert_eq!(error(1), "end should be further than the start, but is 5");
+        assert_eq!(error(2), "density should be 0 or more, but is -0.05");
+    }
+
+    #[test]
+    fn reads_in_shadow_sampler() {
+        let yaml_file = "
+- world:
+    shadow-sampler: blue-noise
+    seed: 12
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert_eq!(w.shadow_sampler, Sampler::BlueNoise { seed: 12 });
+        assert!(w.shadow_cache);
+    }
+
+    #[test]
+    fn shadow_cache_can_be_turned_off() {
+        let yaml_file = "
+- world:
+    shadow-cache: false
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert!(!w.shadow_cache);
+    }
+
+    #[test]
+    fn reads_in_light_with_pattern() {
+        let yaml_file = "
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+  pattern:
+    type: stripe
+    colour-a: [1, 1, 1]
+    colour-b: [0, 0, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0][0];
+        let light = light_from_config(config).unwrap();
+        let expected = PointLight::new(Colour::white(), Tuple::point_new(0.0, 10.0, 0.0))
+            .with_pattern(Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            });
+        assert_eq!(light, expected);
+    }
+
+    #[test]
+    fn reads_in_a_rotation() {
+        let yaml_transform = "
+[rotate-x, 0.345]
+    ";
+        let config = &yaml::YamlLoader::load_from_str(yaml_transform).unwrap()[0];
+        let transform = transform_type_and_data(config).unwrap();
+        assert_eq!(transform, TransformType::RotateX(0.345));
+    }
+
+    #[test]
+    fn reads_in_rotations_in_degrees_and_sums() {
+        let yaml_transform = "
+- [rotate-y-deg, 90]
+- [rotate-z, pi/2]
+- [rotate-x, deg(90)]
+- [translate, 1/4, -2 * 3, (1 + 2) / 2]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_transform).unwrap()[0];
+        let rotation = |t: TransformType| match t {
+            TransformType::RotateX(a) | TransformType::RotateY(a) | TransformType::RotateZ(a) => a,
+            _ => panic!("expected a rotation"),
+        };
+        for i in 0..3 {
+            let angle = rotation(transform_type_and_data(&config[i]).unwrap());
+            assert!(crate::float_eq(angle, crate::precision::consts::FRAC_PI_2));
+        }
+        assert_eq!(
+            transform_type_and_data(&config[3]).unwrap(),
+            TransformType::Translate(0.25, -6.0, 1.5)
+        );
+    }
+
+    #[test]
+    fn reads_in_a_matrix() {
+        let yaml_transform = "
+[matrix, [1, 0, 0, 5,
+          0, 2, 0, 6,
+          0, 0, 3, 7,
+          0, 0, 0, 1]]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_transform).unwrap()[0];
+        let expected = Matrix::translation(5.0, 6.0, 7.0) * Matrix::scaling(1.0, 2.0, 3.0);
+        assert_eq!(
+            transform_type_and_data(config).unwrap(),
+            TransformType::Matrix(expected)
+        );
+    }
+
+    #[test]
+    fn matrix_needs_sixteen_numbers() {
+        let yaml_file = "
+- add: sphere
+  transform:
+    - [scale, 2, 2, 2]
+    - [matrix, [1, 0, 0, 0, 0, 1, 0, 0, 0, 0, 1, 0]]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "entity 1 (sphere).transform[1][1] should be a list of sixteen numbers, a row at a \
+             time, but is a list"
+        );
+    }
+
+    #[test]
+    fn malformed_sums_are_errors() {
+        let yaml_file = "
+- add: sphere
+  transform:
+    - [rotate-x, pi/]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "entity 1 (sphere).transform[0][1] should be a number or a sum like pi/2 \
+             (it ends too soon), but is \"pi/\""
+        );
+    }
+
+    #[test]
+    fn reads_in_a_translation() {
+        let yaml_transform = "
+[translate, 0.345, 5, 7.5]
+    ";
+        let config = &yaml::YamlLoader::load_from_str(yaml_transform).unwrap()[0];
+        let transform = transform_type_and_data(config).unwrap();
+        assert_eq!(transform, TransformType::Translate(0.345, 5.0, 7.5));
+    }
+
+    #[test]
+    fn reads_in_several_transforms() {
+        let yaml_transforms = "
+transform:
+  - [rotate-x, 1.57079632679]
+  - [scale, 5, 5, 5]
+  - [translate, 10, 5, 7]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_transforms).unwrap()[0];
+        let transform = parse_transforms(&config["transform"]).unwrap();
+        let expected = Matrix::from_array([
+            [5.0, 0.0, 0.0, 10.0],
+            [0.0, 0.0, -5.0, 5.0],
+            [0.0, 5.0, 0.0, 7.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        assert_eq!(transform, expected);
+    }
+
+    #[test]
+    fn reads_in_a_sphere() {
+        let yaml_sphere = "
+- add: sphere
+  material:
+    colour: [1,1,1]
+    ambient: 1
+    diffuse: 0
+    specular: 0
+  transform:
+    - [ rotate-x, 1.57079632679]
+    - [translate, 0, 0, 500]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_sphere).unwrap()[0][0];
+        dbg!(config);
+        let sphere = shape_from_config(config).unwrap();
+        let expected = shapes::Shape {
+            material: Material {
+                colour: Colour::new(1.0, 1.0, 1.0),
+                ambient: 1.0,
+                diffuse: 0.0,
+                specular: 0.0,
+                ..Default::default()
+            },
+            transform: Matrix::rotation_x(crate::precision::consts::FRAC_PI_2)
+                .translate(0.0, 0.0, 500.0)
+                .into(),
+            ..Default::default()
+        };
+        assert_eq!(sphere, expected);
+    }
+
+    #[test]
+    fn reads_in_a_named_shape() {
+        let yaml_shape = "
+- add: plane
+  name: floor
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_shape).unwrap()[0][0];
+        let plane = shape_from_config(config).unwrap();
+        assert_eq!(plane.name, Some("floor".to_string()));
+    }
+
+    #[test]
+    fn reads_in_an_open_cylinder() {
+        let yaml_cylinder = "
+- add: cylinder
+  minimum: -1
+  maximum: 2.5
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_cylinder).unwrap()[0][0];
+        let cylinder = shape_from_config(config).unwrap();
+        assert_eq!(
+            cylinder.shape,
+            ShapeType::Cylinder {
+                minimum: -1.0,
+                maximum: 2.5,
+                closed: false
+            }
+        );
+        assert!(cylinder.shape.is_open());
+    }
+
+    #[test]
+    fn reads_in_a_closed_cone() {
+        let yaml_cone = "
+- add: cone
+  minimum: -1
+  maximum: 0
+  closed: true
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_cone).unwrap()[0][0];
+        let cone = shape_from_config(config).unwrap();
+        assert_eq!(
+            cone.shape,
+            ShapeType::Cone {
+                minimum: -1.0,
+                maximum: 0.0,
+                closed: true
+            }
+        );
+    }
+
+    #[test]
+    fn reads_in_a_blended_pattern() {
+        let yaml_pattern = "
+type: blend
+weight: 0.25
+pattern-a:
+  type: stripe
+  colour-a: [1, 1, 1]
+  colour-b: [0, 0, 0]
+pattern-b:
+  type: 3d-check
+  colour-a: [1, 0, 0]
+  colour-b: [0, 0, 1]
+  transform:
+    - [scale, 0.5, 0.5, 0.5]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let pattern = parse_pattern(config).unwrap();
+        let expected = Pattern::Blend {
+            pattern_a: Box::new(Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            }),
+            pattern_b: Box::new(Pattern::Check3D {
+                colour_a: Colour::new(1.0, 0.0, 0.0),
+                colour_b: Colour::new(0.0, 0.0, 1.0),
+                transform: Matrix::scaling(0.5, 0.5, 0.5).into(),
+            }),
+            weight: 0.25,
+            transform: Transform::default(),
+        };
+        assert_eq!(pattern, expected);
+    }
+
+    #[test]
+    fn reads_in_named_and_hex_colours() {
+        let yaml_file = "
+- add: sphere
+  material:
+    colour: '#ff8800'
+    emission: '#000'
+    pattern:
+      type: stripe
+      colour-a: white
+      colour-b: [0, 0, 1]
+- add: light
+  at: [0, 0, 0]
+  intensity: grey
+- add: cube
+  material:
+    colour: chartreuse
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let sphere = shape_from_config(&config[0]).unwrap();
+        assert_eq!(sphere.material.colour, Colour::new(1.0, 136.0 / 255.0, 0.0));
+        assert_eq!(sphere.material.emission, Colour::black());
+        assert_eq!(
+            sphere.material.pattern,
+            Some(Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::new(0.0, 0.0, 1.0),
+                transform: Transform::default(),
+            })
+        );
+        let light = light_from_config(&config[1]).unwrap();
+        assert_eq!(
+            light,
+            PointLight::new(Colour::new(0.5, 0.5, 0.5), Tuple::point_new(0.0, 0.0, 0.0))
+        );
+        let error = shape_from_config(&config[2]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "material.colour should be a list of three numbers, a colour name, or a hex colour \
+             like \"#ff8800\", but is \"chartreuse\""
+        );
+    }
+
+    #[test]
+    fn reads_in_ring_and_radial_gradient_patterns() {
+        let yaml_pattern = "
+- type: ring
+  color-a: [1, 1, 1]
+  color-b: [0, 0, 0]
+- type: radial-gradient
+  stops:
+    - [1, [0, 0, 1]]
+    - [0, [1, 0, 0]]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        assert_eq!(
+            parse_pattern(&config[0]).unwrap(),
+            Pattern::Ring {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            }
+        );
+        assert_eq!(
+            parse_pattern(&config[1]).unwrap(),
+            Pattern::RadialGradient {
+                stops: ColourStops::new(vec![
+                    (0.0, Colour::new(1.0, 0.0, 0.0)),
+                    (1.0, Colour::new(0.0, 0.0, 1.0))
+                ])
+                .unwrap(),
+                transform: Transform::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_in_nested_pattern_colours() {
+        let yaml_pattern = "
+type: stripe
+transform:
+  - [scale, 2, 2, 2]
+colour-a: [1, 0, 0]
+colour-b:
+  type: 3d-check
+  colour-a: [1, 1, 1]
+  colour-b: [0, 0, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let expected = Pattern::Masked {
+            mask: Box::new(Pattern::Stripe {
+                colour_a: Colour::black(),
+                colour_b: Colour::white(),
+                transform: Transform::default(),
+            }),
+            pattern_a: Box::new(Pattern::Solid {
+                colour: Colour::new(1.0, 0.0, 0.0),
+                transform: Transform::default(),
+            }),
+            pattern_b: Box::new(Pattern::Check3D {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            }),
+            transform: Matrix::scaling(2.0, 2.0, 2.0).into(),
+        };
+        assert_eq!(parse_pattern(config).unwrap(), expected);
+    }
+
+    #[test]
+    fn reads_in_a_perturbed_pattern() {
+        let yaml_pattern = "
+type: perturbed
+scale: 0.1
+pattern:
+  type: ring
+  colour-a: [1, 1, 1]
+  colour-b: [0, 0, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let expected = Pattern::Perturbed {
+            pattern: Box::new(Pattern::Ring {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            }),
+            scale: 0.1,
+            transform: Transform::default(),
+        };
+        assert_eq!(parse_pattern(config).unwrap(), expected);
+    }
+
+    #[test]
+    fn reads_in_a_marble_pattern() {
+        let yaml_pattern = "
+type: marble
+colour-a: [1, 1, 1]
+colour-b: [0.1, 0.1, 0.2]
+turbulence: 2.5
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let expected = Pattern::Marble {
+            colour_a: Colour::white(),
+            colour_b: Colour::new(0.1, 0.1, 0.2),
+            turbulence: 2.5,
+            transform: Transform::default(),
+        };
+        assert_eq!(parse_pattern(config).unwrap(), expected);
+    }
+
+    #[test]
+    fn noise_patterns_default_turbulence() {
+        let yaml_pattern = "
+type: wood
+colour-a: [0.6, 0.4, 0.2]
+colour-b: [0.4, 0.2, 0.1]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        match parse_pattern(config).unwrap() {
+            Pattern::Wood { turbulence, .. } => assert_eq!(turbulence, 1.0),
+            _ => panic!("expected a wood pattern"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn reads_in_a_texture_pattern() {
+        let path = std::env::temp_dir().join("rusrat_reads_in_a_texture_pattern.ppm");
+        std::fs::write(&path, "P3\n1 1\n255\n0 255 0\n").unwrap();
+        let yaml_pattern = format!(
+            "
+type: texture
+file: {}
+mapping: cylindrical
+",
+            path.display()
+        );
+        let config = &yaml::YamlLoader::load_from_str(&yaml_pattern).unwrap()[0];
+        let pattern = parse_pattern(config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        match pattern {
+            Pattern::Texture {
+                texture, mapping, ..
+            } => {
+                assert_eq!(mapping, UvMapping::Cylindrical);
+                assert_eq!(*texture.level(0).pixel_at(0, 0), Colour::new(0.0, 1.0, 0.0));
+            }
+            _ => panic!("expected a texture pattern"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn reads_in_a_cube_map_environment() {
+        let dir = std::env::temp_dir();
+        let mut yaml_file = "
+- world:
+    environment:
+      type: cube-map
+      transform:
+        - [rotate-y, 0.5]
+"
+        .to_string();
+        let faces = ["left", "right", "front", "back", "up", "down"];
+        for (i, face) in faces.iter().enumerate() {
+            let path = dir.join(format!("rusrat_reads_in_a_cube_map_{}.ppm", face));
+            std::fs::write(&path, format!("P3\n1 1\n255\n{} 0 0\n", i)).unwrap();
+            yaml_file.push_str(&format!("      {}: {}\n", face, path.display()));
+        }
+        let config = &yaml::YamlLoader::load_from_str(&yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        for face in faces.iter() {
+            let path = dir.join(format!("rusrat_reads_in_a_cube_map_{}.ppm", face));
+            std::fs::remove_file(&path).unwrap();
+        }
+        match w.environment {
+            Some(Pattern::CubeMap { faces, transform }) => {
+                for (i, face) in faces.iter().enumerate() {
+                    assert_eq!(
+                        *face.pixel_at(0, 0),
+                        Colour::new(i as Float / 255.0, 0.0, 0.0)
+                    );
+                }
+                assert_eq!(transform, Matrix::rotation_y(0.5));
+            }
+            _ => panic!("expected a cube map environment"),
+        }
+        assert!(w.objects.is_empty());
+    }
+
+    #[test]
+    fn reads_in_gradient_stops() {
+        let yaml_pattern = "
+type: gradient
+stops:
+  - [1, [0, 0, 1]]
+  - [0, [1, 0, 0]]
+  - [0.5, [0, 1, 0]]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let expected = Pattern::Gradient {
+            stops: ColourStops::new(vec![
+                (0.0, Colour::new(1.0, 0.0, 0.0)),
+                (0.5, Colour::new(0.0, 1.0, 0.0)),
+                (1.0, Colour::new(0.0, 0.0, 1.0)),
+            ])
+            .unwrap(),
+            transform: Transform::default(),
+        };
+        assert_eq!(parse_pattern(config).unwrap(), expected);
+    }
+
+    #[test]
+    fn gradient_stop_must_be_at_a_finite_position() {
+        let yaml_pattern = "
+type: gradient
+stops:
+  - [0, [1, 1, 1]]
+  - [.nan, [0, 0, 0]]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        assert_eq!(
+            parse_pattern(config).err().unwrap(),
+            ParseError::Invalid {
+                key: "stops[1][0]".to_string(),
+                expected: "a number".to_string(),
+                found: ".nan".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_in_two_colour_gradient() {
+        let yaml_pattern = "
+type: gradient
+colour-a: [1, 1, 1]
+colour-b: [0, 0, 0]
 transform:
-  - [rotate-x, 1.57079632679]
-  - [scale, 5, 5, 5]
-  - [translate, 10, 5, 7]
+  - [scale, 4, 1, 1]
 ";
-        let config = &yaml::YamlLoader::load_from_str(yaml_transforms).unwrap()[0];
-        let transform = parse_transforms(&config["transform"]);
-        let expected = Matrix::from_array(&[
-            [5.0, 0.0, 0.0, 10.0],
-            [0.0, 0.0, -5.0, 5.0],
-            [0.0, 5.0, 0.0, 7.0],
-            [0.0, 0.0, 0.0, 1.0],
-        ]);
-        assert_eq!(transform, expected);
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let expected = Pattern::Gradient {
+            stops: ColourStops::between(Colour::white(), Colour::black()),
+            transform: Matrix::scaling(4.0, 1.0, 1.0).into(),
+        };
+        assert_eq!(parse_pattern(config).unwrap(), expected);
     }
 
     #[test]
-    fn reads_in_a_sphere() {
-        let yaml_sphere = "
-- add: sphere
+    fn reads_in_emissive_material() {
+        let yaml_material = "
+colour: [1, 0.9, 0.8]
+emission: [0.5, 0.4, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_material).unwrap()[0];
+        let expected = Material {
+            colour: Colour::new(1.0, 0.9, 0.8),
+            emission: Colour::new(0.5, 0.4, 0.0),
+            ..Default::default()
+        };
+        assert_eq!(parse_material(config).unwrap(), expected);
+    }
+
+    #[test]
+    fn reads_in_an_obj_file_with_materials() {
+        let dir = std::env::temp_dir();
+        let obj_path = dir.join("rusrat_reads_in_an_obj_file.obj");
+        let mtl_path = dir.join("rusrat_reads_in_an_obj_file.mtl");
+        std::fs::write(
+            &obj_path,
+            "mtllib rusrat_reads_in_an_obj_file.mtl
+v 0 0 0
+v 1 0 0
+v 0 1 0
+f 1 2 3
+usemtl red
+f 1 2 3
+usemtl shiny
+f 1 2 3
+",
+        )
+        .unwrap();
+        std::fs::write(&mtl_path, "newmtl red\nKd 1 0 0\nnewmtl shiny\nKs 1 1 1\n").unwrap();
+        let yaml_file = format!(
+            "
+- add: obj
+  file: {}
+  transform:
+    - [translate, 0, 1, 0]
   material:
-    colour: [1,1,1]
-    ambient: 1
-    diffuse: 0
-    specular: 0
+    ambient: 0.5
+  materials:
+    shiny:
+      reflectivity: 1
+",
+            obj_path.display()
+        );
+        let config = &yaml::YamlLoader::load_from_str(&yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        std::fs::remove_file(&obj_path).unwrap();
+        std::fs::remove_file(&mtl_path).unwrap();
+
+        assert_eq!(w.objects.len(), 3);
+        for object in w.objects.iter() {
+            assert_eq!(object.transform, Matrix::translation(0.0, 1.0, 0.0));
+            assert_eq!(object.name, None);
+        }
+        assert_eq!(w.objects[0].material.ambient, 0.5);
+        assert_eq!(w.objects[1].material.colour, Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(w.objects[2].material.reflectivity, 1.0);
+    }
+
+    #[test]
+    fn reads_in_an_obj_file_as_meshes() {
+        let obj_path = std::env::temp_dir().join("rusrat_reads_in_an_obj_file_as_meshes.obj");
+        std::fs::write(
+            &obj_path,
+            "v 0 0 0
+v 1 0 0
+v 0 1 0
+v 1 1 0
+f 1 2 3
+f 2 4 3
+usemtl red
+f 1 2 3
+",
+        )
+        .unwrap();
+        let yaml_file = format!(
+            "
+- add: obj
+  file: {}
+  indexed: true
+  name: model
+  materials:
+    red:
+      colour: [1, 0, 0]
+",
+            obj_path.display()
+        );
+        let config = &yaml::YamlLoader::load_from_str(&yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        std::fs::remove_file(&obj_path).unwrap();
+
+        // one mesh for each material
+        assert_eq!(w.objects.len(), 2);
+        let counts: Vec<_> = w
+            .objects
+            .iter()
+            .map(|o| match &o.shape {
+                ShapeType::Mesh(mesh) => (mesh.vertex_count(), mesh.face_count()),
+                _ => panic!("{:?} isn't a mesh", o.shape),
+            })
+            .collect();
+        assert_eq!(counts, vec![(4, 2), (3, 1)]);
+        assert_eq!(w.objects[1].material.colour, Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(w.objects[1].name.as_deref(), Some("model"));
+    }
+
+    #[test]
+    fn reads_in_background_colour() {
+        let yaml_file = "
+- world:
+    background: [0.1, 0.2, 0.3]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert_eq!(w.background, Colour::new(0.1, 0.2, 0.3));
+        assert_eq!(w.environment, None);
+    }
+
+    #[test]
+    #[cfg(feature = "files")]
+    fn reads_in_environment_map_and_samples() {
+        let path = std::env::temp_dir().join("rusrat_reads_in_environment_map.ppm");
+        std::fs::write(&path, "P3\n1 1\n255\n0 0 255\n").unwrap();
+        let yaml_file = format!(
+            "
+- world:
+    environment:
+      type: texture
+      file: {}
+      mapping: equirectangular
+    environment-samples: 32
+",
+            path.display()
+        );
+        let config = &yaml::YamlLoader::load_from_str(&yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(w.environment_samples, 32);
+        match w.environment {
+            Some(Pattern::Texture { mapping, .. }) => {
+                assert_eq!(mapping, UvMapping::Equirectangular)
+            }
+            _ => panic!("expected a texture environment"),
+        }
+    }
+
+    #[test]
+    fn reads_in_a_cube() {
+        let yaml_file = "
+- add: cube
   transform:
-    - [ rotate-x, 1.57079632679]
-    - [translate, 0, 0, 500]
+    - [scale, 2, 2, 2]
 ";
-        let config = &yaml::YamlLoader::load_from_str(yaml_sphere).unwrap()[0][0];
-        dbg!(config);
-        let sphere = shape_from_config(config);
-        let expected = shapes::Shape {
-            material: Material {
-                colour: Colour::new(1.0, 1.0, 1.0),
-                ambient: 1.0,
-                diffuse: 0.0,
-                specular: 0.0,
-                ..Default::default()
-            },
-            transform: Matrix::rotation_x(1.57079632679).translate(0.0, 0.0, 500.0),
-            ..Default::default()
+        let config = &yaml::YamlLoader::load_from_str(yaml_file).unwrap()[0];
+        let (w, _) = parse_config(config).unwrap();
+        assert_eq!(w.objects[0].shape, ShapeType::Cube);
+        assert_eq!(w.objects[0].transform, Matrix::scaling(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn reads_in_a_uv_check_pattern() {
+        let yaml_pattern = "
+type: uv-check
+colour-a: [0, 0, 0]
+color-b: [1, 1, 1]
+width: 16
+height: 8
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        let expected = Pattern::UvCheck {
+            colour_a: Colour::black(),
+            colour_b: Colour::white(),
+            width: 16.0,
+            height: 8.0,
+            mapping: UvMapping::Spherical,
+            transform: Transform::default(),
         };
-        assert_eq!(sphere, expected);
+        assert_eq!(parse_pattern(config).unwrap(), expected);
     }
 
     #[test]
@@ -445,7 +3936,7 @@ transform:
 ";
         let config = &yaml::YamlLoader::load_from_str(yaml_sphere).unwrap()[0][0];
         dbg!(config);
-        let sphere = shape_from_config(config);
+        let sphere = shape_from_config(config).unwrap();
         let expected = shapes::Shape {
             material: Material {
                 colour: Colour::new(1.0, 1.0, 1.0),
@@ -454,9 +3945,713 @@ transform:
                 specular: 0.0,
                 ..Default::default()
             },
-            transform: Matrix::identity(),
+            transform: Transform::default(),
             ..Default::default()
         };
         assert_eq!(sphere, expected);
     }
+
+    #[test]
+    fn uses_defined_materials_and_transforms() {
+        let yaml_file = "
+- define: white-material
+  value:
+    colour: [1, 1, 1]
+    diffuse: 0.7
+- define: blue-material
+  extend: white-material
+  value:
+    colour: [0.5, 0.8, 0.9]
+- define: standard-transform
+  value:
+    - [translate, 1, -1, 1]
+    - [scale, 0.5, 0.5, 0.5]
+- define: large-object
+  value:
+    - standard-transform
+    - [scale, 3.5, 3.5, 3.5]
+- add: cube
+  material: blue-material
+  transform:
+    - large-object
+    - [translate, 8.5, 1.5, -0.5]
+";
+        let (w, _) = parse_scene(yaml_file, None).unwrap();
+        let cube = &w.objects[0];
+        assert_eq!(cube.material.colour, Colour::new(0.5, 0.8, 0.9));
+        assert!(crate::float_eq(cube.material.diffuse, 0.7));
+        let expected = Matrix::translation(8.5, 1.5, -0.5)
+            * Matrix::scaling(3.5, 3.5, 3.5)
+            * Matrix::scaling(0.5, 0.5, 0.5)
+            * Matrix::translation(1.0, -1.0, 1.0);
+        assert_eq!(cube.transform, expected);
+    }
+
+    #[test]
+    fn using_undefined_name_is_an_error() {
+        let yaml_file = "
+- add: sphere
+  transform:
+    - [scale, 2, 2, 2]
+    - nowhere
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "entity 1 (sphere).transform[1] uses nowhere, which hasn't been defined"
+        );
+    }
+
+    #[test]
+    fn materials_can_be_presets() {
+        let yaml_file = "
+- define: tinted
+  extend: glass
+  value:
+    colour: [0, 0.1, 0]
+- define: matte
+  value:
+    colour: red
+- add: sphere
+  material: glass
+- add: cube
+  material: mirror
+- add: plane
+  material: tinted
+- add: sphere
+  material: matte
+";
+        let (w, _) = parse_scene(yaml_file, None).unwrap();
+        let glass = &w.objects[0].material;
+        assert_eq!(glass.transparency, 0.9);
+        assert_eq!(glass.refractive_index, 1.5);
+        assert_eq!(glass.colour, Colour::black());
+        let mirror = &w.objects[1].material;
+        assert_eq!(mirror.reflectivity, 1.0);
+        assert_eq!(mirror.transparency, 0.0);
+        let tinted = &w.objects[2].material;
+        assert_eq!(tinted.colour, Colour::new(0.0, 0.1, 0.0));
+        assert_eq!(tinted.transparency, 0.9);
+        // a definition takes the place of a preset
+        let matte = &w.objects[3].material;
+        assert_eq!(matte.colour, Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(matte.specular, 0.9);
+    }
+
+    #[test]
+    fn scene_descriptions_have_defined_materials() {
+        let yaml_file = "
+- define: shiny
+  value:
+    colour: [1, 0, 0]
+    reflectivity: 0.5
+- define: big
+  value:
+    - [scale, 2, 2, 2]
+- add: sphere
+  material: shiny
+- add: camera
+  width: 100
+  height: 50
+  field-of-view: 1
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  up: [0, 1, 0]
+";
+        let (scene, warnings) =
+            parse_description(yaml_file, SceneFormat::Yaml, Path::new("")).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(scene.cameras.len(), 1);
+        assert_eq!(scene.objects.len(), 1);
+        assert_eq!(scene.materials.len(), 1);
+        let shiny = scene.material("shiny").unwrap().clone();
+        assert_eq!(shiny.reflectivity, 0.5);
+        assert_eq!(scene.objects[0].material, shiny);
+    }
+
+    #[test]
+    fn includes_entities_from_other_files() {
+        let directory = std::env::temp_dir().join("rusrat_include");
+        std::fs::create_dir_all(directory.join("sets")).unwrap();
+        std::fs::write(
+            directory.join("sets").join("room.yaml"),
+            "
+- define: wall
+  value:
+    colour: [0.5, 0.5, 0.5]
+- add: plane
+  material: wall
+- include: lights.yaml
+",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("sets").join("lights.yaml"),
+            "
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+",
+        )
+        .unwrap();
+        let yaml_file = "
+- include: sets/room.yaml
+- add: sphere
+  material: wall
+";
+        let (w, _) = parse_scene_in(yaml_file, &directory, None).unwrap();
+        assert_eq!(w.lights.len(), 1);
+        assert_eq!(w.objects.len(), 2);
+        assert_eq!(w.objects[0].shape, ShapeType::Plane);
+        assert_eq!(w.objects[1].material.colour, Colour::new(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn templates_are_stamped_out_with_overrides() {
+        let yaml_file = "
+- define: glass
+  value:
+    transparency: 1
+    refractive-index: 1.5
+- define: marble
+  add: sphere
+  material: glass
+  transform:
+    - [scale, 0.5, 0.5, 0.5]
+- define: red-marble
+  add: marble
+  material:
+    colour: [1, 0, 0]
+- add: marble
+- add: marble
+  transform:
+    - [translate, 2, 0, 0]
+- add: red-marble
+  material:
+    reflectivity: 0.5
+- add: group
+  children:
+    - add: marble
+";
+        let (w, _) = parse_scene(yaml_file, None).unwrap();
+        assert_eq!(w.objects.len(), 4);
+        assert!(w.objects.iter().all(|o| o.shape == ShapeType::Sphere));
+        let glass = Material {
+            transparency: 1.0,
+            refractive_index: 1.5,
+            ..Material::default()
+        };
+        assert_eq!(w.objects[0].material, glass);
+        assert_eq!(w.objects[0].transform, Matrix::scaling(0.5, 0.5, 0.5));
+        // transforms are replaced rather than added to
+        assert_eq!(w.objects[1].material, glass);
+        assert_eq!(w.objects[1].transform, Matrix::translation(2.0, 0.0, 0.0));
+        // but materials are merged
+        let red = Material {
+            colour: Colour::new(1.0, 0.0, 0.0),
+            reflectivity: 0.5,
+            ..glass.clone()
+        };
+        assert_eq!(w.objects[2].material, red);
+        assert_eq!(w.objects[2].transform, Matrix::scaling(0.5, 0.5, 0.5));
+        assert_eq!(w.objects[3].material, glass);
+    }
+
+    #[test]
+    fn templates_need_a_real_kind_and_a_new_name() {
+        let errors = [
+            ("- define: blob\n  add: blob\n", "entity 1 (define).add should be one of sphere, plane, cylinder, cone, cube, obj, group, camera, light, or the name of a template, but is \"blob\""),
+            ("- define: cube\n  add: sphere\n", "entity 1 (define).define should be a name that isn't already a kind of entity, but is \"cube\""),
+        ];
+        for (yaml_file, message) in errors.iter() {
+            assert_eq!(
+                parse_scene(yaml_file, None).err().unwrap().to_string(),
+                *message
+            );
+        }
+        // unknown settings are found where the template is used
+        let yaml_file = "
+- define: ball
+  add: sphere
+  shiny: true
+- add: ball
+";
+        let (_, _, warnings) =
+            parse_scene_with_warnings(yaml_file, SceneFormat::Yaml, Path::new(""), None).unwrap();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(
+            warnings[0].to_string(),
+            "entity 2 (ball).shiny isn't a known setting"
+        );
+    }
+
+    #[test]
+    fn each_document_is_a_frame() {
+        let yaml_file = "
+- add: sphere
+---
+- add: sphere
+- add: cube
+---
+- add: light
+  at: [0, 10, 0]
+  intensity: [1, 1, 1]
+";
+        let (frames, _) = parse_frames(yaml_file, SceneFormat::Yaml, Path::new("")).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].objects.len(), 1);
+        assert_eq!(frames[1].objects.len(), 2);
+        assert_eq!(frames[2].lights.len(), 1);
+        // a scene that isn't animated is one frame
+        let (frames, _) = parse_frames("- add: cube", SceneFormat::Yaml, Path::new("")).unwrap();
+        assert_eq!(frames.len(), 1);
+        let (frames, _) = parse_frames("", SceneFormat::Yaml, Path::new("")).unwrap();
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn frames_share_the_rest_of_the_scene() {
+        let json_file = r#"[
+            {"define": "spin", "value": [["rotate-y", 0.5]]},
+            {"add": "sphere"},
+            {"frames": [
+                [{"add": "cube", "transform": ["spin"]}],
+                [{"add": "cube", "transform": ["spin", "spin"]}],
+                [{"add": "cube", "transform": ["spin", "spin", "spin"]}]
+            ]},
+            {"add": "plane"}
+        ]"#;
+        let (frames, warnings) = parse_frames(json_file, SceneFormat::Json, Path::new("")).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(frames.len(), 3);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.objects.len(), 3);
+            assert_eq!(frame.objects[2].shape, ShapeType::Cube);
+            let angle = 0.5 * (i + 1) as Float;
+            assert_eq!(frame.objects[2].transform, Matrix::rotation_y(angle));
+        }
+    }
+
+    #[test]
+    fn errors_in_frames_say_which_frame() {
+        let yaml_file = "
+- add: sphere
+  ambiant: 1
+- frames:
+  - - add: cube
+  - - add: cube
+      colour: [1, 0, 0]
+  - - add: cube
+      material: shiny
+";
+        let error = parse_frames(yaml_file, SceneFormat::Yaml, Path::new(""))
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "in frame 3, entity 1 (cube).material uses shiny, which hasn't been defined"
+        );
+        let yaml_file = "
+- add: sphere
+  ambiant: 1
+- frames:
+  - - add: cube
+  - - add: cube
+      colour: [1, 0, 0]
+";
+        let (_, warnings) = parse_frames(yaml_file, SceneFormat::Yaml, Path::new("")).unwrap();
+        let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "entity 1 (sphere).ambiant isn't a known setting".to_string(),
+                "in frame 2, entity 1 (cube).colour isn't a known setting".to_string(),
+            ]
+        );
+        let error = parse_animation_with_warnings(
+            "- add: sphere\n---\n- render: top\n",
+            SceneFormat::Yaml,
+            Path::new(""),
+            None,
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.to_string(), "in frame 2, there's no camera named top");
+    }
+
+    #[test]
+    fn files_are_relative_to_the_scene() {
+        let directory = std::env::temp_dir().join("rusrat_relative_paths");
+        std::fs::create_dir_all(directory.join("models")).unwrap();
+        std::fs::write(
+            directory.join("models").join("triangle.obj"),
+            "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("models").join("props.yaml"),
+            "
+- define: wallpaper
+  value:
+    pattern:
+      type: texture
+      file: missing.png
+- add: obj
+  file: triangle.obj
+",
+        )
+        .unwrap();
+        let yaml_file = "
+- add: obj
+  file: models/triangle.obj
+- include: models/props.yaml
+";
+        let (w, _) = parse_scene_in(yaml_file, &directory, None).unwrap();
+        assert_eq!(w.objects.len(), 2);
+
+        // a definition's files are relative to the file it's defined in
+        let yaml_file = "
+- include: models/props.yaml
+- add: sphere
+  material: wallpaper
+";
+        match parse_scene_in(yaml_file, &directory, None) {
+            Err(ParseError::File { key, path, .. }) => {
+                assert_eq!(key, "entity 2 (sphere).material.pattern.file");
+                assert_eq!(
+                    path,
+                    directory
+                        .join("models")
+                        .join("missing.png")
+                        .display()
+                        .to_string()
+                );
+            }
+            other => panic!("expected a file error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn errors_in_included_files_say_which_file() {
+        let directory = std::env::temp_dir().join("rusrat_include_errors");
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("broken.yaml"),
+            "- add: sphere\n  name: [1, 2\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("loop.yaml"), "- include: loop.yaml\n").unwrap();
+
+        let error = parse_scene_in("- include: broken.yaml", &directory, None)
+            .err()
+            .unwrap();
+        let (file, line, _) = error.syntax_position().unwrap();
+        assert!(file.unwrap().ends_with("broken.yaml"));
+        assert_eq!(line, 3);
+        match error {
+            ParseError::Included { path, error } => {
+                assert!(path.ends_with("broken.yaml"));
+                assert!(matches!(*error, ParseError::Syntax { line: 3, .. }));
+            }
+            _ => panic!("expected an error in the included file"),
+        }
+
+        let error = parse_scene_in("- include: loop.yaml", &directory, None)
+            .err()
+            .unwrap();
+        assert!(matches!(error, ParseError::Included { .. }));
+    }
+
+    #[test]
+    fn reads_in_groups() {
+        let yaml_file = "
+- add: group
+  transform:
+    - [translate, 0, 2, 0]
+  material:
+    colour: [1, 0, 0]
+  children:
+    - add: sphere
+      transform:
+        - [scale, 2, 2, 2]
+    - add: cube
+      material:
+        colour: [0, 0, 1]
+    - add: group
+      transform:
+        - [translate, 1, 0, 0]
+      children:
+        - add: plane
+          name: floor
+  name: things
+";
+        let (w, _) = parse_scene(yaml_file, None).unwrap();
+        assert_eq!(w.objects.len(), 3);
+        let names: Vec<_> = w.objects.iter().map(|o| o.name.as_deref()).collect();
+        assert_eq!(names, [Some("things"), Some("things"), Some("floor")]);
+        let sphere = &w.objects[0];
+        assert_eq!(sphere.shape, ShapeType::Sphere);
+        assert_eq!(
+            sphere.transform,
+            Matrix::translation(0.0, 2.0, 0.0) * Matrix::scaling(2.0, 2.0, 2.0)
+        );
+        assert_eq!(sphere.material.colour, Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(w.objects[1].material.colour, Colour::new(0.0, 0.0, 1.0));
+        let plane = &w.objects[2];
+        assert_eq!(plane.transform, Matrix::translation(1.0, 2.0, 0.0));
+        assert_eq!(plane.material.colour, Colour::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn lights_cant_be_in_groups() {
+        let yaml_file = "
+- add: group
+  children:
+    - add: light
+      at: [0, 0, 0]
+      intensity: [1, 1, 1]
+";
+        let error = parse_scene(yaml_file, None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "entity 1 (group).children[0].add should be a kind of object, but is \"light\""
+        );
+    }
+
+    #[test]
+    fn unknown_keys_are_warnings() {
+        let yaml_file = "
+- add: camera
+  width: 10
+  height: 10
+  field-of-view: 1
+  from: [0, 0, -5]
+  to: [0, 0, 0]
+  bloom:
+    treshold: 2
+- add: sphere
+  colour: [1, 0, 0]
+  material:
+    ambiant: 0.2
+    pattern:
+      type: stripe
+      colour-a: [1, 1, 1]
+      colour-b:
+        type: ring
+        colour-a: [0, 0, 0]
+        colour-b: [1, 1, 1]
+        turbulence: 3
+- world:
+    fog:
+      type: linear
+      start: 1
+      end: 2
+      dencity: 3
+- add: plane
+  material:
+    diffuse: 0.5
+";
+        let (_, _, warnings) =
+            parse_scene_with_warnings(yaml_file, SceneFormat::Yaml, Path::new(""), None).unwrap();
+        let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "entity 1 (camera).bloom.treshold isn't a known setting - did you mean threshold?",
+                "entity 2 (sphere).colour isn't a known setting",
+                "entity 2 (sphere).material.ambiant isn't a known setting - did you mean ambient?",
+                "entity 2 (sphere).material.pattern.colour-b.turbulence isn't a known setting",
+                "entity 3 (world).world.fog.dencity isn't a known setting - did you mean density?",
+            ]
+        );
+    }
+
+    #[test]
+    fn scenes_are_lists_of_maps() {
+        let error = parse_scene("add: sphere\n", None).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the scene should be a list of entities, but is a map"
+        );
+        let (w, _, warnings) = parse_scene_with_warnings(
+            "- sphere\n- add: sphere\n- 42\n",
+            SceneFormat::Yaml,
+            Path::new(""),
+            None,
+        )
+        .unwrap();
+        assert_eq!(w.objects.len(), 1);
+        let warnings: Vec<String> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            warnings,
+            vec![
+                "entity 1 should be a map, like { add: sphere }, but is \"sphere\"",
+                "entity 3 should be a map, like { add: sphere }, but is 42",
+            ]
+        );
+    }
+
+    #[test]
+    fn finds_closest_key() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("same", "same"), 0);
+        assert_eq!(
+            closest_key("field_of_view", &["from", "field-of-view"]),
+            Some("field-of-view".to_string())
+        );
+        assert_eq!(closest_key("wibble", &["from", "to"]), None);
+    }
+
+    #[test]
+    fn reads_in_the_books_spellings() {
+        let yaml_file = "
+- add: camera
+  width: 100
+  height: 50
+  field_of_view: 0.785
+  from: [0, 1.5, -5]
+  to: [0, 1, 0]
+  up: [0, 1, 0]
+- define: glass
+  value:
+    color: [0.1, 0.1, 0.1]
+    reflective: 0.9
+    transparency: 0.9
+    refractive-index: 1.5
+- add: sphere
+  material: glass
+- add: plane
+  material:
+    pattern:
+      type: checkers
+      colors:
+        - [0.35, 0.35, 0.35]
+        - [0.65, 0.65, 0.65]
+- world:
+    fog:
+      type: exponential
+      density: 0.1
+      color: [0.5, 0.5, 0.5]
+";
+        let (w, c, warnings) =
+            parse_scene_with_warnings(yaml_file, SceneFormat::Yaml, Path::new(""), None).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(c.field_of_view, 0.785);
+        let glass = &w.objects[0].material;
+        assert_eq!(glass.colour, Colour::new(0.1, 0.1, 0.1));
+        assert_eq!(glass.reflectivity, 0.9);
+        assert_eq!(glass.refractive_index, 1.5);
+        assert_eq!(
+            w.objects[1].material.pattern,
+            Some(Pattern::Check3D {
+                colour_a: Colour::new(0.35, 0.35, 0.35),
+                colour_b: Colour::new(0.65, 0.65, 0.65),
+                transform: Transform::default(),
+            })
+        );
+        assert_eq!(
+            w.fog,
+            Some(Fog::Exponential {
+                colour: Colour::new(0.5, 0.5, 0.5),
+                density: 0.1,
+            })
+        );
+    }
+
+    #[test]
+    fn both_colour_spellings_work_for_stripes() {
+        let yaml_pattern = "
+type: stripes
+color-a: [1, 1, 1]
+color-b: [0, 0, 0]
+";
+        let config = &yaml::YamlLoader::load_from_str(yaml_pattern).unwrap()[0];
+        assert_eq!(
+            parse_pattern(config).unwrap(),
+            Pattern::Stripe {
+                colour_a: Colour::white(),
+                colour_b: Colour::black(),
+                transform: Transform::default(),
+            }
+        );
+    }
+
+    #[test]
+    fn reads_in_json_scenes() {
+        let json_file = r##"[
+    {
+        "add": "camera",
+        "width": 20,
+        "height": 10,
+        "field-of-view": 1.2,
+        "from": [0, 0, -5],
+        "to": [0, 0, 0]
+    },
+    {"add": "light", "at": [-10, 10, -10], "intensity": "white"},
+    {
+        "add": "sphere",
+        "material": {"colour": "#ff0000", "diffuse": 0.25},
+        "transform": [["scale", 2, 2, 2], ["rotate-y", "pi/2"]]
+    }
+]"##;
+        let (w, c, warnings) =
+            parse_scene_with_warnings(json_file, SceneFormat::Json, Path::new(""), None).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(c.hsize, 20);
+        assert_eq!(c.field_of_view, 1.2);
+        assert_eq!(w.lights.len(), 1);
+        let sphere = &w.objects[0];
+        assert_eq!(sphere.material.colour, Colour::new(1.0, 0.0, 0.0));
+        assert_eq!(sphere.material.diffuse, 0.25);
+        assert_eq!(
+            sphere.transform,
+            Matrix::rotation_y(crate::precision::consts::FRAC_PI_2)
+                * Matrix::scaling(2.0, 2.0, 2.0)
+        );
+    }
+
+    #[test]
+    fn json_syntax_errors_have_their_line() {
+        let json_file = "[\n  {\"add\": \"sphere\",}\n]";
+        match parse_scene_with_warnings(json_file, SceneFormat::Json, Path::new(""), None) {
+            Err(ParseError::Syntax { line, .. }) => assert_eq!(line, 2),
+            _ => panic!("expected a syntax error"),
+        }
+    }
+
+    #[test]
+    fn scene_format_comes_from_extension() {
+        assert_eq!(
+            SceneFormat::from_path(Path::new("a/scene.json")),
+            SceneFormat::Json
+        );
+        assert_eq!(
+            SceneFormat::from_path(Path::new("SCENE.JSON")),
+            SceneFormat::Json
+        );
+        assert_eq!(
+            SceneFormat::from_path(Path::new("scene.yaml")),
+            SceneFormat::Yaml
+        );
+        assert_eq!(
+            SceneFormat::from_path(Path::new("scene")),
+            SceneFormat::Yaml
+        );
+    }
+
+    #[test]
+    fn the_starter_scene_parses_cleanly() {
+        let (frames, warnings) = parse_frames(
+            include_str!("../examples/starter.yaml"),
+            SceneFormat::Yaml,
+            Path::new(""),
+        )
+        .unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].cameras.len(), 1);
+        assert_eq!(frames[0].lights.len(), 1);
+        assert_eq!(frames[0].objects.len(), 2);
+    }
 }